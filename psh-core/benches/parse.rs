@@ -0,0 +1,60 @@
+//! Measures the throughput of lexing and parsing a large script.
+//!
+//! Run with `cargo bench -p psh-core --bench parse`.
+
+use std::time::{Duration, Instant};
+
+use psh_core::ast::parse;
+use psh_core::tok;
+
+const ITERATIONS: u32 = 20;
+
+const LINES: &[&str] = &[
+    "echo foo \"bar $baz\" 'single quoted' | grep -v x > /dev/null",
+    "FOO=bar BAZ=~/qux cmd --flag=value -abc arg1 arg2 arg3 2>&1",
+    "cd ~/projects/posh && cargo build --release || echo \"failed: $?\"",
+    "ls -la $HOME/some/long/path/to/a/directory; cat < input.txt >> output.txt &",
+    "# a comment describing what is going on in this part of the script",
+];
+
+fn script(lines: usize) -> String {
+    LINES
+        .iter()
+        .cycle()
+        .take(lines)
+        .map(|l| format!("{l}\n"))
+        .collect()
+}
+
+fn bench(input: &str, f: impl Fn(&str)) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(input);
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn report(label: &str, input: &str, time: Duration) {
+    let mib = input.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "  {label:<6} {time:>10.2?}  {:>8.2} MiB/s",
+        mib / time.as_secs_f64()
+    );
+}
+
+fn main() {
+    for lines in [100, 1_000, 10_000] {
+        let input = script(lines);
+        println!("{lines} lines ({} bytes):", input.len());
+
+        let lex = bench(&input, |input| {
+            tok::lex(input);
+        });
+        report("lex", &input, lex);
+
+        let parse = bench(&input, |input| {
+            parse(input, false).expect("could not parse script");
+        });
+        report("parse", &input, parse);
+    }
+}
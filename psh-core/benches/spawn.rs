@@ -0,0 +1,49 @@
+//! Compares the startup latency of external commands when spawned with
+//! `posix_spawn` and with `fork` and `exec`. Since `fork` has to copy the
+//! page tables of the shell, the difference grows with its memory usage,
+//! which is why the commands are also run with a large allocation alive.
+//!
+//! Run with `cargo bench -p psh-core --bench spawn`.
+
+use std::time::{Duration, Instant};
+
+use psh_core::Engine;
+
+const ITERATIONS: u32 = 500;
+
+fn bench(engine: &mut Engine, posix_spawn: bool) -> Duration {
+    engine.options.posix_spawn = posix_spawn;
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        engine.execute_line("true").expect("could not run `true`");
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn report(engine: &mut Engine, label: &str) {
+    // warm up caches, such as the lookup of `true` in `$PATH`
+    bench(engine, true);
+    bench(engine, false);
+
+    let fork = bench(engine, false);
+    let spawn = bench(engine, true);
+
+    println!("{label}:");
+    println!("  fork + exec  {fork:>12.2?} per command");
+    println!("  posix_spawn  {spawn:>12.2?} per command");
+    println!(
+        "  speedup      {:>12.2}x",
+        fork.as_secs_f64() / spawn.as_secs_f64()
+    );
+}
+
+fn main() {
+    let mut engine = Engine::new();
+    report(&mut engine, "small shell");
+
+    // touch every page so that it is actually mapped
+    let ballast = vec![1u8; 512 * 1024 * 1024];
+    report(&mut engine, "shell with 512 MiB resident");
+    drop(ballast);
+}
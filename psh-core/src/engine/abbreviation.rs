@@ -0,0 +1,169 @@
+//! Abbreviations, which are expanded in the line being edited when a space
+//! is typed after them or the line is entered, as set with `abbr`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use regex::Regex;
+
+/// Where in a command an abbreviation is expanded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Position {
+    /// Only as the name of a command, such as at the start of the line or
+    /// after `|`.
+    #[default]
+    Command,
+
+    /// As any word, such as an argument.
+    Anywhere,
+}
+
+impl Position {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "command" => Some(Self::Command),
+            "anywhere" => Some(Self::Anywhere),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Command => write!(f, "command"),
+            Self::Anywhere => write!(f, "anywhere"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Abbreviation {
+    pub expansion: String,
+
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub position: Position,
+
+    /// Expands the words matching it in whole, instead of only the name of
+    /// the abbreviation, as in `abbr --regex '[0-9]+d' ...`.
+    #[cfg_attr(feature = "serde", serde(default, with = "regex_text"))]
+    pub regex: Option<Regex>,
+}
+
+impl Abbreviation {
+    pub fn new(expansion: impl Into<String>) -> Self {
+        Self {
+            expansion: expansion.into(),
+            position: Position::default(),
+            regex: None,
+        }
+    }
+
+    /// Whether `word` expands to this abbreviation named `name`, where
+    /// `command` tells whether it is in command position.
+    pub fn matches(&self, name: &str, word: &str, command: bool) -> bool {
+        if self.position == Position::Command && !command {
+            return false;
+        }
+        match &self.regex {
+            Some(regex) => regex
+                .find(word)
+                .is_some_and(|m| m.start() == 0 && m.end() == word.len()),
+            None => word == name,
+        }
+    }
+}
+
+impl PartialEq for Abbreviation {
+    fn eq(&self, other: &Self) -> bool {
+        self.expansion == other.expansion
+            && self.position == other.position
+            && self.regex.as_ref().map(Regex::as_str) == other.regex.as_ref().map(Regex::as_str)
+    }
+}
+
+impl Eq for Abbreviation {}
+
+/// The abbreviation that `word` expands to, if any. An abbreviation named
+/// `word` wins over the ones with a regex, which are tried in order of
+/// their names.
+pub fn find<'a>(
+    abbreviations: &'a HashMap<String, Abbreviation>,
+    word: &str,
+    command: bool,
+) -> Option<&'a Abbreviation> {
+    if let Some(abbreviation) = abbreviations.get(word) {
+        if abbreviation.regex.is_none() && abbreviation.matches(word, word, command) {
+            return Some(abbreviation);
+        }
+    }
+
+    let mut regexes = abbreviations
+        .iter()
+        .filter(|(_, abbreviation)| abbreviation.regex.is_some())
+        .collect::<Vec<_>>();
+    regexes.sort_by_key(|(name, _)| *name);
+    regexes
+        .into_iter()
+        .find(|(name, abbreviation)| abbreviation.matches(name, word, command))
+        .map(|(_, abbreviation)| abbreviation)
+}
+
+/// Keeps the regex of an abbreviation as its text in a snapshot.
+#[cfg(feature = "serde")]
+mod regex_text {
+    use regex::Regex;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        regex: &Option<Regex>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match regex {
+            Some(regex) => serializer.serialize_some(regex.as_str()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Regex>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| Regex::new(&text).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finding() {
+        let mut anywhere = Abbreviation::new("| less");
+        anywhere.position = Position::Anywhere;
+        let mut days = Abbreviation::new("--since=%|% days");
+        days.position = Position::Anywhere;
+        days.regex = Some(Regex::new("[0-9]+d").unwrap());
+
+        let abbreviations = HashMap::from([
+            ("g".to_string(), Abbreviation::new("git")),
+            ("L".to_string(), anywhere),
+            ("days".to_string(), days),
+        ]);
+        let expansion = |word, command| {
+            find(&abbreviations, word, command).map(|abbreviation| abbreviation.expansion.as_str())
+        };
+
+        assert_eq!(Some("git"), expansion("g", true));
+        assert_eq!(None, expansion("g", false));
+        assert_eq!(Some("| less"), expansion("L", false));
+        assert_eq!(Some("--since=%|% days"), expansion("10d", false));
+        assert_eq!(None, expansion("10days", true));
+        assert_eq!(None, expansion("days", true));
+        assert_eq!(None, expansion("git", true));
+    }
+}
@@ -0,0 +1,413 @@
+//! Evaluation of the expressions of arithmetic expansion, e.g. the
+//! `1 + 2 * x` in `$((1 + 2 * x))`, after they have been expanded.
+//!
+//! The operators are the ones POSIX requires, with the precedence and
+//! associativity of C. Numbers are signed 64-bit integers, with wrapping
+//! arithmetic, and variables can be referred to by their names.
+
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::engine::expand::Environment;
+use crate::{Error, Result};
+
+/// The operators, longest first so that they are tokenized greedily.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=", "-=",
+    "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "=", "?", ":",
+    "(", ")",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+
+    /// An assignment, with the operator of a compound assignment like `+=`.
+    Assign(String, Option<&'static str>, Box<Expr>),
+}
+
+/// Evaluates `expression`, assigning to the variables of `env` as it says.
+pub fn evaluate(expression: &str, env: &mut dyn Environment) -> Result<i64> {
+    let error = |e: String| Error::Arithmetic(format!("{}: {e}", expression.trim()));
+
+    let mut tokens = tokenize(expression).map_err(error)?.into_iter().peekable();
+    if tokens.peek().is_none() {
+        return Ok(0);
+    }
+
+    let expr = parse_assignment(&mut tokens).map_err(error)?;
+    if let Some(token) = tokens.next() {
+        return Err(error(format!("unexpected {}", describe(&token))));
+    }
+
+    eval(&expr, env).map_err(error)
+}
+
+fn tokenize(expression: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("unexpected '{c}'"));
+        };
+
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an integer constant, which is hexadecimal if prefixed by `0x`,
+/// and octal if prefixed by `0`.
+fn parse_number(s: &str) -> std::result::Result<i64, String> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        u64::from_str_radix(&s[1..], 8)
+    } else {
+        s.parse::<u64>()
+    };
+
+    // like in C, constants that are too large wrap around
+    parsed
+        .map(|n| n as i64)
+        .map_err(|_| format!("invalid number '{s}'"))
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => format!("'{n}'"),
+        Token::Name(name) => format!("'{name}'"),
+        Token::Op(op) => format!("'{op}'"),
+    }
+}
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+fn expect(tokens: &mut Tokens, op: &str) -> std::result::Result<(), String> {
+    match tokens.next() {
+        Some(Token::Op(found)) if found == op => Ok(()),
+        Some(token) => Err(format!("expected '{op}', found {}", describe(&token))),
+        None => Err(format!("expected '{op}'")),
+    }
+}
+
+fn parse_assignment(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    let mut lookahead = tokens.clone();
+    if let (Some(Token::Name(name)), Some(Token::Op(op))) = (lookahead.next(), lookahead.next()) {
+        if op.ends_with('=') && !matches!(op, "==" | "!=" | "<=" | ">=") {
+            *tokens = lookahead;
+            let compound = op.strip_suffix('=').filter(|op| !op.is_empty());
+            let compound = compound.and_then(|op| OPERATORS.iter().copied().find(|o| *o == op));
+            let value = parse_assignment(tokens)?;
+            return Ok(Expr::Assign(name, compound, Box::new(value)));
+        }
+    }
+
+    parse_conditional(tokens)
+}
+
+fn parse_conditional(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    let condition = parse_binary(tokens, 1)?;
+    if tokens.peek() != Some(&Token::Op("?")) {
+        return Ok(condition);
+    }
+
+    tokens.next();
+    let then = parse_assignment(tokens)?;
+    expect(tokens, ":")?;
+    let otherwise = parse_conditional(tokens)?;
+
+    Ok(Expr::Conditional(
+        Box::new(condition),
+        Box::new(then),
+        Box::new(otherwise),
+    ))
+}
+
+/// The precedence of a binary operator, where higher binds tighter.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn parse_binary(tokens: &mut Tokens, min_precedence: u8) -> std::result::Result<Expr, String> {
+    let mut lhs = parse_unary(tokens)?;
+
+    while let Some(&Token::Op(op)) = tokens.peek() {
+        let Some(precedence) = precedence(op).filter(|p| *p >= min_precedence) else {
+            break;
+        };
+        tokens.next();
+
+        let rhs = parse_binary(tokens, precedence + 1)?;
+        lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary(tokens: &mut Tokens) -> std::result::Result<Expr, String> {
+    match tokens.next() {
+        Some(Token::Op(op @ ("+" | "-" | "!" | "~"))) => {
+            Ok(Expr::Unary(op, Box::new(parse_unary(tokens)?)))
+        }
+        Some(Token::Op("(")) => {
+            let expr = parse_assignment(tokens)?;
+            expect(tokens, ")")?;
+            Ok(expr)
+        }
+        Some(Token::Number(n)) => Ok(Expr::Number(n)),
+        Some(Token::Name(name)) => Ok(Expr::Variable(name)),
+        Some(token) => Err(format!("unexpected {}", describe(&token))),
+        None => Err(String::from("expected an operand")),
+    }
+}
+
+fn eval(expr: &Expr, env: &mut dyn Environment) -> std::result::Result<i64, String> {
+    Ok(match expr {
+        Expr::Number(n) => *n,
+        Expr::Variable(name) => variable(name, env)?,
+
+        Expr::Unary(op, operand) => {
+            let operand = eval(operand, env)?;
+            match *op {
+                "-" => operand.wrapping_neg(),
+                "!" => (operand == 0) as i64,
+                "~" => !operand,
+                _ => operand,
+            }
+        }
+
+        // the right hand side is only evaluated if needed, which matters
+        // for assignments
+        Expr::Binary(lhs, "&&", rhs) => (eval(lhs, env)? != 0 && eval(rhs, env)? != 0) as i64,
+        Expr::Binary(lhs, "||", rhs) => (eval(lhs, env)? != 0 || eval(rhs, env)? != 0) as i64,
+        Expr::Binary(lhs, op, rhs) => {
+            let lhs = eval(lhs, env)?;
+            let rhs = eval(rhs, env)?;
+            apply(op, lhs, rhs)?
+        }
+
+        Expr::Conditional(condition, then, otherwise) => match eval(condition, env)? {
+            0 => eval(otherwise, env)?,
+            _ => eval(then, env)?,
+        },
+
+        Expr::Assign(name, op, value) => {
+            let mut value = eval(value, env)?;
+            if let Some(op) = op {
+                value = apply(op, variable(name, env)?, value)?;
+            }
+            env.set_var(name, value.to_string());
+            value
+        }
+    })
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> std::result::Result<i64, String> {
+    Ok(match op {
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err(String::from("division by zero")),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "&" => lhs & rhs,
+        "^" => lhs ^ rhs,
+        "|" => lhs | rhs,
+        _ => unreachable!("not a binary operator: {op}"),
+    })
+}
+
+/// The value of the variable `name`, which is 0 if it is unset or empty.
+fn variable(name: &str, env: &mut dyn Environment) -> std::result::Result<i64, String> {
+    let value = env.var(name).unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+
+    let (sign, digits) = match value.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    match digits.starts_with(|c: char| c.is_ascii_digit()) {
+        true => parse_number(digits).map(|n| sign * n),
+        false => Err(format!("{name}: '{value}' is not a number")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::engine::cancel::CancellationToken;
+
+    #[derive(Default)]
+    struct Variables(HashMap<String, String>, CancellationToken);
+
+    impl Environment for Variables {
+        fn var(&self, name: &str) -> Option<String> {
+            self.0.get(name).cloned()
+        }
+
+        fn set_var(&mut self, name: &str, value: String) {
+            self.0.insert(name.to_string(), value);
+        }
+
+        fn last_status(&self) -> String {
+            String::from("0")
+        }
+
+        fn parameters(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn home_dir(&self, _: Option<&str>) -> Option<String> {
+            None
+        }
+
+        fn command_output(&mut self, _: &crate::ast::nodes::SyntaxTree) -> String {
+            String::new()
+        }
+
+        fn noglob(&self) -> bool {
+            true
+        }
+
+        fn cancellation(&self) -> &CancellationToken {
+            &self.1
+        }
+
+        fn braceexpand(&self) -> bool {
+            false
+        }
+
+        fn nounset(&self) -> bool {
+            false
+        }
+
+        fn fail(&mut self, _: crate::Error) {}
+    }
+
+    #[test]
+    fn golden() {
+        let golden: &[(&str, i64)] = &[
+            ("", 0),
+            ("1 + 2 * 3", 7),
+            ("(1 + 2) * 3", 9),
+            ("10 - 4 - 3", 3),
+            ("2 * -3", -6),
+            ("7 / 2 + 7 % 2", 4),
+            ("-7 / 2", -3),
+            ("1 << 4 | 1", 17),
+            ("0x1f + 010", 39),
+            ("3 > 2 && 2 > 3", 0),
+            ("!0 + ~0", 0),
+            ("1 < 2 == 1", 1),
+            ("x", 5),
+            ("x * y", -10),
+            ("empty + unset", 0),
+            ("x > 3 ? 10 : 20", 10),
+            ("0 ? 1 : 0 ? 2 : 3", 3),
+            ("9223372036854775807 + 1", i64::MIN),
+        ];
+
+        for (expression, expected) in golden {
+            let mut env = Variables::default();
+            env.set_var("x", String::from("5"));
+            env.set_var("y", String::from("-2"));
+            env.set_var("empty", String::new());
+            assert_eq!(
+                *expected,
+                evaluate(expression, &mut env).unwrap(),
+                "evaluating {expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        let mut env = Variables::default();
+        assert_eq!(3, evaluate("x = 1 + 2", &mut env).unwrap());
+        assert_eq!(Some("3"), env.var("x").as_deref());
+
+        assert_eq!(12, evaluate("y = x *= 4", &mut env).unwrap());
+        assert_eq!(Some("12"), env.var("x").as_deref());
+        assert_eq!(Some("12"), env.var("y").as_deref());
+
+        // the side that is not evaluated does not assign
+        assert_eq!(0, evaluate("0 && (z = 1)", &mut env).unwrap());
+        assert_eq!(1, evaluate("1 ? 1 : (z = 2)", &mut env).unwrap());
+        assert_eq!(None, env.var("z"));
+    }
+
+    #[test]
+    fn errors() {
+        let mut env = Variables::default();
+        env.set_var("word", String::from("abc"));
+
+        for (expression, message) in [
+            ("1 / 0", "1 / 0: division by zero"),
+            ("1 +", "1 +: expected an operand"),
+            ("(1", "(1: expected ')'"),
+            ("1 2", "1 2: unexpected '2'"),
+            ("08", "08: invalid number '08'"),
+            ("1 # 2", "1 # 2: unexpected '#'"),
+            ("word + 1", "word + 1: word: 'abc' is not a number"),
+        ] {
+            match evaluate(expression, &mut env) {
+                Err(Error::Arithmetic(e)) => assert_eq!(message, e),
+                result => panic!("evaluating {expression}: {result:?}"),
+            }
+        }
+    }
+}
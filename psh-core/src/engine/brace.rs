@@ -0,0 +1,495 @@
+//! Brace expansion, i.e. `a{b,c}d` and `{1..10}`.
+//!
+//! This is the first expansion performed on a word, so that the results
+//! go through the remaining expansions individually. Since the other
+//! expansions refer to ranges of the word, every character remembers
+//! where in the original word it came from, which is used to move the
+//! expansions along with the text they belong to.
+
+use crate::ast::nodes::{Expansion, Word};
+use crate::engine::cancel::CancellationToken;
+use crate::engine::quote::{self, Quoting};
+
+/// The most elements a sequence expression can have. Larger ones are left
+/// as they are, rather than taking up all of the memory.
+const MAX_SEQUENCE: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Unit {
+    c: char,
+
+    /// The byte index of the character in the original word, or `None` if
+    /// it was generated by a sequence expression.
+    origin: Option<usize>,
+
+    /// Whether the character is quoted, escaped, or part of another
+    /// expansion, in which case it is never treated as brace syntax.
+    protected: bool,
+}
+
+/// Performs brace expansion on `word`. If the word contains no brace
+/// expression it is returned as is. If `cancel` is cancelled while
+/// expanding, only the words generated so far are returned.
+pub fn expand_word(word: Word, cancel: &CancellationToken) -> Vec<Word> {
+    if !word.name.contains('{') {
+        return vec![word];
+    }
+
+    let units = units_of(&word);
+    let expanded = expand_units(&units, cancel);
+
+    if expanded.len() == 1 && expanded[0] == units {
+        return vec![word];
+    }
+
+    expanded
+        .into_iter()
+        .map(|units| word_from_units(&word, &units))
+        .collect()
+}
+
+/// Performs brace expansion on a plain string, where quotes and
+/// backslashes protect braces and commas like they do in a word.
+pub fn expand_str(s: &str) -> Vec<String> {
+    expand_word(Word::new(s, ""), &CancellationToken::default())
+        .into_iter()
+        .map(|word| word.name)
+        .collect()
+}
+
+fn units_of(word: &Word) -> Vec<Unit> {
+    let in_expansion = |i: usize| {
+        word.expansions.iter().any(|exp| match exp {
+            Expansion::Tilde { .. } => false,
+            Expansion::Glob { range, .. }
+            | Expansion::Brace { range, .. }
+            | Expansion::Parameter { range, .. }
+            | Expansion::Command { range, .. }
+            | Expansion::Arithmetic { range, .. } => range.contains(&i),
+        })
+    };
+
+    let mut units = Vec::new();
+    let mut prev = None;
+
+    for (i, c, quoting) in quote::scan(&word.name) {
+        let protected = quoting != Quoting::Unquoted
+            || in_expansion(i)
+            // `${` is parameter expansion, not brace expansion
+            || (c == '{' && prev == Some('$'));
+
+        units.push(Unit {
+            c,
+            origin: Some(i),
+            protected,
+        });
+        prev = Some(c);
+    }
+
+    units
+}
+
+fn word_from_units(word: &Word, units: &[Unit]) -> Word {
+    let mut new = Word::new(&units.iter().map(|u| u.c).collect::<String>(), "");
+    new.whitespace = word.whitespace.clone();
+
+    // byte index in the new word of every unit
+    let mut indices = Vec::with_capacity(units.len());
+    let mut index = 0;
+    for unit in units {
+        indices.push(index);
+        index += unit.c.len_utf8();
+    }
+
+    for exp in &word.expansions {
+        let range = exp.range();
+        let Some(start) = units.iter().position(|u| u.origin == Some(*range.start())) else {
+            // the expansion was part of an alternative that was not chosen
+            continue;
+        };
+
+        let offset = indices[start];
+        let moved = units[start..]
+            .iter()
+            .zip(&indices[start..])
+            .take_while(|(u, _)| u.origin.is_some_and(|o| o <= *range.end()))
+            .all(|(u, i)| u.origin.map(|o| o - range.start()) == Some(i - offset));
+
+        if moved {
+            let mut exp = exp.clone();
+            *exp.range_mut() = offset..=offset + (range.end() - range.start());
+            new.expansions.push(exp);
+        }
+    }
+
+    new
+}
+
+fn is_unprotected(unit: &Unit, c: char) -> bool {
+    !unit.protected && unit.c == c
+}
+
+fn expand_units(units: &[Unit], cancel: &CancellationToken) -> Vec<Vec<Unit>> {
+    let mut search_from = 0;
+
+    while let Some(open) = units[search_from..]
+        .iter()
+        .position(|u| is_unprotected(u, '{'))
+        .map(|i| i + search_from)
+    {
+        search_from = open + 1;
+
+        let Some(close) = matching_brace(units, open) else {
+            continue;
+        };
+
+        let inner = &units[open + 1..close];
+        let alternatives = if let Some(seq) = sequence(inner) {
+            seq.take_while(|_| !cancel.is_cancelled())
+                .map(|s| {
+                    s.chars()
+                        .map(|c| Unit {
+                            c,
+                            origin: None,
+                            protected: true,
+                        })
+                        .collect()
+                })
+                .collect::<Vec<Vec<_>>>()
+        } else {
+            let parts = split_on_commas(inner);
+            if parts.len() < 2 {
+                continue;
+            }
+            parts
+                .into_iter()
+                .flat_map(|part| expand_units(part, cancel))
+                .collect()
+        };
+
+        let preamble = &units[..open];
+        let postambles = expand_units(&units[close + 1..], cancel);
+
+        let mut expanded = Vec::with_capacity(alternatives.len() * postambles.len());
+        for alternative in &alternatives {
+            if cancel.is_cancelled() {
+                break;
+            }
+            for postamble in &postambles {
+                let mut units = preamble.to_vec();
+                units.extend_from_slice(alternative);
+                units.extend_from_slice(postamble);
+                expanded.push(units);
+            }
+        }
+        return expanded;
+    }
+
+    vec![units.to_vec()]
+}
+
+fn matching_brace(units: &[Unit], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, unit) in units.iter().enumerate().skip(open) {
+        if is_unprotected(unit, '{') {
+            depth += 1;
+        } else if is_unprotected(unit, '}') {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn split_on_commas(units: &[Unit]) -> Vec<&[Unit]> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, unit) in units.iter().enumerate() {
+        if is_unprotected(unit, '{') {
+            depth += 1;
+        } else if is_unprotected(unit, '}') {
+            depth -= 1;
+        } else if depth == 0 && is_unprotected(unit, ',') {
+            parts.push(&units[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&units[start..]);
+
+    parts
+}
+
+/// Parses a sequence expression, `x..y[..incr]`, where `x` and `y` are
+/// either both integers or both single characters. Integers are zero
+/// padded to the same width if either end has a leading zero. The
+/// elements are generated lazily, since the sequence can be large.
+fn sequence(units: &[Unit]) -> Option<Box<dyn Iterator<Item = String>>> {
+    if units.iter().any(|u| u.protected) {
+        return None;
+    }
+
+    let inner = units.iter().map(|u| u.c).collect::<String>();
+    let mut parts = inner.split("..");
+    let (start, end) = (parts.next()?, parts.next()?);
+    let step = match parts.next() {
+        Some(step) => step.parse::<i64>().ok()?.unsigned_abs().max(1),
+        None => 1,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let has_leading_zero = |s: &str| {
+            let digits = s.strip_prefix('-').unwrap_or(s);
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if has_leading_zero(start) || has_leading_zero(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+
+        return Some(Box::new(
+            steps(from, to, step)?.map(move |n| format!("{n:0width$}")),
+        ));
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None)
+            if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() =>
+        {
+            Some(Box::new(
+                steps(from as i64, to as i64, step)?.map(|c| (c as u8 as char).to_string()),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Every `step`th number from `from` to `to`, both inclusive, counting
+/// down if `to` is less than `from`, or `None` if there are more than
+/// `MAX_SEQUENCE` of them.
+fn steps(from: i64, to: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (from.abs_diff(to) / step)
+        .checked_add(1)
+        .filter(|&count| count <= MAX_SEQUENCE)?;
+    Some((0..count).map_while(move |i| {
+        let offset = i.checked_mul(step)?;
+        match to < from {
+            true => from.checked_sub_unsigned(offset),
+            false => from.checked_add_unsigned(offset),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn expand(s: &str) -> Vec<String> {
+        expand_str(s)
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(vec!["ab", "ac"], expand("a{b,c}"));
+        assert_eq!(vec!["a", "ab"], expand("a{,b}"));
+        assert_eq!(vec!["xaz", "xbz", "xcz"], expand("x{a,{b,c}}z"));
+        assert_eq!(vec!["ac", "ad", "bc", "bd"], expand("{a,b}{c,d}"),);
+    }
+
+    #[test]
+    fn not_expanded() {
+        assert_eq!(vec!["{a}"], expand("{a}"));
+        assert_eq!(vec!["{}"], expand("{}"));
+        assert_eq!(vec!["{a,b"], expand("{a,b"));
+        assert_eq!(vec!["'{a,b}'"], expand("'{a,b}'"));
+        assert_eq!(vec!["\\{a,b}"], expand("\\{a,b}"));
+        assert_eq!(vec!["${a,b}"], expand("${a,b}"));
+        assert_eq!(vec!["{1..}"], expand("{1..}"));
+        assert_eq!(vec!["{a..1}"], expand("{a..1}"));
+        assert_eq!(vec!["{1..2..3..4}"], expand("{1..2..3..4}"));
+    }
+
+    #[test]
+    fn quoted_alternatives() {
+        assert_eq!(vec!["'a,b'", "c"], expand("{'a,b',c}"));
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(vec!["1", "2", "3"], expand("{1..3}"));
+        assert_eq!(vec!["3", "2", "1"], expand("{3..1}"));
+        assert_eq!(vec!["-1", "0", "1"], expand("{-1..1}"));
+        assert_eq!(vec!["a", "b", "c"], expand("{a..c}"));
+        assert_eq!(vec!["c", "b", "a"], expand("{c..a}"));
+        assert_eq!(vec!["a", "c", "e"], expand("{a..e..2}"));
+        assert_eq!(vec!["x1y", "x2y"], expand("x{1..2}y"));
+    }
+
+    #[test]
+    fn zero_padding() {
+        let expected = (1..=10).map(|n| format!("{n:02}")).collect::<Vec<_>>();
+        assert_eq!(expected, expand("{01..10}"));
+        assert_eq!(vec!["010", "009", "008"], expand("{010..8}"));
+        assert_eq!(vec!["-05", "-04", "-03"], expand("{-05..-3}"));
+        assert_eq!(vec!["8", "9", "10"], expand("{8..10}"));
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(
+            (0..=100)
+                .step_by(5)
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>(),
+            expand("{0..100..5}"),
+        );
+        assert_eq!(vec!["10", "7", "4", "1"], expand("{10..1..3}"));
+        assert_eq!(vec!["10", "7", "4", "1"], expand("{10..1..-3}"));
+        assert_eq!(vec!["1", "2"], expand("{1..2..0}"));
+        assert_eq!(
+            [i64::MIN, -1, i64::MAX - 1].map(|n| n.to_string()).to_vec(),
+            expand(&format!("{{{}..{}..{}}}", i64::MIN, i64::MAX, i64::MAX)),
+        );
+        assert_eq!(
+            [i64::MAX, -1].map(|n| n.to_string()).to_vec(),
+            expand(&format!("{{{}..{}..{}}}", i64::MAX, i64::MIN, i64::MIN)),
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let huge = format!("{{{}..{}}}", i64::MIN, i64::MAX);
+        assert_eq!(vec![huge.clone()], expand(&huge));
+        assert_eq!(vec!["{1..1048577}"], expand("{1..1048577}"));
+        assert_eq!(1 << 20, expand("{1..1048576}").len());
+    }
+
+    #[test]
+    fn cancelled() {
+        let cancel = CancellationToken::default();
+        cancel.cancel();
+
+        let words = expand_word(Word::new("x{1..100000}", ""), &cancel);
+        assert!(words.is_empty());
+    }
+
+    #[test]
+    fn expansions_follow_their_text() {
+        let mut word = Word::new("{$a,b$c}", "");
+        word.expansions = vec![
+            Expansion::Parameter {
+                range: 1..=2,
+                name: "a".to_string(),
+                modifier: None,
+                finished: true,
+                quoted: false,
+            },
+            Expansion::Parameter {
+                range: 5..=6,
+                name: "c".to_string(),
+                modifier: None,
+                finished: true,
+                quoted: false,
+            },
+        ];
+
+        let words = expand_word(word, &CancellationToken::default());
+        assert_eq!(2, words.len());
+        assert_eq!("$a", words[0].name);
+        assert_eq!(
+            vec![Expansion::Parameter {
+                range: 0..=1,
+                name: "a".to_string(),
+                modifier: None,
+                finished: true,
+                quoted: false,
+            }],
+            words[0].expansions,
+        );
+        assert_eq!("b$c", words[1].name);
+        assert_eq!(
+            vec![Expansion::Parameter {
+                range: 1..=2,
+                name: "c".to_string(),
+                modifier: None,
+                finished: true,
+                quoted: false,
+            }],
+            words[1].expansions,
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn numeric_ranges(from in -500i64..500, to in -500i64..500, step in 1u64..50) {
+            let expanded = expand(&format!("{{{from}..{to}..{step}}}"))
+                .into_iter()
+                .map(|s| s.parse::<i64>().unwrap())
+                .collect::<Vec<_>>();
+
+            prop_assert_eq!(Some(&from), expanded.first());
+            prop_assert_eq!(from.abs_diff(to) / step + 1, expanded.len() as u64);
+            for pair in expanded.windows(2) {
+                prop_assert_eq!(step, pair[0].abs_diff(pair[1]));
+                prop_assert_eq!(from <= to, pair[0] < pair[1]);
+            }
+            let last = *expanded.last().unwrap();
+            prop_assert!(from.min(to) <= last && last <= from.max(to));
+        }
+
+        #[test]
+        fn reversed_ranges(from in -500i64..500, to in -500i64..500) {
+            let mut reversed = expand(&format!("{{{to}..{from}}}"));
+            reversed.reverse();
+            prop_assert_eq!(expand(&format!("{{{from}..{to}}}")), reversed);
+        }
+
+        #[test]
+        fn padded_ranges(from in 0i64..1000, to in 0i64..1000, width in 4usize..7) {
+            let start = format!("{from:0width$}");
+            let expanded = expand(&format!("{{{start}..{to}}}"));
+            let width = width.max(start.len()).max(to.to_string().len());
+
+            prop_assert_eq!(from.abs_diff(to) + 1, expanded.len() as u64);
+            for s in &expanded {
+                prop_assert_eq!(width, s.len());
+            }
+            let mut sorted = expanded.clone();
+            sorted.sort();
+            if from > to {
+                sorted.reverse();
+            }
+            prop_assert_eq!(sorted, expanded);
+        }
+
+        #[test]
+        fn char_ranges(from in b'a'..=b'z', to in b'a'..=b'z') {
+            let (from, to) = (from as char, to as char);
+            let expanded = expand(&format!("{{{from}..{to}}}"));
+
+            let mut expected = (from.min(to)..=from.max(to))
+                .map(String::from)
+                .collect::<Vec<_>>();
+            if from > to {
+                expected.reverse();
+            }
+            prop_assert_eq!(expected, expanded);
+        }
+    }
+}
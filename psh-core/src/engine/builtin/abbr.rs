@@ -1,37 +1,103 @@
+use regex::Regex;
+
+use crate::engine::abbreviation::{Abbreviation, Position};
+use crate::engine::quote;
 use crate::{Engine, ExitStatus, Result};
 
 const HELP: &str = "\
-usage: abbr [ -h | --help ] [ <key>=<val> | <key> ]
+usage: abbr [ -h | --help ] [ --position command|anywhere ] [ --regex <pattern> ]
+            [ <key>=<val> | <key> ]
 
 Define or query existing abbreviations.
 
 abbr -h         print this text
 abbr            print the current abbreviations
 abbr key        print the abbreviation with key `key`
-abbr key=val    define `key` to expand to `val`";
+abbr key=val    define `key` to expand to `val`
+
+If `val` contains `%|%`, it is removed when the abbreviation is expanded,
+and the cursor is put in its place, as in `abbr gco='git checkout %|%'`.
+
+Abbreviations are only expanded as the name of a command, unless defined
+with `--position anywhere`. With `--regex`, the words matching `pattern`
+in whole are expanded instead of `key`, which only names the abbreviation,
+as in `abbr --position anywhere --regex '[0-9]+d' days='--since=%|% days'`.";
+
+fn print(key: &str, abbreviation: &Abbreviation) {
+    let mut command = String::from("abbr");
+    if abbreviation.position != Position::Command {
+        command += &format!(" --position {}", abbreviation.position);
+    }
+    if let Some(regex) = &abbreviation.regex {
+        command += &format!(" --regex {}", quote::quote(regex.as_str()));
+    }
+    println!(
+        "{command} {}=\"{}\"",
+        key,
+        abbreviation.expansion.replace('"', "\\\"")
+    );
+}
 
 pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
-    match args {
-        args if args.contains(&"-h") || args.contains(&"--help") => {
-            println!("{}", HELP);
-            Ok(ExitStatus::from_code(0))
+    if args.contains(&"-h") || args.contains(&"--help") {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut position = None;
+    let mut regex = None;
+    let mut exprs = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--position" => match args.next().and_then(|arg| Position::parse(arg)) {
+                Some(parsed) => position = Some(parsed),
+                None => {
+                    eprintln!("abbr: --position: expected `command` or `anywhere`");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            "--regex" => match args.next().map(|arg| Regex::new(arg)) {
+                Some(Ok(parsed)) => regex = Some(parsed),
+                Some(Err(e)) => {
+                    eprintln!("abbr: --regex: {e}");
+                    return Ok(ExitStatus::from_code(2));
+                }
+                None => {
+                    eprintln!("abbr: --regex: missing pattern");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            arg => exprs.push(arg),
         }
+    }
 
-        [] => {
-            for (key, val) in &engine.abbreviations {
-                println!("abbr {}=\"{}\"", key, val.replace('"', "\\\""));
+    let conditions = position.is_some() || regex.is_some();
+    match exprs[..] {
+        [] if !conditions => {
+            let mut abbreviations = engine.abbreviations.iter().collect::<Vec<_>>();
+            abbreviations.sort_by_key(|(key, _)| *key);
+            for (key, abbreviation) in abbreviations {
+                print(key, abbreviation);
             }
             Ok(ExitStatus::from_code(0))
         }
 
-        &[expr] => {
+        [expr] => {
             if let Some((lhs, rhs)) = expr.split_once('=') {
-                engine
-                    .abbreviations
-                    .insert(lhs.to_string(), rhs.to_string());
+                let abbreviation = Abbreviation {
+                    expansion: rhs.to_string(),
+                    position: position.unwrap_or_default(),
+                    regex,
+                };
+                engine.abbreviations.insert(lhs.to_string(), abbreviation);
                 Ok(ExitStatus::from_code(0))
-            } else if let Some(val) = engine.abbreviations.get(expr) {
-                println!("abbr {}=\"{}\"", expr, val.replace('"', "\\\""));
+            } else if conditions {
+                eprintln!("abbr: {}: expected key=val", expr);
+                Ok(ExitStatus::from_code(2))
+            } else if let Some(abbreviation) = engine.abbreviations.get(expr) {
+                print(expr, abbreviation);
                 Ok(ExitStatus::from_code(0))
             } else {
                 eprintln!("abbr: {} not found", expr);
@@ -39,6 +105,11 @@ pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
             }
         }
 
+        [] => {
+            eprintln!("abbr: No abbreviation given, see `abbr --help`");
+            Ok(ExitStatus::from_code(2))
+        }
+
         _ => {
             eprintln!("abbr: Too many arguments");
             Ok(ExitStatus::from_code(1))
@@ -1,14 +1,23 @@
 use crate::{Engine, ExitStatus, Result};
 
 const HELP: &str = "\
-usage: alias [ -h | --help ] [ <key>=<val> | <key> ]
+usage: alias [ -h | --help ] [ <key>=<val> | <key> ]...
 
 Define or query existing aliases.
 
 alias -h         print this text
 alias            print the current aliases
 alias key        print the alias with key `key`
-alias key=val    define alias from `key` to `val`";
+alias key=val    define alias from `key` to `val`
+
+An alias is substituted for the first word of a command before it is
+parsed, in scripts as well. If `val` ends with a space, the word after it
+is substituted as well, as in `alias sudo='sudo '`. Several aliases can
+be defined or printed at once.";
+
+fn print(key: &str, val: &str) {
+    println!("alias {}=\"{}\"", key, val.replace('"', "\\\""));
+}
 
 pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
     match args {
@@ -18,28 +27,27 @@ pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
         }
 
         [] => {
-            for (key, val) in &engine.aliases {
-                println!("alias {}=\"{}\"", key, val.replace('"', "\\\""));
+            let mut aliases = engine.aliases.iter().collect::<Vec<_>>();
+            aliases.sort();
+            for (key, val) in aliases {
+                print(key, val);
             }
             Ok(ExitStatus::from_code(0))
         }
 
-        &[expr] => {
-            if let Some((lhs, rhs)) = expr.split_once('=') {
-                engine.aliases.insert(lhs.to_string(), rhs.to_string());
-                Ok(ExitStatus::from_code(0))
-            } else if let Some(val) = engine.aliases.get(expr) {
-                println!("alias {}=\"{}\"", expr, val.replace('"', "\\\""));
-                Ok(ExitStatus::from_code(0))
-            } else {
-                eprintln!("alias: {} not found", expr);
-                Ok(ExitStatus::from_code(1))
+        exprs => {
+            let mut status = 0;
+            for expr in exprs {
+                if let Some((lhs, rhs)) = expr.split_once('=') {
+                    engine.aliases.insert(lhs.to_string(), rhs.to_string());
+                } else if let Some(val) = engine.aliases.get(*expr) {
+                    print(expr, val);
+                } else {
+                    eprintln!("alias: {} not found", expr);
+                    status = 1;
+                }
             }
-        }
-
-        _ => {
-            eprintln!("alias: Too many arguments");
-            Ok(ExitStatus::from_code(1))
+            Ok(ExitStatus::from_code(status))
         }
     }
 }
@@ -0,0 +1,97 @@
+use crate::engine::jobs::{Job, State};
+use crate::engine::util::{self, Priority};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: bg [ -h | --help ] [ --nice n ] [job...]
+
+Continue stopped jobs in the background, as if they had been started with
+`&`.
+
+bg -h            print this text
+bg               continue the most recently stopped job
+bg job...        continue every `job`, given by its number optionally
+                 prefixed by `%`
+bg --nice n ...  also lower the priority of the jobs to `n` below that of
+                 the shell, as `nice` does, which running jobs can be
+                 given too
+
+The priority of every job started with `&` is lowered by `$PSH_BG_NICE`,
+if set, so that e.g. long builds do not make the shell sluggish.
+
+Only available in an interactive shell, see `jobs`.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if args.contains(&"-h") || args.contains(&"--help") {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    if !engine.job_control {
+        eprintln!("bg: No job control");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    let (nice, args) = match args {
+        ["--nice", nice, args @ ..] => match nice.parse::<i32>() {
+            Ok(nice) => (Some(nice), args),
+            Err(_) => {
+                eprintln!("bg: '{nice}': Integer expected.");
+                return Ok(ExitStatus::from_code(2));
+            }
+        },
+        ["--nice"] => {
+            eprintln!("bg: --nice: Missing priority.");
+            return Ok(ExitStatus::from_code(2));
+        }
+        args => (None, args),
+    };
+
+    let specs = match args {
+        [] => vec![None],
+        specs => specs.iter().map(|spec| Some(*spec)).collect(),
+    };
+
+    engine.jobs.update();
+    let mut rc = 0;
+    for spec in specs {
+        let Some(job) = engine.jobs.current(spec) else {
+            eprintln!("bg: {}: no such job", spec.unwrap_or("%%"));
+            rc = 1;
+            continue;
+        };
+
+        if let (Some(nice), false) = (nice, matches!(job.state, State::Done(_))) {
+            if let Err(e) = lower_priority(job, nice) {
+                eprintln!("bg: job %{}: Could not lower its priority: {e}", job.id);
+                rc = 1;
+            }
+        }
+
+        match job.state {
+            State::Stopped => {
+                let id = job.id;
+                println!("[{id}]  {} &", job.command);
+                engine.background_job(id)?;
+            }
+            State::Running if nice.is_some() => {}
+            State::Running => eprintln!("bg: job %{} is already running", job.id),
+            State::Done(_) => {
+                eprintln!("bg: job %{} has finished", job.id);
+                rc = 1;
+            }
+        }
+    }
+
+    Ok(ExitStatus::from_code(rc))
+}
+
+/// Lowers the priority of every process of `job`, see `util::lower_priority`.
+fn lower_priority(job: &Job, nice: i32) -> Result<()> {
+    match job.pgid {
+        Some(pgid) => util::lower_priority(Priority::Group(pgid), nice),
+        None => job
+            .pids()
+            .try_for_each(|pid| util::lower_priority(Priority::Process(pid), nice)),
+    }
+}
@@ -0,0 +1,59 @@
+use crate::engine::Flow;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: break [ -h | --help ] [n]
+
+Stop executing the innermost loop, or the `n` innermost ones, or all of
+them if there are fewer.
+
+break -h        print this text
+break           stop the innermost loop
+break n         stop the `n` innermost loops";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if let ["-h" | "--help"] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    match loops("break", engine, args) {
+        Ok(n) => {
+            engine.flow = Some(Flow::Break(n));
+            Ok(ExitStatus::from_code(0))
+        }
+        Err(status) => Ok(status),
+    }
+}
+
+/// The number of loops that `break` or `continue`, as given by `name`,
+/// applies to with `args`, which is at most the number of loops being
+/// executed, or the status to fail with.
+pub(super) fn loops(
+    name: &str,
+    engine: &Engine,
+    args: &[&str],
+) -> std::result::Result<usize, ExitStatus> {
+    let n = match args {
+        [] => 1,
+
+        [n] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                eprintln!("{name}: invalid loop count: '{n}'");
+                return Err(ExitStatus::from_code(2));
+            }
+        },
+
+        _ => {
+            eprintln!("{name}: Too many arguments");
+            return Err(ExitStatus::from_code(1));
+        }
+    };
+
+    if engine.loops == 0 {
+        eprintln!("{name}: Can only be used in a loop");
+        return Err(ExitStatus::from_code(1));
+    }
+    Ok(n.min(engine.loops))
+}
@@ -0,0 +1,51 @@
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: caller [ -h | --help ] [n]
+
+Print where the file or function currently being executed was run from,
+e.g. for error messages in scripts.
+
+caller -h   print this text
+caller      print the line and file of the `.` command or the call that is
+            running the current file or function
+caller n    print the line, name and file of the frame `n` levels further
+            up the call stack, where 0 is the innermost caller
+
+The name is `main` for the script the shell was started with, `source` for
+a file run with `.`, and the name of the function for a function, which is
+also in `$FUNCNAME` while it runs. Returns with 1 if there is no such frame,
+e.g. at the top level of a script.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let level = match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        [] => None,
+        [n] => match n.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => {
+                eprintln!("caller: {n}: invalid number");
+                return Ok(ExitStatus::from_code(2));
+            }
+        },
+        _ => {
+            eprintln!("caller: Invalid arguments, see `caller --help`");
+            return Ok(ExitStatus::from_code(2));
+        }
+    };
+
+    // the innermost frame is the one `caller` itself runs in
+    let callers = engine.frames.iter().rev().skip(1);
+    let Some(frame) = callers.clone().nth(level.unwrap_or(0)) else {
+        return Ok(ExitStatus::from_code(1));
+    };
+
+    match level {
+        None => println!("{} {}", frame.line, frame.source),
+        Some(_) => println!("{} {} {}", frame.line, frame.name, frame.source),
+    }
+    Ok(ExitStatus::from_code(0))
+}
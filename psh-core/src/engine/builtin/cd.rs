@@ -1,15 +1,14 @@
-use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::path;
 use crate::{Engine, ExitStatus, Result};
 
-pub fn execute(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
     let path = match args {
         [] => PathBuf::from(path::home_dir()),
 
         ["-"] => {
-            if let Ok(old_pwd) = env::var("OLDPWD") {
+            if let Some(old_pwd) = engine.get_value_of("OLDPWD") {
                 PathBuf::from(old_pwd)
             } else {
                 eprintln!("cd: No previous directory.");
@@ -17,17 +16,10 @@ pub fn execute(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
             }
         }
 
-        [dir] if PathBuf::from(dir).is_dir() => PathBuf::from(dir),
-
-        [dir] if PathBuf::from(dir).exists() => {
-            eprintln!("cd: '{}' is not a directory.", dir);
-            return Ok(ExitStatus::from_code(3));
-        }
-
-        [dir] => {
-            eprintln!("cd: '{}' does not exist.", dir);
-            return Ok(ExitStatus::from_code(2));
-        }
+        [dir] => match resolve(engine, "cd", dir) {
+            Ok(path) => path,
+            Err(status) => return Ok(status),
+        },
 
         _ => {
             eprintln!("cd: Too many arguments");
@@ -35,8 +27,46 @@ pub fn execute(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
         }
     };
 
-    env::set_var("OLDPWD", env::current_dir()?);
-    env::set_current_dir(path)?;
-    env::set_var("PWD", env::current_dir()?);
+    engine.set_current_dir(path)?;
     Ok(ExitStatus::from_code(0))
 }
+
+/// The directory that `dir` refers to for `builtin`. A relative `dir` not
+/// starting with `.` or `..` is searched for in the directories of
+/// `$CDPATH`, an empty one meaning the working directory. The directory is
+/// printed if found in one of the others, as the user could not know where
+/// they ended up otherwise.
+pub(super) fn resolve(
+    engine: &Engine,
+    builtin: &str,
+    dir: &str,
+) -> std::result::Result<PathBuf, ExitStatus> {
+    let searched =
+        !Path::new(dir).is_absolute() && !matches!(dir.split('/').next(), Some("." | ".."));
+
+    if let Some(cdpath) = engine.get_value_of("CDPATH").filter(|_| searched) {
+        for base in cdpath.split(':') {
+            let path = match base {
+                "" => PathBuf::from(dir),
+                base => path::expand_tilde_in_dir(base).join(dir),
+            };
+            if path.is_dir() {
+                if !base.is_empty() {
+                    println!("{}", path.display());
+                }
+                return Ok(path);
+            }
+        }
+    }
+
+    let path = PathBuf::from(dir);
+    if path.is_dir() {
+        Ok(path)
+    } else if path.exists() {
+        eprintln!("{builtin}: '{dir}' is not a directory.");
+        Err(ExitStatus::from_code(3))
+    } else {
+        eprintln!("{builtin}: '{dir}' does not exist.");
+        Err(ExitStatus::from_code(2))
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs;
+
+use crate::engine::completion::{CompletionSpec, Files};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: complete [ -h | --help ] [ -p | -r ] [ -F function | -C command ]
+                [ -W words ] [ -d | -f | -G glob ] [ -o option ] [ name... ]
+       compgen [ -W words ] [ -d | -f | -G glob ] [ -- ] [ word ]
+
+Set how the arguments of commands are completed, which is by file names
+unless set otherwise.
+
+complete -h               print this text
+complete, complete -p     print the completions that are set, as commands
+complete -p name...       print the completions of the given commands
+complete -r name...       remove the completions of the given commands
+complete -W words name    complete the arguments of `name` with `words`,
+                          split on whitespace
+complete -d name          complete directories only
+complete -f name          complete every file
+complete -G glob name     complete the files matching `glob`, such as
+                          `'*.rs'`, and directories
+
+complete -F function name
+                          complete the arguments of `name` with the words
+                          that `function` sets `COMPREPLY` to
+complete -C command name  complete the arguments of `name` with the lines
+                          that `command` writes
+complete -o default name  the same as -f, and -o dirnames as -d, while
+                          the other options of bash are accepted and ignored
+
+`-W`, `-F` and `-C` can also be written `--words`, `--function` and
+`--command`.
+
+`-W` can be combined with one of the others, as in
+`complete -W 'start stop' -G '*.conf' name`. With `-F` or `-C`, files are
+offered only if the function or command offers nothing.
+
+The function is called as in bash, with the command, the word being
+completed and the word before it as `$1`, `$2` and `$3`. `COMP_LINE` and
+`COMP_POINT` are the line and the position of the cursor in it, and
+`COMP_CWORD` the index of the word in `COMP_WORDS`, which, as psh has no
+arrays, holds the words separated by spaces. `COMPREPLY` is split on
+whitespace.
+
+The command gets the same `$1`, `$2` and `$3`, which, unlike in bash, are
+not added to it, so that it can be given as it is run, as in
+`complete -C 'ls ~/notes' note`. It offers the lines it writes that start
+with the word being completed. `COMP_LINE` and `COMP_POINT` are exported
+to it.
+
+compgen prints the words and files that complete `word`, one per line, for
+use in completion functions, as in
+`COMPREPLY=$(compgen -W 'start stop' -- \"$2\")`.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        [] | ["-p"] => {
+            let mut names = engine.completions.keys().collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                println!("{}", engine.completions[name].to_command(name));
+            }
+            return Ok(ExitStatus::from_code(0));
+        }
+        ["-p", names @ ..] => {
+            let mut status = 0;
+            for name in names {
+                match engine.completions.get(*name) {
+                    Some(spec) => println!("{}", spec.to_command(name)),
+                    None => {
+                        eprintln!("complete: {name}: no completion set");
+                        status = 1;
+                    }
+                }
+            }
+            return Ok(ExitStatus::from_code(status));
+        }
+        ["-r", names @ ..] => {
+            for name in names {
+                engine.completions.remove(*name);
+            }
+            return Ok(ExitStatus::from_code(0));
+        }
+        _ => {}
+    }
+
+    let Some((spec, names)) = parse("complete", args) else {
+        return Ok(ExitStatus::from_code(2));
+    };
+
+    if spec.words.is_empty()
+        && spec.files == Files::None
+        && spec.function.is_none()
+        && spec.command.is_none()
+    {
+        eprintln!("complete: No completion given, see `complete --help`");
+        return Ok(ExitStatus::from_code(2));
+    }
+    if names.is_empty() {
+        eprintln!("complete: No command given, see `complete --help`");
+        return Ok(ExitStatus::from_code(2));
+    }
+
+    for name in names {
+        engine.completions.insert(name.to_string(), spec.clone());
+    }
+    Ok(ExitStatus::from_code(0))
+}
+
+/// `compgen`, which prints what completes a word.
+pub fn compgen(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if let ["-h" | "--help", ..] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let Some((spec, words)) = parse("compgen", args) else {
+        return Ok(ExitStatus::from_code(2));
+    };
+    if spec.function.is_some() || spec.command.is_some() {
+        let option = if spec.function.is_some() { "-F" } else { "-C" };
+        eprintln!("compgen: {option}: Only supported by `complete`.");
+        return Ok(ExitStatus::from_code(2));
+    }
+    let word = match words[..] {
+        [] => "",
+        [word] => word,
+        _ => {
+            eprintln!("compgen: Only one word can be completed.");
+            return Ok(ExitStatus::from_code(2));
+        }
+    };
+
+    let mut candidates = spec
+        .words(word)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if spec.files != Files::None {
+        candidates.extend(files(&spec, word));
+    }
+
+    for candidate in &candidates {
+        println!("{candidate}");
+    }
+    Ok(ExitStatus::from_code(match candidates.is_empty() {
+        true => 1,
+        false => 0,
+    }))
+}
+
+/// Parses the options that set a completion, returning it along with the
+/// other arguments, or `None` if they are not valid.
+fn parse<'a>(builtin: &str, args: &[&'a str]) -> Option<(CompletionSpec, Vec<&'a str>)> {
+    let mut spec = CompletionSpec::default();
+    let mut args = args.iter();
+    let mut names = Vec::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = |what| {
+            let value = args.next().copied();
+            if value.is_none() {
+                eprintln!("{builtin}: {arg}: missing {what}");
+            }
+            value
+        };
+
+        let files = match *arg {
+            "-W" | "--words" => {
+                let list = value("word list")?;
+                spec.words = list.split_whitespace().map(ToString::to_string).collect();
+                continue;
+            }
+            "-F" | "--function" => {
+                spec.function = Some(value("function")?.to_string());
+                continue;
+            }
+            "-C" | "--command" => {
+                spec.command = Some(value("command")?.to_string());
+                continue;
+            }
+            "-o" => match value("option")? {
+                "default" => Files::All,
+                "dirnames" => Files::Directories,
+                "bashdefault" | "filenames" | "noquote" | "nosort" | "nospace" | "plusdirs" => {
+                    continue;
+                }
+                option => {
+                    eprintln!("{builtin}: -o: '{option}': Invalid option.");
+                    return None;
+                }
+            },
+            "-d" => Files::Directories,
+            "-f" => Files::All,
+            "-G" => Files::Matching(value("pattern")?.to_string()),
+            "--" => {
+                names.extend(args.by_ref());
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("{builtin}: {arg}: invalid option, see `{builtin} --help`");
+                return None;
+            }
+            name => {
+                names.push(name);
+                continue;
+            }
+        };
+
+        if spec.files != Files::None {
+            eprintln!("{builtin}: only one of -d, -f and -G can be given");
+            return None;
+        }
+        spec.files = files;
+    }
+
+    if spec.function.is_some() && spec.command.is_some() {
+        eprintln!("{builtin}: only one of -F and -C can be given");
+        return None;
+    }
+    Some((spec, names))
+}
+
+/// The files that `spec` offers to complete `word`, sorted.
+fn files(spec: &CompletionSpec, word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rsplit_once('/') {
+        Some((dir, prefix)) => (format!("{dir}/"), prefix),
+        None => (String::new(), word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { &dir }) else {
+        return Vec::new();
+    };
+
+    let mut files = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let hidden = name.starts_with('.') && !prefix.starts_with('.');
+            let is_dir = entry.path().is_dir();
+            (name.starts_with(prefix) && !hidden && spec.offers_file(&name, is_dir))
+                .then(|| format!("{dir}{name}"))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
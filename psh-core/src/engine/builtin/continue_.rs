@@ -0,0 +1,28 @@
+use crate::engine::Flow;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: continue [ -h | --help ] [n]
+
+Skip the rest of the current iteration of the innermost loop, or stop the
+`n - 1` innermost loops and skip the rest of the iteration of the one
+around them.
+
+continue -h     print this text
+continue        go on with the next iteration of the innermost loop
+continue n      go on with the next iteration of the `n`th loop";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if let ["-h" | "--help"] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    match super::break_::loops("continue", engine, args) {
+        Ok(n) => {
+            engine.flow = Some(Flow::Continue(n));
+            Ok(ExitStatus::from_code(0))
+        }
+        Err(status) => Ok(status),
+    }
+}
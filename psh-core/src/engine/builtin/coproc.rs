@@ -0,0 +1,65 @@
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: coproc [ -h | --help ] [ -n name ] command [arg...]
+
+Start a command in the background, with its stdin and stdout connected to
+the shell through pipes.
+
+coproc -h               print this text
+coproc cmd              start `cmd` as the coprocess COPROC
+coproc -n name cmd      start `cmd` as the coprocess `name`
+
+The file descriptors of the pipes are stored in variables, which can be
+used in redirections:
+
+  $name_WRITE   write to the stdin of the coprocess, e.g. `echo foo >&$COPROC_WRITE`
+  $name_READ    read from its stdout, e.g. `read line <&$COPROC_READ`
+  $name_PID     the pid of the coprocess
+
+The coprocess is a job, see `jobs`. Once it has finished and been
+reported, the pipes are closed and the variables are unset.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let (name, command) = match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        ["-n", name, command @ ..] => (*name, command),
+        ["-n"] => {
+            eprintln!("coproc: -n requires a name");
+            return Ok(ExitStatus::from_code(1));
+        }
+
+        command => ("COPROC", command),
+    };
+
+    if command.is_empty() {
+        eprintln!("coproc: No command given");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        eprintln!("coproc: '{name}' is not a valid name");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    if let Some(job) = engine.jobs.coprocess(name) {
+        eprintln!("coproc: {name} is still open as job %{}", job.id);
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    let id = engine.start_coprocess(name, command)?;
+    if engine.interactive {
+        eprintln!(
+            "[{id}] {}",
+            engine
+                .get_value_of(format!("{name}_PID"))
+                .unwrap_or_default()
+        );
+    }
+
+    Ok(ExitStatus::from_code(0))
+}
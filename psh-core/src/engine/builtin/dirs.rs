@@ -0,0 +1,113 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::engine::quote;
+use crate::path;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: dirs [ -h | --help ] [-c] [-l] [-v]
+
+Print the directory stack: the working directory, followed by the
+directories saved with `pushd`, the most recent first.
+
+dirs -h     print this text
+dirs -c     clear the directory stack
+dirs -l     print the directories without abbreviating $HOME as ~
+dirs -v     print one directory per line, with its position in the stack";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let (mut long, mut verbose) = (false, false);
+
+    for arg in args {
+        match *arg {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                return Ok(ExitStatus::from_code(0));
+            }
+            "-c" => engine.dir_stack.clear(),
+            "-l" => long = true,
+            "-v" => verbose = true,
+            _ => {
+                eprintln!("dirs: invalid argument: '{arg}'");
+                return Ok(ExitStatus::from_code(1));
+            }
+        }
+    }
+
+    print(engine, long, verbose)?;
+    Ok(ExitStatus::from_code(0))
+}
+
+/// The working directory followed by the directories saved with `pushd`.
+pub(super) fn stack(engine: &Engine) -> Result<Vec<PathBuf>> {
+    let mut stack = vec![env::current_dir()?];
+    stack.extend(engine.dir_stack.iter().cloned());
+    Ok(stack)
+}
+
+/// Prints the directory stack, on one line unless `verbose`.
+pub(super) fn print(engine: &Engine, long: bool, verbose: bool) -> Result<()> {
+    let dirs = stack(engine)?.into_iter().map(|dir| {
+        let dir = dir.display().to_string();
+        let dir = match long {
+            true => dir,
+            false => path::compress_tilde(dir),
+        };
+        quote::escape_controls(&dir).into_owned()
+    });
+
+    match verbose {
+        true => dirs
+            .enumerate()
+            .for_each(|(i, dir)| println!("{i:2}  {dir}")),
+        false => println!("{}", dirs.collect::<Vec<_>>().join(" ")),
+    }
+    Ok(())
+}
+
+/// The position in a stack of `len` directories that `arg` refers to for
+/// `builtin`: `+N` counts from the left of `dirs`, and `-N` from the right,
+/// both from 0. `None` if `arg` is not of that form.
+pub(super) fn position(
+    builtin: &str,
+    arg: &str,
+    len: usize,
+) -> Option<std::result::Result<usize, ExitStatus>> {
+    let (from_left, n) = match arg.split_at_checked(1)? {
+        ("+", n) => (true, n),
+        ("-", n) => (false, n),
+        _ => return None,
+    };
+    if n.is_empty() || !n.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    match n.parse::<usize>() {
+        Ok(n) if n < len && from_left => Some(Ok(n)),
+        Ok(n) if n < len => Some(Ok(len - 1 - n)),
+        _ => {
+            eprintln!("{builtin}: {arg}: directory stack index out of range");
+            Some(Err(ExitStatus::from_code(1)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        assert_eq!(Some(Ok(0)), position("dirs", "+0", 3));
+        assert_eq!(Some(Ok(2)), position("dirs", "+2", 3));
+        assert_eq!(Some(Ok(2)), position("dirs", "-0", 3));
+        assert_eq!(Some(Ok(0)), position("dirs", "-2", 3));
+        assert!(matches!(position("dirs", "+3", 3), Some(Err(_))));
+        assert!(matches!(position("dirs", "-3", 3), Some(Err(_))));
+
+        for arg in ["dir", "+", "-", "+x", "-1x", "1", ""] {
+            assert_eq!(None, position("dirs", arg, 3), "{arg}");
+        }
+    }
+}
@@ -4,7 +4,10 @@ const HELP: &str = "\
 usage: . <file>
 
 Execute the file in the current execution context. If the file
-does not contain a '/' character, $PATH is searched for it.";
+does not contain a '/' character, $PATH is searched for it.
+
+Returns with the status of the last command executed in the file, or
+the one given to `return`, which stops executing the file.";
 
 pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
     match args {
@@ -16,16 +19,43 @@ pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
         }
 
         [file, ..] => {
-            if file.contains('/') {
-                engine.execute_file(file.into())?;
-                Ok(ExitStatus::from_code(0))
+            let statuses = if file.contains('/') {
+                engine.execute_file(file.into(), true)?
             } else if let Some(file) = engine.get_file_in_path(file) {
-                engine.execute_file(file.into())?;
-                Ok(ExitStatus::from_code(0))
+                engine.execute_file(file.into(), true)?
             } else {
                 println!(".: '{file}': no such file");
-                Ok(ExitStatus::from_code(1))
-            }
+                return Ok(ExitStatus::from_code(1));
+            };
+
+            // the status of the last command, or the one given to `return`
+            Ok(statuses.last().copied().unwrap_or(ExitStatus::from_code(0)))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::engine::history::DummyHistory;
+    use crate::test_util::TempDir;
+    use crate::{Engine, ExitStatus};
+
+    #[test]
+    fn return_leaves_a_file_sourced_outside_of_a_script() {
+        let dir = TempDir::new("dot");
+        let file = dir.join("f.sh");
+        fs::write(&file, "return 3\nreached=1\n").unwrap();
+
+        // as with `psh -c`, no file is being executed when `.` is run
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        let statuses = engine
+            .execute_line(format!(". {}", file.display()))
+            .unwrap();
+
+        assert_eq!(vec![ExitStatus::from_code(3)], statuses);
+        assert_eq!(ExitStatus::from_code(3), engine.last_status);
+        assert_eq!(None, engine.get_value_of("reached"));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::engine::variables::Change;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: envdiff [ -h | --help ] [ -a | --all ] [name...]
+
+Show which variables are exported to child processes and which
+only exist in the shell, and how they differ from the environment
+the shell was started with.
+
+envdiff -h      print this text
+envdiff         print variables that are shell-local or have changed
+envdiff -a      print all variables
+envdiff name    print the state of the variable `name`
+
+Each line is prefixed by one of the following markers:
+
+  +        the variable did not exist at shell start
+  ~        the value has changed since shell start
+  -        the variable existed at shell start but has been unset
+  (blank)  the variable has not changed";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if args.contains(&"-h") || args.contains(&"--help") {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let all = args.contains(&"-a") || args.contains(&"--all");
+    let names = args
+        .iter()
+        .filter(|a| !matches!(**a, "-a" | "--all"))
+        .collect::<Vec<_>>();
+
+    let mut rc = 0;
+    for (name, change) in engine.variables.diff() {
+        if !names.is_empty() && !names.contains(&&name) {
+            continue;
+        }
+
+        let var = engine.variables.get(name);
+        let exported = var.map(|v| v.exported).unwrap_or(false);
+
+        if names.is_empty() && !all && change == Change::Unchanged && exported {
+            continue;
+        }
+
+        let marker = match change {
+            Change::Unchanged => ' ',
+            Change::Modified => '~',
+            Change::Added => '+',
+            Change::Removed => '-',
+        };
+
+        match var {
+            Some(var) => println!(
+                "{marker} {:<8} {name}=\"{}\"",
+                if exported { "exported" } else { "local" },
+                var.value.replace('"', "\\\""),
+            ),
+            None => println!("{marker} {:<8} {name}", "unset"),
+        }
+
+        if change == Change::Modified {
+            let initial = engine.variables.initial_value(name).unwrap_or_default();
+            println!("  {:<8} (was \"{}\")", "", initial.replace('"', "\\\""));
+        }
+    }
+
+    for name in names {
+        if !engine.variables.contains(name) && engine.variables.initial_value(name).is_none() {
+            eprintln!("envdiff: {} not found", name);
+            rc = 1;
+        }
+    }
+
+    Ok(ExitStatus::from_code(rc))
+}
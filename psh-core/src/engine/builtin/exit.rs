@@ -1,17 +1,43 @@
 use crate::{Engine, ExitStatus, Result};
 
-pub fn execute(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+const HELP: &str = "\
+usage: exit [ -h | --help ] [n]
+
+Exit the shell with the status `n`, taken modulo 256, or with the status
+of the last command if `n` is omitted.
+
+exit -h         print this text
+exit            exit with the status of the last command
+exit n          exit with the status `n`
+
+If `n` is not an integer, the status is 2, and only non-interactive
+shells exit.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
     let code = match args {
-        [code] | [code, ..] => {
-            if let Ok(code) = code.parse::<i32>() {
-                code
-            } else {
+        ["-h" | "--help"] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        [] => engine.last_status.raw_code() as i64,
+
+        [code] => match code.parse::<i64>() {
+            Ok(code) => code,
+            Err(_) => {
                 eprintln!("exit: invalid integer: '{}'", code);
-                return Ok(ExitStatus::from_code(1));
+                if engine.interactive {
+                    return Ok(ExitStatus::from_code(2));
+                }
+                2
             }
+        },
+
+        _ => {
+            eprintln!("exit: Too many arguments");
+            return Ok(ExitStatus::from_code(1));
         }
-        _ => 0,
     };
 
-    std::process::exit(code);
+    engine.exit(ExitStatus::from_code(code.rem_euclid(256) as i32));
 }
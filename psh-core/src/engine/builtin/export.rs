@@ -0,0 +1,83 @@
+use crate::engine::quote;
+use crate::engine::variables::is_name;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: export [ -h | --help ] [ -n ] [ -p ] [ <name>[=<val>]... ]
+
+Pass variables to the commands started by the shell, in their environment.
+Other variables only exist in the shell.
+
+export -h          print this text
+export, export -p  print the exported variables, as commands
+export name        export the variable `name`
+export name=val    set `name` to `val` and export it
+export -n name     stop exporting `name`, which keeps its value
+
+A variable can also be passed to a single command without being set in
+the shell, as in `name=val command`.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut unexport = false;
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                return Ok(ExitStatus::from_code(0));
+            }
+            "-n" => unexport = true,
+            "-p" => {}
+            "--" => {
+                operands.extend(args.by_ref());
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("export: {arg}: invalid option, see `export --help`");
+                return Ok(ExitStatus::from_code(2));
+            }
+            operand => operands.push(operand),
+        }
+    }
+
+    if operands.is_empty() {
+        let mut exported = engine
+            .variables
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .collect::<Vec<_>>();
+        exported.sort_by_key(|(name, _)| *name);
+        for (name, var) in exported {
+            println!("export {name}={}", quote::quote(&var.value));
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut status = 0;
+    for operand in operands {
+        let (name, value) = match operand.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (operand, None),
+        };
+        if !is_name(name) {
+            eprintln!("export: {name}: not a valid name");
+            status = 1;
+            continue;
+        }
+
+        if let Some(value) = value {
+            if let Err(e) = engine.variables.set(name, value) {
+                eprintln!("export: {e}");
+                status = 1;
+                continue;
+            }
+        }
+        match unexport {
+            true => engine.variables.unexport(name),
+            false => engine.variables.export(name),
+        }
+    }
+    Ok(ExitStatus::from_code(status))
+}
@@ -0,0 +1,49 @@
+use crate::engine::jobs::State;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: fg [ -h | --help ] [job]
+
+Continue a job in the foreground, and wait for it to finish or be stopped
+again with ^Z.
+
+fg -h       print this text
+fg          continue the most recently stopped job, or else the most
+            recently started one
+fg job      continue `job`, given by its number optionally prefixed by `%`
+
+Returns with the exit status of the job, or 148 if it was stopped again.
+Only available in an interactive shell, see `jobs`.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let spec = match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        [] => None,
+        [spec] => Some(*spec),
+        _ => {
+            eprintln!("fg: Invalid arguments, see `fg --help`");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    if !engine.job_control {
+        eprintln!("fg: No job control");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    engine.jobs.update();
+    let job = match engine.jobs.current(spec) {
+        Some(job) if !matches!(job.state, State::Done(_)) => job,
+        _ => {
+            eprintln!("fg: {}: no such job", spec.unwrap_or("%%"));
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    let id = job.id;
+    println!("{}", job.command);
+    engine.foreground_job(id)
+}
@@ -0,0 +1,548 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "serde")]
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::engine::history::Record;
+use crate::engine::{glob, log};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: history [ -h | --help ] [ -c ] [ --import file --format bash|zsh|fish ]
+               [ --export [ --format text|json ] [ --match pattern ]
+                 [ --since date ] [ --until date ] [ --dir dir ] ]
+
+Print or change the history of entered commands.
+
+history -h      print this text
+history         print the history, numbered as `!n` refers to it
+history -c      clear the history
+history --import file --format bash|zsh|fish
+                add the history of another shell, as in
+                `history --import ~/.zsh_history --format zsh`
+history --export [ --format text|json ] [ --match pattern ]
+                 [ --since date ] [ --until date ] [ --dir dir ]
+                print the history in a form to be shared or read by
+                other programs: one command per line, which is the
+                default, or a JSON array of objects with the `number`,
+                `command`, `time` and `dir` of each entry, as in
+                `history --export --format json --match 'git *' > git.json`
+
+Imported commands are added after those already in the history, with the
+times they were entered if the file has them. The ones it already has are
+left out, so that importing a file again adds nothing, as are commands that
+span multiple lines, since every entry of the history is a line of its own.
+
+Exported entries can be picked by a pattern, which is matched against the
+whole command as in `case`, by when they were entered, from the start of
+the --since date to the end of the --until date, given as YYYY-MM-DD in
+UTC, and by the directory they were entered in, which is --dir or one
+below it. Entries whose time or directory is not known, such as those
+imported without a time, or entered before psh kept them, are left out
+when picking by it.";
+
+/// The shells whose history can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Which entries of the history are exported.
+#[derive(Debug, Default)]
+struct Filter {
+    pattern: Option<Vec<(char, bool)>>,
+    /// The seconds since the Unix epoch from which entries are exported.
+    since: Option<u64>,
+    /// The seconds since the Unix epoch before which entries are exported.
+    until: Option<u64>,
+    dir: Option<PathBuf>,
+}
+
+impl Filter {
+    fn matches(&self, record: &Record) -> bool {
+        if let Some(pattern) = &self.pattern {
+            if !glob::matches_str(pattern, &record.command) {
+                return false;
+            }
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Some(time) = record.time else {
+                return false;
+            };
+            if self.since.is_some_and(|since| time < since)
+                || self.until.is_some_and(|until| time >= until)
+            {
+                return false;
+            }
+        }
+        match (&self.dir, &record.dir) {
+            (None, _) => true,
+            (Some(dir), Some(entered)) => entered.starts_with(dir),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The forms the history can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Export {
+    Text,
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut file = None;
+    let mut export = false;
+    let mut format = None;
+    let mut filter = Filter::default();
+    let mut filtered = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                return Ok(ExitStatus::from_code(0));
+            }
+            "-c" => {
+                engine.history.clear()?;
+                return Ok(ExitStatus::from_code(0));
+            }
+            "--import" => match args.next() {
+                Some(arg) => file = Some(*arg),
+                None => {
+                    eprintln!("history: --import: missing file");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            "--export" => export = true,
+            "--format" => match args.next() {
+                Some(arg) => format = Some(*arg),
+                None => {
+                    eprintln!("history: --format: missing format");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            "--match" => match args.next() {
+                Some(arg) => {
+                    filter.pattern = Some(arg.chars().map(|c| (c, false)).collect());
+                    filtered = Some("--match");
+                }
+                None => {
+                    eprintln!("history: --match: missing pattern");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            option @ ("--since" | "--until") => match args.next().map(|arg| log::parse_date(arg)) {
+                Some(Some(days)) => {
+                    match option {
+                        "--since" => filter.since = Some(days * 86400),
+                        _ => filter.until = Some((days + 1) * 86400),
+                    }
+                    filtered = Some(option);
+                }
+                Some(None) => {
+                    eprintln!("history: {option}: Expected a date as YYYY-MM-DD.");
+                    return Ok(ExitStatus::from_code(2));
+                }
+                None => {
+                    eprintln!("history: {option}: missing date");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            "--dir" => match args.next() {
+                Some(arg) => {
+                    filter.dir = Some(absolute(Path::new(arg)));
+                    filtered = Some("--dir");
+                }
+                None => {
+                    eprintln!("history: --dir: missing directory");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+            arg => {
+                eprintln!("history: {arg}: invalid option, see `history --help`");
+                return Ok(ExitStatus::from_code(2));
+            }
+        }
+    }
+
+    if export && file.is_some() {
+        eprintln!("history: --export: Cannot be given with --import.");
+        return Ok(ExitStatus::from_code(2));
+    }
+    if let Some(option) = filtered.filter(|_| !export) {
+        eprintln!("history: {option}: Only used with --export.");
+        return Ok(ExitStatus::from_code(2));
+    }
+
+    if export {
+        let export = match format {
+            None | Some("text") => Export::Text,
+            #[cfg(feature = "serde")]
+            Some("json") => Export::Json,
+            Some(format) => {
+                eprintln!("history: --format: '{format}': Expected text or json.");
+                return Ok(ExitStatus::from_code(2));
+            }
+        };
+        return export_to_stdout(engine, export, &filter);
+    }
+
+    let format = match format {
+        None => None,
+        Some("bash") => Some(Format::Bash),
+        Some("zsh") => Some(Format::Zsh),
+        Some("fish") => Some(Format::Fish),
+        Some(format) => {
+            eprintln!("history: --format: '{format}': Expected bash, zsh or fish.");
+            return Ok(ExitStatus::from_code(2));
+        }
+    };
+
+    match (file, format) {
+        (None, None) => {
+            for (i, line) in engine.history.read_lines()?.iter().enumerate() {
+                println!("{:5}  {line}", i + 1);
+            }
+            Ok(ExitStatus::from_code(0))
+        }
+        (Some(file), Some(format)) => import(engine, file, format),
+        (Some(_), None) => {
+            eprintln!("history: --import: The format of the file is needed, see `history --help`.");
+            Ok(ExitStatus::from_code(2))
+        }
+        (None, Some(_)) => {
+            eprintln!("history: --format: Only used with --import or --export.");
+            Ok(ExitStatus::from_code(2))
+        }
+    }
+}
+
+fn export_to_stdout(engine: &mut Engine, export: Export, filter: &Filter) -> Result<ExitStatus> {
+    let records = engine.history.read_records()?;
+    let entries = exported(&records, filter);
+
+    let mut stdout = io::stdout().lock();
+    match export {
+        Export::Text => {
+            for (_, record) in entries {
+                writeln!(stdout, "{}", record.command)?;
+            }
+        }
+        #[cfg(feature = "serde")]
+        Export::Json => {
+            let entries = entries
+                .map(|(number, record)| {
+                    let time = record
+                        .time
+                        .map(|secs| log::timestamp(UNIX_EPOCH + Duration::from_secs(secs)));
+                    serde_json::json!({
+                        "number": number,
+                        "command": record.command,
+                        "time": time,
+                        "dir": record.dir,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut stdout, &entries)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(ExitStatus::from_code(0))
+}
+
+/// The entries of the history `records` that `filter` picks, with their
+/// numbers as `!n` refers to them.
+fn exported<'a>(
+    records: &'a [Record],
+    filter: &'a Filter,
+) -> impl Iterator<Item = (usize, &'a Record)> {
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| (i + 1, record))
+        .filter(|(_, record)| filter.matches(record))
+}
+
+/// `dir` made absolute and without symbolic links, as the directories of the
+/// entries are, or only made absolute if it does not exist.
+fn absolute(dir: &Path) -> PathBuf {
+    fs::canonicalize(dir).unwrap_or_else(|_| match std::env::current_dir() {
+        Ok(cwd) => cwd.join(dir),
+        Err(_) => dir.to_path_buf(),
+    })
+}
+
+fn import(engine: &mut Engine, file: &str, format: Format) -> Result<ExitStatus> {
+    let content = match fs::read(file) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("history: {file}: {e}");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    let (entries, multiline) = parse(&content, format)
+        .into_iter()
+        .partition::<Vec<_>, _>(|entry| !entry.command.contains('\n'));
+    let added = engine.history.import(&entries)?;
+
+    println!("Imported {added} of {} commands.", entries.len());
+    if !multiline.is_empty() {
+        println!("Left out {} that span multiple lines.", multiline.len());
+    }
+    Ok(ExitStatus::from_code(0))
+}
+
+/// The commands in the history file `content` of a shell, the oldest first,
+/// with the times they were entered if the file has them.
+fn parse(content: &[u8], format: Format) -> Vec<Record> {
+    match format {
+        Format::Bash => bash(&String::from_utf8_lossy(content)),
+        Format::Zsh => zsh(&String::from_utf8_lossy(&unmetafy(content))),
+        Format::Fish => fish(&String::from_utf8_lossy(content)),
+    }
+}
+
+/// The commands of a bash history. When `HISTTIMEFORMAT` is set, bash writes
+/// the time of every command on the line before it, as in `#1700000000`.
+fn bash(content: &str) -> Vec<Record> {
+    let mut entries = Vec::new();
+    let mut time = None;
+
+    for line in content.lines() {
+        match line.strip_prefix('#').and_then(seconds) {
+            Some(seconds) => time = Some(seconds),
+            None => entries.push(Record::imported(line, time.take())),
+        }
+    }
+
+    entries
+}
+
+/// The commands of a zsh history, in which a line that ends in a backslash
+/// is continued on the next one. With `EXTENDED_HISTORY`, every command is
+/// preceded by its time and duration, as in `: 1700000000:0;make`.
+fn zsh(content: &str) -> Vec<Record> {
+    let mut entries = Vec::new();
+    let mut continued: Option<Record> = None;
+
+    for line in content.lines() {
+        let mut entry = match continued.take() {
+            Some(mut entry) => {
+                entry.command = entry.command + "\n" + line;
+                entry
+            }
+            None => {
+                let (time, command) = with_time(line);
+                Record::imported(command, time)
+            }
+        };
+        match entry.command.strip_suffix('\\') {
+            Some(command) => {
+                entry.command = command.to_string();
+                continued = Some(entry);
+            }
+            None => entries.push(entry),
+        }
+    }
+
+    entries.extend(continued);
+    entries
+}
+
+/// The time of a line of a zsh history, if it has one, and its command.
+fn with_time(line: &str) -> (Option<u64>, &str) {
+    let Some((time, command)) = line.strip_prefix(": ").and_then(|l| l.split_once(';')) else {
+        return (None, line);
+    };
+    let mut parts = time.split(':');
+    let (Some(seconds), Some(duration), None) = (parts.next(), parts.next(), parts.next()) else {
+        return (None, line);
+    };
+    match (self::seconds(seconds), self::seconds(duration)) {
+        (Some(seconds), Some(_)) => (Some(seconds), command),
+        _ => (None, line),
+    }
+}
+
+/// The commands of a fish history, each of which starts with a `- cmd:`
+/// line, followed by a `when:` line with its time.
+fn fish(content: &str) -> Vec<Record> {
+    let mut entries: Vec<Record> = Vec::new();
+
+    for line in content.lines() {
+        if let Some(cmd) = line.strip_prefix("- cmd: ") {
+            entries.push(Record::imported(unescape_fish(cmd), None));
+        } else if let Some(when) = line.strip_prefix("  when: ") {
+            if let Some(entry) = entries.last_mut() {
+                entry.time = seconds(when);
+            }
+        }
+    }
+
+    entries
+}
+
+/// The seconds since the Unix epoch written as `time`, if it is a number.
+fn seconds(time: &str) -> Option<u64> {
+    match !time.is_empty() && time.bytes().all(|b| b.is_ascii_digit()) {
+        true => time.parse().ok(),
+        false => None,
+    }
+}
+
+/// Undoes how zsh stores bytes such as those of non-ASCII characters in its
+/// history, which is as `0x83` followed by the byte XOR 32.
+fn unmetafy(content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(content.len());
+    let mut iter = content.iter();
+    while let Some(&b) = iter.next() {
+        match b {
+            0x83 => bytes.extend(iter.next().map(|b| b ^ 32)),
+            b => bytes.push(b),
+        }
+    }
+    bytes
+}
+
+/// The command of a `- cmd:` line of a fish history, in which newlines and
+/// backslashes are escaped.
+fn unescape_fish(cmd: &str) -> String {
+    let mut unescaped = String::with_capacity(cmd.len());
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            _ => {
+                unescaped.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let entries = |expected: &[(&str, Option<u64>)]| {
+            expected
+                .iter()
+                .map(|&(command, time)| Record::imported(command, time))
+                .collect::<Vec<_>>()
+        };
+
+        let bash = b"ls\n#1700000000\nmake test\n#comment\n";
+        assert_eq!(
+            entries(&[
+                ("ls", None),
+                ("make test", Some(1700000000)),
+                ("#comment", None)
+            ]),
+            parse(bash, Format::Bash)
+        );
+
+        let zsh = b": 1700000000:0;git status\n: 1700000001:5;for x in a b\\\ndo echo $x\\\ndone\ncaf\x83\xe3\x83\x89\n: 1:x;ls\n";
+        assert_eq!(
+            entries(&[
+                ("git status", Some(1700000000)),
+                ("for x in a b\ndo echo $x\ndone", Some(1700000001)),
+                ("café", None),
+                (": 1:x;ls", None),
+            ]),
+            parse(zsh, Format::Zsh)
+        );
+
+        let fish = b"- cmd: echo a\\\\b\n  when: 1700000000\n  paths:\n    - a\\\\b\n- cmd: echo 1\\necho 2\n  when: 1700000001\n";
+        assert_eq!(
+            entries(&[
+                ("echo a\\b", Some(1700000000)),
+                ("echo 1\necho 2", Some(1700000001)),
+            ]),
+            parse(fish, Format::Fish)
+        );
+    }
+
+    #[test]
+    fn exports() {
+        let record = |command: &str, time, dir: Option<&str>| Record {
+            command: command.to_string(),
+            time,
+            dir: dir.map(PathBuf::from),
+        };
+        let records = [
+            record("git status", None, None),
+            record("ls", Some(1700000000), Some("/home/user")),
+            record("git log -p", Some(1700100000), Some("/home/user/src")),
+        ];
+        let numbers = |filter: Filter| {
+            exported(&records, &filter)
+                .map(|(number, _)| number)
+                .collect::<Vec<_>>()
+        };
+        let pattern = |p: &str| Some(p.chars().map(|c| (c, false)).collect());
+
+        assert_eq!(
+            vec![1, 3],
+            numbers(Filter {
+                pattern: pattern("git *"),
+                ..Default::default()
+            })
+        );
+        assert_eq!(vec![1, 2, 3], numbers(Filter::default()));
+        assert!(numbers(Filter {
+            pattern: pattern("git"),
+            ..Default::default()
+        })
+        .is_empty());
+
+        let day = |date| log::parse_date(date).unwrap() * 86400;
+        assert_eq!(
+            vec![2],
+            numbers(Filter {
+                since: Some(day("2023-11-14")),
+                until: Some(day("2023-11-15")),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![3],
+            numbers(Filter {
+                since: Some(day("2023-11-15")),
+                ..Default::default()
+            })
+        );
+
+        let dir = |dir: &str| Some(PathBuf::from(dir));
+        assert_eq!(
+            vec![2, 3],
+            numbers(Filter {
+                dir: dir("/home/user"),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            vec![3],
+            numbers(Filter {
+                dir: dir("/home/user/src"),
+                ..Default::default()
+            })
+        );
+        assert!(numbers(Filter {
+            dir: dir("/home/use"),
+            ..Default::default()
+        })
+        .is_empty());
+    }
+}
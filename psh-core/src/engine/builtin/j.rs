@@ -0,0 +1,88 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::engine::jump::{self, JumpDb};
+use crate::engine::quote;
+use crate::path;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: j [ -h | --help ] [ -l | --list ] [ -x | --delete ] [arg...]
+
+Jump to a previously visited directory by parts of its path. Directories
+are ranked by how frequently and recently they were visited in an
+interactive shell.
+
+j -h                print this text
+j pattern...        cd to the best matching directory
+j -l [pattern...]   list the matching directories, best match last
+j -x [dir...]       forget `dir`, by default the current directory
+
+A directory matches if the patterns occur in its path in order, and the
+last pattern occurs in its final component. Patterns are case-sensitive,
+unless nothing matches that way.
+
+The directories are stored in $PSH_JUMP_DB, by default
+~/.config/psh/jump.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        [] | ["-l" | "--list", ..] => {
+            let patterns = args.get(1..).unwrap_or_default();
+            let db = JumpDb::of(engine)?;
+            let now = jump::now();
+
+            for entry in db.matches(patterns, now).into_iter().rev() {
+                let dir = path::compress_tilde(entry.dir.display().to_string());
+                let dir = quote::escape_controls(&dir);
+                println!("{:>10.1}  {dir}", entry.frecency(now));
+            }
+            Ok(ExitStatus::from_code(0))
+        }
+
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            Ok(ExitStatus::from_code(0))
+        }
+
+        ["-x" | "--delete", dirs @ ..] => {
+            let dirs = match dirs {
+                [] => vec![env::current_dir()?],
+                dirs => dirs
+                    .iter()
+                    .map(|dir| PathBuf::from(dir).canonicalize().unwrap_or(dir.into()))
+                    .collect(),
+            };
+
+            let mut db = JumpDb::of(engine)?;
+            let mut rc = 0;
+            for dir in dirs {
+                if !db.remove(&dir) {
+                    eprintln!("j: '{}' is not in the database", dir.display());
+                    rc = 1;
+                }
+            }
+            db.save()?;
+            Ok(ExitStatus::from_code(rc))
+        }
+
+        patterns => {
+            let db = JumpDb::of(engine)?;
+            let best = db
+                .matches(patterns, jump::now())
+                .into_iter()
+                .find(|entry| entry.dir.is_dir());
+
+            match best {
+                Some(entry) => {
+                    engine.set_current_dir(&entry.dir)?;
+                    Ok(ExitStatus::from_code(0))
+                }
+                None => {
+                    eprintln!("j: No matching directory.");
+                    Ok(ExitStatus::from_code(1))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::io::{self, Write};
+
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: jobs [ -h | --help ] [ -o | --output [job] ]
+
+List the pipelines running in the background, or stopped with ^Z.
+
+jobs -h         print this text
+jobs            list the jobs, and forget the ones that have finished
+jobs -o [job]   print the output collected from `job`, by default the
+                most recently started one
+
+Stopped jobs are continued with `fg` and `bg`. A job is given by its
+number, optionally prefixed by `%`. Output is only
+collected if `capture_bg` was set when the job was started, see `shopt`.
+A finished job is kept until its output has been printed. Long output
+can be paged with e.g. `jobs -o %1 | less`.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            Ok(ExitStatus::from_code(0))
+        }
+
+        [] => {
+            for job in engine.list_jobs() {
+                println!("{job}");
+            }
+            Ok(ExitStatus::from_code(0))
+        }
+
+        ["-o" | "--output"] | ["-o" | "--output", _] => {
+            engine.jobs.update();
+
+            let spec = args.get(1).copied();
+            let Some(job) = engine.jobs.get(spec) else {
+                eprintln!("jobs: {}: no such job", spec.unwrap_or("%%"));
+                return Ok(ExitStatus::from_code(1));
+            };
+            let Some(output) = &job.output else {
+                eprintln!("jobs: %{}: output was not captured", job.id);
+                return Ok(ExitStatus::from_code(1));
+            };
+
+            let id = job.id;
+            let mut stdout = io::stdout();
+            stdout.write_all(&output.contents())?;
+            stdout.flush()?;
+
+            engine.jobs.output_shown(id);
+            Ok(ExitStatus::from_code(0))
+        }
+
+        _ => {
+            eprintln!("jobs: Invalid arguments, see `jobs --help`");
+            Ok(ExitStatus::from_code(1))
+        }
+    }
+}
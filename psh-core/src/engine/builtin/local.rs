@@ -0,0 +1,74 @@
+use crate::engine::quote;
+use crate::engine::variables::is_name;
+use crate::{Engine, Error, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: local [ -h | --help ] [ <name>[=<val>]... ]
+
+Make variables local to the function being called, so that they get back
+the values they had before it once it returns.
+
+local -h        print this text
+local           print the local variables of the function, as commands
+local name      make `name` local, and unset it until it is assigned
+local name=val  make `name` local, and set it to `val`";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if let ["-h" | "--help"] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let Some(call) = engine.calls.last() else {
+        eprintln!("local: Can only be used in a function");
+        return Ok(ExitStatus::from_code(1));
+    };
+
+    if args.is_empty() {
+        let mut locals = call
+            .locals
+            .keys()
+            .filter_map(|name| Some((name, engine.variables.value_of(name)?)))
+            .collect::<Vec<_>>();
+        locals.sort();
+        for (name, value) in locals {
+            println!("local {name}={}", quote::quote(value));
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+        if !is_name(name) {
+            eprintln!("local: {name}: not a valid name");
+            status = 1;
+            continue;
+        }
+
+        let previous = engine.variables.get(name).cloned();
+        if previous.as_ref().is_some_and(|var| var.readonly) {
+            eprintln!("local: {}", Error::Readonly(name.to_string()));
+            status = 1;
+            continue;
+        }
+
+        let Some(call) = engine.calls.last_mut() else {
+            break;
+        };
+        let newly = !call.locals.contains_key(name);
+        call.locals.entry(name.to_string()).or_insert(previous);
+
+        match value {
+            Some(value) => engine.variables.set(name, value)?,
+            None if newly => {
+                engine.variables.unset(name);
+            }
+            None => {}
+        }
+    }
+    Ok(ExitStatus::from_code(status))
+}
@@ -1,12 +1,41 @@
 mod abbr;
 mod alias;
+mod bg;
+mod break_;
 mod builtins;
+mod caller;
 mod cd;
 mod colon;
+mod complete;
+mod continue_;
+mod coproc;
+mod dirs;
 mod dot;
+mod envdiff;
 mod exit;
+mod export;
+mod fg;
+mod history;
+mod j;
+mod jobs;
+mod local;
+mod popd;
+mod posh_config;
+mod pushd;
+mod read;
+mod readonly;
+mod rehash;
+mod reset_term;
+mod return_;
+mod set;
+mod shopt;
+mod test;
+mod theme;
+mod trap;
 mod unabbr;
 mod unalias;
+mod unset;
+mod wait;
 
 use crate::{Engine, Error, ExitStatus, Result};
 
@@ -15,13 +44,44 @@ type Builtin = fn(&mut Engine, &[&str]) -> Result<ExitStatus>;
 pub(crate) const BUILTINS: &[(&str, Builtin)] = &[
     (".", dot::execute),
     (":", colon::execute),
+    ("[", test::bracket),
     ("abbr", abbr::execute),
     ("alias", alias::execute),
+    ("bg", bg::execute),
+    ("break", break_::execute),
     ("builtins", builtins::execute),
+    ("caller", caller::execute),
     ("cd", cd::execute),
+    ("compgen", complete::compgen),
+    ("complete", complete::execute),
+    ("continue", continue_::execute),
+    ("coproc", coproc::execute),
+    ("dirs", dirs::execute),
+    ("envdiff", envdiff::execute),
     ("exit", exit::execute),
+    ("export", export::execute),
+    ("fg", fg::execute),
+    ("history", history::execute),
+    ("j", j::execute),
+    ("jobs", jobs::execute),
+    ("local", local::execute),
+    ("popd", popd::execute),
+    ("posh-config", posh_config::execute),
+    ("pushd", pushd::execute),
+    ("read", read::execute),
+    ("readonly", readonly::execute),
+    ("rehash", rehash::execute),
+    ("reset-term", reset_term::execute),
+    ("return", return_::execute),
+    ("set", set::execute),
+    ("shopt", shopt::execute),
+    ("test", test::execute),
+    ("theme", theme::execute),
+    ("trap", trap::execute),
     ("unabbr", unabbr::execute),
     ("unalias", unalias::execute),
+    ("unset", unset::execute),
+    ("wait", wait::execute),
 ];
 
 fn get(builtin: &str) -> Option<Builtin> {
@@ -0,0 +1,50 @@
+use super::dirs;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: popd [ -h | --help ] [+N | -N]
+
+Remove a directory from the directory stack, then print the stack like
+`dirs`.
+
+popd -h      print this text
+popd         remove the top of the stack and change to it
+popd +N      remove the Nth directory from the left of `dirs`, counting
+             from 0, changing to the next one if it is the working directory
+popd -N      the same, counting from the right";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut stack = dirs::stack(engine)?;
+    if let ["-h" | "--help"] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+    if stack.len() < 2 {
+        eprintln!("popd: Directory stack empty.");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    let removed = match args {
+        [] => 0,
+        [arg] => match dirs::position("popd", arg, stack.len()) {
+            Some(Ok(i)) => i,
+            Some(Err(status)) => return Ok(status),
+            None => {
+                eprintln!("popd: invalid argument: '{arg}'");
+                return Ok(ExitStatus::from_code(1));
+            }
+        },
+        _ => {
+            eprintln!("popd: Too many arguments");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    stack.remove(removed);
+    if removed == 0 {
+        engine.set_current_dir(&stack[0])?;
+    }
+    engine.dir_stack = stack.split_off(1);
+    dirs::print(engine, false, false)?;
+    Ok(ExitStatus::from_code(0))
+}
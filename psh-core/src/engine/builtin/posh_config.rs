@@ -0,0 +1,238 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use super::theme::THEMES;
+use crate::{path, Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: posh-config [ -h | --help ] [ -n | --dry-run ]
+
+Walk through choosing a prompt, a color theme, the key bindings and
+history options, and write the corresponding commands to the init file.
+
+posh-config -h      print this text
+posh-config         ask for the settings, apply them and write them to
+                    the init file
+posh-config -n      ask for the settings, and print the commands instead
+
+The commands are written between two marker comments, replacing the ones
+written by an earlier run, and the rest of the init file is left as is.
+The previous init file is kept with a `.bak` suffix.";
+
+const BEGIN: &str = "# >>> posh-config >>>";
+const END: &str = "# <<< posh-config <<<";
+
+const PROMPTS: &[(&str, &str)] = &[
+    ("$ ", "minimal"),
+    ("\\w $ ", "the current directory"),
+    ("$USER \\w $ ", "the user and the current directory"),
+];
+
+/// The settings chosen by the user, as indices into the tables above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Choices {
+    prompt: usize,
+    theme: usize,
+
+    /// Whether the key bindings are those of vi rather than emacs.
+    vi: bool,
+    frecency: bool,
+}
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let dry_run = match args {
+        [] => false,
+        ["-n" | "--dry-run"] => true,
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        _ => {
+            eprintln!("posh-config: Invalid arguments, see `posh-config --help`");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    let mut input = io::stdin().lock();
+    let Some(choices) = ask(&mut input, engine.options.frecency, engine.options.vi)? else {
+        eprintln!("posh-config: Aborted, nothing was written");
+        return Ok(ExitStatus::from_code(1));
+    };
+    let block = block(&choices);
+
+    if dry_run {
+        print!("{block}");
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let file = path::init_file();
+    let old = match fs::read_to_string(&file) {
+        Ok(old) => {
+            let mut backup = file.clone().into_os_string();
+            backup.push(".bak");
+            fs::write(&backup, &old)?;
+            old
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            String::new()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    fs::write(&file, replace_block(&old, &block))?;
+
+    engine.execute_line(&block)?;
+    println!("Wrote the settings to {}", file.display());
+
+    Ok(ExitStatus::from_code(0))
+}
+
+/// Asks for every setting in turn, returning `None` if the input ends.
+fn ask(input: &mut impl BufRead, frecency: bool, vi: bool) -> Result<Option<Choices>> {
+    let prompts = PROMPTS
+        .iter()
+        .map(|(ps1, desc)| format!("'{ps1}'  {desc}"))
+        .collect::<Vec<_>>();
+    let Some(prompt) = choose(input, "Prompt", &prompts, 0)? else {
+        return Ok(None);
+    };
+
+    let themes = THEMES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    let Some(theme) = choose(input, "Color theme", &themes, 0)? else {
+        return Ok(None);
+    };
+
+    let bindings = [String::from("emacs"), String::from("vi")];
+    let Some(bindings) = choose(input, "Key bindings", &bindings, vi as usize)? else {
+        return Ok(None);
+    };
+
+    let history = [
+        String::from("by how often and recently they appear in the history"),
+        String::from("alphabetically"),
+    ];
+    let default = if frecency { 0 } else { 1 };
+    let Some(order) = choose(input, "Sort completions", &history, default)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(Choices {
+        prompt,
+        theme,
+        vi: bindings == 1,
+        frecency: order == 0,
+    }))
+}
+
+/// Asks the user to pick one of `options` until a valid answer is given. An
+/// empty answer picks `default`.
+fn choose(
+    input: &mut impl BufRead,
+    title: &str,
+    options: &[String],
+    default: usize,
+) -> Result<Option<usize>> {
+    println!("{title}:");
+    for (i, option) in options.iter().enumerate() {
+        println!("  {}) {option}", i + 1);
+    }
+
+    let choice = loop {
+        print!("[{}] ", default + 1);
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            println!();
+            return Ok(None);
+        }
+
+        match answer.trim() {
+            "" => break default,
+            answer => match answer.parse::<usize>() {
+                Ok(n @ 1..) if n <= options.len() => break n - 1,
+                _ => println!("Answer with a number between 1 and {}.", options.len()),
+            },
+        }
+    };
+
+    println!();
+    Ok(Some(choice))
+}
+
+/// The commands making up the chosen settings, between the markers.
+fn block(choices: &Choices) -> String {
+    let mut block = format!("{BEGIN}\nPS1='{}'\n", PROMPTS[choices.prompt].0);
+
+    // the plain theme leaves the colors as they are
+    if let (name, Some(_)) = THEMES[choices.theme] {
+        block.push_str(&format!("theme {name}\n"));
+    }
+
+    let mode = if choices.vi { "vi" } else { "emacs" };
+    block.push_str(&format!("set -o {mode}\n"));
+
+    let flag = if choices.frecency { "-s" } else { "-u" };
+    block.push_str(&format!("shopt {flag} frecency\n{END}\n"));
+    block
+}
+
+/// Replaces the block written by an earlier run in `init` with `block`, or
+/// appends it if there is none.
+fn replace_block(init: &str, block: &str) -> String {
+    let begin = init.find(BEGIN);
+    let end = init.find(END).map(|i| i + END.len());
+
+    match (begin, end) {
+        (Some(begin), Some(end)) if begin < end => {
+            let rest = init[end..].strip_prefix('\n').unwrap_or(&init[end..]);
+            format!("{}{block}{rest}", &init[..begin])
+        }
+        _ if init.is_empty() || init.ends_with('\n') => format!("{init}{block}"),
+        _ => format!("{init}\n{block}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn earlier_settings_are_replaced() {
+        let mut input = "\n2\n2\n4\nx\n2\n".as_bytes();
+        let choices = ask(&mut input, true, false).unwrap().unwrap();
+        assert_eq!(
+            Choices {
+                prompt: 0,
+                theme: 1,
+                vi: true,
+                frecency: false
+            },
+            choices
+        );
+        assert_eq!(None, ask(&mut "1\n".as_bytes(), true, false).unwrap());
+
+        let first = block(&choices);
+        assert!(first.contains("\nset -o vi\n"));
+        let init = replace_block("alias ll='ls -l'", &first);
+        assert_eq!(format!("alias ll='ls -l'\n{first}"), init);
+
+        let second = block(&Choices {
+            prompt: 1,
+            theme: 0,
+            vi: false,
+            frecency: true,
+        });
+        let init = replace_block(&format!("{init}abbr g git\n"), &second);
+        assert_eq!(format!("alias ll='ls -l'\n{second}abbr g git\n"), init);
+        assert_eq!(
+            "# >>> posh-config >>>\nPS1='\\w $ '\nset -o emacs\nshopt -s frecency\n# <<< posh-config <<<\n",
+            second
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::env;
+
+use super::{cd, dirs};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: pushd [ -h | --help ] [dir | +N | -N]
+
+Save the working directory on the directory stack and change to another
+one, then print the stack like `dirs`.
+
+pushd -h      print this text
+pushd         swap the working directory with the top of the stack
+pushd dir     save the working directory and change to `dir`
+pushd +N      rotate the stack so that the Nth directory from the left of
+              `dirs`, counting from 0, becomes the working directory
+pushd -N      the same, counting from the right";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut stack = dirs::stack(engine)?;
+
+    let rotation = match args {
+        ["-h" | "--help"] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        [] if stack.len() < 2 => {
+            eprintln!("pushd: No other directory.");
+            return Ok(ExitStatus::from_code(1));
+        }
+        [] => 1,
+
+        [arg] => match dirs::position("pushd", arg, stack.len()) {
+            Some(Ok(i)) => i,
+            Some(Err(status)) => return Ok(status),
+            None => {
+                let path = match cd::resolve(engine, "pushd", arg) {
+                    Ok(path) => path,
+                    Err(status) => return Ok(status),
+                };
+                let cwd = env::current_dir()?;
+                engine.set_current_dir(path)?;
+                engine.dir_stack.insert(0, cwd);
+                dirs::print(engine, false, false)?;
+                return Ok(ExitStatus::from_code(0));
+            }
+        },
+
+        _ => {
+            eprintln!("pushd: Too many arguments");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    stack.rotate_left(rotation);
+    engine.set_current_dir(&stack[0])?;
+    engine.dir_stack = stack.split_off(1);
+    dirs::print(engine, false, false)?;
+    Ok(ExitStatus::from_code(0))
+}
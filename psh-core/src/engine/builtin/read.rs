@@ -0,0 +1,293 @@
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use nix::unistd;
+
+use crate::engine::util;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: read [ -h | --help ] [ -r ] [ -d delim ] [ -t seconds ] [ -u fd ] [name...]
+
+Read a line from stdin and split it into fields.
+
+read -h          print this text
+read             read a line into the variable `REPLY`
+read a b         assign the first field to `a` and the rest of the line to `b`
+read -r name     do not treat backslashes as escape characters, which
+                 otherwise keep the next character from separating fields
+read -d x name   read until the first character of `x` instead of a newline,
+                 or until a NUL byte if `x` is empty, e.g. for `find -print0`
+read -t 1.5 a    give up if no complete line was read within 1.5 seconds
+read -t 0        only check whether there is input to read
+read -u 3 name   read from file descriptor 3 instead of stdin
+
+The line is split on the characters in `IFS`, whitespace around another
+character of it being part of the same separator. If there are more
+fields than names, the last name is assigned the rest of the line.
+Returns with 1 if end of file is reached before the delimiter, and with
+142 if the timeout is reached, in both cases assigning the input read so
+far.
+
+Nothing after the line is read, so that the input of a loop like
+`while read -r line; do ...; done < file` is read a line at a time, by
+each `read` in it continuing where the last one stopped.";
+
+/// The exit status when the timeout is reached, as if killed by SIGALRM.
+const TIMED_OUT: i32 = 142;
+
+/// How reading the input ended, with the input read. Unless reading raw,
+/// the backslashes escaping characters are kept for `split` to remove.
+#[derive(Debug, PartialEq, Eq)]
+enum Input {
+    /// The delimiter was reached.
+    Line(String),
+    Eof(String),
+    TimedOut(String),
+}
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if args.contains(&"-h") || args.contains(&"--help") {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut raw = false;
+    let mut delim = b'\n';
+    let mut timeout = None;
+    let mut fd = 0;
+    let mut names = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "-r" => raw = true,
+
+            "-d" => match args.next() {
+                Some(arg) => delim = arg.bytes().next().unwrap_or(0),
+                None => {
+                    eprintln!("read: -d: option requires an argument");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+
+            "-t" => match args.next().map(|arg| (arg, arg.parse::<f64>())) {
+                Some((_, Ok(seconds))) if seconds.is_finite() && seconds >= 0.0 => {
+                    timeout = Some(Duration::from_secs_f64(seconds));
+                }
+                Some((arg, _)) => {
+                    eprintln!("read: {arg}: invalid timeout");
+                    return Ok(ExitStatus::from_code(2));
+                }
+                None => {
+                    eprintln!("read: -t: option requires an argument");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+
+            "-u" => match args.next().map(|arg| (arg, arg.parse::<RawFd>())) {
+                Some((_, Ok(n))) if n >= 0 => fd = n,
+                Some((arg, _)) => {
+                    eprintln!("read: {arg}: invalid file descriptor");
+                    return Ok(ExitStatus::from_code(2));
+                }
+                None => {
+                    eprintln!("read: -u: option requires an argument");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+
+            flag if flag.starts_with('-') => {
+                eprintln!("read: {}: invalid option", flag);
+                return Ok(ExitStatus::from_code(2));
+            }
+
+            name => names.push(name),
+        }
+    }
+
+    if timeout == Some(Duration::ZERO) {
+        let code = match util::wait_for_input(fd, Some(Instant::now()))? {
+            true => 0,
+            false => 1,
+        };
+        return Ok(ExitStatus::from_code(code));
+    }
+
+    if names.is_empty() {
+        names.push("REPLY");
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (line, mut code) = match read_line(fd, raw, delim, deadline) {
+        Ok(Input::Line(line)) => (line, 0),
+        Ok(Input::Eof(line)) => (line, 1),
+        Ok(Input::TimedOut(line)) => (line, TIMED_OUT),
+        Err(e) => {
+            eprintln!("read: {e}");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    let ifs = engine
+        .get_value_of("IFS")
+        .unwrap_or_else(|| String::from(" \t\n"));
+
+    for (name, value) in names.iter().zip(split(&line, &ifs, names.len(), raw)) {
+        if let Err(e) = engine.variables.set(name, value) {
+            eprintln!("read: {e}");
+            code = 1;
+        }
+    }
+
+    Ok(ExitStatus::from_code(code))
+}
+
+/// Reads a line from `fd` up to `delim`, one byte at a time so that nothing
+/// after the delimiter is consumed. Unless `raw`, a backslash escapes the
+/// following character, and a backslash-newline pair continues the line.
+fn read_line(fd: RawFd, raw: bool, delim: u8, deadline: Option<Instant>) -> Result<Input> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 1];
+    let mut is_escaped = false;
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+
+    loop {
+        if deadline.is_some() && !util::wait_for_input(fd, deadline)? {
+            return Ok(Input::TimedOut(text(&bytes)));
+        }
+
+        if unistd::read(fd, &mut buf)? == 0 {
+            return Ok(Input::Eof(text(&bytes)));
+        }
+
+        match buf[0] {
+            b'\n' if is_escaped => {
+                bytes.pop();
+                is_escaped = false;
+            }
+            byte if byte == delim && !is_escaped => return Ok(Input::Line(text(&bytes))),
+            // dropped like in bash, since they could not be passed to commands
+            0 => is_escaped = false,
+            b'\\' if !raw && !is_escaped => {
+                bytes.push(b'\\');
+                is_escaped = true;
+            }
+            byte => {
+                bytes.push(byte);
+                is_escaped = false;
+            }
+        }
+    }
+}
+
+/// Splits `line` into `n` fields on the characters in `ifs`, the last field
+/// getting the rest of the line. IFS whitespace at either end of the line
+/// is ignored, and around another IFS character is part of the separator.
+/// Unless `raw`, a backslash is removed, and the character it escapes does
+/// not separate fields.
+fn split(line: &str, ifs: &str, n: usize, raw: bool) -> Vec<String> {
+    // every character, and whether it was escaped
+    let mut chars = Vec::new();
+    let mut iter = line.chars();
+    while let Some(c) = iter.next() {
+        match iter.clone().next() {
+            Some(escaped) if c == '\\' && !raw => {
+                chars.push((escaped, true));
+                iter.next();
+            }
+            _ => chars.push((c, false)),
+        }
+    }
+
+    let is_ifs = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let is_ifs_whitespace = |c: &(char, bool)| is_ifs(c) && c.0.is_whitespace();
+    let start = chars.iter().position(|c| !is_ifs_whitespace(c));
+    let end = chars.iter().rposition(|c| !is_ifs_whitespace(c));
+    let mut rest = match (start, end) {
+        (Some(start), Some(end)) => &chars[start..=end],
+        _ => &[],
+    };
+
+    let text = |chars: &[(char, bool)]| chars.iter().map(|(c, _)| c).collect::<String>();
+    let whitespace =
+        |chars: &[(char, bool)]| chars.iter().take_while(|c| is_ifs_whitespace(c)).count();
+    let mut fields = Vec::new();
+
+    while fields.len() + 1 < n && !rest.is_empty() {
+        let Some(i) = rest.iter().position(is_ifs) else {
+            break;
+        };
+        fields.push(text(&rest[..i]));
+
+        // a separator is IFS whitespace around at most one other character
+        // of IFS
+        rest = &rest[i..];
+        rest = &rest[whitespace(rest)..];
+        if rest
+            .first()
+            .is_some_and(|c| is_ifs(c) && !is_ifs_whitespace(c))
+        {
+            rest = &rest[1..];
+            rest = &rest[whitespace(rest)..];
+        }
+    }
+
+    if !rest.is_empty() {
+        fields.push(text(rest));
+    }
+
+    fields.resize(n, String::new());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_into_names() {
+        assert_eq!(vec!["foo bar"], split("  foo bar ", " \t\n", 1, true));
+        assert_eq!(
+            vec!["foo", "bar  baz"],
+            split("foo  bar  baz", " \t\n", 2, true)
+        );
+        assert_eq!(vec!["a", "", ""], split("a", " \t\n", 3, true));
+        assert_eq!(vec!["a", "", "c"], split("a::c", ":", 3, true));
+        assert_eq!(vec!["a", "b:c"], split("a:b:c", ":", 2, true));
+        assert_eq!(vec!["a", "b", "c"], split("a : b:  c ", ": ", 3, true));
+        assert_eq!(vec!["a", "", "b"], split("a : : b", ": ", 3, true));
+    }
+
+    #[test]
+    fn escaped_characters_do_not_split() {
+        assert_eq!(vec!["a b", "c"], split("a\\ b c", " ", 2, false));
+        assert_eq!(vec!["a\\", "b c"], split("a\\\\ b c", " ", 2, false));
+        assert_eq!(vec!["a:b", ""], split("a\\:b", ":", 2, false));
+        assert_eq!(vec!["a\\", "b"], split("a\\ b", " ", 2, true));
+        assert_eq!(vec!["a "], split("a\\ ", " ", 1, false));
+    }
+
+    #[test]
+    fn read_until_delimiter_or_timeout() {
+        let (read, write) = unistd::pipe().unwrap();
+        unistd::write(write, b"a b\\\0c\0\nx\0y\nd").unwrap();
+
+        let line = |delim| read_line(read, false, delim, None).unwrap();
+        assert_eq!(Input::Line("a b\\c".to_string()), line(0));
+        assert_eq!(Input::Line(String::new()), line(b'\n'));
+        assert_eq!(Input::Line("xy".to_string()), line(b'\n'));
+
+        let deadline = Some(Instant::now() + Duration::from_millis(10));
+        assert_eq!(
+            Input::TimedOut("d".to_string()),
+            read_line(read, false, b'\n', deadline).unwrap()
+        );
+
+        unistd::write(write, b"e\\\nf").unwrap();
+        unistd::close(write).unwrap();
+        assert_eq!(Input::Eof("ef".to_string()), line(b'\n'));
+        assert_eq!(Input::Eof(String::new()), line(b'\n'));
+        unistd::close(read).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use crate::engine::quote;
+use crate::engine::variables::is_name;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: readonly [ -h | --help ] [ -p ] [ <name>[=<val>]... ]
+
+Keep variables from being changed or unset for as long as the shell runs.
+
+readonly -h            print this text
+readonly, readonly -p  print the readonly variables, as commands
+readonly name          make the variable `name` readonly
+readonly name=val      set `name` to `val` and make it readonly";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                return Ok(ExitStatus::from_code(0));
+            }
+            "-p" => {}
+            "--" => {
+                operands.extend(args.by_ref());
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("readonly: {arg}: invalid option, see `readonly --help`");
+                return Ok(ExitStatus::from_code(2));
+            }
+            operand => operands.push(operand),
+        }
+    }
+
+    if operands.is_empty() {
+        let mut readonly = engine
+            .variables
+            .iter()
+            .filter(|(_, var)| var.readonly)
+            .collect::<Vec<_>>();
+        readonly.sort_by_key(|(name, _)| *name);
+        for (name, var) in readonly {
+            println!("readonly {name}={}", quote::quote(&var.value));
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut status = 0;
+    for operand in operands {
+        let (name, value) = match operand.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (operand, None),
+        };
+        if !is_name(name) {
+            eprintln!("readonly: {name}: not a valid name");
+            status = 1;
+            continue;
+        }
+
+        if let Some(value) = value {
+            if let Err(e) = engine.variables.set(name, value) {
+                eprintln!("readonly: {e}");
+                status = 1;
+                continue;
+            }
+        }
+        engine.variables.set_readonly(name);
+    }
+    Ok(ExitStatus::from_code(status))
+}
@@ -0,0 +1,29 @@
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: rehash [ -h | --help ]
+
+Look up the executables in $PATH again. Executables being added or
+removed are usually noticed automatically, but e.g. a file becoming
+executable might not be.
+
+rehash -h       print this text";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["-h" | "--help"] => {
+            println!("{}", HELP);
+            Ok(ExitStatus::from_code(0))
+        }
+
+        [] => {
+            engine.rehash();
+            Ok(ExitStatus::from_code(0))
+        }
+
+        _ => {
+            eprintln!("rehash: Too many arguments");
+            Ok(ExitStatus::from_code(1))
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::engine::terminal::{self, Terminal};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: reset-term [ -h | --help ] [ -s ]
+
+Put the terminal back in a usable state after a command left it in a weird
+one, e.g. with what is typed not shown, the cursor hidden, or only part of
+the screen scrolling, like `stty sane` and `tput reset` but without clearing
+the screen.
+
+reset-term -h    print this text
+reset-term       reset the terminal
+reset-term -s    keep the current settings of the terminal, such as those
+                 set with `stty`, as the ones to go back to
+
+An interactive shell checks the settings of the terminal before every
+prompt, and resets it if a command changed them without changing them
+back.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            Ok(ExitStatus::from_code(0))
+        }
+        [] => {
+            if let Err(e) = terminal::reset(engine.terminal.as_ref()) {
+                eprintln!("reset-term: {e}");
+                return Ok(ExitStatus::from_code(1));
+            }
+            Ok(ExitStatus::from_code(0))
+        }
+        ["-s"] => match Terminal::save(0) {
+            Some(terminal) => {
+                engine.terminal = Some(terminal);
+                Ok(ExitStatus::from_code(0))
+            }
+            None => {
+                eprintln!("reset-term: Not a terminal.");
+                Ok(ExitStatus::from_code(1))
+            }
+        },
+        [arg, ..] => {
+            eprintln!("reset-term: {arg}: invalid option, see `reset-term --help`");
+            Ok(ExitStatus::from_code(2))
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::engine::Flow;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: return [ -h | --help ] [n]
+
+Stop executing the function being called, or the file sourced with `.`,
+which then returns with the status `n`, taken modulo 256, or with the
+status of the last command if `n` is omitted.
+
+return -h       print this text
+return          return with the status of the last command
+return n        return with the status `n`";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let code = match args {
+        ["-h" | "--help"] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        [] => engine.last_status.raw_code() as i64,
+
+        [code] => match code.parse::<i64>() {
+            Ok(code) => code,
+            Err(_) => {
+                eprintln!("return: invalid integer: '{}'", code);
+                return Ok(ExitStatus::from_code(2));
+            }
+        },
+
+        _ => {
+            eprintln!("return: Too many arguments");
+            return Ok(ExitStatus::from_code(1));
+        }
+    };
+
+    let sourced = engine
+        .frames
+        .last()
+        .is_some_and(|frame| frame.name == "source");
+    if engine.calls.is_empty() && !sourced {
+        eprintln!("return: Can only be used in a function or a file sourced with `.`");
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    let status = ExitStatus::from_code(code.rem_euclid(256) as i32);
+    engine.flow = Some(Flow::Return(status));
+    Ok(status)
+}
@@ -0,0 +1,125 @@
+use std::iter;
+
+use crate::engine::options::Options;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: set [ -h | --help ] [ -Beux | +Beux ] [ -o [name] | +o [name] ] [ -- ] [ arg... ]
+
+Set or unset the options POSIX specifies, with `-` and `+` respectively.
+See `shopt` for the other options. The arguments after the options, if
+any, or after `--`, become the positional parameters `$1` and on.
+
+set -h          print this text
+set, set -o     print the state of all options
+set +o          print the commands that restore the current options
+set -o name     set the option `name`
+set +o name     unset the option `name`
+set -e, +e      set or unset `errexit`, and likewise for `-B`, `-u` and `-x`
+set -- a b      set `$1` to `a` and `$2` to `b`, and `$#` to 2
+set --          unset the positional parameters
+
+Flags can be combined, as in `set -eu`.
+
+Available options:
+
+  braceexpand (-B)
+                 expand `a{b,c}` to `ab ac` and `{1..3}` to `1 2 3`, as
+                 bash does, which POSIX does not specify
+  emacs          edit the input line with Emacs-like keys, the default
+  errexit  (-e)  exit as soon as a pipeline fails, unless it is negated
+                 with `!` or followed by `&&` or `||`
+  nounset  (-u)  fail when expanding a variable that is not set
+  pipefail       give pipelines the status of their last failed command
+  vi             edit the input line with Vi-like keys, starting each line
+                 in insert mode, where Esc switches to normal mode
+  xtrace   (-x)  print every command to stderr before executing it, once
+                 it is expanded, prefixed by `$PS4` (`+ ` by default)";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        [] => {
+            print(engine, true);
+            return Ok(ExitStatus::from_code(0));
+        }
+        _ => {}
+    }
+
+    let mut changes = Vec::new();
+    let mut parameters = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let (value, flags) = match arg.split_at_checked(1) {
+            Some(("-", "-")) => {
+                parameters = Some(args.map(ToString::to_string).collect());
+                break;
+            }
+            Some(("-", flags)) if !flags.is_empty() => (true, flags),
+            Some(("+", flags)) if !flags.is_empty() => (false, flags),
+            _ => {
+                let rest = args.map(ToString::to_string);
+                parameters = Some(iter::once(arg.to_string()).chain(rest).collect());
+                break;
+            }
+        };
+
+        for flag in flags.chars() {
+            // `-o` takes the name of the option from the next argument, as
+            // in `set -eo pipefail`
+            let name = if flag == 'o' {
+                let Some(name) = args.next() else {
+                    print(engine, value);
+                    continue;
+                };
+                Options::SET_NAMES.iter().find(|(n, _)| n == name)
+            } else {
+                Options::SET_NAMES.iter().find(|(_, f)| *f == Some(flag))
+            };
+
+            let Some((name, _)) = name else {
+                let sign = if value { '-' } else { '+' };
+                eprintln!("set: {sign}{flag}: invalid option, see `set --help`");
+                return Ok(ExitStatus::from_code(2));
+            };
+            changes.push((name, value));
+        }
+    }
+
+    for (name, value) in changes {
+        if let Some(option) = engine.options.get_mut(name) {
+            *option = value;
+        }
+
+        // only one editing mode is in use at a time
+        let other = match *name {
+            "emacs" => "vi",
+            "vi" => "emacs",
+            _ => continue,
+        };
+        if let Some(option) = engine.options.get_mut(other) {
+            *option = !value;
+        }
+    }
+    if let Some(parameters) = parameters {
+        engine.parameters = parameters;
+    }
+
+    Ok(ExitStatus::from_code(0))
+}
+
+/// Prints the state of the options as a table, or otherwise as the
+/// commands that restore it.
+fn print(engine: &Engine, table: bool) {
+    for (name, _) in Options::SET_NAMES {
+        let value = engine.options.get(name).unwrap_or_default();
+        match table {
+            true => println!("{:<16}{}", name, if value { "on" } else { "off" }),
+            false => println!("set {}o {name}", if value { '-' } else { '+' }),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::engine::options::Options;
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: shopt [ -h | --help ] [ -s | -u ] [ -q ] [name...]
+
+Set, unset, or query shell options.
+
+shopt -h           print this text
+shopt              print the state of all options
+shopt name         print the state of `name`, returns with 0 if it is set
+shopt -s name      set the option `name`
+shopt -u name      unset the option `name`
+shopt -q name      like `shopt name`, but print nothing
+
+Available options:
+
+  capture_bg    collect the output of background jobs, see `jobs -o`
+  cmd_status    show the status and duration of every command
+  crash_report  write a report to attach to an issue if psh crashes
+  errtrace      keep the ERR trap in subshells, see `trap`
+  frecency      sort completions by how often and recently they were used (default)
+  histexpand    expand history references such as `!!` and `!$` (default)
+  histverify    show a line changed by expanding an abbreviation or the history
+                before executing it
+  inputrc       read key bindings from `$INPUTRC` or `~/.inputrc` at startup
+  lastpipe      run the last command of a pipeline in the current shell when
+                job control is off
+  noglob        do not expand pathnames such as `*.rs`
+  posix_spawn   start commands with `posix_spawn` rather than `fork` (default)
+  transient_prompt
+                redraw the prompts of entered commands as `$PSH_TRANSIENT_PROMPT`";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if args.contains(&"-h") || args.contains(&"--help") {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut set = None;
+    let mut quiet = false;
+    let mut names = Vec::new();
+
+    for arg in args {
+        match *arg {
+            "-s" => set = Some(true),
+            "-u" => set = Some(false),
+            "-q" => quiet = true,
+            flag if flag.starts_with('-') => {
+                eprintln!("shopt: {}: invalid option", flag);
+                return Ok(ExitStatus::from_code(2));
+            }
+            name => names.push(name),
+        }
+    }
+
+    if let Some(name) = names.iter().find(|name| engine.options.get(name).is_none()) {
+        eprintln!("shopt: {}: invalid shell option name", name);
+        return Ok(ExitStatus::from_code(1));
+    }
+
+    if let Some(value) = set {
+        for name in names {
+            *engine.options.get_mut(name).unwrap() = value;
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    // listing every option succeeds, querying specific ones only does if
+    // they are all set
+    let query = !names.is_empty();
+    if !query {
+        names = Options::NAMES.to_vec();
+    }
+
+    let mut rc = 0;
+    for name in names {
+        let value = engine.options.get(name).unwrap();
+        if query && !value {
+            rc = 1;
+        }
+        if !quiet {
+            println!("{:<16}{}", name, if value { "on" } else { "off" });
+        }
+    }
+
+    Ok(ExitStatus::from_code(rc))
+}
@@ -0,0 +1,290 @@
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+
+use nix::unistd::{self, AccessFlags};
+
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: test expression
+       [ expression ]
+
+Evaluate a conditional expression, returning with 0 if it is true, 1 if it
+is false, and 2 if it is not valid.
+
+[ --help ]             print this text
+
+-e file                `file` exists
+-f file                `file` is a regular file
+-d file                `file` is a directory
+-L file, -h file       `file` is a symbolic link
+-p file                `file` is a named pipe
+-S file                `file` is a socket
+-r file                `file` is readable
+-w file                `file` is writable
+-x file                `file` is executable, or searchable if a directory
+-s file                `file` is not empty
+
+-z string              `string` is empty
+-n string              `string` is not empty
+string                 `string` is not empty
+a = b, a != b          the strings are equal, or not
+
+a -eq b, a -ne b       the integers are equal, or not
+a -lt b, a -le b       `a` is less than, or at most, `b`
+a -gt b, a -ge b       `a` is greater than, or at least, `b`
+
+! expr                 `expr` is false
+expr -a expr           both expressions are true
+expr -o expr           either expression is true
+( expr )               `expr`, for grouping
+
+With up to four arguments, the expression is read as specified by POSIX,
+so that e.g. `[ \"$a\" = \"$b\" ]` works whatever `$a` and `$b` are.";
+
+pub fn execute(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    Ok(status("test", args))
+}
+
+/// `[`, which is `test` with a closing `]`.
+pub fn bracket(_: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    match args {
+        ["--help"] => {
+            println!("{}", HELP);
+            Ok(ExitStatus::from_code(0))
+        }
+        [args @ .., "]"] => Ok(status("[", args)),
+        _ => {
+            eprintln!("[: Missing `]`.");
+            Ok(ExitStatus::from_code(2))
+        }
+    }
+}
+
+fn status(builtin: &str, args: &[&str]) -> ExitStatus {
+    match evaluate(args) {
+        Ok(true) => ExitStatus::from_code(0),
+        Ok(false) => ExitStatus::from_code(1),
+        Err(e) => {
+            eprintln!("{builtin}: {e}");
+            ExitStatus::from_code(2)
+        }
+    }
+}
+
+/// Evaluates the expression in `args`, going by the number of arguments as
+/// POSIX specifies for up to four of them, so that operands looking like
+/// operators are not mistaken for them.
+fn evaluate(args: &[&str]) -> std::result::Result<bool, String> {
+    match args {
+        [] => Ok(false),
+        [s] => Ok(!s.is_empty()),
+        ["!", s] => Ok(s.is_empty()),
+        [op, operand] => unary(op, operand),
+        [a, op, b] if is_binary(op) => binary(a, op, b),
+        ["!", a, b] => Ok(!evaluate(&[a, b])?),
+        ["(", s, ")"] => Ok(!s.is_empty()),
+        [_, op, _] => Err(format!("'{op}': Binary operator expected.")),
+        ["!", rest @ ..] if rest.len() == 3 => Ok(!evaluate(rest)?),
+        ["(", a, b, ")"] => evaluate(&[a, b]),
+        _ => Parser { args, pos: 0 }.parse(),
+    }
+}
+
+fn is_unary(op: &str) -> bool {
+    matches!(
+        op,
+        "-e" | "-f" | "-d" | "-L" | "-h" | "-p" | "-S" | "-r" | "-w" | "-x" | "-s" | "-z" | "-n"
+    )
+}
+
+/// Whether `op` compares its operands. `-a` and `-o` are binary too, but
+/// combine expressions rather than compare strings.
+fn is_comparison(op: &str) -> bool {
+    matches!(
+        op,
+        "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"
+    )
+}
+
+fn is_binary(op: &str) -> bool {
+    is_comparison(op) || op == "-a" || op == "-o"
+}
+
+fn unary(op: &str, operand: &str) -> std::result::Result<bool, String> {
+    let access = |flags| unistd::access(operand, flags).is_ok();
+    let symlink = || fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink());
+    let metadata = fs::metadata(operand).ok();
+    let file_type = || metadata.as_ref().map(fs::Metadata::file_type);
+
+    Ok(match op {
+        "-e" => metadata.is_some(),
+        "-f" => file_type().is_some_and(|t| t.is_file()),
+        "-d" => file_type().is_some_and(|t| t.is_dir()),
+        "-L" | "-h" => symlink(),
+        "-p" => file_type().is_some_and(|t| t.is_fifo()),
+        "-S" => file_type().is_some_and(|t| t.is_socket()),
+        "-r" => access(AccessFlags::R_OK),
+        "-w" => access(AccessFlags::W_OK),
+        "-x" => access(AccessFlags::X_OK),
+        "-s" => metadata.is_some_and(|m| m.len() > 0),
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        _ => return Err(format!("'{op}': Unary operator expected.")),
+    })
+}
+
+fn binary(a: &str, op: &str, b: &str) -> std::result::Result<bool, String> {
+    let int = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("'{s}': Integer expected."))
+    };
+
+    Ok(match op {
+        "=" | "==" => a == b,
+        "!=" => a != b,
+        "-a" => !a.is_empty() && !b.is_empty(),
+        "-o" => !a.is_empty() || !b.is_empty(),
+        "-eq" => int(a)? == int(b)?,
+        "-ne" => int(a)? != int(b)?,
+        "-lt" => int(a)? < int(b)?,
+        "-le" => int(a)? <= int(b)?,
+        "-gt" => int(a)? > int(b)?,
+        "-ge" => int(a)? >= int(b)?,
+        _ => return Err(format!("'{op}': Binary operator expected.")),
+    })
+}
+
+/// Parses and evaluates longer expressions, in which `-a` binds tighter
+/// than `-o`, and `!` tighter than both.
+struct Parser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(&mut self) -> std::result::Result<bool, String> {
+        let value = self.or()?;
+        match self.peek(0) {
+            None => Ok(value),
+            Some(arg) => Err(format!("'{arg}': Unexpected argument.")),
+        }
+    }
+
+    fn peek(&self, n: usize) -> Option<&'a str> {
+        self.args.get(self.pos + n).copied()
+    }
+
+    fn next(&mut self) -> std::result::Result<&'a str, String> {
+        let arg = self.peek(0).ok_or("Argument expected.")?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn or(&mut self) -> std::result::Result<bool, String> {
+        let mut value = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            value |= self.and()?;
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> std::result::Result<bool, String> {
+        let mut value = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            value &= self.not()?;
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> std::result::Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> std::result::Result<bool, String> {
+        let arg = self.next()?;
+
+        if let Some(op) = self.peek(0).filter(|op| is_comparison(op)) {
+            if self.peek(1).is_some() {
+                self.pos += 1;
+                return binary(arg, op, self.next()?);
+            }
+        }
+
+        if arg == "(" {
+            let value = self.or()?;
+            return match self.next() {
+                Ok(")") => Ok(value),
+                _ => Err("Missing `)`.".to_string()),
+            };
+        }
+
+        if is_unary(arg) {
+            if let Some(operand) = self.peek(0) {
+                self.pos += 1;
+                return unary(arg, operand);
+            }
+        }
+
+        Ok(!arg.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(expr: &str) -> std::result::Result<bool, String> {
+        evaluate(&expr.split(' ').collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn posix_forms() {
+        assert_eq!(Ok(false), evaluate(&[]));
+        assert_eq!(Ok(true), test("-n"));
+        assert_eq!(Ok(false), evaluate(&[""]));
+        assert_eq!(Ok(false), test("! x"));
+        assert_eq!(Ok(true), test("-z "));
+        assert_eq!(Ok(true), test("= = ="));
+        assert_eq!(Ok(false), test("! = x"));
+        assert_eq!(Ok(true), test("! -a x"));
+        assert_eq!(Ok(true), test("( -z )"));
+        assert_eq!(Ok(false), test("! a = a"));
+        assert_eq!(Ok(true), test("( a != b )"));
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(Ok(true), test("10 -gt 9"));
+        assert_eq!(Ok(true), test("-3 -le -3"));
+        assert_eq!(Ok(false), test("1 -eq 2"));
+        assert!(test("a -lt 1").is_err());
+    }
+
+    #[test]
+    fn combinators() {
+        assert_eq!(Ok(true), test("a = b -o 1 -lt 2"));
+        assert_eq!(Ok(false), test("a = a -a ! 1 -lt 2"));
+        assert_eq!(Ok(true), test("x -o y -a -z z"));
+        assert_eq!(Ok(false), test("( x -o y ) -a -z z"));
+        assert_eq!(Ok(true), test("! ! ! -z x"));
+        assert!(test("( a = a -a b").is_err());
+        assert!(test("a = a b c").is_err());
+    }
+
+    #[test]
+    fn files() {
+        assert_eq!(Ok(true), test("-d /"));
+        assert_eq!(Ok(false), test("-f /"));
+        assert_eq!(Ok(true), test("-e / -a -x /"));
+        assert_eq!(Ok(false), test("-e /nonexistent/file"));
+        assert!(test("-q x").is_err());
+    }
+}
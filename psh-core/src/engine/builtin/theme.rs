@@ -0,0 +1,192 @@
+use crate::{Engine, Error, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: theme [ -h | --help ] [ -l | --list ] [name] [element=color...]
+
+Set the colors of the syntax highlighting and of the prompt, which are
+kept in the PSH_*_COL variables and used from the next key pressed.
+
+theme -h              print this text
+theme                 print the colors that are set, as commands
+theme -l              list the themes and the elements
+theme name            use the colors of the theme `name`
+theme element=color   set the color of `element`, such as
+                      `command=green`, `string=214` or `prompt=#ff8700`
+
+A color is a name such as `red` or `dark_blue`, an index in the 256
+color palette, or hex RGB. Setting an element to nothing unsets its
+variable.";
+
+/// The elements that can be colored, with the variables their colors are
+/// kept in.
+pub(super) const ELEMENTS: &[(&str, &str, &str)] = &[
+    ("command", "PSH_VALID_CMD_COL", "commands that exist"),
+    (
+        "invalid",
+        "PSH_INVALID_CMD_COL",
+        "commands that do not exist",
+    ),
+    ("comment", "PSH_COMMENT_COL", "comments and hints"),
+    (
+        "separator",
+        "PSH_SEPARATOR_COL",
+        "`;`, `&&`, `|` and the like",
+    ),
+    ("substitution", "PSH_CMD_SUB_COL", "command substitutions"),
+    (
+        "operator",
+        "PSH_OP_COL",
+        "`=` and the operators of redirections",
+    ),
+    (
+        "lhs",
+        "PSH_LHS_COL",
+        "names assigned to, and redirected descriptors",
+    ),
+    ("rhs", "PSH_RHS_COL", "values assigned"),
+    ("normal", "PSH_NORMAL_COL", "arguments"),
+    ("unparsed", "PSH_UNPARSED_COL", "what could not be parsed"),
+    ("prompt", "PSH_PROMPT_COL", "the prompt"),
+    ("string", "PSH_STRING_COL", "quoted text"),
+];
+
+/// The colors of a theme, in the order of `ELEMENTS`, or `None` for the
+/// default colors.
+pub(super) const THEMES: &[(&str, Option<[u8; 12]>)] = &[
+    ("plain", None),
+    ("dark", Some([10, 9, 8, 11, 13, 14, 12, 15, 15, 15, 12, 3])),
+    ("light", Some([2, 1, 244, 3, 5, 6, 4, 0, 0, 0, 4, 130])),
+];
+
+/// The names that `crossterm` knows colors by.
+const COLOR_NAMES: &[&str] = &[
+    "black",
+    "dark_grey",
+    "red",
+    "dark_red",
+    "green",
+    "dark_green",
+    "yellow",
+    "dark_yellow",
+    "blue",
+    "dark_blue",
+    "magenta",
+    "dark_magenta",
+    "cyan",
+    "dark_cyan",
+    "white",
+    "grey",
+];
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let (theme, colors) = match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        ["-l" | "--list"] => {
+            println!("themes:");
+            for (name, _) in THEMES {
+                println!("  {name}");
+            }
+            println!("elements:");
+            for (element, _, description) in ELEMENTS {
+                println!("  {element:<14}{description}");
+            }
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        [] => {
+            for (element, var, _) in ELEMENTS {
+                if let Some(color) = engine.get_value_of(var) {
+                    println!("theme {element}={color}");
+                }
+            }
+            return Ok(ExitStatus::from_code(0));
+        }
+
+        [theme, colors @ ..] if !theme.contains('=') => (Some(*theme), colors),
+        colors => (None, colors),
+    };
+
+    let mut assignments = Vec::new();
+    if let Some(name) = theme {
+        let Some((_, theme)) = THEMES.iter().find(|(theme, _)| *theme == name) else {
+            eprintln!("theme: unknown theme: '{name}', see `theme -l`");
+            return Ok(ExitStatus::from_code(1));
+        };
+        for (i, (_, var, _)) in ELEMENTS.iter().enumerate() {
+            let color = theme
+                .map(|colors| colors[i].to_string())
+                .unwrap_or_default();
+            assignments.push((*var, color));
+        }
+    }
+
+    for arg in colors {
+        let Some((element, color)) = arg.split_once('=') else {
+            eprintln!("theme: expected element=color, found '{arg}'");
+            return Ok(ExitStatus::from_code(1));
+        };
+        let Some((_, var, _)) = ELEMENTS.iter().find(|(name, _, _)| *name == element) else {
+            eprintln!("theme: unknown element: '{element}', see `theme -l`");
+            return Ok(ExitStatus::from_code(1));
+        };
+        if !color.is_empty() && !is_color(color) {
+            eprintln!("theme: invalid color: '{color}'");
+            return Ok(ExitStatus::from_code(1));
+        }
+        assignments.push((*var, color.to_string()));
+    }
+
+    for (var, color) in assignments {
+        let readonly = engine.variables.get(var).is_some_and(|var| var.readonly);
+        let result = match color.is_empty() {
+            true if readonly => Err(Error::Readonly(var.to_string())),
+            true => {
+                engine.variables.unset(var);
+                Ok(())
+            }
+            false => engine.variables.set(var, color),
+        };
+        if let Err(e) = result {
+            eprintln!("theme: {e}");
+            return Ok(ExitStatus::from_code(1));
+        }
+    }
+
+    Ok(ExitStatus::from_code(0))
+}
+
+/// Whether `s` is a color as the line editor reads it: a name, an index in
+/// the 256 color palette, or hex RGB such as `#ff8700` or `#f80`.
+fn is_color(s: &str) -> bool {
+    match s.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => s.parse::<u8>().is_ok() || COLOR_NAMES.contains(&s.to_lowercase().as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        for color in ["red", "Dark_Blue", "0", "255", "#f80", "#FF8700"] {
+            assert!(is_color(color), "{color}");
+        }
+        for color in ["", "reddish", "256", "-1", "#ff87", "#ggg", "#"] {
+            assert!(!is_color(color), "{color}");
+        }
+    }
+
+    #[test]
+    fn themes_color_every_element() {
+        assert!(THEMES
+            .iter()
+            .filter_map(|(_, colors)| *colors)
+            .all(|colors| colors.len() == ELEMENTS.len()));
+    }
+}
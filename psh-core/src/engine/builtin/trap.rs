@@ -0,0 +1,155 @@
+use nix::sys::signal::Signal;
+
+use crate::engine::signals::{SignalAction, SignalTraps};
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: trap [ -h | --help ] [ -p ] [ action condition... ]
+
+Execute commands when the shell exits, when a command fails, before every
+command, or when the shell receives a signal.
+
+trap -h             print this text
+trap                print the traps that are set
+trap -p [cond...]   print the traps of the given conditions
+trap action cond    execute `action` on `cond`
+trap - cond         reset the trap of `cond`
+trap '' cond        ignore `cond`
+
+Available conditions:
+
+  EXIT  when the shell exits, with `$?` set to its exit status (also `0`)
+  ERR   when a pipeline returns with a non-zero status, with `$?` set to
+        it. Not for pipelines negated with `!`, or followed by `&&` or `||`.
+        Only kept in subshells if `errtrace` is set, see `shopt`.
+  DEBUG before every simple command, with `$PSH_COMMAND` set to the command
+        about to be executed. Not kept in subshells.
+
+Signals are given by name, with or without the `SIG` prefix, or by number,
+as in `INT`, `SIGTERM` or `1`. Their traps are executed once the command
+running when they are received has finished. Caught signals are reset in
+subshells, while ignored ones stay ignored, as they do for the commands
+the shell starts. `KILL` and `STOP` cannot be trapped.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    Exit,
+    Err,
+    Debug,
+    Signal(Signal),
+}
+
+impl Condition {
+    const SHELL: [Condition; 3] = [Condition::Exit, Condition::Err, Condition::Debug];
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "EXIT" | "0" => Some(Self::Exit),
+            "ERR" => Some(Self::Err),
+            "DEBUG" => Some(Self::Debug),
+            _ => SignalTraps::parse(s).map(Self::Signal),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Exit => "EXIT",
+            Self::Err => "ERR",
+            Self::Debug => "DEBUG",
+            Self::Signal(signal) => signal.as_str().trim_start_matches("SIG"),
+        }
+    }
+
+    /// The action of the trap, where an ignored condition has an empty one.
+    fn get(&self, engine: &Engine) -> Option<String> {
+        match self {
+            Self::Exit => engine.exit_trap.clone(),
+            Self::Err => engine.err_trap.clone(),
+            Self::Debug => engine.debug_trap.clone(),
+            Self::Signal(signal) => match engine.signal_traps.get(*signal)? {
+                SignalAction::Execute(action) => Some(action.clone()),
+                SignalAction::Ignore => Some(String::new()),
+            },
+        }
+    }
+
+    fn set(&self, engine: &mut Engine, action: Option<&str>) -> Result<()> {
+        let trap = match self {
+            Self::Exit => &mut engine.exit_trap,
+            Self::Err => &mut engine.err_trap,
+            Self::Debug => &mut engine.debug_trap,
+            Self::Signal(signal) => {
+                let action = action.map(|action| match action {
+                    "" => SignalAction::Ignore,
+                    action => SignalAction::Execute(action.to_string()),
+                });
+                return engine.signal_traps.set(*signal, action);
+            }
+        };
+        *trap = action.map(str::to_string);
+        Ok(())
+    }
+}
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let (print, action, conditions) = match args {
+        ["-h" | "--help", ..] => {
+            println!("{}", HELP);
+            return Ok(ExitStatus::from_code(0));
+        }
+        [] => (true, None, &[][..]),
+        ["-p", conditions @ ..] => (true, None, conditions),
+        ["--", action, conditions @ ..] | [action, conditions @ ..] => {
+            (false, Some(*action), conditions)
+        }
+    };
+
+    let mut parsed = Vec::new();
+    for condition in conditions {
+        match Condition::parse(condition) {
+            Some(Condition::Signal(signal @ (Signal::SIGKILL | Signal::SIGSTOP))) if !print => {
+                eprintln!("trap: {condition}: {} cannot be trapped", signal.as_str());
+                return Ok(ExitStatus::from_code(1));
+            }
+            Some(condition) => parsed.push(condition),
+            None => {
+                eprintln!("trap: {condition}: unsupported condition");
+                return Ok(ExitStatus::from_code(1));
+            }
+        }
+    }
+
+    if print {
+        if parsed.is_empty() {
+            parsed.extend(Condition::SHELL);
+            parsed.extend(
+                engine
+                    .signal_traps
+                    .iter()
+                    .map(|(s, _)| Condition::Signal(s)),
+            );
+        }
+        for condition in parsed {
+            if let Some(action) = condition.get(engine) {
+                let action = action.replace('\'', "'\\''");
+                println!("trap -- '{action}' {}", condition.name());
+            }
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    if parsed.is_empty() {
+        eprintln!("trap: No condition given, see `trap --help`");
+        return Ok(ExitStatus::from_code(2));
+    }
+
+    let action = match action {
+        Some("-") | None => None,
+        action => action,
+    };
+    for condition in parsed {
+        condition.set(engine, action)?;
+    }
+
+    Ok(ExitStatus::from_code(0))
+}
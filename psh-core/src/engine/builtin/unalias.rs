@@ -1,12 +1,13 @@
 use crate::{Engine, ExitStatus, Result};
 
 const HELP: &str = "\
-usage: unalias [ -h | --help ] <key>
+usage: unalias [ -h | --help ] [ -a | <key>... ]
 
-Erase an existing alias.
+Erase existing aliases.
 
-unalias -h   print this text
-unalias key  remove the alias with key `key`";
+unalias -h      print this text
+unalias -a      remove every alias
+unalias key...  remove the aliases with the given keys";
 
 pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
     match args {
@@ -15,19 +16,20 @@ pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
             Ok(ExitStatus::from_code(0))
         }
 
-        &[key] => {
-            if engine.aliases.contains_key(key) {
-                engine.aliases.remove(key);
-                Ok(ExitStatus::from_code(0))
-            } else {
-                eprintln!("unalias: {} not found", key);
-                Ok(ExitStatus::from_code(1))
-            }
+        ["-a"] => {
+            engine.aliases.clear();
+            Ok(ExitStatus::from_code(0))
         }
 
-        _ => {
-            eprintln!("unalias: Too many arguments");
-            Ok(ExitStatus::from_code(1))
+        keys => {
+            let mut status = 0;
+            for key in keys {
+                if engine.aliases.remove(*key).is_none() {
+                    eprintln!("unalias: {} not found", key);
+                    status = 1;
+                }
+            }
+            Ok(ExitStatus::from_code(status))
         }
     }
 }
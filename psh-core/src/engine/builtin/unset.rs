@@ -0,0 +1,58 @@
+use crate::engine::variables::is_name;
+use crate::{Engine, Error, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: unset [ -h | --help ] [ -v | -f ] <name>...
+
+Remove variables or functions. Names that are not set are not an error,
+but readonly variables cannot be removed.
+
+unset -h            print this text
+unset name...       remove the variables with the given names
+unset -v name...    same as above
+unset -f name...    remove the functions with the given names";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    let mut functions = false;
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match *arg {
+            "-h" | "--help" => {
+                println!("{}", HELP);
+                return Ok(ExitStatus::from_code(0));
+            }
+            "-v" => functions = false,
+            "-f" => functions = true,
+            "--" => {
+                operands.extend(args.by_ref());
+                break;
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("unset: {arg}: invalid option, see `unset --help`");
+                return Ok(ExitStatus::from_code(2));
+            }
+            operand => operands.push(operand),
+        }
+    }
+
+    let mut status = 0;
+    for name in operands {
+        if functions {
+            engine.functions.remove(name);
+            continue;
+        }
+
+        if !is_name(name) {
+            eprintln!("unset: {name}: not a valid name");
+            status = 1;
+        } else if engine.variables.get(name).is_some_and(|var| var.readonly) {
+            eprintln!("unset: {}", Error::Readonly(name.to_string()));
+            status = 1;
+        } else {
+            engine.variables.unset(name);
+        }
+    }
+    Ok(ExitStatus::from_code(status))
+}
@@ -0,0 +1,59 @@
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+use crate::{Engine, ExitStatus, Result};
+
+const HELP: &str = "\
+usage: wait [ -h | --help ] [ job... ]
+
+Wait for the jobs running in the background to finish.
+
+wait -h       print this text
+wait          wait for every job, and return 0
+wait job...   wait for the given jobs, returning the exit status of the
+              last one, or 127 if there is no such job
+
+A job is given by its number prefixed by `%`, or by the pid of one of its
+processes, as in `wait $!`. Waiting for a job stops if it is stopped with
+^Z, returning 148.";
+
+pub fn execute(engine: &mut Engine, args: &[&str]) -> Result<ExitStatus> {
+    if let ["-h" | "--help", ..] = args {
+        println!("{}", HELP);
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    if args.is_empty() {
+        let ids = engine.jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+        for id in ids {
+            engine.jobs.wait(id)?;
+        }
+        return Ok(ExitStatus::from_code(0));
+    }
+
+    let mut status = ExitStatus::from_code(0);
+    for arg in args {
+        let job = match arg.starts_with('%') {
+            true => engine.jobs.get(Some(arg)),
+            false => match arg.parse() {
+                Ok(pid) => engine.jobs.by_pid(Pid::from_raw(pid)),
+                Err(_) => {
+                    eprintln!("wait: {arg}: not a pid or job");
+                    return Ok(ExitStatus::from_code(2));
+                }
+            },
+        };
+
+        status = match job.map(|job| job.id) {
+            Some(id) => match engine.jobs.wait(id)? {
+                Some(status) => status,
+                None => ExitStatus::from_code(128 + Signal::SIGTSTP as i32),
+            },
+            None => {
+                eprintln!("wait: {arg}: no such job");
+                ExitStatus::from_code(127)
+            }
+        };
+    }
+    Ok(status)
+}
@@ -0,0 +1,214 @@
+//! A cache of the executables found in `$PATH`, which would otherwise be
+//! listed every time the input line is highlighted or completed.
+//!
+//! The cache is rebuilt whenever `$PATH` changes, `rehash` is run, or an
+//! executable is added to or removed from one of the directories. With the
+//! `watch-path` feature on Linux, the directories are watched with inotify,
+//! which also notices files that become executable. Otherwise the
+//! modification times of the directories are compared on every lookup.
+//!
+//! Looking a name up is a hash lookup, as it happens for the name of every
+//! command on the input line, on every key pressed.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+#[cfg(not(all(feature = "watch-path", target_os = "linux")))]
+use std::time::SystemTime;
+
+use crate::path;
+
+#[derive(Debug, Default)]
+pub struct CommandCache {
+    /// The `$PATH` that the cache was built from.
+    path: String,
+    commands: Vec<(String, PathBuf)>,
+    /// The index of every name in `commands`.
+    index: HashMap<String, usize>,
+    stale: bool,
+
+    /// The modification time of every directory in `$PATH` when the cache
+    /// was built, `None` if it could not be read.
+    #[cfg(not(all(feature = "watch-path", target_os = "linux")))]
+    mtimes: Vec<Option<SystemTime>>,
+
+    #[cfg(all(feature = "watch-path", target_os = "linux"))]
+    watcher: Option<watch::Watcher>,
+}
+
+impl CommandCache {
+    /// Every executable in `path` together with the directory it was found
+    /// in, as returned by `path::get_cmds_from_path`.
+    pub fn commands(&mut self, path: &str) -> &[(String, PathBuf)] {
+        if self.stale || self.path != path || self.has_changed() {
+            self.build(path);
+        }
+        &self.commands
+    }
+
+    /// The directory containing the executable `name`, if any.
+    pub fn find(&mut self, path: &str, name: &str) -> Option<PathBuf> {
+        self.commands(path);
+        self.index.get(name).map(|&i| self.commands[i].1.clone())
+    }
+
+    /// Makes the next lookup rebuild the cache.
+    pub fn rehash(&mut self) {
+        self.stale = true;
+    }
+
+    fn build(&mut self, path: &str) {
+        self.path = path.to_string();
+        self.commands = path::get_cmds_from_path(path);
+        self.index = self
+            .commands
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.clone(), i))
+            .collect();
+        self.stale = false;
+
+        #[cfg(not(all(feature = "watch-path", target_os = "linux")))]
+        {
+            self.mtimes = mtimes(path);
+        }
+
+        #[cfg(all(feature = "watch-path", target_os = "linux"))]
+        {
+            self.watcher = watch::Watcher::new(path).ok();
+        }
+    }
+
+    #[cfg(not(all(feature = "watch-path", target_os = "linux")))]
+    fn has_changed(&self) -> bool {
+        self.mtimes != mtimes(&self.path)
+    }
+
+    #[cfg(all(feature = "watch-path", target_os = "linux"))]
+    fn has_changed(&self) -> bool {
+        // without a watcher there is no way of knowing, so rebuild every time
+        self.watcher
+            .as_ref()
+            .is_none_or(watch::Watcher::has_changed)
+    }
+}
+
+#[cfg(not(all(feature = "watch-path", target_os = "linux")))]
+fn mtimes(path: &str) -> Vec<Option<SystemTime>> {
+    dirs(path)
+        .map(|dir| std::fs::metadata(dir).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn dirs(path: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(path::expand_tilde_in_dir)
+}
+
+#[cfg(all(feature = "watch-path", target_os = "linux"))]
+mod watch {
+    use nix::errno::Errno;
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+    use nix::unistd::close;
+
+    use crate::Result;
+
+    /// Watches the directories of a `$PATH` for executables being added,
+    /// removed or having their permissions changed.
+    #[derive(Debug)]
+    pub struct Watcher {
+        inotify: Inotify,
+    }
+
+    impl Watcher {
+        pub fn new(path: &str) -> Result<Self> {
+            let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?;
+            let flags = AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_MOVED_FROM
+                | AddWatchFlags::IN_MOVED_TO
+                | AddWatchFlags::IN_ATTRIB
+                | AddWatchFlags::IN_DELETE_SELF
+                | AddWatchFlags::IN_MOVE_SELF;
+
+            // directories that do not exist are not watched, so creating one
+            // later goes unnoticed until the next `rehash`
+            for dir in super::dirs(path) {
+                let _ = inotify.add_watch(&dir, flags);
+            }
+
+            Ok(Self { inotify })
+        }
+
+        /// Whether anything has happened in the directories since the last
+        /// call.
+        pub fn has_changed(&self) -> bool {
+            let mut changed = false;
+            loop {
+                match self.inotify.read_events() {
+                    Ok(events) if !events.is_empty() => changed = true,
+                    Ok(_) | Err(Errno::EAGAIN) => return changed,
+                    Err(Errno::EINTR) => continue,
+                    Err(_) => return true,
+                }
+            }
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            let _ = close(std::os::fd::AsRawFd::as_raw_fd(&self.inotify));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn notices_new_executables() {
+        let dir = TempDir::new("commands");
+        let path = dir.display().to_string();
+
+        let mut cache = CommandCache::default();
+        assert!(cache.find(&path, "psh-test-cmd").is_none());
+
+        // the modification time of the directory is only updated every few
+        // milliseconds
+        thread::sleep(Duration::from_millis(50));
+
+        let file = dir.join("psh-test-cmd");
+        fs::write(&file, "").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(Some(dir.to_path_buf()), cache.find(&path, "psh-test-cmd"));
+
+        thread::sleep(Duration::from_millis(50));
+        fs::remove_file(&file).unwrap();
+        assert!(cache.find(&path, "psh-test-cmd").is_none());
+    }
+
+    #[test]
+    fn earlier_directories_win() {
+        let base = TempDir::new("shadowed");
+        let (first, second) = (base.join("first"), base.join("second"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir).unwrap();
+            let file = dir.join("psh-test-cmd");
+            fs::write(&file, "").unwrap();
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path = format!("{}:{}", first.display(), second.display());
+
+        let mut cache = CommandCache::default();
+        assert_eq!(Some(first.clone()), cache.find(&path, "psh-test-cmd"));
+        assert_eq!(1, cache.commands(&path).len());
+    }
+}
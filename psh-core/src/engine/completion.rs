@@ -0,0 +1,327 @@
+//! How the arguments of commands are completed, as set with `complete`,
+//! for those that need something other than every file name.
+//!
+//! Completions can also be given by a function, as with `complete -F` in
+//! bash, so that completion scripts written for it can be sourced. The
+//! function is called with the name of the command, the word being completed
+//! and the word before it, and sets `COMPREPLY` to the words it offers. As
+//! psh has no arrays, `COMP_WORDS` holds the words of the command separated
+//! by spaces, and `COMPREPLY` is split on whitespace.
+//!
+//! Or by a command, as with `complete -C`, which gets the same arguments
+//! as positional parameters, and writes the words it offers one per line.
+
+use std::mem;
+
+use crate::engine::glob;
+use crate::engine::log;
+use crate::engine::quote;
+use crate::{ast, Engine};
+
+/// The files offered when completing the arguments of a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Files {
+    /// No files, only the words of the completion.
+    #[default]
+    None,
+
+    /// Every file, as without a completion.
+    All,
+
+    Directories,
+
+    /// The files whose names match a pattern such as `*.rs`, along with
+    /// the directories, so that the files in them can be reached.
+    Matching(String),
+}
+
+/// How to complete the arguments of a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompletionSpec {
+    /// Offered as they are, such as the subcommands of the command.
+    pub words: Vec<String>,
+
+    pub files: Files,
+
+    /// The function that gives the words, if any. The files are then only
+    /// offered if it gives none, as with `complete -o default` in bash.
+    pub function: Option<String>,
+
+    /// The command whose output gives the words, if any, which the files
+    /// are only offered without, as with the function.
+    pub command: Option<String>,
+}
+
+impl CompletionSpec {
+    /// The words that complete `prefix`.
+    pub fn words<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> {
+        self.words
+            .iter()
+            .map(String::as_str)
+            .filter(move |word| word.starts_with(prefix))
+    }
+
+    /// Whether a file named `name` is offered, where `dir` tells whether it
+    /// is a directory.
+    pub fn offers_file(&self, name: &str, dir: bool) -> bool {
+        match &self.files {
+            Files::None => false,
+            Files::All => true,
+            Files::Directories => dir,
+            Files::Matching(pattern) => {
+                let pattern = pattern.chars().map(|c| (c, false)).collect::<Vec<_>>();
+                dir || glob::matches(&pattern, name)
+            }
+        }
+    }
+
+    /// The `complete` command that sets this completion for `name`.
+    pub fn to_command(&self, name: &str) -> String {
+        let mut command = String::from("complete");
+        if let Some(function) = &self.function {
+            command += &format!(" -F {}", quote::quote(function));
+        }
+        if let Some(words_of) = &self.command {
+            command += &format!(" -C {}", quote::quote(words_of));
+        }
+        if !self.words.is_empty() {
+            command += &format!(" -W {}", quote::quote(&self.words.join(" ")));
+        }
+        match &self.files {
+            Files::None => {}
+            Files::All => command += " -f",
+            Files::Directories => command += " -d",
+            Files::Matching(pattern) => command += &format!(" -G {}", quote::quote(pattern)),
+        }
+        format!("{command} {}", quote::quote(name))
+    }
+}
+
+/// The variables set for a completion function, which are put back as they
+/// were once it returns.
+const VARIABLES: [&str; 5] = [
+    "COMP_WORDS",
+    "COMP_CWORD",
+    "COMP_LINE",
+    "COMP_POINT",
+    "COMPREPLY",
+];
+
+/// Calls the completion `function` to complete the last of `words`, the
+/// words of the command that `line` ends in before the cursor at byte index
+/// `point`, and returns the words it sets `COMPREPLY` to.
+pub fn reply(
+    engine: &mut Engine,
+    function: &str,
+    words: &[&str],
+    line: &str,
+    point: usize,
+) -> Vec<String> {
+    let Some(body) = engine.functions.get(function).cloned() else {
+        return Vec::new();
+    };
+    let Some([command, current, previous]) = arguments(words) else {
+        return Vec::new();
+    };
+
+    with_variables(engine, words, line, point, |engine| {
+        let result = engine.call_function(body, &[function, command, current, previous]);
+        if let Err(e) = result {
+            engine.log(log::Kind::Exec, format!("{function}: {e}"));
+        }
+
+        engine
+            .get_value_of("COMPREPLY")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect()
+    })
+}
+
+/// Runs the completion `command` to complete the last of `words`, as
+/// `reply` calls a function, and returns the lines it writes that start
+/// with that word. `COMP_LINE` and `COMP_POINT` are exported to it, as in
+/// bash, but the arguments are only its positional parameters, rather than
+/// being added to it, so that a command such as `git branch` can be given
+/// as it is.
+pub fn output(
+    engine: &mut Engine,
+    command: &str,
+    words: &[&str],
+    line: &str,
+    point: usize,
+) -> Vec<String> {
+    let Some(arguments) = arguments(words) else {
+        return Vec::new();
+    };
+    let current = arguments[1];
+    let commands = match ast::parse(command, false) {
+        Ok(commands) => commands,
+        Err(e) => {
+            engine.log(log::Kind::Exec, format!("{command}: {e}"));
+            return Vec::new();
+        }
+    };
+
+    // completing does not change `$?`
+    let status = engine.last_status;
+    let arguments = arguments.map(ToString::to_string).to_vec();
+    let parameters = mem::replace(&mut engine.parameters, arguments);
+    let output = with_variables(engine, words, line, point, |engine| {
+        engine.variables.export("COMP_LINE");
+        engine.variables.export("COMP_POINT");
+        engine.command_substitution(commands)
+    });
+    engine.parameters = parameters;
+    engine.last_status = status;
+
+    match output {
+        Ok(output) => output
+            .lines()
+            .filter(|line| !line.is_empty() && line.starts_with(current))
+            .map(ToString::to_string)
+            .collect(),
+        Err(e) => {
+            engine.log(log::Kind::Exec, format!("{command}: {e}"));
+            Vec::new()
+        }
+    }
+}
+
+/// The arguments a completion is called with for `words`: the command, the
+/// word being completed, and the word before it.
+fn arguments<'a>(words: &[&'a str]) -> Option<[&'a str; 3]> {
+    let [command, .., current] = words else {
+        return None;
+    };
+    let previous = match words.len() {
+        1 => "",
+        n => words[n - 2],
+    };
+    Some([command, current, previous])
+}
+
+/// Sets the variables a completion gets for `words`, see `reply`, while
+/// running `complete`, and puts them back as they were afterwards.
+fn with_variables<T>(
+    engine: &mut Engine,
+    words: &[&str],
+    line: &str,
+    point: usize,
+    complete: impl FnOnce(&mut Engine) -> T,
+) -> T {
+    let saved = VARIABLES.map(|name| engine.variables.unset(name));
+    let _ = engine.variables.set("COMP_WORDS", words.join(" "));
+    let _ = engine.variables.set("COMP_CWORD", words.len() - 1);
+    let _ = engine.variables.set("COMP_LINE", line);
+    let _ = engine
+        .variables
+        .set("COMP_POINT", line[..point].chars().count());
+
+    let completed = complete(engine);
+
+    for (name, var) in VARIABLES.into_iter().zip(saved) {
+        engine.variables.restore(name, var);
+    }
+    completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::history::DummyHistory;
+    use crate::ExitStatus;
+
+    #[test]
+    fn offering() {
+        let spec = CompletionSpec {
+            words: vec![
+                "start".to_string(),
+                "stop".to_string(),
+                "status".to_string(),
+            ],
+            files: Files::Matching("*.service".to_string()),
+            function: None,
+            command: None,
+        };
+        assert_eq!(
+            vec!["start", "status"],
+            spec.words("sta").collect::<Vec<_>>()
+        );
+        assert!(spec.offers_file("ssh.service", false));
+        assert!(spec.offers_file("system", true));
+        assert!(!spec.offers_file("notes.txt", false));
+        assert_eq!(
+            "complete -W 'start stop status' -G '*.service' svc",
+            spec.to_command("svc")
+        );
+
+        let spec = CompletionSpec {
+            words: Vec::new(),
+            files: Files::Directories,
+            function: None,
+            command: None,
+        };
+        assert!(!spec.offers_file("notes.txt", false));
+        assert!(spec.offers_file("src", true));
+        assert_eq!("complete -d cd", spec.to_command("cd"));
+
+        let spec = CompletionSpec {
+            function: Some("_git".to_string()),
+            ..spec
+        };
+        assert_eq!("complete -F _git -d git", spec.to_command("git"));
+
+        let spec = CompletionSpec {
+            function: None,
+            command: Some("make -qp".to_string()),
+            ..spec
+        };
+        assert_eq!("complete -C 'make -qp' -d make", spec.to_command("make"));
+    }
+
+    #[test]
+    fn functions() {
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine
+            .execute_line("_svc() { COMPREPLY=\"$1:$2:$3 $COMP_CWORD $COMP_POINT $COMP_WORDS\"; }")
+            .unwrap();
+        engine.variables.set("COMP_LINE", "kept").unwrap();
+
+        let line = "svc start s";
+        let words = ["svc", "start", "s"];
+        assert_eq!(
+            vec!["svc:s:start", "2", "11", "svc", "start", "s"],
+            reply(&mut engine, "_svc", &words, line, line.len())
+        );
+        assert_eq!(Some("kept"), engine.get_value_of("COMP_LINE").as_deref());
+        assert_eq!(None, engine.get_value_of("COMPREPLY"));
+
+        assert!(reply(&mut engine, "_missing", &words, line, line.len()).is_empty());
+    }
+
+    #[test]
+    fn commands() {
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine.last_status = ExitStatus::from_code(3);
+
+        let line = "svc st";
+        let words = ["svc", "st"];
+        let command = "printf '%s\\n' start stop restart \"$COMP_POINT\"; false";
+        assert_eq!(
+            vec!["start", "stop"],
+            output(&mut engine, command, &words, line, line.len())
+        );
+
+        let command = "printf '%s\\n' \"$1:$2:$3\"; printenv COMP_LINE";
+        assert_eq!(
+            vec!["svc:s:svc", "svc s"],
+            output(&mut engine, command, &["svc", "s"], "svc s", 5)
+        );
+
+        assert_eq!(3, engine.last_status.raw_code());
+        assert_eq!(None, engine.get_value_of("COMP_LINE"));
+        assert!(std::env::var("COMP_LINE").is_err());
+    }
+}
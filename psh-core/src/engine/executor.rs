@@ -0,0 +1,225 @@
+//! Starting external commands, once they have been expanded and their file
+//! descriptors have been set up by the engine.
+//!
+//! The engine hands every external command to an `Executor`, which by
+//! default runs it as a child process. Embedders can plug in their own, e.g.
+//! to record commands in tests or to run them somewhere else entirely.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStrExt;
+
+use nix::unistd::{close, dup2, execvp, setpgid, Pid};
+
+use crate::engine::options::Options;
+use crate::engine::util;
+use crate::{ExitStatus, Result};
+
+/// An expanded external command, and how its file descriptors should be set
+/// up when it is started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub args: Vec<String>,
+
+    /// The path of the executable if it was found in `$PATH`. If `None`, the
+    /// first argument is the command to run.
+    pub file: Option<String>,
+
+    /// Variables set only in the environment of the command.
+    pub assignments: HashMap<String, String>,
+
+    /// File descriptors to close, before duplicating `dups`.
+    pub close: Vec<RawFd>,
+
+    /// The `(src, dst)` pairs of file descriptors to duplicate, in order.
+    pub dups: Vec<(RawFd, RawFd)>,
+
+    /// The process group to put the command in, where a pid of 0 starts a
+    /// new one. If `None`, it stays in the process group of the shell.
+    pub pgroup: Option<Pid>,
+}
+
+pub trait Executor {
+    /// Starts the command without waiting for it to finish. The returned pid
+    /// is later passed to `wait`.
+    fn spawn(&mut self, invocation: &Invocation, options: &Options) -> Result<Pid>;
+
+    /// Waits for a command started by `spawn` to finish. With job control,
+    /// the shell waits for the jobs itself instead, to notice when they are
+    /// stopped.
+    fn wait(&mut self, child: Pid) -> Result<ExitStatus>;
+}
+
+/// Runs commands as child processes of the shell.
+#[derive(Debug, Default)]
+pub struct ProcessExecutor;
+
+impl Executor for ProcessExecutor {
+    fn spawn(&mut self, invocation: &Invocation, options: &Options) -> Result<Pid> {
+        if options.posix_spawn {
+            // if this fails, e.g. due to a script without a shebang, fall back
+            // to `execvp` which handles that, and reports any other errors
+            if let Ok(child) = posix_spawn(invocation) {
+                return Ok(child);
+            }
+        }
+
+        // checked before forking, so that the error is reported by the shell
+        let (file, args) = c_args(invocation)?;
+        c_env(invocation)?;
+
+        util::spawn_subshell(|| {
+            for &fd in &invocation.close {
+                close(fd)?;
+            }
+            for &(src, dst) in &invocation.dups {
+                dup2(src, dst)?;
+            }
+
+            if let Some(pgid) = invocation.pgroup {
+                setpgid(Pid::from_raw(0), pgid)?;
+            }
+
+            // Rust ignores SIGPIPE, which would be inherited by the command,
+            // making e.g. `yes | head` print errors instead of just exiting,
+            // and an interactive shell ignores the job control signals
+            util::restore_signals()?;
+
+            for (key, val) in &invocation.assignments {
+                env::set_var(key, val);
+            }
+
+            match execvp(&file, &args) {
+                Ok(_) => unreachable!(),
+                Err(e) => panic!("psh: error in exec: {e}"),
+            }
+        })
+    }
+
+    fn wait(&mut self, child: Pid) -> Result<ExitStatus> {
+        util::wait_for(child)
+    }
+}
+
+/// The file to execute and the arguments as C strings, which fails if any
+/// of them contains a NUL byte.
+fn c_args(invocation: &Invocation) -> Result<(CString, Vec<CString>)> {
+    let args = invocation
+        .args
+        .iter()
+        .map(|s| CString::new(s.as_str()).map_err(io::Error::from))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let file = match &invocation.file {
+        Some(file) => CString::new(file.as_str()).map_err(io::Error::from)?,
+        None => args[0].clone(),
+    };
+
+    Ok((file, args))
+}
+
+/// The environment of the command as C strings, which fails if any of the
+/// assignments contains a NUL byte.
+fn c_env(invocation: &Invocation) -> Result<Vec<CString>> {
+    let assignments = &invocation.assignments;
+    let env = env::vars_os()
+        .filter(|(key, _)| match key.to_str() {
+            Some(key) => !assignments.contains_key(key),
+            None => true,
+        })
+        .map(|(key, val)| [key.as_bytes(), b"=", val.as_bytes()].concat())
+        .chain(
+            assignments
+                .iter()
+                .map(|(key, val)| format!("{key}={val}").into_bytes()),
+        )
+        .map(|var| CString::new(var).map_err(io::Error::from))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(env)
+}
+
+fn posix_spawn(invocation: &Invocation) -> Result<Pid> {
+    let (file, args) = c_args(invocation)?;
+    let env = c_env(invocation)?;
+
+    util::posix_spawn(
+        &file,
+        &args,
+        &env,
+        &invocation.close,
+        &invocation.dups,
+        invocation.pgroup,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::engine::history::DummyHistory;
+    use crate::Engine;
+
+    /// Records the commands instead of running them.
+    #[derive(Default)]
+    struct Recorder(Rc<RefCell<Vec<Invocation>>>);
+
+    impl Executor for Recorder {
+        fn spawn(&mut self, invocation: &Invocation, _: &Options) -> Result<Pid> {
+            let mut invocations = self.0.borrow_mut();
+            invocations.push(invocation.clone());
+            Ok(Pid::from_raw(invocations.len() as i32))
+        }
+
+        fn wait(&mut self, child: Pid) -> Result<ExitStatus> {
+            Ok(ExitStatus::from_code(child.as_raw()))
+        }
+    }
+
+    #[test]
+    fn commands_go_through_the_executor() {
+        let invocations = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine.executor = Box::new(Recorder(Rc::clone(&invocations)));
+
+        let statuses = engine.execute_line("a=1 ls foo | cat -n").unwrap();
+
+        let invocations = invocations.borrow();
+        assert_eq!(2, invocations.len());
+        assert_eq!(vec!["ls", "foo"], invocations[0].args);
+        assert_eq!(
+            Some("1"),
+            invocations[0].assignments.get("a").map(String::as_str)
+        );
+        assert_eq!(vec!["cat", "-n"], invocations[1].args);
+        assert!(invocations[1].dups.iter().any(|&(_, dst)| dst == 0));
+
+        // the status of the last command, as reported by `wait`
+        assert_eq!(2, statuses[0].raw_code());
+    }
+
+    #[test]
+    fn nul_bytes_are_an_error() {
+        let mut invocation = Invocation {
+            args: vec![String::from("echo"), String::from("a\0b")],
+            file: None,
+            assignments: HashMap::new(),
+            close: Vec::new(),
+            dups: Vec::new(),
+            pgroup: None,
+        };
+        assert!(c_args(&invocation).is_err());
+
+        invocation.args.pop();
+        invocation.args.push(String::from("b"));
+        assert!(c_args(&invocation).is_ok());
+        invocation
+            .assignments
+            .insert(String::from("a"), String::from("\0"));
+        assert!(c_env(&invocation).is_err());
+    }
+}
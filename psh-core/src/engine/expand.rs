@@ -1,266 +1,1274 @@
-use std::env;
-use std::ffi::{CStr, CString};
-use std::ops::RangeInclusive;
-
-use nix::libc::getpwnam;
+use std::iter;
 
 use crate::ast::nodes::*;
+use crate::ast::Parser;
+use crate::engine::cancel::CancellationToken;
+#[cfg(feature = "engine")]
+use crate::engine::prompt;
+use crate::engine::quote::{self, Quoting};
+use crate::engine::variables::is_name;
+use crate::engine::{arith, brace, glob};
+use crate::tok::lex;
+use crate::Error;
+#[cfg(feature = "engine")]
 use crate::{path, Engine, Result};
 
+#[cfg(feature = "engine")]
 pub trait Expand {
     fn expand(self, engine: &mut Engine) -> Vec<String>;
 }
 
+#[cfg(feature = "engine")]
 impl Expand for Word {
-    fn expand(mut self, engine: &mut Engine) -> Vec<String> {
-        let og = self.name.clone();
+    fn expand(self, engine: &mut Engine) -> Vec<String> {
+        let cancel = engine.cancel.clone();
+        expand(self, engine, &cancel)
+    }
+}
+
+/// Expands `word` into fields in `env`: brace expansion if enabled, then
+/// every step of `STEPS`. If `cancel` is cancelled, only the fields of the
+/// words generated so far are returned.
+pub fn expand(word: Word, env: &mut dyn Environment, cancel: &CancellationToken) -> Vec<String> {
+    let words = match env.braceexpand() {
+        true => brace::expand_word(word, cancel),
+        false => vec![word],
+    };
+    words
+        .into_iter()
+        .take_while(|_| !cancel.is_cancelled())
+        .flat_map(|word| expand_word(word, env))
+        .collect()
+}
 
-        expand_tilde(&mut self.name, &mut self.expansions);
+/// What the expansions need to know about the shell. Implemented by
+/// `Engine`, and by fixtures in tests.
+pub trait Environment {
+    /// The value of the variable `name`.
+    fn var(&self, name: &str) -> Option<String>;
 
-        let field_split_candidates =
-            expand_parameters(&mut self.name, &mut self.expansions, engine);
+    /// Sets the variable `name`, as done by assignments in arithmetic
+    /// expressions.
+    fn set_var(&mut self, name: &str, value: String);
 
-        // FIXME: command substitution
-        // FIXME: arithmetic expression
+    /// The value of `$?`.
+    fn last_status(&self) -> String;
 
-        let remove_empty = !og.contains(['\'', '"']);
+    /// The positional parameters, `$1` and on.
+    fn parameters(&self) -> Vec<String>;
 
-        let it = field_split(self.name, field_split_candidates, remove_empty, engine);
+    /// The home directory of `user`, or of the current user if `None`.
+    fn home_dir(&self, user: Option<&str>) -> Option<String>;
 
-        // FIXME: pathname expand
+    /// What `commands` write to stdout when executed in a subshell, without
+    /// trailing newlines. `$?` is set to their exit status.
+    fn command_output(&mut self, commands: &SyntaxTree) -> String;
 
-        it.into_iter()
-            .filter_map(|s| {
-                let remove_empty = !s.contains(['\'', '"']);
-                remove_quotes(&s, remove_empty)
-            })
-            .collect()
-    }
+    /// Whether pathname expansion is disabled.
+    fn noglob(&self) -> bool;
+
+    /// The token that stops long running expansions, such as pathname
+    /// expansion, when cancelled.
+    fn cancellation(&self) -> &CancellationToken;
+
+    /// Whether brace expansion is enabled.
+    fn braceexpand(&self) -> bool;
+
+    /// Whether expanding a variable that is not set is an error.
+    fn nounset(&self) -> bool;
+
+    /// Records an error that keeps the command being expanded from running.
+    fn fail(&mut self, error: Error);
 }
 
-fn field_split(
-    input: String,
-    ranges: Vec<RangeInclusive<usize>>,
-    remove_empty: bool,
-    engine: &Engine,
-) -> Vec<String> {
-    let ifs_chars = engine
-        .get_value_of("IFS")
-        .unwrap_or_else(|| String::from(" \n\t"));
-
-    let mut fields = Vec::new();
-    let mut current_field: Option<String> = None;
-
-    let mut chars = input.chars().enumerate().peekable();
-
-    while let Some((i, c)) = chars.next() {
-        if ifs_chars.contains(c) && ranges.iter().any(|range| range.contains(&i)) {
-            if let Some(field) = current_field {
-                if !(remove_empty && field.is_empty()) {
-                    fields.push(field);
-                }
-            }
-            current_field = None;
-        } else if let Some(field) = current_field.as_mut() {
-            field.push(c);
-            if chars.peek().is_none() {
-                fields.push(field.to_string());
+#[cfg(feature = "engine")]
+impl Environment for Engine {
+    fn var(&self, name: &str) -> Option<String> {
+        match name {
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "0" => Some(self.shell_name.clone()),
+            _ => self.get_value_of(name),
+        }
+    }
+
+    fn set_var(&mut self, name: &str, value: String) {
+        if let Err(e) = self.variables.set(name, value) {
+            self.fail(e);
+        }
+    }
+
+    fn last_status(&self) -> String {
+        self.last_status.to_string()
+    }
+
+    fn parameters(&self) -> Vec<String> {
+        self.parameters.clone()
+    }
+
+    fn home_dir(&self, user: Option<&str>) -> Option<String> {
+        match user {
+            None => Some(path::home_dir()),
+            Some(user) => path::user_home_dir(user),
+        }
+    }
+
+    fn noglob(&self) -> bool {
+        self.options.noglob
+    }
+
+    fn cancellation(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    fn braceexpand(&self) -> bool {
+        self.options.braceexpand
+    }
+
+    fn nounset(&self) -> bool {
+        self.options.nounset
+    }
+
+    fn fail(&mut self, error: Error) {
+        self.expansion_error.get_or_insert(error);
+    }
+
+    fn command_output(&mut self, commands: &SyntaxTree) -> String {
+        match self.command_substitution(commands.clone()) {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("psh: command substitution: {e}");
+                String::new()
             }
-        } else if chars.peek().is_none() {
-            fields.push(c.to_string());
-        } else {
-            current_field = Some(c.to_string());
         }
     }
+}
 
-    fields
+/// Where a byte of a field comes from, which decides the steps that apply to
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The byte was written as part of the word.
+    Word(Quoting),
+
+    /// The byte is the result of an expansion, which was quoted if it was
+    /// written inside double quotes. The results of tilde expansion are
+    /// always treated as quoted.
+    Expansion { quoted: bool },
 }
 
-fn expand_tilde(input: &mut String, expansions: &mut Vec<Expansion>) {
-    let mut indices = Vec::new();
+/// A field of a word that is being expanded. A word starts out as a single
+/// field, which is split into several by field splitting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub text: String,
+
+    /// The expansions that have not been performed yet, with byte ranges
+    /// into `text`.
+    pub expansions: Vec<Expansion>,
+
+    /// The source of every byte of `text`.
+    pub sources: Vec<Source>,
+
+    /// Whether the word contained quotes, in which case it expands to an
+    /// empty field rather than to nothing.
+    pub quoted: bool,
+}
+
+impl Field {
+    /// Whether the byte at `i` is the result of an unquoted expansion, the
+    /// only kind of text subject to field splitting.
+    pub fn is_splittable(&self, i: usize) -> bool {
+        self.sources[i] == Source::Expansion { quoted: false }
+    }
+
+    /// Whether the byte at `i` is quoted, in which case it only matches
+    /// itself in pathname expansion.
+    pub fn is_quoted(&self, i: usize) -> bool {
+        !matches!(
+            self.sources[i],
+            Source::Word(Quoting::Unquoted) | Source::Expansion { quoted: false }
+        )
+    }
+}
 
-    for (i, exp) in expansions.iter().enumerate() {
-        if let Expansion::Tilde { .. } = exp {
-            indices.push(i);
+impl Field {
+    /// A field made of the result of an expansion.
+    fn expanded(text: String, quoted: bool, word_quoted: bool) -> Self {
+        Self {
+            sources: vec![Source::Expansion { quoted }; text.len()],
+            text,
+            expansions: Vec::new(),
+            quoted: word_quoted,
         }
     }
 
-    while let Some(index) = indices.pop() {
-        let Expansion::Tilde { range, name } = expansions.remove(index) else {
-            unreachable!()
-        };
+    /// Splits the field in two at byte `at`, returning the second part.
+    fn split_off(&mut self, at: usize) -> Self {
+        let (kept, moved) = self
+            .expansions
+            .drain(..)
+            .partition::<Vec<_>, _>(|exp| *exp.range().start() < at);
+        self.expansions = kept;
 
-        if !name.is_empty() && path::is_portable_filename(&name) {
-            let c_str = CString::new(name).unwrap();
-            let pointer = c_str.as_ptr();
-            // SAFETY: we own the pointer which was created via CString::new
-            //         from a known Rust string
-            let passwd = unsafe { getpwnam(pointer) };
-
-            if !passwd.is_null() {
-                // SAFETY: the input is the return value of the `getpwnam`
-                //         library function, and we know it is not null
-                let dir = unsafe { CStr::from_ptr((*passwd).pw_dir) };
-                let dir = dir.to_str().unwrap();
-                input.replace_range(range, dir);
-            }
-        } else if name.is_empty() {
-            input.replace_range(range, &path::home_dir());
+        Self {
+            text: self.text.split_off(at),
+            sources: self.sources.split_off(at),
+            expansions: moved
+                .into_iter()
+                .map(|mut exp| {
+                    *exp.range_mut() = exp.range().start() - at..=exp.range().end() - at;
+                    exp
+                })
+                .collect(),
+            quoted: self.quoted,
         }
     }
+
+    /// Appends `other` to the end of the field.
+    fn append(&mut self, other: Self) {
+        let len = self.text.len();
+        self.expansions
+            .extend(other.expansions.into_iter().map(|mut exp| {
+                *exp.range_mut() = exp.range().start() + len..=exp.range().end() + len;
+                exp
+            }));
+        self.text += &other.text;
+        self.sources.extend(other.sources);
+    }
 }
 
-fn expand_parameters(
-    input: &mut String,
-    expansions: &mut Vec<Expansion>,
-    engine: &mut Engine,
-) -> Vec<RangeInclusive<usize>> {
-    let mut indices = Vec::new();
-    for (i, exp) in expansions.iter().enumerate() {
-        if let Expansion::Parameter { .. } = exp {
-            indices.push(i);
+impl From<Word> for Field {
+    fn from(word: Word) -> Self {
+        let sources = quote::scan(&word.name)
+            .into_iter()
+            .flat_map(|(_, c, quoting)| iter::repeat_n(Source::Word(quoting), c.len_utf8()))
+            .collect();
+
+        Self {
+            quoted: word.name.contains(['\'', '"']),
+            text: word.name,
+            expansions: word.expansions,
+            sources,
         }
     }
+}
 
-    let mut field_split_candidates = Vec::new();
+pub type FieldVec = Vec<Field>;
 
-    while let Some(index) = indices.pop() {
-        let Expansion::Parameter { range, name, finished: true, quoted } = expansions.remove(index) else {
-            unreachable!()
-        };
+/// A step of the expansion of a word.
+pub type Step = fn(FieldVec, &mut dyn Environment) -> FieldVec;
 
-        if name == "?" {
-            let status = engine
-                .last_status
-                .iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<_>>()
-                .join("|");
-            if !quoted {
-                let start = *range.start();
-                let len = status.len();
-                let range = start..=start + len;
-                field_split_candidates.push(range);
+/// The steps performed on every word resulting from brace expansion, in the
+/// order POSIX specifies.
+pub const STEPS: &[Step] = &[substitute, field_split, pathname_expansion, quote_removal];
+
+fn expand_word(word: Word, env: &mut dyn Environment) -> Vec<String> {
+    let fields = vec![Field::from(word)];
+    STEPS
+        .iter()
+        .fold(fields, |fields, step| step(fields, env))
+        .into_iter()
+        .map(|field| field.text)
+        .collect()
+}
+
+/// Expands the value of an assignment, which is neither split into fields
+/// nor subject to pathname expansion.
+#[cfg(feature = "engine")]
+pub fn expand_assignment(word: Word, engine: &mut Engine) -> String {
+    let fields = vec![Field::from(word)];
+    let fields = quote_removal(substitute(fields, engine), engine);
+    fields.into_iter().map(|field| field.text).collect()
+}
+
+/// Expands a pattern of a `case` item, which is neither split into fields
+/// nor subject to pathname expansion, and whose quoted parts only match
+/// themselves.
+#[cfg(feature = "engine")]
+pub fn expand_pattern(word: Word, engine: &mut Engine) -> Vec<glob::PatternChar> {
+    pattern_of(expand_argument(&word, engine))
+}
+
+/// Expands the body of a here-document whose delimiter is not quoted, like
+/// a word in double quotes in which `"` is not special: only parameters,
+/// command substitutions and arithmetic are expanded, and a backslash only
+/// quotes `$`, `` ` ``, `\` and newlines.
+pub fn here_document(body: &str, env: &mut dyn Environment) -> String {
+    let mut expanded = String::new();
+    let mut rest = body;
+
+    while let Some(i) = rest.find(['\\', '$', '`']) {
+        expanded += &rest[..i];
+        rest = &rest[i..];
+
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            match escaped.chars().next() {
+                Some('\n') => {}
+                Some(c @ ('$' | '`' | '\\')) => expanded.push(c),
+                _ => {
+                    expanded.push('\\');
+                    rest = escaped;
+                    continue;
+                }
             }
-            input.replace_range(range, &status);
-        } else {
-            let val = engine.get_value_of(&name).unwrap_or_default();
-            if !quoted {
-                let start = *range.start();
-                let len = val.len();
-                let range = start..=start + len;
-                field_split_candidates.push(range);
+            rest = &escaped[1..];
+            continue;
+        }
+
+        let len = substitution_len(rest);
+        if len == 0 {
+            expanded += &rest[..1];
+            rest = &rest[1..];
+            continue;
+        }
+
+        // the substitution is parsed and expanded as a word in double quotes
+        let quoted = format!("\"{}\"", &rest[..len]);
+        match lex(&quoted).into_iter().peekable().parse_word(false) {
+            Ok(word) => {
+                let fields = quote_removal(substitute(vec![Field::from(word)], env), env);
+                expanded.extend(fields.into_iter().map(|field| field.text));
             }
-            input.replace_range(range, &val);
+            Err(_) => expanded += &rest[..len],
         }
+        rest = &rest[len..];
     }
 
-    field_split_candidates
+    expanded + rest
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum QuoteState {
-    Single,
-    Double,
-    None,
+/// The length of the parameter, command substitution or arithmetic at the
+/// start of `s`, or 0 if there is none.
+fn substitution_len(s: &str) -> usize {
+    let mut chars = s.char_indices().peekable();
+    match chars.next() {
+        Some((_, '$')) => match chars.peek() {
+            Some((_, '?' | '$' | '!' | '#' | '@' | '*' | '0'..='9')) => 2,
+            Some(&(_, open @ ('(' | '{'))) => {
+                // up to the matching paren or brace, skipping the quoted ones
+                let close = if open == '(' { ')' } else { '}' };
+                let mut depth = 0;
+                let mut quote = None;
+                while let Some((i, c)) = chars.next() {
+                    match (c, quote) {
+                        ('\\', _) if quote != Some('\'') => {
+                            chars.next();
+                        }
+                        ('\'' | '"', None) => quote = Some(c),
+                        (c, Some(q)) if c == q => quote = None,
+                        (c, None) if c == open => depth += 1,
+                        (c, None) if c == close && depth == 1 => return i + 1,
+                        (c, None) if c == close => depth -= 1,
+                        _ => {}
+                    }
+                }
+                0
+            }
+            _ => {
+                let name = s[1..]
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(s.len() - 1);
+                if name == 0 {
+                    0
+                } else {
+                    1 + name
+                }
+            }
+        },
+        Some((_, '`')) => {
+            let mut escaped = false;
+            for (i, c) in chars {
+                match c {
+                    '`' if !escaped => return i + 1,
+                    '\\' => escaped = !escaped,
+                    _ => escaped = false,
+                }
+            }
+            0
+        }
+        _ => 0,
+    }
 }
 
-pub fn remove_quotes(s: &str, remove_empty: bool) -> Option<String> {
-    let mut name = String::new();
-    let mut state = QuoteState::None;
-    let mut is_escaped = false;
-
-    let mut chars = s.chars().peekable();
-    while let Some(c) = chars.next() {
-        match (c, state, is_escaped) {
-            ('\'', QuoteState::Single, _) => {
-                state = QuoteState::None;
-                is_escaped = false;
-            }
+/// Performs tilde expansion, parameter expansion, command substitution and
+/// arithmetic expansion, which POSIX specifies as a single step going from
+/// the start of the word to the end.
+pub fn substitute(fields: FieldVec, env: &mut dyn Environment) -> FieldVec {
+    fields
+        .into_iter()
+        .flat_map(|field| substitute_field(field, env))
+        .collect()
+}
 
-            ('\'', QuoteState::None, false) => {
-                state = QuoteState::Single;
-                is_escaped = false;
-            }
+/// What a substitution expands to.
+enum Value {
+    /// Text that becomes part of the field.
+    Text(String),
+
+    /// The result of expanding a word, whose quoted parts stay quoted.
+    Field(Field),
+
+    /// Fields of their own, of which the first is joined with the text
+    /// before the substitution and the last with the text after it, as the
+    /// positional parameters in `"$@"`.
+    Fields(Vec<String>),
+}
+
+/// Substitutes the expansions of `field`, which only results in several
+/// fields if it contains `$@` or `$*`.
+fn substitute_field(mut field: Field, env: &mut dyn Environment) -> FieldVec {
+    let (mut substitutions, rest) = field.expansions.into_iter().partition::<Vec<_>, _>(|exp| {
+        matches!(
+            exp,
+            Expansion::Tilde { .. }
+                | Expansion::Parameter { finished: true, .. }
+                | Expansion::Command { finished: true, .. }
+                | Expansion::Arithmetic { finished: true, .. }
+        )
+    });
+    field.expansions = rest;
+
+    // `"$@"` or `"${@}"` without positional parameters expands to no field
+    // at all, if nothing but the double quotes around it is part of the field
+    let lone_at = match substitutions.as_slice() {
+        [Expansion::Parameter {
+            name,
+            modifier: None,
+            quoted: true,
+            range,
+            ..
+        }] if name == "@" && field.expansions.is_empty() => {
+            *range.start() == 1
+                && *range.end() + 2 == field.text.len()
+                && [0, field.text.len() - 1]
+                    .iter()
+                    .all(|&i| field.sources[i] == Source::Word(Quoting::Syntax))
+        }
+        _ => false,
+    };
 
-            ('"', QuoteState::Double, false) => {
-                state = QuoteState::None;
-                is_escaped = false;
+    // the values are found from left to right, since a command substitution
+    // changes `$?` for the ones after it
+    substitutions.sort_by_key(|exp| *exp.range().start());
+    let values = substitutions
+        .iter()
+        .map(|exp| match exp {
+            Expansion::Tilde { name, .. } if name.is_empty() => env.home_dir(None).map(Value::Text),
+            Expansion::Tilde { name, .. } if name == "+" => env.var("PWD").map(Value::Text),
+            Expansion::Tilde { name, .. } if name == "-" => env.var("OLDPWD").map(Value::Text),
+            Expansion::Tilde { name, .. } => env.home_dir(Some(name)).map(Value::Text),
+            Expansion::Parameter {
+                name,
+                modifier: Some(modifier),
+                ..
+            } => Some(modify(name, modifier, env)),
+            Expansion::Parameter { name, .. } if name == "?" => {
+                Some(Value::Text(env.last_status()))
+            }
+            Expansion::Parameter { name, range, .. } if name == "@" || name == "*" => {
+                let quoted = field.is_quoted(*range.start());
+                Some(all_parameters(name == "@" || !quoted, env))
+            }
+            Expansion::Parameter { name, .. } => Some(Value::Text(parameter(name, env))),
+            Expansion::Command { tree, .. } => Some(Value::Text(env.command_output(tree))),
+            Expansion::Arithmetic { expression, .. } => {
+                Some(Value::Text(arithmetic(expression, env)))
             }
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
 
-            ('"', QuoteState::None, false) => {
-                state = QuoteState::Double;
-                is_escaped = false;
+    if lone_at && matches!(values.as_slice(), [Some(Value::Fields(fields))] if fields.is_empty()) {
+        return Vec::new();
+    }
+
+    // the fields after the last `$@` or `$*` substituted so far
+    let mut after = Vec::new();
+
+    // replacing from the end keeps the ranges of the remaining ones valid
+    for (exp, value) in substitutions.into_iter().zip(values).rev() {
+        let range = exp.range().clone();
+        let quoted = match exp {
+            Expansion::Tilde { .. } => true,
+            _ => field.is_quoted(*range.start()),
+        };
+
+        // an unknown user is left as is
+        let (value, sources) = match value {
+            Some(Value::Text(value)) => {
+                let sources = vec![Source::Expansion { quoted }; value.len()];
+                (value, sources)
+            }
+            Some(Value::Field(expanded)) => {
+                let sources = expanded
+                    .sources
+                    .iter()
+                    .enumerate()
+                    .map(|(i, _)| Source::Expansion {
+                        quoted: quoted || expanded.is_quoted(i),
+                    })
+                    .collect();
+                (expanded.text, sources)
             }
+            Some(Value::Fields(values)) => {
+                let tail = field.split_off(range.end() + 1);
+                field.split_off(*range.start());
 
-            ('\\', QuoteState::None | QuoteState::Double, false)
-                if matches!(chars.peek(), Some('\n')) =>
-            {
-                chars.next();
-                is_escaped = false;
+                let mut fields = values
+                    .into_iter()
+                    .map(|value| Field::expanded(value, quoted, field.quoted))
+                    .collect::<Vec<_>>();
+                match fields.last_mut() {
+                    Some(last) => last.append(tail),
+                    None => field.append(tail),
+                }
+                if !fields.is_empty() {
+                    field.append(fields.remove(0));
+                }
+                after.splice(0..0, fields);
+                continue;
             }
+            None => continue,
+        };
+
+        let delta = value.len() as isize - range.clone().count() as isize;
 
-            ('\\', QuoteState::None, false) => {
-                is_escaped = true;
+        for later in &mut field.expansions {
+            if later.range().start() > range.end() {
+                let moved = later.range().start().wrapping_add_signed(delta)
+                    ..=later.range().end().wrapping_add_signed(delta);
+                *later.range_mut() = moved;
             }
+        }
+
+        field.sources.splice(range.clone(), sources);
+        field.text.replace_range(range, &value);
+    }
+
+    after.insert(0, field);
+    after
+}
+
+/// The positional parameters, as fields of their own if `split`, as for
+/// `$@` and unquoted `$*`, and otherwise joined by the first character of
+/// `$IFS`, as for `"$*"`.
+fn all_parameters(split: bool, env: &mut dyn Environment) -> Value {
+    let parameters = env.parameters();
+    if split {
+        return Value::Fields(parameters);
+    }
+
+    let separator = match env.var("IFS") {
+        Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+        None => String::from(" "),
+    };
+    Value::Text(parameters.join(&separator))
+}
 
-            ('\\', QuoteState::Double, false) if matches!(chars.peek(), Some('"')) => {
-                is_escaped = true;
+/// The value of the parameter `name`, or `None` if it is not set.
+fn lookup(name: &str, env: &dyn Environment) -> Option<String> {
+    match name {
+        "?" => Some(env.last_status()),
+        "#" => Some(env.parameters().len().to_string()),
+        "@" | "*" => {
+            let parameters = env.parameters();
+            (!parameters.is_empty()).then(|| parameters.join(" "))
+        }
+        "0" => env.var(name),
+        _ => match name.parse::<usize>() {
+            Ok(n) => env.parameters().get(n.checked_sub(1)?).cloned(),
+            Err(_) => env.var(name),
+        },
+    }
+}
+
+/// The value of the parameter `name`, which is empty if it is not set, or an
+/// error if `nounset` is also set.
+fn parameter(name: &str, env: &mut dyn Environment) -> String {
+    match lookup(name, env) {
+        Some(value) => value,
+        None if env.nounset() && !matches!(name, "@" | "*") => {
+            env.fail(Error::UnboundVariable(name.to_string()));
+            String::new()
+        }
+        None => String::new(),
+    }
+}
+
+/// The value of the parameter `name` as changed by `modifier`.
+fn modify(name: &str, modifier: &Modifier, env: &mut dyn Environment) -> Value {
+    let value = lookup(name, env);
+    let unset = |colon: bool| match colon {
+        true => value.as_deref().is_none_or(str::is_empty),
+        false => value.is_none(),
+    };
+
+    match modifier {
+        Modifier::Length if matches!(name, "@" | "*") => {
+            Value::Text(env.parameters().len().to_string())
+        }
+        Modifier::Length => {
+            let value = parameter(name, env);
+            Value::Text(value.chars().count().to_string())
+        }
+        Modifier::Default { colon, word } if unset(*colon) => {
+            Value::Field(expand_argument(word, env))
+        }
+        Modifier::Assign { colon, word } if unset(*colon) => {
+            let expanded = expand_argument(word, env);
+            if is_name(name) {
+                env.set_var(name, expanded.text.clone());
+            } else {
+                let message = String::from("cannot assign in this way");
+                env.fail(Error::ParameterNotSet(name.to_string(), message));
             }
+            Value::Text(expanded.text)
+        }
+        Modifier::Error { colon, word } if unset(*colon) => {
+            let message = match expand_argument(word, env).text {
+                message if message.is_empty() => String::from("parameter null or not set"),
+                message => message,
+            };
+            env.fail(Error::ParameterNotSet(name.to_string(), message));
+            Value::Text(String::new())
+        }
+        Modifier::Alternative { colon, word } => match unset(*colon) {
+            true => Value::Text(String::new()),
+            false => Value::Field(expand_argument(word, env)),
+        },
+        Modifier::RemovePrefix { longest, pattern } => {
+            let value = parameter(name, env);
+            let pattern = pattern_of(expand_argument(pattern, env));
+            let mut ends = value.char_indices().map(|(i, _)| i).chain([value.len()]);
+            let end = match longest {
+                true => ends
+                    .rev()
+                    .find(|&i| glob::matches_str(&pattern, &value[..i])),
+                false => ends.find(|&i| glob::matches_str(&pattern, &value[..i])),
+            };
+            Value::Text(value[end.unwrap_or(0)..].to_string())
+        }
+        Modifier::RemoveSuffix { longest, pattern } => {
+            let value = parameter(name, env);
+            let pattern = pattern_of(expand_argument(pattern, env));
+            let mut starts = value.char_indices().map(|(i, _)| i).chain([value.len()]);
+            let start = match longest {
+                true => starts.find(|&i| glob::matches_str(&pattern, &value[i..])),
+                false => starts
+                    .rev()
+                    .find(|&i| glob::matches_str(&pattern, &value[i..])),
+            };
+            Value::Text(value[..start.unwrap_or(value.len())].to_string())
+        }
+        _ => Value::Text(parameter(name, env)),
+    }
+}
+
+/// Expands the word of a parameter expansion such as `${name:-word}` into
+/// a single field, which is neither split nor subject to pathname
+/// expansion yet.
+fn expand_argument(word: &Word, env: &mut dyn Environment) -> Field {
+    let fields = quote_removal(substitute(vec![Field::from(word.clone())], env), env);
+    let mut expanded = Field::expanded(String::new(), false, false);
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            expanded.append(Field::expanded(String::from(" "), false, false));
+        }
+        expanded.append(field);
+    }
+    expanded
+}
+
+/// The pattern that an expanded word stands for, where the quoted parts
+/// only match themselves.
+fn pattern_of(field: Field) -> Vec<glob::PatternChar> {
+    field
+        .text
+        .char_indices()
+        .map(|(i, c)| (c, field.is_quoted(i)))
+        .collect()
+}
+
+/// Expands `expression` like a word in double quotes, and evaluates it.
+/// Errors are reported, and make the expansion empty.
+fn arithmetic(expression: &Word, env: &mut dyn Environment) -> String {
+    let fields = substitute_field(Field::from(expression.clone()), env);
+    let expression = quote_removal(fields, env)
+        .into_iter()
+        .map(|field| field.text)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    match arith::evaluate(&expression, env) {
+        Ok(value) => value.to_string(),
+        Err(e) => {
+            env.fail(e);
+            String::new()
+        }
+    }
+}
+
+/// Splits the fields on the characters in `$IFS`, but only where they are
+/// the result of unquoted expansions. Empty fields are dropped.
+pub fn field_split(fields: FieldVec, env: &mut dyn Environment) -> FieldVec {
+    let ifs = env.var("IFS").unwrap_or_else(|| String::from(" \n\t"));
 
-            (c, _, _) => {
-                name.push(c);
-                is_escaped = false;
+    let mut split = Vec::new();
+    for field in fields {
+        let is_separator = |(i, c): &(usize, char)| ifs.contains(*c) && field.is_splittable(*i);
+
+        let mut start = 0;
+        let separators = field
+            .text
+            .char_indices()
+            .filter(is_separator)
+            .map(|(i, c)| (i, i + c.len_utf8()))
+            .chain([(field.text.len(), field.text.len())]);
+
+        for (end, next) in separators {
+            if start < end {
+                let expansions = field
+                    .expansions
+                    .iter()
+                    .filter(|exp| *exp.range().start() >= start && *exp.range().end() < end)
+                    .cloned()
+                    .map(|mut exp| {
+                        let range = exp.range().start() - start..=exp.range().end() - start;
+                        *exp.range_mut() = range;
+                        exp
+                    })
+                    .collect();
+
+                split.push(Field {
+                    text: field.text[start..end].to_string(),
+                    expansions,
+                    sources: field.sources[start..end].to_vec(),
+                    quoted: field.quoted,
+                });
             }
+            start = next;
         }
     }
 
-    if name.is_empty() && remove_empty {
-        None
-    } else {
-        Some(name)
+    split
+}
+
+/// Replaces the fields containing unquoted `*`, `?` or `[` by the pathnames
+/// they match. Patterns that match nothing are left as they are.
+pub fn pathname_expansion(fields: FieldVec, env: &mut dyn Environment) -> FieldVec {
+    if env.noglob() {
+        return fields;
+    }
+
+    let mut expanded = Vec::new();
+    for field in fields {
+        // the quotes are not part of the pattern, but make what they quote
+        // match literally
+        let pattern = field
+            .text
+            .char_indices()
+            .filter(|(i, _)| field.sources[*i] != Source::Word(Quoting::Syntax))
+            .map(|(i, c)| (c, field.is_quoted(i)))
+            .collect::<Vec<_>>();
+
+        let paths = match glob::has_special(&pattern) {
+            true => glob::expand(&pattern, env.cancellation()),
+            false => Vec::new(),
+        };
+        if paths.is_empty() {
+            expanded.push(field);
+            continue;
+        }
+
+        expanded.extend(paths.into_iter().map(|path| Field {
+            sources: vec![Source::Expansion { quoted: true }; path.len()],
+            text: path,
+            expansions: Vec::new(),
+            quoted: field.quoted,
+        }));
     }
+
+    expanded
+}
+
+/// Removes the quotes and backslashes that are not the result of an
+/// expansion. Fields that become empty are dropped, unless the word
+/// contained quotes.
+pub fn quote_removal(fields: FieldVec, _: &mut dyn Environment) -> FieldVec {
+    fields
+        .into_iter()
+        .filter_map(|field| {
+            let (text, sources) = field
+                .text
+                .char_indices()
+                .filter(|(i, _)| field.sources[*i] != Source::Word(Quoting::Syntax))
+                .map(|(i, c)| (c, field.sources[i]))
+                .fold(
+                    (String::new(), Vec::new()),
+                    |(mut text, mut sources), (c, source)| {
+                        text.push(c);
+                        sources.extend(iter::repeat_n(source, c.len_utf8()));
+                        (text, sources)
+                    },
+                );
+
+            if text.is_empty() && !field.quoted {
+                return None;
+            }
+
+            Some(Field {
+                text,
+                sources,
+                ..field
+            })
+        })
+        .collect()
 }
 
+/// Expands the prompt `word`, and then its backslash escapes, such as `\w`
+/// for the working directory and `\n` for a new line; see `prompt`. Only the
+/// line editor uses it.
+#[cfg(feature = "engine")]
 pub fn expand_prompt(mut word: Word, engine: &mut Engine) -> Result<String> {
-    expand_parameters(&mut word.name, &mut word.expansions, engine);
+    word.expansions
+        .retain(|exp| matches!(exp, Expansion::Parameter { .. }));
+    let input = substitute_field(Field::from(word), engine)
+        .into_iter()
+        .map(|field| field.text)
+        .collect::<Vec<_>>()
+        .join(" ");
     // FIXME: command substitution
     // FIXME: arithmetic expression
     // FIXME: ! expansion
 
-    let input = word.name;
-    let output = if input.contains("\\w") {
-        let cwd = env::var("PWD")?;
-        let compressed_cwd = path::compress_tilde(cwd);
-
-        input.replace("\\w", &compressed_cwd)
-    } else {
-        input
-    };
-
-    Ok(output)
+    Ok(prompt::expand_escapes(&input, engine))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
+    use crate::ast::parse;
+    use crate::test_util::TempDir;
+
+    /// The state of the shell that the golden tests are expanded in.
+    struct Fixture {
+        vars: HashMap<String, String>,
+
+        /// Off by default, so that the results do not depend on the files
+        /// in the working directory.
+        glob: bool,
+
+        braces: bool,
+
+        nounset: bool,
+
+        /// The positional parameters.
+        parameters: Vec<String>,
+
+        /// The errors recorded with `fail`.
+        errors: Vec<String>,
+
+        cancel: CancellationToken,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                vars: HashMap::from([
+                    ("a", "1"),
+                    ("ab", "x y"),
+                    ("empty", ""),
+                    ("spaced", "  lead  trail  "),
+                    ("path", "/bin:/usr/bin"),
+                    ("glob", "*"),
+                    ("quotes", "'a  b' \\c"),
+                    ("PWD", "/src"),
+                    ("OLDPWD", "/tmp"),
+                ])
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+                glob: false,
+                braces: true,
+                nounset: false,
+                parameters: vec![String::from("one"), String::from("two  words")],
+                errors: Vec::new(),
+                cancel: CancellationToken::default(),
+            }
+        }
+
+        fn with(mut self, name: &str, value: &str) -> Self {
+            self.set_var(name, value.to_string());
+            self
+        }
+    }
+
+    impl Environment for Fixture {
+        fn var(&self, name: &str) -> Option<String> {
+            self.vars.get(name).cloned()
+        }
+
+        fn set_var(&mut self, name: &str, value: String) {
+            self.vars.insert(name.to_string(), value);
+        }
+
+        fn last_status(&self) -> String {
+            String::from("3")
+        }
+
+        fn parameters(&self) -> Vec<String> {
+            self.parameters.clone()
+        }
+
+        fn home_dir(&self, user: Option<&str>) -> Option<String> {
+            match user {
+                None => Some(String::from("/home/user")),
+                Some("root") => Some(String::from("/root")),
+                Some(_) => None,
+            }
+        }
+
+        fn command_output(&mut self, commands: &SyntaxTree) -> String {
+            match commands.to_string().trim() {
+                "lines" => String::from("a\n  b"),
+                "glob" => String::from("*"),
+                commands => commands.to_uppercase(),
+            }
+        }
+
+        fn noglob(&self) -> bool {
+            !self.glob
+        }
+
+        fn cancellation(&self) -> &CancellationToken {
+            &self.cancel
+        }
+
+        fn braceexpand(&self) -> bool {
+            self.braces
+        }
+
+        fn nounset(&self) -> bool {
+            self.nounset
+        }
+
+        fn fail(&mut self, error: Error) {
+            self.errors.push(error.to_string());
+        }
+    }
+
+    /// Parses `input` as the name of a simple command.
+    fn word(input: &str) -> Word {
+        let ast = parse(input, false).unwrap();
+        let (commands, _) = ast.commands.unwrap();
+        let CompleteCommand::List { list, .. } = commands.head else {
+            panic!("not a list: {input}");
+        };
+        let Some(Command::Simple(cmd)) = list.head.head.full().into_iter().next() else {
+            panic!("not a simple command: {input}");
+        };
+        cmd.name.unwrap()
+    }
+
+    fn check(golden: &[(&str, &[&str])], env: &mut Fixture) {
+        for (input, expected) in golden {
+            assert_eq!(
+                expected.to_vec(),
+                expand_word(word(input), env),
+                "expanding {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn golden() {
+        let golden: &[(&str, &[&str])] = &[
+            ("foo", &["foo"]),
+            ("~", &["/home/user"]),
+            ("~/bin", &["/home/user/bin"]),
+            ("~root/x", &["/root/x"]),
+            ("~nobody/x", &["~nobody/x"]),
+            ("~+/x", &["/src/x"]),
+            ("~-", &["/tmp"]),
+            ("~+x", &["~+x"]),
+            ("'~'", &["~"]),
+            ("$a", &["1"]),
+            ("$?", &["3"]),
+            ("$ab", &["x", "y"]),
+            ("\"$ab\"", &["x y"]),
+            ("'$ab'", &["$ab"]),
+            ("$spaced", &["lead", "trail"]),
+            ("x$spaced", &["x", "lead", "trail"]),
+            ("$empty", &[]),
+            ("x$empty", &["x"]),
+            ("\"$empty\"", &[""]),
+            ("''", &[""]),
+            ("$unset", &[]),
+            ("~/$a", &["/home/user/1"]),
+            ("$a$ab", &["1x", "y"]),
+            ("$ab$a", &["x", "y1"]),
+            ("$ab\"$ab\"$ab", &["x", "yx yx", "y"]),
+            ("é$ab", &["éx", "y"]),
+            ("a\\ b", &["a b"]),
+            ("\"a b\"", &["a b"]),
+            ("\"\\$a\"", &["$a"]),
+            ("\"c\\\\d\"", &["c\\d"]),
+            ("\"c\\\\$a\"", &["c\\1"]),
+            ("\"\\`lines\\`\"", &["`lines`"]),
+            ("\"a\\\nb\"", &["ab"]),
+            ("\"\\a\"", &["\\a"]),
+            ("\"$a\"'$a'", &["1$a"]),
+            ("$path", &["/bin:/usr/bin"]),
+            ("$glob", &["*"]),
+            ("$quotes", &["'a", "b'", "\\c"]),
+            ("\"$quotes\"", &["'a  b' \\c"]),
+            ("$(lines)", &["a", "b"]),
+            ("x$(lines)y", &["xa", "by"]),
+            ("\"$(lines)\"", &["a\n  b"]),
+            ("'$(lines)'", &["$(lines)"]),
+            ("$(glob)", &["*"]),
+            ("$(echo $a)", &["ECHO", "$A"]),
+            ("`lines`", &["a", "b"]),
+            ("\"`lines`\"", &["a\n  b"]),
+            ("`echo \\`x\\``", &["ECHO", "`X`"]),
+            ("\\`lines\\`", &["`lines`"]),
+            ("$((1 + 2 * a))", &["3"]),
+            ("x$(( (a + 1) * -3 ))y", &["x-6y"]),
+            ("\"$((a << 4))\"", &["16"]),
+            ("'$((a))'", &["$((a))"]),
+            ("$(($a + $(echo)))", &["1"]),
+            ("$((1 / 0))", &[]),
+        ];
+        check(golden, &mut Fixture::new());
+    }
 
     #[test]
-    fn backslash_removal() {
-        let input = "hello\\ there";
-        let output = remove_quotes(input, false);
-        assert_eq!(Some("hello there".to_string()), output);
-
-        let input = "'hello\\ there'";
-        let output = remove_quotes(input, false);
-        assert_eq!(Some("hello\\ there".to_string()), output);
-
-        let input = "\"hello\\ there\"";
-        let output = remove_quotes(input, false);
-        assert_eq!(Some("hello\\ there".to_string()), output);
-
-        let input = r#""'foo' \"bar\"""#;
-        let output = remove_quotes(input, false);
-        assert_eq!(Some(r#"'foo' "bar""#.to_string()), output);
+    fn braces_are_expanded_first() {
+        let cancel = CancellationToken::default();
+        assert_eq!(
+            vec!["z1", "zx", "y"],
+            expand(word("z{$a,$ab}"), &mut Fixture::new(), &cancel)
+        );
+
+        let mut env = Fixture::new();
+        env.braces = false;
+        assert_eq!(
+            vec!["z{1,x", "y}"],
+            expand(word("z{$a,$ab}"), &mut env, &cancel)
+        );
+
+        cancel.cancel();
+        assert!(expand(word("z{x,y}"), &mut Fixture::new(), &cancel).is_empty());
+    }
+
+    #[test]
+    fn golden_ifs() {
+        let golden: &[(&str, &[&str])] = &[
+            ("$path", &["/bin", "/usr/bin"]),
+            ("x:$a", &["x:1"]),
+            ("$ab", &["x y"]),
+        ];
+        check(golden, &mut Fixture::new().with("IFS", ":"));
+
+        // the results of tilde expansion are never split
+        check(
+            &[("~/$path", &["/home/user/", "bin", "usr", "bin"])],
+            &mut Fixture::new().with("IFS", "/:"),
+        );
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let golden: &[(&str, &[&str])] = &[
+            ("$#", &["2"]),
+            ("$1", &["one"]),
+            ("$2", &["two", "words"]),
+            ("\"$2\"", &["two  words"]),
+            ("$3", &[]),
+            ("$10", &["one0"]),
+            ("$@", &["one", "two", "words"]),
+            ("$*", &["one", "two", "words"]),
+            ("\"$@\"", &["one", "two  words"]),
+            ("\"$*\"", &["one two  words"]),
+            ("\"<$@>\"", &["<one", "two  words>"]),
+            ("x\"$@\"$a", &["xone", "two  words1"]),
+            ("\"$@\"\"$@\"", &["one", "two  wordsone", "two  words"]),
+        ];
+        check(golden, &mut Fixture::new());
+
+        check(
+            &[
+                ("\"$*\"", &["one:two  words"]),
+                ("$*", &["one", "two  words"]),
+            ],
+            &mut Fixture::new().with("IFS", ":"),
+        );
+
+        let mut env = Fixture::new();
+        env.parameters.clear();
+        let golden: &[(&str, &[&str])] = &[
+            ("$#", &["0"]),
+            ("\"$@\"", &[]),
+            ("\"${@}\"", &[]),
+            ("\"${@}\"\"\"", &[""]),
+            ("\"${@}x\"", &["x"]),
+            ("\"${@:-d}\"", &["d"]),
+            ("\"$*\"", &[""]),
+            ("$@", &[]),
+            ("\"x$@\"", &["x"]),
+        ];
+        check(golden, &mut env);
+    }
+
+    #[test]
+    fn modifiers() {
+        let golden: &[(&str, &[&str])] = &[
+            ("${a}", &["1"]),
+            ("${ab}", &["x", "y"]),
+            ("${#ab}", &["3"]),
+            ("${#unset}", &["0"]),
+            ("${unset:-d}", &["d"]),
+            ("${empty:-d}", &["d"]),
+            ("${empty-d}", &[]),
+            ("${a:-d}", &["1"]),
+            ("${unset:-x  y}", &["x", "y"]),
+            ("\"${unset:-x  y}\"", &["x  y"]),
+            ("${unset:-'x  y'}", &["x  y"]),
+            ("${unset:-$ab}", &["x", "y"]),
+            ("${a:+alt}", &["alt"]),
+            ("${empty:+alt}", &[]),
+            ("${empty+alt}", &["alt"]),
+            ("${path#*/}", &["bin:/usr/bin"]),
+            ("${path##*/}", &["bin"]),
+            ("${path%/*}", &["/bin:/usr"]),
+            ("${path%%:*}", &["/bin"]),
+            ("\"${path%%:*}\"", &["/bin"]),
+            ("${path#'/*'}", &["/bin:/usr/bin"]),
+            ("${glob#\\*}", &[]),
+            ("${#}", &["2"]),
+            ("${#@}", &["2"]),
+            ("${1%e}", &["on"]),
+            ("${10:-ten}", &["ten"]),
+            ("${a!}", &["${a!}"]),
+        ];
+        check(golden, &mut Fixture::new());
+
+        let mut env = Fixture::new();
+        check(&[("${new:=x  y}", &["x", "y"])], &mut env);
+        assert_eq!(Some("x  y"), env.vars.get("new").map(String::as_str));
+
+        check(&[("${unset:?must be set}", &[])], &mut env);
+        assert_eq!(vec!["unset: must be set"], env.errors);
+
+        let mut env = Fixture::new();
+        check(&[("$((1 / 0))", &[])], &mut env);
+        assert_eq!(
+            vec!["arithmetic expansion: 1 / 0: division by zero"],
+            env.errors
+        );
+    }
+
+    #[test]
+    fn quoting_is_tracked_through_expansion() {
+        let fields = substitute(
+            vec![Field::from(word("\"$glob\"*'*'$glob"))],
+            &mut Fixture::new(),
+        );
+        let [field] = fields.as_slice() else {
+            panic!("expected a single field: {fields:?}");
+        };
+        assert_eq!("\"*\"*'*'*", field.text);
+
+        let quoted = field
+            .text
+            .char_indices()
+            .filter(|(_, c)| *c == '*')
+            .map(|(i, _)| field.is_quoted(i))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![true, false, true, false], quoted);
+    }
+
+    #[test]
+    fn pathnames() {
+        let temp = TempDir::new("expand-glob");
+        for file in ["a.rs", "b.rs", "c d.rs", "*.txt"] {
+            std::fs::write(temp.join(file), "").unwrap();
+        }
+        let dir = temp.display().to_string();
+
+        let mut env = Fixture::new().with("dir", &dir).with("star", "*");
+        env.glob = true;
+        let golden: &[(&str, &[&str])] = &[
+            ("$dir/*.rs", &["a.rs", "b.rs", "c d.rs"]),
+            ("$dir/$star.rs", &["a.rs", "b.rs", "c d.rs"]),
+            ("\"$dir\"/[ab].rs", &["a.rs", "b.rs"]),
+            ("$dir/'*'.txt", &["*.txt"]),
+            ("$dir/\\*.rs", &["*.rs"]),
+            ("\"$dir/*.rs\"", &["*.rs"]),
+            ("$dir/*.none", &["*.none"]),
+        ];
+        for (input, expected) in golden {
+            let expected = expected
+                .iter()
+                .map(|file| format!("{dir}/{file}"))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                expected,
+                expand_word(word(input), &mut env),
+                "expanding {input}"
+            );
+        }
+
+        env.glob = false;
+        assert_eq!(
+            vec![format!("{dir}/*.rs")],
+            expand_word(word("$dir/*.rs"), &mut env)
+        );
+
+        env.glob = true;
+        env.cancel.cancel();
+        assert_eq!(
+            vec![format!("{dir}/*.rs")],
+            expand_word(word("$dir/*.rs"), &mut env)
+        );
+    }
+
+    #[test]
+    fn unset_variables() {
+        let mut env = Fixture::new();
+        assert_eq!(vec!["[]"], expand_word(word("[$unset]"), &mut env));
+        assert!(env.errors.is_empty());
+
+        env.nounset = true;
+        assert_eq!(vec!["[1]"], expand_word(word("[$a]"), &mut env));
+        assert_eq!(vec!["[]"], expand_word(word("[$empty]"), &mut env));
+        assert!(env.errors.is_empty());
+
+        expand_word(word("\"$unset$@\""), &mut env);
+        assert_eq!(vec!["unset: unbound variable"], env.errors);
+    }
+    #[test]
+    fn here_documents() {
+        let golden = [
+            ("$a $ab\n", "1 x y\n"),
+            ("\"$a\" '$a'\n", "\"1\" '1'\n"),
+            ("$(cmd) `cmd` $((a + 1))\n", "CMD CMD 2\n"),
+            ("$(echo \")\")\n", "ECHO \")\"\n"),
+            ("\\$a \\` \\\\ \\\" \\x\n", "$a ` \\ \\\" \\x\n"),
+            ("one \\\ntwo\n", "one two\n"),
+            ("$ $? cost $5\n", "$ 3 cost \n"),
+            ("${a} ${#ab} $(unfinished\n", "1 3 $(unfinished\n"),
+        ];
+        for (body, expected) in golden {
+            let mut env = Fixture::new();
+            assert_eq!(
+                expected,
+                here_document(body, &mut env),
+                "expanding {body:?}"
+            );
+        }
     }
 }
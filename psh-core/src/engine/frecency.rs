@@ -0,0 +1,101 @@
+//! Scores for how frequently and recently commands and words have been used,
+//! computed from the history, which is used to rank completion candidates.
+
+use std::collections::HashMap;
+
+use crate::engine::history::History;
+use crate::Result;
+
+/// The amount of recent history entries that are taken into account.
+const WINDOW: usize = 1000;
+
+/// The amount of entries after which a use counts half as much.
+const HALF_LIFE: f64 = 100.0;
+
+#[derive(Debug, Default, Clone)]
+pub struct Frecency {
+    commands: HashMap<String, f64>,
+    words: HashMap<String, f64>,
+}
+
+impl Frecency {
+    /// Computes the scores from the most recent entries of `history`.
+    pub fn from_history(history: &mut dyn History) -> Result<Self> {
+        let entries = history
+            .search("")?
+            .take(WINDOW)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_entries(entries.iter().map(String::as_str)))
+    }
+
+    /// Computes the scores from history entries, the most recent first.
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut frecency = Self::default();
+
+        for (age, entry) in entries.into_iter().enumerate() {
+            let weight = 0.5f64.powf(age as f64 / HALF_LIFE);
+
+            for command in entry.split([';', '|', '&']) {
+                let mut words = command
+                    .split_whitespace()
+                    .skip_while(|word| is_assignment(word));
+
+                if let Some(name) = words.next() {
+                    *frecency.commands.entry(name.to_string()).or_default() += weight;
+                }
+                for word in words {
+                    let word = word.trim_end_matches('/').to_string();
+                    *frecency.words.entry(word).or_default() += weight;
+                }
+            }
+        }
+
+        frecency
+    }
+
+    /// The score of `name` used as a command.
+    pub fn command(&self, name: &str) -> f64 {
+        self.commands.get(name).copied().unwrap_or_default()
+    }
+
+    /// The score of `word` used as an argument. A trailing slash is ignored,
+    /// so that directories score the same however they were written.
+    pub fn word(&self, word: &str) -> f64 {
+        let word = word.trim_end_matches('/');
+        self.words.get(word).copied().unwrap_or_default()
+    }
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequent_and_recent_score_higher() {
+        let frecency = Frecency::from_entries([
+            "git status",
+            "FOO=1 cargo build | tee log",
+            "cd src/",
+            "git commit",
+            "cargo test",
+        ]);
+
+        assert!(frecency.command("git") > frecency.command("cargo"));
+        assert!(frecency.command("cargo") > frecency.command("tee"));
+        assert!(frecency.command("tee") > frecency.command("cd"));
+        assert_eq!(0.0, frecency.command("FOO=1"));
+        assert_eq!(0.0, frecency.command("ls"));
+
+        assert_eq!(frecency.word("src"), frecency.word("src/"));
+        assert!(frecency.word("status") > frecency.word("src"));
+    }
+}
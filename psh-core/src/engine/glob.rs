@@ -0,0 +1,343 @@
+//! Pathname expansion: matching patterns with `*`, `?` and bracket
+//! expressions like `[a-z]` against the names of files.
+//!
+//! A pattern is given as characters paired with whether they were quoted,
+//! since quoted characters only match themselves.
+
+use std::fs;
+
+use crate::engine::cancel::CancellationToken;
+
+/// A character of a pattern, and whether it was quoted.
+pub type PatternChar = (char, bool);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Element {
+    /// `*`, matching any string.
+    Star,
+
+    /// `?`, matching any character.
+    Any,
+
+    Bracket(Bracket),
+    Char(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bracket {
+    negated: bool,
+    items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Char(char),
+    Range(char, char),
+
+    /// A character class such as `[:alpha:]`.
+    Class(String),
+}
+
+impl Bracket {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            Item::Char(x) => *x == c,
+            Item::Range(from, to) => (*from..=*to).contains(&c),
+            Item::Class(class) => match class.as_str() {
+                "alnum" => c.is_alphanumeric(),
+                "alpha" => c.is_alphabetic(),
+                "blank" => c == ' ' || c == '\t',
+                "cntrl" => c.is_control(),
+                "digit" => c.is_ascii_digit(),
+                "graph" => c.is_ascii_graphic(),
+                "lower" => c.is_lowercase(),
+                "print" => c.is_ascii_graphic() || c == ' ',
+                "punct" => c.is_ascii_punctuation(),
+                "space" => c.is_whitespace(),
+                "upper" => c.is_uppercase(),
+                "xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            },
+        });
+        found != self.negated
+    }
+}
+
+/// Whether `pattern` contains an unquoted `*`, `?` or `[`, in which case it
+/// may be a pattern.
+pub fn has_special(pattern: &[PatternChar]) -> bool {
+    pattern
+        .iter()
+        .any(|&(c, quoted)| !quoted && matches!(c, '*' | '?' | '['))
+}
+
+/// The pathnames matching `pattern`, sorted. Empty if nothing matches, or
+/// if `pattern` is not a pattern at all. If `cancel` is cancelled, no more
+/// directories are read, and only the pathnames found so far are returned.
+pub fn expand(pattern: &[PatternChar], cancel: &CancellationToken) -> Vec<String> {
+    let mut components = pattern.split(|&(c, _)| c == '/').collect::<Vec<_>>();
+    let mut paths = vec![String::new()];
+    if pattern.first().is_some_and(|&(c, _)| c == '/') {
+        components.remove(0);
+        paths = vec![String::from("/")];
+    }
+
+    let parsed = components
+        .iter()
+        .map(|component| parse(component))
+        .collect::<Vec<_>>();
+    if parsed
+        .iter()
+        .flatten()
+        .all(|e| matches!(e, Element::Char(_)))
+    {
+        return Vec::new();
+    }
+
+    for (i, elements) in parsed.iter().enumerate() {
+        let separator = if i + 1 == parsed.len() { "" } else { "/" };
+        let mut next = Vec::new();
+
+        for path in paths {
+            if cancel.is_cancelled() {
+                break;
+            }
+
+            // components without special characters are used as they are,
+            // and only need to exist
+            if elements.iter().all(|e| matches!(e, Element::Char(_))) {
+                let literal = components[i].iter().map(|(c, _)| c).collect::<String>();
+                next.push(format!("{path}{literal}{separator}"));
+                continue;
+            }
+
+            let dir = if path.is_empty() { "." } else { path.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| matches_name(elements, name))
+                .collect::<Vec<_>>();
+            names.sort();
+
+            next.extend(
+                names
+                    .into_iter()
+                    .map(|name| format!("{path}{name}{separator}")),
+            );
+        }
+
+        paths = next;
+    }
+
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths
+}
+
+/// Whether the file name `name` matches `pattern`.
+pub fn matches(pattern: &[PatternChar], name: &str) -> bool {
+    matches_name(&parse(pattern), name)
+}
+
+/// Whether `s` matches `pattern`, where unlike in file names, a leading `.`
+/// is matched like any other character, as in `${name#pattern}`.
+pub fn matches_str(pattern: &[PatternChar], s: &str) -> bool {
+    matches_elements(&parse(pattern), s)
+}
+
+/// Whether `name` matches `elements`, where a leading `.` must be matched
+/// explicitly.
+fn matches_name(elements: &[Element], name: &str) -> bool {
+    if name.starts_with('.') && elements.first() != Some(&Element::Char('.')) {
+        return false;
+    }
+    matches_elements(elements, name)
+}
+
+fn matches_elements(elements: &[Element], name: &str) -> bool {
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut e, mut n) = (0, 0);
+
+    // where to continue if the current attempt fails: after the last `*`,
+    // which then matches one more character
+    let mut backtrack = None;
+
+    while n < name.len() {
+        let matched = match elements.get(e) {
+            Some(Element::Star) => {
+                backtrack = Some((e + 1, n));
+                e += 1;
+                continue;
+            }
+            Some(Element::Any) => true,
+            Some(Element::Bracket(bracket)) => bracket.matches(name[n]),
+            Some(Element::Char(c)) => *c == name[n],
+            None => false,
+        };
+
+        if matched {
+            e += 1;
+            n += 1;
+        } else if let Some((star_e, star_n)) = backtrack {
+            e = star_e;
+            n = star_n + 1;
+            backtrack = Some((star_e, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    elements[e.min(elements.len())..]
+        .iter()
+        .all(|e| *e == Element::Star)
+}
+
+fn parse(pattern: &[PatternChar]) -> Vec<Element> {
+    let mut elements = Vec::new();
+    let mut i = 0;
+
+    while let Some(&(c, quoted)) = pattern.get(i) {
+        i += 1;
+        let element = match (c, quoted) {
+            ('*', false) => Element::Star,
+            ('?', false) => Element::Any,
+            ('[', false) => match parse_bracket(&pattern[i..]) {
+                Some((bracket, len)) => {
+                    i += len;
+                    Element::Bracket(bracket)
+                }
+                None => Element::Char('['),
+            },
+            (c, _) => Element::Char(c),
+        };
+        elements.push(element);
+    }
+
+    elements
+}
+
+/// Parses the bracket expression following a `[`, returning it along with
+/// the number of characters up to and including the closing `]`. Returns
+/// `None` if it is not closed, in which case the `[` is a literal.
+fn parse_bracket(pattern: &[PatternChar]) -> Option<(Bracket, usize)> {
+    let negated = matches!(pattern.first(), Some(('!' | '^', false)));
+    let mut i = negated as usize;
+    let mut items = Vec::new();
+
+    loop {
+        let &(c, quoted) = pattern.get(i)?;
+
+        // a `]` right after the opening bracket is a literal
+        if c == ']' && !quoted && !items.is_empty() {
+            return Some((Bracket { negated, items }, i + 1));
+        }
+
+        if c == '[' && !quoted && pattern.get(i + 1) == Some(&(':', false)) {
+            let rest = &pattern[i + 2..];
+            if let Some(end) = rest.windows(2).position(|w| w[0].0 == ':' && w[1].0 == ']') {
+                let class = rest[..end].iter().map(|(c, _)| c).collect();
+                items.push(Item::Class(class));
+                i += 2 + end + 2;
+                continue;
+            }
+        }
+
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some(('-', _)), Some(&(to, to_quoted))) if to != ']' || to_quoted => {
+                items.push(Item::Range(c, to));
+                i += 3;
+            }
+            _ => {
+                items.push(Item::Char(c));
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn unquoted(s: &str) -> Vec<PatternChar> {
+        s.chars().map(|c| (c, false)).collect()
+    }
+
+    #[test]
+    fn matching() {
+        let golden = [
+            ("*", "foo", true),
+            ("*", ".hidden", false),
+            (".*", ".hidden", true),
+            ("*.rs", "main.rs", true),
+            ("*.rs", "main.rst", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("?", "é", true),
+            ("??", "a", false),
+            ("[abc]x", "bx", true),
+            ("[!abc]x", "bx", false),
+            ("[^abc]x", "dx", true),
+            ("[a-c]", "b", true),
+            ("[a-c]", "d", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+            ("[[:digit:]]*", "1st", true),
+            ("[[:upper:]]", "a", false),
+            ("[ab", "[ab", true),
+            ("**", "", true),
+        ];
+        for (pattern, name, expected) in golden {
+            assert_eq!(
+                expected,
+                matches(&unquoted(pattern), name),
+                "matching {name} against {pattern}"
+            );
+        }
+
+        let quoted = [('*', true), ('*', false)];
+        assert!(matches(&quoted, "*x"));
+        assert!(!matches(&quoted, "x"));
+    }
+
+    #[test]
+    fn expanding() {
+        let dir = TempDir::new("glob");
+        for path in [
+            "a.rs",
+            "b.rs",
+            "c.txt",
+            ".hidden.rs",
+            "sub/d.rs",
+            "sub/e.txt",
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let dir = dir.display();
+
+        let cancel = CancellationToken::default();
+        let expand = |pattern: &str| expand(&unquoted(&format!("{dir}/{pattern}")), &cancel);
+        let paths = |paths: &[&str]| {
+            paths
+                .iter()
+                .map(|path| format!("{dir}/{path}"))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(paths(&["a.rs", "b.rs"]), expand("*.rs"));
+        assert_eq!(paths(&["sub/d.rs"]), expand("*/*.rs"));
+        assert_eq!(paths(&["sub/"]), expand("*/"));
+        assert_eq!(paths(&["a.rs", "b.rs", "c.txt"]), expand("[a-c].*"));
+        assert_eq!(paths(&["sub/e.txt"]), expand("s?b/e.txt"));
+        assert!(expand("*.nothing").is_empty());
+        assert!(expand("sub/d.rs").is_empty());
+
+        cancel.cancel();
+        assert!(expand("*/*.rs").is_empty());
+    }
+}
@@ -1,23 +1,154 @@
-use std::fs;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::collections::{vec_deque, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fmt};
+
+use nix::fcntl::{flock, FlockArg};
 
 use crate::path::history_file;
 use crate::{Error, Result};
 
+/// The amount of entries read at a time from the history file, both when it is
+/// opened and when moving past the oldest or newest loaded entry.
+const PAGE_SIZE: usize = 1000;
+
+/// The maximum amount of entries kept in memory. Entries furthest from the
+/// ones being looked at are dropped, and read from the file again if needed.
+const MAX_LOADED: usize = 4 * PAGE_SIZE;
+
 pub trait History {
     fn prev(&mut self) -> Result<Option<&String>>;
     fn next(&mut self) -> Result<Option<&String>>;
     fn read(&mut self) -> Result<Option<&String>>;
     fn read_lines(&mut self) -> Result<Vec<String>>;
+
+    /// Returns all the entries, the oldest first, with when and where they
+    /// were entered where that is known.
+    fn read_records(&mut self) -> Result<Vec<Record>>;
     fn append(&mut self, line: &str) -> Result<()>;
     fn reload(&mut self) -> Result<()>;
     fn clear(&mut self) -> Result<()>;
+
+    /// Adds `entries`, such as those of another shell, to the end of the
+    /// history. Entries that are already in it, and those repeating the one
+    /// before, are left out, so that importing them again adds nothing.
+    /// Returns how many were added.
+    fn import(&mut self, entries: &[Record]) -> Result<usize>;
+
+    /// Returns the entries containing `query`, the most recent first. The
+    /// entries are searched lazily, so that only as much of the history as
+    /// needed to find a match is read.
+    fn search(&mut self, query: &str) -> Result<Box<dyn Iterator<Item = Result<String>>>>;
+}
+
+/// An entry of the history, with the time it was entered, in seconds since
+/// the Unix epoch, and the directory it was entered in. These are not known
+/// for imported entries, nor for those written by older versions of psh.
+///
+/// In the history file, they come before the command and are separated from
+/// it by a tab, as in `: 1700000000 /home/user\tmake`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub command: String,
+    pub time: Option<u64>,
+    pub dir: Option<PathBuf>,
+}
+
+impl Record {
+    /// The record of an imported `command`, entered at `time` in a directory
+    /// that is not known.
+    pub fn imported(command: impl Into<String>, time: Option<u64>) -> Self {
+        Self {
+            command: command.into(),
+            time,
+            dir: None,
+        }
+    }
+
+    /// The record of `command` entered now, in the current directory.
+    fn now(command: &str) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .ok();
+        // a directory that cannot be written on the line is left out
+        let dir = env::current_dir()
+            .ok()
+            .filter(|dir| dir.to_str().is_some_and(|dir| !dir.contains(['\t', '\n'])));
+
+        Self {
+            command: command.to_string(),
+            time,
+            dir,
+        }
+    }
+
+    /// The record of a `line` of the history file.
+    fn parse(line: &str) -> Self {
+        let plain = || Self {
+            command: line.to_string(),
+            time: None,
+            dir: None,
+        };
+
+        let Some((meta, command)) = line.strip_prefix(": ").and_then(|l| l.split_once('\t')) else {
+            return plain();
+        };
+        let (time, dir) = match meta.split_once(' ') {
+            Some((time, dir)) if dir.starts_with('/') => (time, Some(PathBuf::from(dir))),
+            Some(_) => return plain(),
+            None => (meta, None),
+        };
+        if time.is_empty() || !time.bytes().all(|b| b.is_ascii_digit()) {
+            return plain();
+        }
+
+        Self {
+            command: command.to_string(),
+            time: time.parse().ok(),
+            dir,
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.time, &self.dir) {
+            (Some(time), Some(dir)) => write!(f, ": {time} {}\t", dir.display())?,
+            (Some(time), None) => write!(f, ": {time}\t")?,
+            _ => {}
+        }
+        write!(f, "{}", self.command)
+    }
 }
 
+#[derive(Debug)]
+struct Entry {
+    /// The byte offset in the history file where the entry starts.
+    offset: u64,
+    /// The command of the entry, without when and where it was entered.
+    line: String,
+}
+
+/// The history, as stored in a file with one entry per line. Only a window of
+/// the most recently used part of the file is kept in memory, so that large
+/// history files do not slow down startup.
 pub struct FileHistory {
     pub path: PathBuf,
-    lines: Vec<String>,
+
+    /// The loaded entries, which are a contiguous part of the file.
+    entries: VecDeque<Entry>,
+
+    /// The byte offset in the file right after the last loaded entry.
+    end: u64,
+
+    /// The length of the file when it was last read, used to notice entries
+    /// appended by other shells.
+    len: u64,
+
     cursor: usize,
 }
 
@@ -30,26 +161,79 @@ impl FileHistory {
             fs::create_dir_all(path.parent().expect("could not get parent of history file"))?;
         }
 
-        // To read the amount of lines, and create the file if it does not yet exist.
-        let mut file = fs::OpenOptions::new()
-            .read(true)
+        Self::open(path)
+    }
+
+    /// Opens the history file at `path`, creating it if it does not yet exist,
+    /// and reads its most recent entries.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        fs::OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&path)?;
 
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer)?;
-        let lines = buffer
-            .trim()
-            .split('\n')
-            .map(ToString::to_string)
-            .collect::<Vec<_>>();
-
-        Ok(Self {
+        let mut history = Self {
             path,
-            cursor: lines.len(),
-            lines,
-        })
+            entries: Default::default(),
+            end: 0,
+            len: 0,
+            cursor: 0,
+        };
+        history.load_tail()?;
+
+        Ok(history)
+    }
+
+    /// Replaces the loaded entries with the most recent ones, and moves the
+    /// cursor past them.
+    fn load_tail(&mut self) -> Result<()> {
+        let mut file = File::open(&self.path)?;
+        self.len = file.metadata()?.len();
+        self.end = self.len;
+        self.entries = read_before(&mut file, self.len, PAGE_SIZE)?.into();
+        self.cursor = self.entries.len();
+        Ok(())
+    }
+
+    fn start(&self) -> u64 {
+        self.entries.front().map_or(self.end, |entry| entry.offset)
+    }
+
+    fn load_older(&mut self) -> Result<()> {
+        let mut file = File::open(&self.path)?;
+        let older = read_before(&mut file, self.start(), PAGE_SIZE)?;
+
+        self.cursor += older.len();
+        for entry in older.into_iter().rev() {
+            self.entries.push_front(entry);
+        }
+
+        while self.entries.len() > MAX_LOADED {
+            if let Some(entry) = self.entries.pop_back() {
+                self.end = entry.offset;
+            }
+        }
+        self.cursor = self.cursor.min(self.entries.len());
+
+        Ok(())
+    }
+
+    fn load_newer(&mut self) -> Result<()> {
+        let mut file = File::open(&self.path)?;
+        let (newer, end) = read_after(&mut file, self.end, PAGE_SIZE)?;
+
+        self.entries.extend(newer);
+        self.end = end;
+
+        while self.entries.len() > MAX_LOADED {
+            self.entries.pop_front();
+            self.cursor = self.cursor.saturating_sub(1);
+        }
+
+        Ok(())
     }
 }
 
@@ -59,56 +243,105 @@ impl History for FileHistory {
             .write(true)
             .open(&self.path)?
             .set_len(0)?;
-        self.reload()
+        self.load_tail()
     }
 
+    /// Picks up entries appended to the file since it was last read, e.g. by
+    /// another shell, without reading the rest of it again.
     fn reload(&mut self) -> Result<()> {
-        if !self.path.exists() {
-            self.lines = Default::default();
-        } else {
-            let contents = std::fs::read_to_string(&self.path)?;
-            self.lines = contents
-                .trim()
-                .split('\n')
-                .map(ToString::to_string)
-                .collect();
+        let len = match self.path.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+
+        if len < self.len {
+            return self.load_tail();
         }
-        Ok(())
-    }
 
-    fn append(&mut self, line: &str) -> Result<()> {
-        self.reload()?;
+        if len > self.len && self.end == self.len {
+            let at_end = self.cursor == self.entries.len();
+            let mut file = File::open(&self.path)?;
+            let (newer, end) = read_after(&mut file, self.end, usize::MAX)?;
 
-        self.lines.push(line.to_string());
-        self.cursor = self.lines.len();
+            self.entries.extend(newer);
+            self.end = end;
 
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&self.path)?;
-        file.write_all(self.lines.join("\n").as_bytes())?;
-        file.write_all(b"\n")?;
+            while self.entries.len() > MAX_LOADED {
+                self.entries.pop_front();
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+
+            if at_end {
+                self.cursor = self.entries.len();
+            }
+        }
+
+        self.len = len;
 
         Ok(())
     }
 
-    fn read_lines(&mut self) -> Result<Vec<String>> {
-        self.reload()?;
+    fn import(&mut self, entries: &[Record]) -> Result<usize> {
+        let mut file = open_locked(&self.path)?;
+        let known = fs::read(&self.path)?
+            .split(|&b| b == b'\n')
+            .map(|line| Record::parse(&String::from_utf8_lossy(line)).command)
+            .collect::<HashSet<_>>();
+
+        let mut imported = String::new();
+        let mut added = 0;
+        let mut previous = None;
+        for entry in entries {
+            let command = &entry.command;
+            if command.is_empty() || known.contains(command) || previous == Some(command) {
+                continue;
+            }
+            imported += &format!("{entry}\n");
+            added += 1;
+            previous = Some(command);
+        }
+        if added == 0 {
+            return Ok(0);
+        }
 
-        let prev_cursor = self.cursor;
-        self.cursor = 0;
+        // written at once, so that the entries of another shell appending at
+        // the same time do not end up in between
+        file.write_all(imported.as_bytes())?;
+        drop(file);
 
-        let mut vec = Vec::with_capacity(self.lines.len());
+        self.load_tail()?;
+        Ok(added)
+    }
 
-        if let Ok(Some(line)) = self.read() {
-            vec.push(line.clone());
+    fn append(&mut self, line: &str) -> Result<()> {
+        let mut file = open_locked(&self.path)?;
+        file.write_all(format!("{}\n", Record::now(line)).as_bytes())?;
+        drop(file);
+
+        if self.end == self.len {
+            self.reload()?;
+            self.cursor = self.entries.len();
+            Ok(())
+        } else {
+            self.load_tail()
         }
+    }
 
-        while let Ok(Some(line)) = self.next() {
-            vec.push(line.clone());
-        }
+    fn read_lines(&mut self) -> Result<Vec<String>> {
+        let records = self.read_records()?;
+        Ok(records.into_iter().map(|record| record.command).collect())
+    }
 
-        self.cursor = prev_cursor;
+    fn read_records(&mut self) -> Result<Vec<Record>> {
+        let file = File::open(&self.path)?;
+
+        let mut vec = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.is_empty() {
+                vec.push(Record::parse(&line));
+            }
+        }
 
         Ok(vec)
     }
@@ -116,17 +349,21 @@ impl History for FileHistory {
     fn read(&mut self) -> Result<Option<&String>> {
         self.reload()?;
 
-        if self.cursor >= self.lines.len() {
+        if self.cursor >= self.entries.len() {
             return Ok(None);
         }
 
-        match self.lines.get(self.cursor) {
-            Some(line) => Ok(Some(line)),
+        match self.entries.get(self.cursor) {
+            Some(entry) => Ok(Some(&entry.line)),
             None => Err(Error::HistoryOutOfBounds),
         }
     }
 
     fn prev(&mut self) -> Result<Option<&String>> {
+        self.reload()?;
+        if self.cursor == 0 && self.start() > 0 {
+            self.load_older()?;
+        }
         if self.cursor > 0 {
             self.cursor -= 1;
         }
@@ -134,29 +371,152 @@ impl History for FileHistory {
     }
 
     fn next(&mut self) -> Result<Option<&String>> {
-        if self.cursor < self.lines.len() {
+        self.reload()?;
+        if self.cursor + 1 >= self.entries.len() && self.end < self.len {
+            self.load_newer()?;
+        }
+        if self.cursor < self.entries.len() {
             self.cursor += 1;
         }
         self.read()
     }
+
+    fn search(&mut self, query: &str) -> Result<Box<dyn Iterator<Item = Result<String>>>> {
+        let file = File::open(&self.path)?;
+        let pos = file.metadata()?.len();
+        let entries = Backwards {
+            file,
+            pos,
+            page: Vec::new(),
+        };
+
+        let query = query.to_string();
+        let matches = entries.filter(move |line| match line {
+            Ok(line) => line.contains(&query),
+            Err(_) => true,
+        });
+
+        Ok(Box::new(matches))
+    }
 }
 
+/// Opens the history file at `path` for appending, locked until it is closed
+/// so that entries are not written by several shells at once.
+fn open_locked(path: &Path) -> Result<File> {
+    let file = fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)?;
+    flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+    Ok(file)
+}
+
+/// Reads the entries of the history file from the end towards the start, one
+/// page at a time.
+struct Backwards {
+    file: File,
+    pos: u64,
+    page: Vec<Entry>,
+}
+
+impl Iterator for Backwards {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.page.is_empty() && self.pos > 0 {
+            match read_before(&mut self.file, self.pos, PAGE_SIZE) {
+                Ok(page) => {
+                    self.pos = page.first().map_or(0, |entry| entry.offset);
+                    self.page = page;
+                }
+                Err(e) => {
+                    self.pos = 0;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.page.pop().map(|entry| Ok(entry.line))
+    }
+}
+
+/// Reads at most `max` entries ending right before byte `pos` of `file`, in
+/// the order they appear in the file.
+fn read_before(file: &mut File, pos: u64, max: usize) -> Result<Vec<Entry>> {
+    let mut start = pos;
+    let mut buf = Vec::new();
+    let mut chunk_size = 8 * 1024;
+
+    // read backwards until there are more than `max` line breaks, since the
+    // first line is likely only partially read
+    while start > 0 && buf.iter().filter(|&&b| b == b'\n').count() <= max {
+        let chunk_start = start.saturating_sub(chunk_size);
+        let mut chunk = vec![0; (start - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(&mut chunk)?;
+
+        chunk.append(&mut buf);
+        buf = chunk;
+        start = chunk_start;
+        chunk_size *= 2;
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = start;
+    for (i, line) in buf.split(|&b| b == b'\n').enumerate() {
+        let complete = i > 0 || start == 0;
+        if complete && !line.is_empty() {
+            entries.push(Entry {
+                offset,
+                line: Record::parse(&String::from_utf8_lossy(line)).command,
+            });
+        }
+        offset += line.len() as u64 + 1;
+    }
+
+    let skip = entries.len().saturating_sub(max);
+    Ok(entries.split_off(skip))
+}
+
+/// Reads at most `max` entries starting at byte `pos` of `file`. Returns the
+/// entries and the byte offset right after the last of them.
+fn read_after(file: &mut File, pos: u64, max: usize) -> Result<(Vec<Entry>, u64)> {
+    file.seek(SeekFrom::Start(pos))?;
+    let mut reader = BufReader::new(file);
+
+    let mut entries = Vec::new();
+    let mut offset = pos;
+    let mut buf = Vec::new();
+    while entries.len() < max {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        if !line.is_empty() {
+            entries.push(Entry {
+                offset,
+                line: Record::parse(&String::from_utf8_lossy(line)).command,
+            });
+        }
+        offset += read as u64;
+    }
+
+    Ok((entries, offset))
+}
+
+/// Iterates over the entries currently loaded in memory.
 pub struct FileHistoryIntoIterator {
-    history: FileHistory,
-    index: usize,
+    entries: vec_deque::IntoIter<Entry>,
 }
 
 impl Iterator for FileHistoryIntoIterator {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.history.lines.len() - 1 {
-            return None;
-        }
-
-        let entry = self.history.lines.swap_remove(self.index);
-        self.index += 1;
-        Some(entry)
+        self.entries.next().map(|entry| entry.line)
     }
 }
 
@@ -167,8 +527,7 @@ impl IntoIterator for FileHistory {
 
     fn into_iter(self) -> Self::IntoIter {
         Self::IntoIter {
-            history: self,
-            index: 0,
+            entries: self.entries.into_iter(),
         }
     }
 }
@@ -192,6 +551,10 @@ impl History for DummyHistory {
         Ok(vec![])
     }
 
+    fn read_records(&mut self) -> Result<Vec<Record>> {
+        Ok(vec![])
+    }
+
     fn append(&mut self, _line: &str) -> Result<()> {
         Ok(())
     }
@@ -203,4 +566,165 @@ impl History for DummyHistory {
     fn clear(&mut self) -> Result<()> {
         Ok(())
     }
+
+    fn import(&mut self, _entries: &[Record]) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn search(&mut self, _query: &str) -> Result<Box<dyn Iterator<Item = Result<String>>>> {
+        Ok(Box::new(std::iter::empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// A history file of `entries` lines, in a directory of its own.
+    struct TempHistory {
+        path: PathBuf,
+        _dir: TempDir,
+    }
+
+    impl TempHistory {
+        fn new(name: &str, entries: usize) -> Self {
+            let dir = TempDir::new(&format!("history-{name}"));
+            let path = dir.join("history");
+            let content = (0..entries)
+                .map(|i| format!("entry {i}\n"))
+                .collect::<String>();
+            fs::write(&path, content).unwrap();
+            Self { path, _dir: dir }
+        }
+    }
+
+    #[test]
+    fn only_recent_entries_are_loaded() {
+        let file = TempHistory::new("recent", 3 * PAGE_SIZE);
+        let mut history = FileHistory::open(&file.path).unwrap();
+        assert_eq!(PAGE_SIZE, history.entries.len());
+
+        let last = format!("entry {}", 3 * PAGE_SIZE - 1);
+        assert_eq!(Some(&last), history.prev().unwrap());
+    }
+
+    #[test]
+    fn older_entries_are_loaded_on_demand() {
+        let n = MAX_LOADED + PAGE_SIZE + 10;
+        let file = TempHistory::new("older", n);
+        let mut history = FileHistory::open(&file.path).unwrap();
+
+        for _ in 0..n - 1 {
+            history.prev().unwrap();
+        }
+        assert_eq!(Some(&"entry 0".to_string()), history.prev().unwrap());
+        assert_eq!(Some(&"entry 0".to_string()), history.prev().unwrap());
+        assert_eq!(Some(&"entry 1".to_string()), history.next().unwrap());
+        assert!(history.entries.len() <= MAX_LOADED);
+
+        for _ in 0..n - 2 {
+            history.next().unwrap();
+        }
+        let last = format!("entry {}", n - 1);
+        assert_eq!(Some(&last), history.read().unwrap());
+        assert_eq!(None, history.next().unwrap());
+        assert!(history.entries.len() <= MAX_LOADED);
+    }
+
+    #[test]
+    fn appends_are_picked_up() {
+        let file = TempHistory::new("append", 10);
+        let mut history = FileHistory::open(&file.path).unwrap();
+        let mut other = FileHistory::open(&file.path).unwrap();
+
+        history.append("echo foo").unwrap();
+        other.append("echo bar").unwrap();
+
+        assert_eq!(Some(&"echo bar".to_string()), history.prev().unwrap());
+        assert_eq!(Some(&"echo foo".to_string()), history.prev().unwrap());
+        assert_eq!(12, history.read_lines().unwrap().len());
+    }
+
+    #[test]
+    fn imports_are_appended() {
+        let file = TempHistory::new("import", 2);
+        let mut history = FileHistory::open(&file.path).unwrap();
+
+        let entries = [
+            ("ls", Some(1)),
+            ("ls", None),
+            ("entry 1", None),
+            ("make", None),
+        ]
+        .map(|(command, time)| Record::imported(command, time));
+        assert_eq!(2, history.import(&entries).unwrap());
+        assert_eq!(
+            vec!["entry 0", "entry 1", "ls", "make"],
+            history.read_lines().unwrap()
+        );
+        assert_eq!(Some(1), history.read_records().unwrap()[2].time);
+        assert_eq!(Some(&"make".to_string()), history.prev().unwrap());
+
+        assert_eq!(0, history.import(&entries).unwrap());
+    }
+
+    #[test]
+    fn imports_keep_a_symlinked_file() {
+        let file = TempHistory::new("import-symlink", 1);
+        let link = file.path.with_file_name("link");
+        std::os::unix::fs::symlink(&file.path, &link).unwrap();
+        let mut history = FileHistory::open(&link).unwrap();
+
+        history.import(&[Record::imported("ls", None)]).unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!("entry 0\nls\n", fs::read_to_string(&file.path).unwrap());
+    }
+
+    #[test]
+    fn records_keep_time_and_dir() {
+        let file = TempHistory::new("records", 1);
+        let mut history = FileHistory::open(&file.path).unwrap();
+        history.append("make\ttest").unwrap();
+
+        let records = history.read_records().unwrap();
+        assert_eq!(
+            Record {
+                command: "entry 0".to_string(),
+                time: None,
+                dir: None
+            },
+            records[0]
+        );
+        assert_eq!("make\ttest", records[1].command);
+        assert!(records[1].time.is_some());
+        assert_eq!(env::current_dir().ok(), records[1].dir);
+
+        assert_eq!(Some(&"make\ttest".to_string()), history.prev().unwrap());
+        assert_eq!(vec!["entry 0", "make\ttest"], history.read_lines().unwrap());
+
+        let line = ": 1700000000 /home/user dir\tls";
+        assert_eq!(line, Record::parse(line).to_string());
+        assert_eq!(Some(1700000000), Record::parse(": 1700000000\tls").time);
+        for line in [": echo\tx", ": 17x\tls", ": 1 dir\tls", ":  1\tls"] {
+            assert_eq!(line, Record::parse(line).command);
+        }
+    }
+
+    #[test]
+    fn search_covers_the_whole_file() {
+        let file = TempHistory::new("search", 3 * PAGE_SIZE);
+        let mut history = FileHistory::open(&file.path).unwrap();
+
+        let matches = history
+            .search("entry 1")
+            .unwrap()
+            .take(3)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(vec!["entry 1999", "entry 1998", "entry 1997"], matches);
+
+        let oldest = history.search("entry 0").unwrap().next();
+        assert_eq!(Some("entry 0".to_string()), oldest.transpose().unwrap());
+    }
 }
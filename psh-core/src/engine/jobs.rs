@@ -0,0 +1,549 @@
+//! Pipelines running in the background, and the output they have written if
+//! the `capture_bg` option was set when they were started. Coprocesses are
+//! jobs as well, which keep the pipes to them open until they are forgotten.
+//!
+//! With job control, every pipeline runs in a process group of its own and
+//! is a job, including the ones in the foreground, which are forgotten once
+//! they finish unless they were stopped with ^Z.
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{FromRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use nix::errno::Errno;
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{close, Pid};
+
+use crate::{ExitStatus, Result};
+
+/// The amount of bytes kept of the output of a job. Anything written before
+/// that is dropped.
+const MAX_OUTPUT: usize = 1 << 20;
+
+/// The status of a job that was waited for, or `None` if it was stopped, and
+/// the processes of it that have finished, with their statuses.
+pub type Waited = (Option<ExitStatus>, Vec<(Pid, ExitStatus)>);
+
+/// The output of a job, which is collected in a thread for as long as the job
+/// keeps the pipe it writes to open.
+#[derive(Debug, Clone, Default)]
+pub struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    /// Starts collecting everything that can be read from `fd`, which is
+    /// closed once everything has been read.
+    pub fn capture(fd: RawFd) -> Self {
+        let output = Self::default();
+        let buffer = Arc::clone(&output.0);
+
+        thread::spawn(move || {
+            // SAFETY: the fd is handed over to this thread, which closes it
+            //         when the file is dropped
+            let mut file = unsafe { File::from_raw_fd(fd) };
+            let mut chunk = [0; 4096];
+
+            while let Ok(n @ 1..) = file.read(&mut chunk) {
+                let Ok(mut buffer) = buffer.lock() else {
+                    break;
+                };
+                buffer.extend_from_slice(&chunk[..n]);
+                if buffer.len() > MAX_OUTPUT {
+                    let excess = buffer.len() - MAX_OUTPUT;
+                    buffer.drain(..excess);
+                }
+            }
+        });
+
+        output
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        match self.0.lock() {
+            Ok(buffer) => buffer.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// The ends of the pipes connected to a coprocess that belong to the shell.
+/// They are closed when the job is forgotten.
+#[derive(Debug)]
+pub struct Coprocess {
+    pub name: String,
+
+    /// The read end of the pipe connected to its stdout.
+    pub read: RawFd,
+
+    /// The write end of the pipe connected to its stdin.
+    pub write: RawFd,
+}
+
+impl Drop for Coprocess {
+    fn drop(&mut self) {
+        let _ = close(self.read);
+        let _ = close(self.write);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    Stopped,
+    Done(ExitStatus),
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    pub state: State,
+
+    /// The captured output, if `capture_bg` was set.
+    pub output: Option<Output>,
+
+    pub coprocess: Option<Coprocess>,
+
+    /// The process group of the job, if it was started with job control.
+    pub pgid: Option<Pid>,
+
+    /// The processes of the pipeline, and their status once they have
+    /// finished.
+    processes: Vec<(Pid, Option<ExitStatus>)>,
+
+    /// Whether the status of the job is that of its last process that
+    /// failed, see `pipefail` in `Options`.
+    pipefail: bool,
+
+    /// Whether the user has been told that the job finished.
+    reported: bool,
+}
+
+impl fmt::Display for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            State::Running => "Running".to_string(),
+            State::Stopped => "Stopped".to_string(),
+            State::Done(status) if status.is_ok() => "Done".to_string(),
+            State::Done(status) => format!("Exit {}", status.to_string()),
+        };
+        let captured = match self.output {
+            Some(_) => " (output captured)",
+            None => "",
+        };
+        write!(f, "[{}]  {state:<10} {}{captured}", self.id, self.command)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Jobs {
+    jobs: Vec<Job>,
+
+    /// The names of the coprocesses that have been forgotten since the last
+    /// call to `take_closed`.
+    closed: Vec<String>,
+}
+
+impl Job {
+    /// The status of the job once every process has finished, or `None` if
+    /// it was stopped.
+    fn status(&self) -> Option<ExitStatus> {
+        match self.state {
+            State::Done(status) => Some(status),
+            State::Running | State::Stopped => None,
+        }
+    }
+
+    /// The processes of the job that have not finished.
+    pub fn pids(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.processes
+            .iter()
+            .filter(|(_, status)| status.is_none())
+            .map(|&(pid, _)| pid)
+    }
+
+    fn is_done(&self) -> bool {
+        matches!(self.state, State::Done(_))
+    }
+
+    /// Records that `pid` has changed state, updating the state of the job.
+    fn record(&mut self, pid: Pid, wait_status: WaitStatus) {
+        let status = match wait_status {
+            WaitStatus::Exited(_, code) => ExitStatus::from_code(code),
+            WaitStatus::Signaled(_, signal, _) => ExitStatus::from_code(128 + signal as i32),
+            WaitStatus::Stopped(..) => {
+                self.state = State::Stopped;
+                return;
+            }
+            WaitStatus::Continued(_) => {
+                self.state = State::Running;
+                return;
+            }
+            _ => return,
+        };
+
+        if let Some((_, process)) = self.processes.iter_mut().find(|(p, _)| *p == pid) {
+            *process = Some(status);
+        }
+
+        // like in the foreground, the status of the pipeline is the status
+        // of its last command, or of its last failed one with `pipefail`
+        if self.processes.iter().all(|(_, status)| status.is_some()) {
+            let mut statuses = self.processes.iter().filter_map(|(_, status)| *status);
+            let failed = statuses.rfind(|status| !status.is_ok());
+            if let Some(&(_, Some(status))) = self.processes.last() {
+                self.state = State::Done(match self.pipefail {
+                    true => failed.unwrap_or(status),
+                    false => status,
+                });
+            }
+        }
+    }
+
+    /// Waits until every process of the job has finished, or the job has
+    /// been stopped. A process stopped for reading from or writing to the
+    /// terminal before it was handed the terminal is continued.
+    fn wait(&mut self) -> Result<()> {
+        while self.state == State::Running {
+            let Some(&(pid, _)) = self.processes.iter().find(|(_, status)| status.is_none()) else {
+                break;
+            };
+
+            let wait_status = match waitpid(pid, Some(WaitPidFlag::WUNTRACED)) {
+                Err(Errno::EINTR) => continue,
+                // e.g. already reaped by someone else
+                Err(Errno::ECHILD) => WaitStatus::Exited(pid, 0),
+                wait_status => wait_status?,
+            };
+
+            match (wait_status, self.pgid) {
+                (WaitStatus::Stopped(_, Signal::SIGTTIN | Signal::SIGTTOU), Some(pgid)) => {
+                    killpg(pgid, Signal::SIGCONT)?;
+                }
+                (wait_status, _) => self.record(pid, wait_status),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Jobs {
+    /// Adds a job consisting of the processes `pids`, returning its id.
+    pub fn add(
+        &mut self,
+        command: String,
+        pids: Vec<Pid>,
+        pgid: Option<Pid>,
+        output: Option<Output>,
+        pipefail: bool,
+    ) -> usize {
+        self.push(command, pids, pgid, output, None, pipefail)
+    }
+
+    /// Adds a job for the coprocess `pid`, returning its id.
+    pub fn add_coprocess(
+        &mut self,
+        command: String,
+        pid: Pid,
+        pgid: Option<Pid>,
+        coprocess: Coprocess,
+    ) -> usize {
+        self.push(command, vec![pid], pgid, None, Some(coprocess), false)
+    }
+
+    fn push(
+        &mut self,
+        command: String,
+        pids: Vec<Pid>,
+        pgid: Option<Pid>,
+        output: Option<Output>,
+        coprocess: Option<Coprocess>,
+        pipefail: bool,
+    ) -> usize {
+        let id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        self.jobs.push(Job {
+            id,
+            command,
+            state: State::Running,
+            output,
+            coprocess,
+            pgid,
+            processes: pids.into_iter().map(|pid| (pid, None)).collect(),
+            pipefail,
+            reported: false,
+        });
+        id
+    }
+
+    /// Waits for the job `id` to finish or be stopped. A finished job is
+    /// forgotten, and its status returned. If it was stopped, `None` is
+    /// returned.
+    pub fn wait(&mut self, id: usize) -> Result<Option<ExitStatus>> {
+        self.wait_processes(id).map(|(status, _)| status)
+    }
+
+    /// Like `wait`, but also returns the processes of the job that have
+    /// finished, with their statuses, as they are gone once it is forgotten.
+    pub fn wait_processes(&mut self, id: usize) -> Result<Waited> {
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else {
+            return Ok((None, Vec::new()));
+        };
+
+        job.wait()?;
+        let finished = job
+            .processes
+            .iter()
+            .filter_map(|&(pid, status)| Some((pid, status?)))
+            .collect();
+        if job.state == State::Stopped {
+            return Ok((None, finished));
+        }
+
+        let status = job.status();
+        job.reported = true;
+        self.forget(|job| job.id == id && job.output.is_none());
+        Ok((status, finished))
+    }
+
+    /// Continues the stopped job `id`, in the background unless it is waited
+    /// for afterwards.
+    pub fn resume(&mut self, id: usize) -> Result<()> {
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else {
+            return Ok(());
+        };
+
+        match job.pgid {
+            Some(pgid) => killpg(pgid, Signal::SIGCONT)?,
+            None => {
+                for (pid, _) in job.processes.iter().filter(|(_, status)| status.is_none()) {
+                    kill(*pid, Signal::SIGCONT)?;
+                }
+            }
+        }
+        if job.state == State::Stopped {
+            job.state = State::Running;
+        }
+        Ok(())
+    }
+
+    /// The job of the coprocess called `name`, if it has not been forgotten.
+    pub fn coprocess(&self, name: &str) -> Option<&Job> {
+        self.jobs
+            .iter()
+            .find(|job| matches!(&job.coprocess, Some(coprocess) if coprocess.name == name))
+    }
+
+    /// The names of the coprocesses that have been forgotten, and whose pipes
+    /// have been closed, since the last call.
+    pub fn take_closed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.closed)
+    }
+
+    /// Looks up a job by its id, optionally prefixed by `%`. Without a
+    /// spec, the most recently started job is returned.
+    pub fn get(&self, spec: Option<&str>) -> Option<&Job> {
+        match spec {
+            Some(spec) => {
+                let id = spec
+                    .strip_prefix('%')
+                    .unwrap_or(spec)
+                    .parse::<usize>()
+                    .ok()?;
+                self.by_id(id)
+            }
+            None => self.jobs.last(),
+        }
+    }
+
+    pub fn by_id(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    /// The job that the process `pid` is part of.
+    pub fn by_pid(&self, pid: Pid) -> Option<&Job> {
+        self.jobs
+            .iter()
+            .find(|job| job.processes.iter().any(|(p, _)| *p == pid))
+    }
+
+    /// Like `get`, but without a spec the most recently stopped job is
+    /// preferred, as the one most likely to be continued.
+    pub fn current(&self, spec: Option<&str>) -> Option<&Job> {
+        match spec {
+            Some(_) => self.get(spec),
+            None => self
+                .jobs
+                .iter()
+                .rev()
+                .find(|job| job.state == State::Stopped)
+                .or_else(|| self.jobs.last()),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// Reaps the processes that have finished, and notices the ones that
+    /// have been stopped or continued, without blocking.
+    pub fn update(&mut self) {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+
+        for job in &mut self.jobs {
+            let pids = job
+                .processes
+                .iter()
+                .filter(|(_, status)| status.is_none())
+                .map(|(pid, _)| *pid)
+                .collect::<Vec<_>>();
+
+            for pid in pids {
+                match waitpid(pid, Some(flags)) {
+                    Ok(WaitStatus::StillAlive) => {}
+                    Ok(wait_status) => job.record(pid, wait_status),
+                    // e.g. already reaped by someone else
+                    Err(_) => job.record(pid, WaitStatus::Exited(pid, 0)),
+                }
+            }
+        }
+    }
+
+    /// Describes every job, forgetting the ones that have finished.
+    pub fn list(&mut self) -> Vec<String> {
+        self.update();
+        let lines = self.jobs.iter().map(Job::to_string).collect();
+        self.mark_reported(|_| true);
+        lines
+    }
+
+    /// Describes the jobs that have finished since the last call, and then
+    /// forgets them.
+    pub fn notify(&mut self) -> Vec<String> {
+        self.update();
+        let lines = self
+            .jobs
+            .iter()
+            .filter(|job| job.is_done() && !job.reported)
+            .map(Job::to_string)
+            .collect();
+        self.mark_reported(|_| true);
+        lines
+    }
+
+    /// Forgets the job `id` if it has finished, once its captured output has
+    /// been shown.
+    pub fn output_shown(&mut self, id: usize) {
+        self.mark_reported(|job| job.id == id);
+        self.forget(|job| job.id == id && job.is_done());
+    }
+
+    /// Marks the finished jobs matching `predicate` as reported, and forgets
+    /// them, unless there is captured output that has not been shown yet.
+    fn mark_reported<P>(&mut self, predicate: P)
+    where
+        P: Fn(&Job) -> bool,
+    {
+        for job in &mut self.jobs {
+            if job.is_done() && predicate(job) {
+                job.reported = true;
+            }
+        }
+        self.forget(|job| job.reported && job.output.is_none());
+    }
+
+    fn forget<P>(&mut self, predicate: P)
+    where
+        P: Fn(&Job) -> bool,
+    {
+        let (forgotten, jobs) = std::mem::take(&mut self.jobs)
+            .into_iter()
+            .partition::<Vec<_>, _>(predicate);
+        self.jobs = jobs;
+
+        let names = forgotten
+            .into_iter()
+            .filter_map(|job| job.coprocess.map(|coprocess| coprocess.name.clone()));
+        self.closed.extend(names);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use nix::unistd::{close, pipe};
+
+    use super::*;
+    use crate::engine::util;
+
+    #[test]
+    fn finished_jobs_are_reported_once() {
+        let (read, write) = pipe().unwrap();
+        let child = util::spawn_subshell(|| {
+            nix::unistd::write(write, b"out")?;
+            std::process::exit(3);
+        })
+        .unwrap();
+        close(write).unwrap();
+
+        let mut jobs = Jobs::default();
+        let id = jobs.add(
+            "cmd".to_string(),
+            vec![child],
+            None,
+            Some(Output::capture(read)),
+            false,
+        );
+        assert_eq!(1, id);
+
+        let started = Instant::now();
+        let mut notified = Vec::new();
+        while notified.is_empty() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+            notified = jobs.notify();
+        }
+        assert_eq!(vec!["[1]  Exit 3     cmd (output captured)"], notified);
+        assert!(jobs.notify().is_empty());
+
+        // kept until the output has been shown
+        let output = jobs.get(Some("%1")).unwrap().output.clone().unwrap();
+        while output.contents().is_empty() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(b"out".to_vec(), output.contents());
+        jobs.output_shown(1);
+        assert!(jobs.get(None).is_none());
+    }
+
+    #[test]
+    fn stopped_jobs_are_kept_until_they_finish() {
+        let child = util::spawn_subshell(|| {
+            thread::sleep(Duration::from_secs(10));
+            Ok(())
+        })
+        .unwrap();
+
+        let mut jobs = Jobs::default();
+        let id = jobs.add("sleep".to_string(), vec![child], None, None, false);
+
+        kill(child, Signal::SIGSTOP).unwrap();
+        assert_eq!(None, jobs.wait(id).unwrap());
+        assert_eq!(State::Stopped, jobs.by_id(id).unwrap().state);
+        assert!(jobs.notify().is_empty());
+        assert_eq!(vec!["[1]  Stopped    sleep"], jobs.list());
+
+        jobs.resume(id).unwrap();
+        assert_eq!(State::Running, jobs.by_id(id).unwrap().state);
+
+        kill(child, Signal::SIGTERM).unwrap();
+        let status = jobs.wait(id).unwrap();
+        assert_eq!(
+            Some(ExitStatus::from_code(128 + Signal::SIGTERM as i32)),
+            status
+        );
+        assert!(jobs.by_id(id).is_none());
+    }
+}
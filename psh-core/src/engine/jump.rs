@@ -0,0 +1,296 @@
+//! A database of visited directories, ranked by how frequently and recently
+//! they were visited, used by the `j` builtin to jump to them by a part of
+//! their name.
+//!
+//! The database is kept up to date by a `chpwd` hook, which only the
+//! interactive shell registers, so that scripts do not fill it with the
+//! directories they pass through. It is stored at the path in
+//! `$PSH_JUMP_DB`, by default `~/.config/psh/jump`, with one directory per
+//! line, prefixed by its rank and the time of the last visit.
+
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::log;
+use crate::{path, Engine, Result};
+
+/// Once the ranks of all directories add up to more than this, they are aged
+/// so that directories that are no longer visited eventually fall out.
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+/// How much the ranks are multiplied by when aged.
+const AGING: f64 = 0.99;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub dir: PathBuf,
+    pub rank: f64,
+
+    /// The time of the last visit, in seconds since the epoch.
+    pub time: u64,
+}
+
+impl Entry {
+    /// The rank weighted by how long ago the directory was last visited.
+    pub fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.time);
+        let weight = match age {
+            age if age < HOUR => 4.0,
+            age if age < DAY => 2.0,
+            age if age < WEEK => 0.5,
+            _ => 0.25,
+        };
+        self.rank * weight
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JumpDb {
+    path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+impl JumpDb {
+    /// The database used by `engine`.
+    pub fn of(engine: &Engine) -> Result<Self> {
+        let path = match engine.get_value_of("PSH_JUMP_DB") {
+            Some(path) if !path.is_empty() => PathBuf::from(path),
+            _ => path::jump_file(),
+        };
+        Self::open(path)
+    }
+
+    /// Reads the database at `path`, which does not have to exist yet.
+    /// Malformed lines are ignored.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entries = content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let rank = fields.next()?.parse().ok()?;
+                let time = fields.next()?.parse().ok()?;
+                let dir = PathBuf::from(fields.next()?);
+                Some(Entry { dir, rank, time })
+            })
+            .collect();
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write to a temporary file first, so that a shell exiting halfway
+        // through, or another one saving at the same time, can not leave
+        // behind a truncated database
+        let tmp = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+        let mut file = fs::File::create(&tmp)?;
+        for entry in &self.entries {
+            writeln!(
+                file,
+                "{}\t{}\t{}",
+                entry.rank,
+                entry.time,
+                entry.dir.display()
+            )?;
+        }
+        drop(file);
+
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Records a visit to `dir`.
+    pub fn visit(&mut self, dir: &Path, now: u64) {
+        // the format is line based
+        if dir.to_string_lossy().contains('\n') {
+            return;
+        }
+
+        match self.entries.iter_mut().find(|entry| entry.dir == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.time = now;
+            }
+            None => self.entries.push(Entry {
+                dir: dir.to_path_buf(),
+                rank: 1.0,
+                time: now,
+            }),
+        }
+
+        if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for entry in &mut self.entries {
+                entry.rank *= AGING;
+            }
+            self.entries.retain(|entry| entry.rank >= 1.0);
+        }
+    }
+
+    /// Removes `dir` from the database. Returns whether it was in it.
+    pub fn remove(&mut self, dir: &Path) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.dir != dir);
+        self.entries.len() != len
+    }
+
+    /// The entries matching all of `patterns`, best match first. A directory
+    /// matches if the patterns occur in its path in the given order, and the
+    /// last one occurs in its final component. Matching is case-sensitive,
+    /// unless nothing matches that way.
+    pub fn matches(&self, patterns: &[&str], now: u64) -> Vec<&Entry> {
+        let mut matches = self.matches_with(patterns, now, |s| s.to_string());
+        if matches.is_empty() {
+            matches = self.matches_with(patterns, now, |s| s.to_lowercase());
+        }
+        matches
+    }
+
+    fn matches_with<F>(&self, patterns: &[&str], now: u64, fold: F) -> Vec<&Entry>
+    where
+        F: Fn(&str) -> String,
+    {
+        let patterns = patterns.iter().map(|p| fold(p)).collect::<Vec<_>>();
+
+        let mut matches = self
+            .entries
+            .iter()
+            .filter(|entry| {
+                let dir = fold(&entry.dir.to_string_lossy());
+                let name = fold(&entry.dir.file_name().unwrap_or_default().to_string_lossy());
+                matches_in_order(&dir, &patterns)
+                    && patterns
+                        .last()
+                        .is_none_or(|last| name.contains(last.as_str()))
+            })
+            .collect::<Vec<_>>();
+
+        matches.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        matches
+    }
+}
+
+fn matches_in_order(mut s: &str, patterns: &[String]) -> bool {
+    for pattern in patterns {
+        match s.find(pattern.as_str()) {
+            Some(i) => s = &s[i + pattern.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The current time in seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A `chpwd` hook recording the new working directory in the database. A
+/// database that cannot be read or written, as under a read-only home, is
+/// only noted in the error log, instead of being reported on every `cd`.
+pub fn record(engine: &mut Engine, dir: &Path) -> Result<()> {
+    let recorded = JumpDb::of(engine).and_then(|mut db| {
+        db.visit(dir, now());
+        db.save()
+    });
+    if let Err(e) = recorded {
+        engine.log(log::Kind::Exec, format!("chpwd: {e}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn ranks_by_frecency() {
+        let dir = TempDir::new("jump");
+        let path = dir.join("jump");
+        let now = 10 * WEEK;
+
+        let mut db = JumpDb::open(&path).unwrap();
+        for _ in 0..5 {
+            db.visit(Path::new("/home/user/src/project"), now - 2 * WEEK);
+        }
+        db.visit(Path::new("/home/user/src/project/docs"), now);
+        db.visit(Path::new("/home/user/Projects/other"), now);
+        db.save().unwrap();
+
+        let mut db = JumpDb::open(&path).unwrap();
+        assert_eq!(3, db.entries.len());
+
+        let dirs = |db: &JumpDb, patterns: &[&str]| {
+            db.matches(patterns, now)
+                .into_iter()
+                .map(|entry| entry.dir.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // visits from two weeks ago count for a sixteenth of recent ones
+        assert_eq!(
+            vec![
+                "/home/user/src/project/docs",
+                "/home/user/Projects/other",
+                "/home/user/src/project",
+            ],
+            dirs(&db, &[])
+        );
+        assert_eq!(vec!["/home/user/src/project"], dirs(&db, &["proj"]));
+        assert_eq!(vec!["/home/user/Projects/other"], dirs(&db, &["Proj", "o"]));
+        assert_eq!(
+            vec!["/home/user/Projects/other"],
+            dirs(&db, &["projects", "o"])
+        );
+        assert_eq!(
+            vec!["/home/user/src/project/docs"],
+            dirs(&db, &["proj", "do"])
+        );
+        assert!(dirs(&db, &["do", "proj"]).is_empty());
+
+        assert!(db.remove(Path::new("/home/user/src/project")));
+        assert!(!db.remove(Path::new("/home/user/src/project")));
+        assert_eq!(2, db.entries.len());
+    }
+
+    #[test]
+    fn aging_drops_unused_directories() {
+        let mut db = JumpDb {
+            path: PathBuf::new(),
+            entries: vec![Entry {
+                dir: PathBuf::from("/old"),
+                rank: 1.0,
+                time: 0,
+            }],
+        };
+
+        for _ in 0..MAX_TOTAL_RANK as usize {
+            db.visit(Path::new("/new"), 0);
+        }
+
+        assert_eq!(1, db.entries.len());
+        assert_eq!(PathBuf::from("/new"), db.entries[0].dir);
+    }
+}
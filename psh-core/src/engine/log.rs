@@ -0,0 +1,191 @@
+//! The error log: when `$PSH_LOG` is set to a file, every error psh reports
+//! is also appended to it, one line each, with when and in which process it
+//! happened. Meant for tracking down failures that are hard to reproduce.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::quote;
+use crate::Error;
+
+/// Where an error comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The input could not be parsed.
+    Parse,
+
+    /// A command could not be run.
+    Exec,
+
+    /// The terminal could not be read from or drawn to.
+    Terminal,
+}
+
+impl Kind {
+    /// The kind of an error returned when running a command.
+    pub fn of(e: &Error) -> Self {
+        match e.inner() {
+            Error::SyntaxError(..) | Error::ParseError(_) | Error::Incomplete(_) => Kind::Parse,
+            _ => Kind::Exec,
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Parse => write!(f, "parse"),
+            Kind::Exec => write!(f, "exec"),
+            Kind::Terminal => write!(f, "terminal"),
+        }
+    }
+}
+
+/// Appends the error `message` to the log at `path`.
+pub fn append(path: &Path, kind: Kind, message: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    let line = line(SystemTime::now(), std::process::id(), kind, message);
+    file.write_all(line.as_bytes())
+}
+
+/// A line of the log, in which `message` is kept on one line.
+fn line(time: SystemTime, pid: u32, kind: Kind, message: &str) -> String {
+    format!(
+        "{} {pid} {kind}: {}\n",
+        timestamp(time),
+        quote::escape_controls(message)
+    )
+}
+
+/// `time` as in RFC 3339, in UTC and with milliseconds.
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let secs = secs % 86400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// The date that is `days` days after 1970-01-01, by Howard Hinnant's
+/// `civil_from_days`.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The days from 1970-01-01 to the date, which must not be before it, by
+/// Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The days from 1970-01-01 to `date`, given as `YYYY-MM-DD`.
+pub(crate) fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.split('-');
+    let mut part = |len: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|part| part.parse::<u64>().ok())
+    };
+    let (year, month, day) = (part(4)?, part(2)?, part(2)?);
+    if parts.next().is_some() || year < 1970 || !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+
+    // a day past the end of the month, such as 02-30, would end up in the next
+    let days = days_from_civil(year, month, day);
+    (civil_from_days(days) == (year, month, day)).then_some(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn timestamps() {
+        let at = |secs, millis| {
+            timestamp(UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis))
+        };
+        assert_eq!("1970-01-01T00:00:00.000Z", at(0, 0));
+        assert_eq!("2000-02-29T23:59:59.042Z", at(951868799, 42));
+        assert_eq!("2024-12-31T12:30:05.999Z", at(1735648205, 999));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(Some(0), parse_date("1970-01-01"));
+        assert_eq!(Some(951868799 / 86400), parse_date("2000-02-29"));
+        assert_eq!(Some(1735648205 / 86400), parse_date("2024-12-31"));
+        for date in [
+            "2023-02-29",
+            "1969-12-31",
+            "2024-1-01",
+            "2024-13-01",
+            "2024-01-01-",
+        ] {
+            assert_eq!(None, parse_date(date), "{date}");
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        let at = |e| Error::At("init.psh".to_string(), 1, Box::new(e));
+        assert_eq!(
+            Kind::Parse,
+            Kind::of(&at(Error::Incomplete("(".to_string())))
+        );
+        assert_eq!(Kind::Exec, Kind::of(&at(Error::Cancelled)));
+    }
+
+    #[test]
+    fn messages_are_kept_on_one_line() {
+        assert_eq!(
+            "1970-01-01T00:00:01.000Z 42 parse: a^Jb\n",
+            line(UNIX_EPOCH + Duration::from_secs(1), 42, Kind::Parse, "a\nb")
+        );
+    }
+
+    #[test]
+    fn lines_are_appended() {
+        let dir = TempDir::new("log");
+        let path = dir.join("log");
+
+        append(&path, Kind::Exec, "first").unwrap();
+        append(&path, Kind::Terminal, "second").unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].ends_with(&format!(" {} exec: first", std::process::id())));
+        assert!(lines[1].ends_with(" terminal: second"));
+    }
+}
@@ -1,31 +1,233 @@
+pub mod abbreviation;
+pub mod arith;
+pub mod brace;
 pub mod builtin;
+pub mod cancel;
+pub mod commands;
+pub mod completion;
+pub mod executor;
 pub mod expand;
+pub mod frecency;
+pub mod glob;
 pub mod history;
+pub mod jobs;
+pub mod jump;
+pub mod log;
+pub mod options;
+pub mod prompt;
+pub mod quote;
+pub mod sandbox;
+pub mod session;
+pub mod signals;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod terminal;
+#[cfg(feature = "serde")]
+pub mod trace;
 mod util;
+pub mod variables;
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::ffi::CString;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::mem;
 use std::ops::Not;
-use std::os::fd::RawFd;
+use std::os::fd::{FromRawFd, RawFd};
 use std::os::unix::prelude::ExitStatusExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::thread;
+#[cfg(feature = "serde")]
+use std::time::Instant;
 
-use nix::sys::wait::{waitpid, WaitStatus};
-use nix::unistd::{close, dup, dup2, execvp, pipe};
+use nix::fcntl::{fcntl, open, FcntlArg, OFlag};
+use nix::sys::signal::{self, SigHandler, Signal};
+use nix::sys::stat::Mode;
+use nix::unistd::{close, dup2, getpgrp, getpid, isatty, pipe, pipe2, setpgid, tcsetpgrp, Pid};
 
 use crate::ast::nodes::*;
-use crate::ast::parse;
+use crate::ast::{alias, parse};
+use crate::engine::abbreviation::Abbreviation;
+use crate::engine::cancel::CancellationToken;
+use crate::engine::commands::CommandCache;
+use crate::engine::completion::CompletionSpec;
+use crate::engine::executor::{Executor, Invocation, ProcessExecutor};
 use crate::engine::expand::Expand;
 use crate::engine::history::{FileHistory, History};
+use crate::engine::jobs::{Coprocess, Jobs, Output};
+use crate::engine::options::Options;
+use crate::engine::session::Session;
+use crate::engine::signals::SignalTraps;
+use crate::engine::terminal::Terminal;
+use crate::engine::variables::{Variable, Variables};
 use crate::{path, Error, Result};
 
+pub type ChpwdHook = fn(&mut Engine, &Path) -> Result<()>;
+
+/// A change of control flow requested by a builtin. No more commands are
+/// executed until it has been handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Stop executing the file sourced with `.`, which returns with the
+    /// given status.
+    Return(ExitStatus),
+
+    /// Stop executing the given number of the innermost loops.
+    Break(usize),
+
+    /// Stop executing the given number of the innermost loops, less one,
+    /// and go on with the next iteration of the loop around them.
+    Continue(usize),
+}
+
+/// A file or function being executed, as an entry of the call stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// What is being executed: `main` for the script the shell was started
+    /// with, `source` for a file run with `.`, and the name of a function
+    /// otherwise, which the innermost one sets `$FUNCNAME` to.
+    pub name: String,
+
+    /// The path of the file, which for a function is that of the file it
+    /// was called from, or empty if it was not called from one.
+    pub source: String,
+
+    /// The line of the command currently executed in the file. For a
+    /// function, this is the line it was called from.
+    pub line: usize,
+}
+
+/// How deep calls to functions can nest, so that runaway recursion is
+/// reported instead of overflowing the stack.
+const MAX_CALLS: usize = 100;
+
+/// A function being called, as an entry of `Engine::calls`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub name: String,
+
+    /// The variables made local to the call with `local`, along with what
+    /// they were before it, which they are restored to once it returns.
+    pub locals: HashMap<String, Option<Variable>>,
+}
+
 pub struct Engine {
     pub history: Box<dyn History>,
-    pub assignments: HashMap<String, String>,
+    pub variables: Variables,
+
+    /// The id and temporary directory of this shell, in `$PSH_SESSION_ID`
+    /// and `$PSH_SESSION_DIR`.
+    pub session: Session,
     pub aliases: HashMap<String, String>,
-    pub abbreviations: HashMap<String, String>,
-    pub last_status: Vec<ExitStatus>,
+    pub abbreviations: HashMap<String, Abbreviation>,
+
+    /// How the arguments of commands are completed, set with `complete`.
+    pub completions: HashMap<String, CompletionSpec>,
+    /// The status of the last pipeline, which is `$?`.
+    pub last_status: ExitStatus,
+    pub options: Options,
+
+    /// Pipelines started in the background.
+    pub jobs: Jobs,
+
+    /// Checked by expansions, which stop early once it is cancelled.
+    pub cancel: CancellationToken,
+
+    /// Starts external commands.
+    pub executor: Box<dyn Executor>,
+
+    /// Called with the new working directory whenever it is changed.
+    pub chpwd: Vec<ChpwdHook>,
+
+    /// The directories saved with `pushd`, the most recent first. Together
+    /// with the working directory, they make up the stack shown by `dirs`.
+    pub dir_stack: Vec<PathBuf>,
+
+    /// The files and functions being executed, innermost last, see
+    /// `caller`.
+    pub frames: Vec<Frame>,
+
+    /// The functions defined with `name() command`.
+    pub functions: HashMap<String, FunctionBody>,
+
+    /// The functions being called, innermost last.
+    pub calls: Vec<Call>,
+
+    /// Set by builtins such as `return`, see `Flow`.
+    pub flow: Option<Flow>,
+
+    /// The number of loops being executed, which `break` and `continue`
+    /// can stop.
+    pub loops: usize,
+
+    /// The first error that occurred while expanding the command about to
+    /// be executed, such as an unset variable with `nounset`.
+    expansion_error: Option<Error>,
+
+    /// Executed when the shell exits, with `$?` set to the exit status.
+    pub exit_trap: Option<String>,
+
+    /// Executed whenever a pipeline fails, with `$?` set to its exit status.
+    /// Pipelines negated with `!`, and those followed by `&&` or `||`, do
+    /// not trigger it.
+    pub err_trap: Option<String>,
+
+    /// Executed before every simple command, with `$PSH_COMMAND` set to the
+    /// text of the command.
+    pub debug_trap: Option<String>,
+
+    /// Whether the pipeline being executed is one whose failure does not
+    /// trigger the ERR trap or `errexit`, such as one followed by `&&`, so
+    /// that the failures of the commands in a group of it do not either.
+    unchecked: bool,
+
+    /// Executed between commands after a signal was received, see
+    /// `signals`.
+    pub signal_traps: SignalTraps,
+
+    /// `$0`: the name of the shell, or the path of the script it executes.
+    pub shell_name: String,
+
+    /// The positional parameters, `$1` and on.
+    pub parameters: Vec<String>,
+
+    /// The pid of the last pipeline started in the background, `$!`.
+    pub last_background: Option<Pid>,
+
+    /// The pid of the shell, `$$`, which subshells keep.
+    pid: Pid,
+
+    /// Whether the shell reads commands from a user, in which case errors
+    /// such as an invalid argument to `exit` do not end it.
+    pub interactive: bool,
+
+    /// Whether every pipeline runs in a process group of its own, so that it
+    /// can be stopped with ^Z and continued with `fg` or `bg`. Set by
+    /// `enable_job_control`.
+    pub job_control: bool,
+
+    /// The settings of the terminal that it is put back to when a command
+    /// leaves it in a weird state, saved when an interactive shell starts.
+    pub terminal: Option<Terminal>,
+
+    /// How many times the terminal has been handed to a job, which may have
+    /// drawn anything on it, so positions on it from before are stale.
+    pub terminal_handoffs: usize,
+
+    /// The executables in `$PATH`, used to highlight and complete commands.
+    commands: RefCell<CommandCache>,
+
+    /// If set, every executed simple command is recorded here.
+    #[cfg(feature = "serde")]
+    pub tracer: Option<trace::Tracer>,
+
+    /// The entries of the traced commands that have not been waited for
+    /// yet, with when they were started.
+    #[cfg(feature = "serde")]
+    traced: Vec<(Pid, Instant, trace::TraceEntry)>,
 }
 
 #[derive(Debug, Clone)]
@@ -35,30 +237,156 @@ struct ExecutionContext {
     stderr: RawFd,
     fds: Vec<(FileDescriptor, FileDescriptor)>,
     assignments: HashMap<String, String>,
+
+    /// File descriptors that a forked child should close, such as the read
+    /// end of the pipe it writes to.
+    close: Vec<RawFd>,
+
+    /// The process group to put a child in, see `Invocation::pgroup`.
+    pgroup: Option<Pid>,
+}
+
+/// The `(src, dst)` pairs of file descriptors that the redirections of a
+/// command set up, and the `(dst, type, target)` of each one to a file.
+type Redirected = (
+    Vec<(FileDescriptor, FileDescriptor)>,
+    Vec<(FileDescriptor, RedirectionType, String)>,
+);
+
+/// How a command of a pipeline is connected to the rest of it.
+#[derive(Debug, Clone, Copy)]
+struct Stage {
+    stdin: RawFd,
+    stdout: RawFd,
+    stderr: RawFd,
+
+    /// The read end of the pipe the command writes to, if any.
+    pipe_read: Option<RawFd>,
+
+    /// Whether a builtin should run in the current shell instead of a subshell.
+    in_shell: bool,
+
+    /// Whether the shell should wait for the command to finish.
+    wait: bool,
     background: bool,
+
+    /// The process group of the pipeline, see `Invocation::pgroup`.
+    pgroup: Option<Pid>,
+}
+
+/// The outcome of starting a command of a pipeline.
+#[derive(Debug)]
+enum Started {
+    Finished(ExitStatus),
+
+    /// The command is still running and should be waited for once the rest
+    /// of the pipeline has finished.
+    Running(Child),
+
+    /// Nothing was executed, e.g. for a command consisting of assignments.
+    Skipped,
+
+    /// The pipeline should not continue, e.g. since a redirection failed.
+    Aborted,
+}
+
+/// A command that was started without being waited for.
+#[derive(Debug, Clone, Copy)]
+enum Child {
+    /// A builtin running in a subshell.
+    Subshell(Pid),
+
+    /// An external command started by the executor.
+    External(Pid),
+}
+
+impl Child {
+    fn pid(self) -> Pid {
+        match self {
+            Self::Subshell(pid) | Self::External(pid) => pid,
+        }
+    }
+}
+
+/// Puts `child` in the process group `pgroup` from the side of the shell as
+/// well, so that the group exists before the next command of the pipeline
+/// joins it or the terminal is handed to it. The child may already have
+/// done so itself, or even exited, so errors are ignored.
+fn join_pgroup(child: Pid, pgroup: Option<Pid>) {
+    if let Some(pgid) = pgroup {
+        let pgid = if pgid.as_raw() == 0 { child } else { pgid };
+        let _ = setpgid(child, pgid);
+    }
+}
+
+/// Whether `pipeline` is a single compound command, such as `{ ...; }`.
+fn is_group(pipeline: &Pipeline) -> bool {
+    pipeline.sequence.tail.is_empty() && matches!(*pipeline.sequence.head, Command::Compound(..))
+}
+
+/// What a command started in the background reads instead of the input of
+/// the shell, when there is no job control to stop it from reading it.
+fn background_stdin() -> Result<RawFd> {
+    Ok(open(
+        "/dev/null",
+        OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?)
+}
+
+/// The read end of a pipe that `document` is written to, which is done by
+/// a thread of its own, since it may not fit in the pipe before the command
+/// starts reading it.
+fn here_document_fd(document: String) -> Result<FileDescriptor> {
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    // SAFETY: the write end of the pipe is only used by this file, which
+    //         closes it when dropped
+    let mut file = unsafe { File::from_raw_fd(write) };
+    thread::spawn(move || {
+        // the command may exit without reading all of it
+        let _ = file.write_all(document.as_bytes());
+    });
+    Ok(FileDescriptor::from(read))
 }
 
 impl ExecutionContext {
+    fn close_fds(&self) -> Result<()> {
+        for &fd in &self.close {
+            close(fd)?;
+        }
+        Ok(())
+    }
+
     fn dup_fds(&self) -> Result<()> {
-        for &(src, dst) in &self.fds {
-            if src != dst {
-                dup2(src.as_raw_fd(), dst.as_raw_fd())?;
-            }
+        for (src, dst) in self.dups() {
+            dup2(src, dst)?;
         }
+        Ok(())
+    }
+
+    /// The `(src, dst)` pairs of file descriptors to duplicate for the
+    /// command, in order.
+    fn dups(&self) -> Vec<(RawFd, RawFd)> {
+        let mut dups = self
+            .fds
+            .iter()
+            .filter(|(src, dst)| src != dst)
+            .map(|(src, dst)| (src.as_raw_fd(), dst.as_raw_fd()))
+            .collect::<Vec<_>>();
 
         if !self.fds.iter().any(|&(_, dst)| dst.is_stdin()) {
-            dup2(self.stdin, FileDescriptor::Stdin.as_raw_fd())?;
+            dups.push((self.stdin, FileDescriptor::Stdin.as_raw_fd()));
         }
 
         if !self.fds.iter().any(|&(_, dst)| dst.is_stdout()) {
-            dup2(self.stdout, FileDescriptor::Stdout.as_raw_fd())?;
+            dups.push((self.stdout, FileDescriptor::Stdout.as_raw_fd()));
         }
 
         if !self.fds.iter().any(|&(_, dst)| dst.is_stderr()) {
-            dup2(self.stderr, FileDescriptor::Stderr.as_raw_fd())?;
+            dups.push((self.stderr, FileDescriptor::Stderr.as_raw_fd()));
         }
 
-        Ok(())
+        dups
     }
 }
 
@@ -70,7 +398,8 @@ impl Default for ExecutionContext {
             stderr: 2,
             fds: Default::default(),
             assignments: Default::default(),
-            background: false,
+            close: Default::default(),
+            pgroup: None,
         }
     }
 }
@@ -78,51 +407,463 @@ impl Default for ExecutionContext {
 impl Engine {
     pub fn new() -> Self {
         let history = FileHistory::init().expect("could not initialize history");
+        Self::with_history(Box::new(history))
+    }
+
+    /// An engine that keeps its history in `history`, rather than in the file
+    /// `PSH_HISTORY` names.
+    pub fn with_history(history: Box<dyn History>) -> Self {
+        let session = Session::start();
+        let mut variables = Variables::from_env();
+        session.export(&mut variables);
+
         Self {
-            history: Box::new(history),
-            assignments: Default::default(),
+            history,
+            variables,
+            session,
             aliases: Default::default(),
             abbreviations: Default::default(),
-            last_status: vec![ExitStatus::from_code(0)],
+            completions: Default::default(),
+            last_status: ExitStatus::from_code(0),
+            options: Default::default(),
+            jobs: Default::default(),
+            cancel: Default::default(),
+            executor: Box::new(ProcessExecutor),
+            chpwd: Vec::new(),
+            dir_stack: Vec::new(),
+            frames: Vec::new(),
+            functions: Default::default(),
+            calls: Vec::new(),
+            flow: None,
+            unchecked: false,
+            loops: 0,
+            expansion_error: None,
+            exit_trap: None,
+            err_trap: None,
+            debug_trap: None,
+            signal_traps: Default::default(),
+            shell_name: String::from("psh"),
+            parameters: Vec::new(),
+            last_background: None,
+            pid: getpid(),
+            interactive: false,
+            job_control: false,
+            terminal: None,
+            terminal_handoffs: 0,
+            commands: Default::default(),
+            #[cfg(feature = "serde")]
+            tracer: None,
+            #[cfg(feature = "serde")]
+            traced: Vec::new(),
         }
     }
 
+    /// The path of `file` in the first directory of `$PATH` that has it, as
+    /// `.` looks for the files it runs.
     pub fn get_file_in_path(&self, file: &str) -> Option<String> {
+        let path = self.get_value_of("PATH")?;
+        path::find_in_path(&path, file, false).map(|file| file.display().to_string())
+    }
+
+    /// The path of the executable `cmd` in the first directory of `$PATH`
+    /// that has it, so that a file there that cannot be executed does not
+    /// shadow one in a later directory.
+    pub fn get_command_in_path(&self, cmd: &str) -> Option<String> {
+        let path = self.get_value_of("PATH")?;
+        path::find_in_path(&path, cmd, true).map(|cmd| cmd.display().to_string())
+    }
+
+    /// Returns every command name that can currently be run, together with
+    /// where it comes from. Names shadowed by an earlier source (in the order
+    /// aliases, builtins, `$PATH`) are only returned once.
+    pub fn command_candidates(&self) -> Vec<(String, CommandSource)> {
+        let mut candidates = Vec::new();
+
+        for name in self.aliases.keys() {
+            candidates.push((name.clone(), CommandSource::Alias));
+        }
+
+        // the names of abbreviations with a regex are not what is typed
+        for (name, abbreviation) in &self.abbreviations {
+            if abbreviation.regex.is_none() {
+                candidates.push((name.clone(), CommandSource::Abbreviation));
+            }
+        }
+
+        for name in self.functions.keys() {
+            candidates.push((name.clone(), CommandSource::Function));
+        }
+
+        for (name, _) in builtin::BUILTINS {
+            candidates.push((name.to_string(), CommandSource::Builtin));
+        }
+
         if let Some(path) = self.get_value_of("PATH") {
-            let paths = path.split(':');
+            for (name, dir) in self.commands.borrow_mut().commands(&path) {
+                candidates.push((name.clone(), CommandSource::Path(dir.clone())));
+            }
+        }
 
-            for path in paths {
-                if let Ok(dirs) = std::fs::read_dir(path) {
-                    for entry in dirs.filter_map(|f| f.ok()) {
-                        if file == entry.file_name() {
-                            return Some(format!("{}", entry.path().display()));
-                        }
-                    }
+        let mut seen = HashSet::new();
+        candidates.retain(|(name, _)| seen.insert(name.clone()));
+        candidates
+    }
+
+    /// Starts `args` as a coprocess: a background job with its stdin and
+    /// stdout connected to the shell through pipes. The fds of the shell's
+    /// ends are stored in `$<name>_WRITE` and `$<name>_READ`, and its pid in
+    /// `$<name>_PID`, until the job is forgotten. Returns the job id.
+    pub fn start_coprocess(&mut self, name: &str, args: &[&str]) -> Result<usize> {
+        let mut args = args.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let alias_args = self.expand_alias(&args[0]);
+        args.splice(0..1, alias_args);
+        if !self.has_executable(&args[0]) {
+            return Err(Error::UnknownCommand(args[0].to_string()));
+        }
+
+        // the shell's ends are not inherited by any other command
+        let (stdin_read, stdin_write) = pipe2(OFlag::O_CLOEXEC)?;
+        let (stdout_read, stdout_write) = pipe2(OFlag::O_CLOEXEC)?;
+        let coprocess = Coprocess {
+            name: name.to_string(),
+            read: stdout_read,
+            write: stdin_write,
+        };
+
+        let context = ExecutionContext {
+            stdin: stdin_read,
+            stdout: stdout_write,
+            close: vec![stdout_read, stdin_write],
+            pgroup: self.job_control.then_some(Pid::from_raw(0)),
+            ..Default::default()
+        };
+        let child = if self.has_builtin(&args[0]) {
+            self.spawn_builtin(&args, context)
+        } else {
+            self.spawn_external_command(&args, context)
+        };
+
+        close(stdin_read)?;
+        close(stdout_write)?;
+        let child = child?;
+
+        self.variables.set(format!("{name}_READ"), coprocess.read)?;
+        self.variables
+            .set(format!("{name}_WRITE"), coprocess.write)?;
+        self.variables.set(format!("{name}_PID"), child)?;
+        self.last_background = Some(child);
+
+        let pgid = self.job_control.then_some(child);
+        Ok(self
+            .jobs
+            .add_coprocess(args.join(" "), child, pgid, coprocess))
+    }
+
+    /// Describes the background jobs that have finished since the last call,
+    /// see `Jobs::notify`.
+    pub fn notify_jobs(&mut self) -> Vec<String> {
+        let lines = self.jobs.notify();
+        self.unset_closed_coprocesses();
+        lines
+    }
+
+    /// Describes every background job, see `Jobs::list`.
+    pub fn list_jobs(&mut self) -> Vec<String> {
+        let lines = self.jobs.list();
+        self.unset_closed_coprocesses();
+        lines
+    }
+
+    /// Makes the shell run pipelines in process groups of their own, if its
+    /// stdin is a terminal. The shell puts itself in the foreground, and
+    /// ignores the signals that would otherwise stop it, such as ^Z.
+    pub fn enable_job_control(&mut self) -> Result<()> {
+        if !isatty(0).unwrap_or(false) {
+            return Ok(());
+        }
+
+        for signal in [Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU] {
+            // SAFETY: ignoring a signal installs no handler
+            unsafe { signal::signal(signal, SigHandler::SigIgn)? };
+        }
+
+        // fails if the shell already leads its session, which is fine
+        let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+        tcsetpgrp(0, getpgrp())?;
+
+        self.job_control = true;
+        Ok(())
+    }
+
+    /// Continues the job `id` in the foreground, and waits for it to finish
+    /// or be stopped again.
+    pub fn foreground_job(&mut self, id: usize) -> Result<ExitStatus> {
+        self.run_in_foreground(id, true)
+    }
+
+    /// Continues the job `id` in the background.
+    pub fn background_job(&mut self, id: usize) -> Result<()> {
+        self.jobs.resume(id)
+    }
+
+    /// Hands the terminal to the job `id` and waits for it, continuing it
+    /// first if `resume` is set. A job that is stopped is reported, and
+    /// makes the status 128 plus SIGTSTP.
+    fn run_in_foreground(&mut self, id: usize, resume: bool) -> Result<ExitStatus> {
+        let pgid = self.jobs.by_id(id).and_then(|job| job.pgid);
+        if let Some(pgid) = pgid {
+            let _ = tcsetpgrp(0, pgid);
+            self.terminal_handoffs += 1;
+        }
+
+        let waited = match resume {
+            true => self
+                .jobs
+                .resume(id)
+                .and_then(|_| self.jobs.wait_processes(id)),
+            false => self.jobs.wait_processes(id),
+        };
+
+        if pgid.is_some() {
+            let _ = tcsetpgrp(0, getpgrp());
+        }
+
+        let (status, finished) = waited?;
+        #[cfg(feature = "serde")]
+        for (pid, status) in finished {
+            self.trace_finished(pid, status);
+        }
+        #[cfg(not(feature = "serde"))]
+        let _ = finished;
+
+        match status {
+            Some(status) => {
+                // the shell does not get the ^C meant for the job, so stop
+                // what it is doing as if it had, unless it has a trap
+                if status.raw_code() == 128 + Signal::SIGINT as i32
+                    && !self.signal_traps.deliver(Signal::SIGINT)
+                {
+                    self.cancel.cancel();
+                }
+                Ok(status)
+            }
+            None => {
+                if let Some(job) = self.jobs.by_id(id) {
+                    eprintln!("\n{job}");
                 }
+                Ok(ExitStatus::from_code(128 + Signal::SIGTSTP as i32))
+            }
+        }
+    }
+
+    fn unset_closed_coprocesses(&mut self) {
+        for name in self.jobs.take_closed() {
+            for suffix in ["READ", "WRITE", "PID"] {
+                self.variables.unset(format!("{name}_{suffix}"));
             }
         }
+    }
+
+    /// Exits the shell with `status`, after executing the EXIT trap if any.
+    /// The trap can exit with a different status by calling `exit` itself.
+    pub fn exit(&mut self, status: ExitStatus) -> ! {
+        self.last_status = status;
 
-        None
+        if let Some(trap) = self.exit_trap.take() {
+            if let Err(e) = self.execute_line(trap) {
+                eprintln!("psh: EXIT trap: {e}");
+            }
+        }
+
+        let _ = io::stdout().flush();
+        self.session.end();
+        std::process::exit(status.raw_code());
+    }
+
+    /// Executes the ERR trap if any, after a pipeline failed with `status`.
+    /// The trap does not trigger itself, and `$?` is kept as `status`.
+    fn err_trap(&mut self, status: ExitStatus) {
+        let Some(trap) = self.err_trap.take() else {
+            return;
+        };
+
+        self.last_status = status;
+        if let Err(e) = self.execute_line(&trap) {
+            eprintln!("psh: ERR trap: {e}");
+        }
+        self.last_status = status;
+
+        // unless the trap was replaced by the trap itself
+        self.err_trap.get_or_insert(trap);
+    }
+
+    /// Called when a pipeline that is not negated with `!` or followed by
+    /// `&&` or `||` failed with `status`. Executes the ERR trap, and exits
+    /// if `errexit` is set.
+    fn failed(&mut self, status: ExitStatus) {
+        if self.unchecked {
+            return;
+        }
+        self.err_trap(status);
+        if self.options.errexit {
+            if let Some(location) = self.location() {
+                let code = status.raw_code();
+                eprintln!("psh: {location}: exiting on status {code}, as errexit is set");
+            }
+            self.exit(status);
+        }
+    }
+
+    /// Where the command being executed is, as `file:line`, if it is in a
+    /// file.
+    pub fn location(&self) -> Option<String> {
+        let frame = self
+            .frames
+            .last()
+            .filter(|frame| !frame.source.is_empty())?;
+        Some(format!("{}:{}", frame.source, frame.line))
+    }
+
+    /// Reports the error `e` to stderr, along with where it happened if in
+    /// a file.
+    pub fn report(&self, e: impl fmt::Display) {
+        let message = match self.location() {
+            Some(location) => format!("{location}: {e}"),
+            None => e.to_string(),
+        };
+        eprintln!("psh: {message}");
+        self.log(log::Kind::Exec, &message);
+    }
+
+    /// Appends the error `message` to the file in `$PSH_LOG`, if set.
+    pub fn log(&self, kind: log::Kind, message: impl fmt::Display) {
+        let Some(file) = self.get_value_of("PSH_LOG").filter(|file| !file.is_empty()) else {
+            return;
+        };
+        if let Err(e) = log::append(Path::new(&file), kind, &message.to_string()) {
+            eprintln!("psh: Could not write to the error log `{file}`: {e}");
+        }
+    }
+
+    /// How far below that of the shell the priority of background jobs is
+    /// set, from `$PSH_BG_NICE`, if at all.
+    fn background_nice(&self) -> Option<i32> {
+        let nice = self
+            .get_value_of("PSH_BG_NICE")
+            .filter(|nice| !nice.is_empty())?;
+        match nice.trim().parse() {
+            Ok(nice) => Some(nice),
+            Err(_) => {
+                eprintln!("psh: PSH_BG_NICE: '{nice}': Integer expected.");
+                None
+            }
+        }
+    }
+
+    /// Prints a command about to be executed to stderr if `xtrace` is set,
+    /// prefixed by `$PS4`: its assignments, and then its arguments.
+    fn xtrace(&self, cmd: &SimpleCommand, assignments: &HashMap<String, String>, args: &[String]) {
+        if !self.options.xtrace {
+            return;
+        }
+
+        let assignments = cmd.assignments().filter_map(|assignment| {
+            let name = assignment.lhs.to_string();
+            let value = assignments.get(&name)?;
+            Some(format!("{name}={}", quote::quote(value)))
+        });
+        let args = args.iter().map(|arg| quote::quote(arg).into_owned());
+
+        let ps4 = self.get_value_of("PS4").unwrap_or_else(|| "+ ".to_string());
+        let words = assignments.chain(args).collect::<Vec<_>>();
+        eprintln!("{ps4}{}", words.join(" "));
+    }
+
+    /// Executes the DEBUG trap if any, before the simple command `cmd`. The
+    /// commands of the trap do not trigger it, and `$?` is left as is.
+    fn debug_trap(&mut self, cmd: &SimpleCommand) {
+        let Some(trap) = self.debug_trap.take() else {
+            return;
+        };
+
+        let last_status = self.last_status;
+        if let Err(e) = self.variables.set("PSH_COMMAND", cmd.to_string().trim()) {
+            eprintln!("psh: DEBUG trap: {e}");
+        }
+        if let Err(e) = self.execute_line(&trap) {
+            eprintln!("psh: DEBUG trap: {e}");
+        }
+        self.last_status = last_status;
+
+        // unless the trap was replaced by the trap itself
+        self.debug_trap.get_or_insert(trap);
+    }
+
+    /// Executes the traps of the signals received since the last call, in
+    /// order of signal number. `$?` is left as is.
+    pub fn signal_traps(&mut self) {
+        let pending = self.signal_traps.take_pending();
+        if pending.is_empty() {
+            return;
+        }
+
+        let last_status = self.last_status;
+        for (signal, trap) in pending {
+            if let Err(e) = self.execute_line(&trap) {
+                let name = signal.as_str().trim_start_matches("SIG");
+                eprintln!("psh: {name} trap: {e}");
+            }
+        }
+        self.last_status = last_status;
+    }
+
+    /// Changes the working directory, updating and exporting `$PWD` and
+    /// `$OLDPWD`, and runs the `chpwd` hooks. Errors from the hooks are only
+    /// reported.
+    pub fn set_current_dir(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let old_pwd = env::current_dir()?;
+        env::set_current_dir(path)?;
+
+        let pwd = env::current_dir()?;
+        self.variables.set("OLDPWD", old_pwd.display())?;
+        self.variables.set("PWD", pwd.display())?;
+        self.variables.export("OLDPWD");
+        self.variables.export("PWD");
+
+        for hook in self.chpwd.clone() {
+            if let Err(e) = hook(self, &pwd) {
+                eprintln!("psh: chpwd: {e}");
+            }
+        }
+
+        Ok(())
     }
 
     pub fn get_value_of(&self, var_name: impl AsRef<str>) -> Option<String> {
-        let var = var_name.as_ref();
-        self.assignments
-            .get(var)
-            .cloned()
-            .or_else(|| env::var(var).ok())
+        self.variables.value_of(var_name).map(ToString::to_string)
     }
 
     pub fn has_executable(&self, cmd: &str) -> bool {
-        self.has_command(cmd) || self.has_alias(cmd) || builtin::has(cmd)
+        self.has_command(cmd) || self.has_alias(cmd) || self.has_builtin(cmd)
+    }
+
+    /// Whether `cmd` is run by the shell itself, as a function or a builtin.
+    pub fn has_builtin(&self, cmd: &str) -> bool {
+        self.functions.contains_key(cmd) || builtin::has(cmd)
     }
 
     pub fn has_command(&self, cmd: &str) -> bool {
         path::has_relative_command(cmd)
-            || (self
-                .get_file_in_path(cmd)
-                .map(|file| util::is_executable(&file))
-                .unwrap_or(false))
+            || self
+                .get_value_of("PATH")
+                .and_then(|path| self.commands.borrow_mut().find(&path, cmd))
+                .is_some()
+    }
+
+    /// Forgets the executables found in `$PATH`, so that they are looked up
+    /// again.
+    pub fn rehash(&self) {
+        self.commands.borrow_mut().rehash();
     }
 
     pub fn has_alias(&self, cmd: impl AsRef<str>) -> bool {
@@ -130,15 +871,15 @@ impl Engine {
         self.aliases.keys().any(|a| a == cmd)
     }
 
+    /// Whether `cmd` is expanded as an abbreviation in command position.
     pub fn has_abbreviation(&self, cmd: impl AsRef<str>) -> bool {
-        let cmd = cmd.as_ref();
-        self.abbreviations.keys().any(|a| a == cmd)
+        abbreviation::find(&self.abbreviations, cmd.as_ref(), true).is_some()
     }
 
-    // FIXME: this needs to be totally reworked. the best way would be
-    //        to replace the actual input string as needed, but this
-    //        would require us to be able to take a SyntaxTree, update
-    //        the originating string and re-parse
+    /// Expands the alias `name` into words, for the commands that are not
+    /// parsed as such, such as the command of a coprocess. Aliases in
+    /// commands that are parsed are substituted before that, see
+    /// `alias::substitute`.
     fn expand_alias(&self, name: &str) -> Vec<String> {
         let (mut name, mut args) = (name.to_string(), Vec::new());
         // should also be recursive
@@ -157,13 +898,41 @@ impl Engine {
 
     pub fn execute_line(&mut self, line: impl ToString) -> Result<Vec<ExitStatus>> {
         let ast = parse(line.to_string(), false)?;
-        self.walk_ast(ast)
+        self.walk_ast(ast, false)
     }
 
-    pub fn execute_file(&mut self, path: PathBuf) -> Result<Vec<ExitStatus>> {
-        let lines = std::fs::read_to_string(path)?;
-        let ast = parse(lines, false)?;
-        self.walk_ast(ast)
+    /// Executes the file at `path`, whose errors tell the file and line
+    /// they happened at. A file that is `sourced`, as with `.`, can be left
+    /// with `return`, while the script the shell was started with cannot.
+    pub fn execute_file(&mut self, path: PathBuf, sourced: bool) -> Result<Vec<ExitStatus>> {
+        let source = path.display().to_string();
+        let lines = std::fs::read_to_string(&path)?;
+        let ast = match parse(lines, false) {
+            Ok(ast) => ast,
+            Err(e @ Error::SyntaxError(_, Some(line))) => return Err(e.at(&source, line)),
+            Err(e) => return Err(e),
+        };
+
+        self.frames.push(Frame {
+            name: match sourced {
+                true => "source".to_string(),
+                false => "main".to_string(),
+            },
+            source,
+            line: 0,
+        });
+        let results = self.walk_ast(ast, true);
+        let frame = self.frames.pop();
+
+        let flow = self.flow.take();
+        let mut results = match (results, frame) {
+            (Err(e), Some(frame)) => return Err(e.at(&frame.source, frame.line)),
+            (results, _) => results?,
+        };
+        if let Some(Flow::Return(status)) = flow {
+            results.push(status);
+        }
+        Ok(results)
     }
 
     fn execute_builtin(
@@ -172,152 +941,977 @@ impl Engine {
         context: ExecutionContext,
     ) -> Result<ExitStatus> {
         let args = args.iter().map(|s| s.as_ref()).collect::<Vec<_>>();
+        let assignments = context.assignments.clone();
+        self.execute_redirected(context, |engine| {
+            engine.with_assignments(assignments, |engine| engine.run_builtin(&args))
+        })
+    }
 
-        let old_fds = [(dup(0)?, 0), (dup(1)?, 1), (dup(2)?, 2)];
-        context.dup_fds()?;
-        let status = builtin::execute(self, args[0], &args[1..])?;
+    /// Runs the function or builtin named by the first of `args`, where a
+    /// function takes the place of a builtin of the same name.
+    fn run_builtin(&mut self, args: &[&str]) -> Result<ExitStatus> {
+        match self.functions.get(args[0]) {
+            Some(body) => self.call_function(body.clone(), args),
+            None => builtin::execute(self, args[0], &args[1..]),
+        }
+    }
 
-        for (fd, n) in old_fds {
-            dup2(fd, n)?;
-            close(fd)?;
+    /// Calls the function defined as `body`, with the arguments after its
+    /// name as the positional parameters. These, and the variables it made
+    /// local, are restored once it returns.
+    fn call_function(&mut self, body: FunctionBody, args: &[&str]) -> Result<ExitStatus> {
+        if self.calls.len() >= MAX_CALLS {
+            return Err(Error::TooManyCalls(args[0].to_string()));
         }
 
-        Ok(status)
+        let parameters = args[1..].iter().map(ToString::to_string).collect();
+        let parameters = mem::replace(&mut self.parameters, parameters);
+        self.calls.push(Call {
+            name: args[0].to_string(),
+            locals: HashMap::new(),
+        });
+        let (source, line) = self
+            .frames
+            .last()
+            .map(|frame| (frame.source.clone(), frame.line))
+            .unwrap_or_default();
+        self.frames.push(Frame {
+            name: args[0].to_string(),
+            source,
+            line,
+        });
+        let funcname = self.variables.get("FUNCNAME").cloned();
+        let _ = self.variables.set("FUNCNAME", args[0]);
+
+        let stage = Stage {
+            stdin: 0,
+            stdout: 1,
+            stderr: 2,
+            pipe_read: None,
+            in_shell: true,
+            wait: true,
+            background: false,
+            pgroup: None,
+        };
+        let started = self.start_compound_command(body.command, &body.redirections, stage);
+
+        if let Some(call) = self.calls.pop() {
+            for (name, var) in call.locals {
+                self.variables.restore(name, var);
+            }
+        }
+        self.frames.pop();
+        self.variables.restore("FUNCNAME", funcname);
+        self.parameters = parameters;
+
+        let status = match started? {
+            Started::Finished(status) => status,
+            Started::Aborted => ExitStatus::from_code(1),
+            Started::Running(_) | Started::Skipped => ExitStatus::from_code(0),
+        };
+        match self.flow {
+            Some(Flow::Return(status)) => {
+                self.flow = None;
+                Ok(status)
+            }
+            _ => Ok(status),
+        }
     }
 
-    fn execute_external_command(
+    /// Runs `f` with the variables of `assignments`, given before the name
+    /// of a command, set and exported, and puts them back as they were
+    /// afterwards.
+    fn with_assignments(
         &mut self,
-        args: &[impl AsRef<str>],
-        context: ExecutionContext,
+        assignments: HashMap<String, String>,
+        f: impl FnOnce(&mut Self) -> Result<ExitStatus>,
     ) -> Result<ExitStatus> {
-        let child = util::spawn_subshell(|| {
-            context.dup_fds()?;
+        let saved = assignments
+            .keys()
+            .map(|name| (name.clone(), self.variables.get(name).cloned()))
+            .collect::<Vec<_>>();
+
+        let assign = || -> Result<()> {
+            for (name, value) in assignments {
+                self.variables.set(&name, value)?;
+                self.variables.export(name);
+            }
+            Ok(())
+        };
+        let status = assign().and_then(|()| f(self));
 
-            for (key, val) in &context.assignments {
-                env::set_var(key, val);
+        for (name, var) in saved {
+            self.variables.restore(name, var);
+        }
+        status
+    }
+
+    /// Runs `f` in the current shell with the file descriptors of `context`,
+    /// and restores the ones of the shell afterwards.
+    fn execute_redirected(
+        &mut self,
+        context: ExecutionContext,
+        f: impl FnOnce(&mut Self) -> Result<ExitStatus>,
+    ) -> Result<ExitStatus> {
+        // the copies are kept above the descriptors that a redirection can
+        // name, and those that were not open are closed again
+        let mut saved = Vec::new();
+        for (_, dst) in context.dups() {
+            if saved.iter().all(|&(fd, _)| fd != dst) {
+                saved.push((dst, fcntl(dst, FcntlArg::F_DUPFD_CLOEXEC(10)).ok()));
             }
+        }
 
-            let args = args
-                .iter()
-                .map(|s| CString::new(s.as_ref()).unwrap())
-                .collect::<Vec<_>>();
+        let status = context.dup_fds().and_then(|()| f(self));
+        // output that can no longer be written is dropped
+        let _ = io::stdout().flush();
 
-            match execvp(&args[0], &args) {
-                Ok(_) => unreachable!(),
-                Err(e) => panic!("psh: error in exec: {e}"),
+        for (fd, copy) in saved {
+            match copy {
+                Some(copy) => {
+                    dup2(copy, fd)?;
+                    close(copy)?;
+                }
+                None => {
+                    let _ = close(fd);
+                }
             }
+        }
+
+        status
+    }
+
+    /// Runs a function or builtin in a subshell, so that it can be part of
+    /// a pipeline without blocking the shell or affecting its state.
+    fn spawn_builtin(
+        &mut self,
+        args: &[impl AsRef<str>],
+        context: ExecutionContext,
+    ) -> Result<Pid> {
+        let args = args.iter().map(|s| s.as_ref()).collect::<Vec<_>>();
+        let assignments = context.assignments.clone();
+        self.spawn_redirected(context, |engine| {
+            engine.with_assignments(assignments, |engine| engine.run_builtin(&args))
+        })
+    }
+
+    /// Runs `f` in a subshell with the file descriptors of `context`, which
+    /// exits with the status it returns.
+    fn spawn_redirected(
+        &mut self,
+        context: ExecutionContext,
+        f: impl FnOnce(&mut Self) -> Result<ExitStatus>,
+    ) -> Result<Pid> {
+        let pgroup = context.pgroup;
+        let child = util::spawn_subshell(|| {
+            self.enter_subshell();
+
+            let run = || -> Result<i32> {
+                if let Some(pgid) = context.pgroup {
+                    setpgid(Pid::from_raw(0), pgid)?;
+                }
+                util::restore_signals()?;
+
+                context.close_fds()?;
+                context.dup_fds()?;
+                let status = f(self)?;
+                io::stdout().flush()?;
+                Ok(status.raw_code())
+            };
+
+            // the subshell must never return into the shell it was forked from
+            let code = run().unwrap_or_else(|e| {
+                self.report(e);
+                1
+            });
+            std::process::exit(code);
         })?;
 
-        let mut rc = 0;
-        if !context.background {
-            if let Ok(WaitStatus::Exited(_, code)) = waitpid(child, None) {
-                rc = code;
+        join_pgroup(child, pgroup);
+        Ok(child)
+    }
+
+    /// Resets the state that belongs to the shell rather than its subshells,
+    /// in a child that was just forked.
+    fn enter_subshell(&mut self) {
+        self.exit_trap = None;
+        self.debug_trap = None;
+        if !self.options.errtrace {
+            self.err_trap = None;
+        }
+        if let Err(e) = self.signal_traps.reset_caught() {
+            self.report(e);
+        }
+        self.job_control = false;
+    }
+
+    /// Executes `commands` in a subshell, returning what they write to
+    /// stdout without the trailing newlines, and setting `$?` to their exit
+    /// status.
+    pub fn command_substitution(&mut self, commands: SyntaxTree) -> Result<String> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+
+        let child = util::spawn_subshell(|| {
+            self.enter_subshell();
+
+            let run = || -> Result<i32> {
+                util::restore_signals()?;
+                close(read)?;
+                dup2(write, 1)?;
+                close(write)?;
+
+                let status = self.walk_ast(commands, false)?.last().copied();
+                io::stdout().flush()?;
+                Ok(status.map_or(0, |status| status.raw_code()))
+            };
+
+            // the subshell must never return into the shell it was forked from
+            let code = run().unwrap_or_else(|e| {
+                self.report(e);
+                1
+            });
+            std::process::exit(code);
+        });
+
+        close(write)?;
+        // SAFETY: the read end of the pipe is only used by this file, which
+        //         closes it when dropped
+        let mut file = unsafe { File::from_raw_fd(read) };
+        let child = child?;
+
+        let mut output = Vec::new();
+        file.read_to_end(&mut output)?;
+        self.last_status = util::wait_for(child)?;
+
+        // like in bash, since they could not be passed to commands
+        if output.contains(&0) {
+            eprintln!("psh: warning: command substitution: ignored null byte in input");
+            output.retain(|&b| b != 0);
+        }
+
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        output.truncate(output.trim_end_matches('\n').len());
+        Ok(output)
+    }
+
+    fn spawn_external_command(
+        &mut self,
+        args: &[impl AsRef<str>],
+        context: ExecutionContext,
+    ) -> Result<Pid> {
+        // `$PATH` may contain entries such as `~/bin`, which execvp does not
+        // know how to search, so resolve the command up front if possible.
+        let file = match args[0].as_ref() {
+            cmd if cmd.contains('/') => None,
+            cmd => self.get_command_in_path(cmd),
+        };
+
+        let invocation = Invocation {
+            args: args.iter().map(|s| s.as_ref().to_string()).collect(),
+            file,
+            dups: context.dups(),
+            assignments: context.assignments,
+            close: context.close,
+            pgroup: context.pgroup,
+        };
+
+        let child = self.executor.spawn(&invocation, &self.options)?;
+        join_pgroup(child, invocation.pgroup);
+        Ok(child)
+    }
+
+    fn wait_for(&mut self, child: Child) -> Result<ExitStatus> {
+        match child {
+            Child::Subshell(pid) => util::wait_for(pid),
+            Child::External(pid) => self.executor.wait(pid),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn trace(&mut self, entry: trace::TraceEntry) {
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.record(&entry) {
+                eprintln!("psh: could not write trace: {e}");
             }
         }
+    }
 
-        Ok(ExitStatus::from_code(rc))
+    /// Records the trace entry of the command `pid`, which was started at
+    /// some point before and has now finished with `status`, if it was
+    /// traced.
+    #[cfg(feature = "serde")]
+    fn trace_finished(&mut self, pid: Pid, status: ExitStatus) {
+        let Some(i) = self.traced.iter().position(|(traced, ..)| *traced == pid) else {
+            return;
+        };
+        let (_, started, mut entry) = self.traced.remove(i);
+        entry.status = Some(status.raw_code());
+        entry.duration_us = started.elapsed().as_micros();
+        self.trace(entry);
     }
 
     pub fn execute_pipeline(&mut self, pipeline: Pipeline, background: bool) -> Result<ExitStatus> {
         let has_bang = pipeline.has_bang();
+        let job_control = self.job_control;
+        let command = match background || job_control {
+            true => pipeline.to_string().trim().to_string(),
+            false => String::new(),
+        };
         let pipeline_cmds = pipeline.full();
         let pipeline_amount = pipeline_cmds.len();
-        let mut pipeline_iter = pipeline_cmds.into_iter().peekable();
 
-        let mut stdin = 0;
+        let mut stdin = match background && !job_control {
+            true => background_stdin()?,
+            false => 0,
+        };
         let mut last_status = ExitStatus::from_code(0);
-
-        'outer: while let Some(cmd) = pipeline_iter.next() {
-            if let Command::Simple(cmd) = cmd {
+        let mut children = Vec::new();
+        let mut error = None;
+
+        // the status of every command that finished, with its position in
+        // the pipeline, for `pipefail`
+        let mut statuses = Vec::new();
+
+        // with job control, the first command started determines the process
+        // group, and whether the last one ran in the shell decides where
+        // the status comes from
+        let mut pgid = None;
+        let mut last_started = false;
+
+        // the priority of a background job is lowered as soon as each of
+        // its commands is started, before it has had the time to start
+        // others that would keep the priority of the shell
+        let nice = match background {
+            true => self.background_nice(),
+            false => None,
+        };
+
+        // the output of a background pipeline is collected instead of being
+        // written over the input line, if `capture_bg` is set
+        let capture = match background && self.options.capture_bg {
+            true => Some(pipe2(OFlag::O_CLOEXEC)?),
+            false => None,
+        };
+        let (stdout, stderr) = match capture {
+            Some((_, capture_write)) => (capture_write, capture_write),
+            None => (1, 2),
+        };
+
+        for (i, cmd) in pipeline_cmds.into_iter().enumerate() {
+            let is_last = i + 1 == pipeline_amount;
+            let (pipe_read, stdout) = if is_last {
+                (None, stdout)
+            } else {
                 let (pipe_read, pipe_write) = pipe()?;
+                (Some(pipe_read), pipe_write)
+            };
+
+            // a lone builtin or group runs in the current shell unless it is
+            // in the background, and so does the last command of a pipeline
+            // if `lastpipe` is set and job control is off, since otherwise
+            // the other commands would not own the terminal
+            let stage = Stage {
+                stdin,
+                stdout,
+                stderr,
+                pipe_read,
+                in_shell: is_last
+                    && !background
+                    && (pipeline_amount == 1 || (self.options.lastpipe && !job_control)),
+                wait: is_last && !background && !job_control,
+                background,
+                pgroup: (job_control || background).then(|| pgid.unwrap_or(Pid::from_raw(0))),
+            };
+            let started = match cmd {
+                Command::Simple(cmd) => self.start_simple_command(&cmd, stage, pipeline_amount),
+                Command::Compound(cmd, redirections) => {
+                    self.start_compound_command(cmd, &redirections, stage)
+                }
+                Command::FunctionDefinition(definition) => {
+                    // like an assignment, a definition in a pipeline is only
+                    // seen by its own subshell
+                    if let (Some(body), true) = (definition.body, stage.in_shell) {
+                        self.functions.insert(definition.name.name, body);
+                    }
+                    Ok(Started::Finished(ExitStatus::from_code(0)))
+                }
+            };
+
+            // the pipe ends belong to the started command from here on
+            if stdin != 0 {
+                close(stdin)?;
+                stdin = 0;
+            }
+            if let Some(pipe_read) = pipe_read {
+                close(stdout)?;
+                stdin = pipe_read;
+            }
 
-                let stdout = if pipeline_iter.peek().is_some() {
-                    pipe_write
+            match started {
+                Ok(Started::Finished(status)) => {
+                    last_status = status;
+                    statuses.push((i, status));
+                }
+                Ok(Started::Running(child)) => {
+                    if let Some(nice) = nice {
+                        let _ = util::lower_priority(util::Priority::Process(child.pid()), nice);
+                    }
+                    pgid.get_or_insert(child.pid());
+                    last_started = is_last;
+                    children.push((i, child));
+                }
+                Ok(Started::Skipped) => {}
+                Ok(Started::Aborted) => {
+                    last_status = ExitStatus::from_code(1);
+                    statuses.push((i, last_status));
+                    break;
+                }
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if stdin != 0 {
+            close(stdin)?;
+        }
+
+        let output = match capture {
+            Some((capture_read, capture_write)) => {
+                close(capture_write)?;
+                Some(Output::capture(capture_read))
+            }
+            None => None,
+        };
+
+        if (background || job_control) && !children.is_empty() {
+            let pids = children.iter().map(|(_, child)| child.pid()).collect();
+            if background {
+                self.add_background_job(command, pids, pgid, output);
+            } else {
+                let id = self
+                    .jobs
+                    .add(command, pids, pgid, output, self.options.pipefail);
+
+                // the job comes before the command that ran in the shell, if
+                // any
+                let status = self.run_in_foreground(id, false)?;
+                if last_started {
+                    last_status = status;
+                }
+                statuses.insert(0, (0, status));
+            }
+        } else if !background {
+            // earlier commands of the pipeline are waited for once the last
+            // one has finished, so that they all run concurrently
+            for (i, child) in children {
+                let status = self.wait_for(child)?;
+                #[cfg(feature = "serde")]
+                self.trace_finished(child.pid(), status);
+                statuses.push((i, status));
+            }
+        }
+
+        if self.options.pipefail && !background {
+            statuses.sort_by_key(|(i, _)| *i);
+            if let Some(&(_, failed)) = statuses.iter().rev().find(|(_, status)| !status.is_ok()) {
+                last_status = failed;
+            }
+        }
+
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        if has_bang {
+            last_status = !last_status;
+        }
+        self.last_status = last_status;
+        Ok(last_status)
+    }
+
+    fn start_simple_command(
+        &mut self,
+        cmd: &SimpleCommand,
+        stage: Stage,
+        pipeline_amount: usize,
+    ) -> Result<Started> {
+        let Stage {
+            stdin,
+            stdout,
+            stderr,
+            pipe_read,
+            in_shell,
+            wait,
+            background,
+            pgroup,
+        } = stage;
+
+        self.debug_trap(cmd);
+        self.expansion_error = None;
+
+        let Some((fds, redirections)) = self.redirect(cmd.redirections(), stdin, stdout, stderr)?
+        else {
+            return Ok(Started::Aborted);
+        };
+
+        let assignments = {
+            let mut assignments = HashMap::new();
+            for assignment in cmd.assignments() {
+                let rhs = if let Some(rhs) = &assignment.rhs {
+                    expand::expand_assignment(rhs.clone(), self)
                 } else {
-                    1
+                    Default::default()
                 };
+                assignments.insert(assignment.lhs.to_string(), rhs);
+            }
+            assignments
+        };
+
+        let context = ExecutionContext {
+            stdin,
+            stdout,
+            stderr,
+            fds,
+            assignments,
+            close: pipe_read.into_iter().collect(),
+            pgroup,
+        };
+
+        if let Some(e) = self.expansion_error.take() {
+            return Err(e);
+        }
 
-                let mut fds = Vec::new();
+        if cmd.name().is_none() {
+            self.xtrace(cmd, &context.assignments, &[]);
 
-                for redirection in cmd.redirections() {
-                    let Redirection::File {
-                        input_fd,
-                        ty,
-                        target,
-                        ..
-                    } = redirection else {
-                        continue;
-                    };
+            // as in other shells, assigning to a readonly variable stops a
+            // script, and the rest of the line in an interactive shell
+            if pipeline_amount == 1 && !background {
+                for (key, val) in context.assignments {
+                    self.variables.set(key, val)?;
+                }
+            }
 
-                    let target = target.clone().expand(self).join(" ");
-                    match ty.default_src_fd(&target) {
-                        Ok(mut src_fd) => {
-                            let dst_fd = input_fd.unwrap_or_else(|| ty.default_dst_fd());
-                            if src_fd == FileDescriptor::Stdin {
-                                src_fd = FileDescriptor::from(stdin);
-                            } else if src_fd == FileDescriptor::Stdout {
-                                src_fd = FileDescriptor::from(stdout);
-                            }
-                            fds.push((src_fd, dst_fd));
-                        }
-                        Err(e) => {
-                            eprintln!("psh: {e}");
-                            break 'outer;
-                        }
-                    }
+            // the status of bare assignments is that of their last command
+            // substitution
+            let has_cmd_sub = cmd
+                .assignments()
+                .filter_map(|assignment| assignment.rhs.as_ref())
+                .flat_map(|rhs| &rhs.expansions)
+                .any(|exp| matches!(exp, Expansion::Command { .. }));
+            return Ok(match has_cmd_sub {
+                true => Started::Finished(self.last_status),
+                false => Started::Skipped,
+            });
+        }
+
+        let args = cmd.expand_into_args(self);
+        if self.cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        if let Some(e) = self.expansion_error.take() {
+            return Err(e);
+        }
+        if args.is_empty() {
+            return Ok(Started::Skipped);
+        }
+
+        self.xtrace(cmd, &context.assignments, &args);
+        // aliases were substituted before parsing, so a name left is not one;
+        // like any other failed command, it only ends with a status, which
+        // the rest of the and-or list sees
+        if !self.has_command(&args[0]) && !self.has_builtin(&args[0]) {
+            self.report(Error::UnknownCommand(args[0].to_string()));
+            return Ok(Started::Finished(ExitStatus::from_code(127)));
+        }
+
+        // the assignments only apply to the command, but a readonly variable
+        // cannot be changed even for that long
+        let readonly = |name: &&String| self.variables.get(name).is_some_and(|var| var.readonly);
+        if let Some(name) = context.assignments.keys().find(readonly) {
+            self.report(Error::Readonly(name.clone()));
+            return Ok(Started::Finished(ExitStatus::from_code(1)));
+        }
+
+        // the working directory may have been removed, which should not stop
+        // the command from running
+        #[cfg(feature = "serde")]
+        let (started, cwd) = (
+            Instant::now(),
+            self.tracer.is_some().then(|| match env::current_dir() {
+                Ok(cwd) => cwd.display().to_string(),
+                Err(_) => self.get_value_of("PWD").unwrap_or_default(),
+            }),
+        );
+
+        let builtin = self.has_builtin(&args[0]);
+        let (status, child) = if builtin && in_shell {
+            (Some(self.execute_builtin(&args, context)?), None)
+        } else {
+            let child = if builtin {
+                Child::Subshell(self.spawn_builtin(&args, context)?)
+            } else {
+                Child::External(self.spawn_external_command(&args, context)?)
+            };
+
+            if wait {
+                (Some(self.wait_for(child)?), Some(child))
+            } else {
+                (None, Some(child))
+            }
+        };
+
+        #[cfg(feature = "serde")]
+        if self.tracer.is_some() {
+            let kind = match builtin {
+                true if self.functions.contains_key(&args[0]) => trace::CommandKind::Function,
+                true => trace::CommandKind::Builtin,
+                false => trace::CommandKind::External,
+            };
+            let entry = trace::TraceEntry {
+                argv: args,
+                redirections: redirections
+                    .into_iter()
+                    .map(|(fd, ty, target)| trace::TraceRedirection::new(fd, ty, target))
+                    .collect(),
+                cwd: cwd.unwrap_or_default(),
+                pid: child.map_or_else(getpid, Child::pid).as_raw(),
+                kind,
+                background,
+                status: status.map(|s| s.raw_code()),
+                duration_us: started.elapsed().as_micros(),
+            };
+
+            // a command that is waited for later is recorded once it has
+            // finished, unless it runs in the background
+            match child {
+                Some(child) if status.is_none() && !background => {
+                    self.traced.push((child.pid(), started, entry));
                 }
+                _ => self.trace(entry),
+            }
+        }
 
-                let assignments = {
-                    let mut assignments = HashMap::new();
-                    for assignment in cmd.assignments() {
-                        let rhs = if let Some(rhs) = &assignment.rhs {
-                            rhs.clone().expand(self).join(" ")
-                        } else {
-                            Default::default()
-                        };
-                        assignments.insert(assignment.lhs.to_string(), rhs);
-                    }
-                    assignments
-                };
+        #[cfg(not(feature = "serde"))]
+        let _ = (redirections, background);
 
-                let context = ExecutionContext {
-                    stdin,
-                    stdout,
-                    stderr: 2,
-                    fds,
-                    background,
-                    assignments,
-                };
+        Ok(match (status, child) {
+            (Some(status), _) => Started::Finished(status),
+            (None, Some(child)) => Started::Running(child),
+            (None, None) => Started::Skipped,
+        })
+    }
 
-                if cmd.name().is_some() {
-                    let mut args = cmd.expand_into_args(self);
-
-                    if !args.is_empty() {
-                        let alias_args = self.expand_alias(&args[0]);
-                        args.splice(0..1, alias_args);
-                        last_status = if !self.has_executable(&args[0]) {
-                            return Err(Error::UnknownCommand(args[0].to_string()));
-                        } else if cmd.is_builtin() {
-                            // TODO: assignments
-                            self.execute_builtin(&args, context)?
-                        } else {
-                            self.execute_external_command(&args, context)?
-                        };
-                    }
-                } else if pipeline_amount == 1 {
-                    for (key, val) in context.assignments {
-                        self.assignments.insert(key, val);
+    /// Opens the files of `redirections`, returning what they redirect, or
+    /// `None` if one of them could not be opened.
+    fn redirect<'a>(
+        &mut self,
+        redirections: impl IntoIterator<Item = &'a Redirection>,
+        stdin: RawFd,
+        stdout: RawFd,
+        stderr: RawFd,
+    ) -> Result<Option<Redirected>> {
+        let mut fds = Vec::new();
+        let mut targets = Vec::new();
+
+        for redirection in redirections {
+            let (input_fd, ty, target) = match redirection {
+                Redirection::File {
+                    input_fd,
+                    ty,
+                    target,
+                    ..
+                } => (input_fd, ty, target),
+
+                Redirection::Here {
+                    input_fd,
+                    ty,
+                    end,
+                    content,
+                    ..
+                } => {
+                    let document = self.here_document(ty, end, content);
+                    let dst_fd = input_fd.unwrap_or(FileDescriptor::Stdin);
+                    fds.push((here_document_fd(document)?, dst_fd));
+                    continue;
+                }
+            };
+
+            let target = target.clone().expand(self).join(" ");
+            match ty.default_src_fd(&target) {
+                Ok(mut src_fd) => {
+                    let dst_fd = input_fd.unwrap_or_else(|| ty.default_dst_fd());
+                    if src_fd == FileDescriptor::Stdin {
+                        src_fd = FileDescriptor::from(stdin);
+                    } else if src_fd == FileDescriptor::Stdout {
+                        src_fd = FileDescriptor::from(stdout);
+                    } else if src_fd == FileDescriptor::Stderr {
+                        src_fd = FileDescriptor::from(stderr);
                     }
+                    fds.push((src_fd, dst_fd));
+                    targets.push((dst_fd, ty.clone(), target));
                 }
+                Err(e) => {
+                    self.report(e);
+                    return Ok(None);
+                }
+            }
+        }
 
-                stdin = pipe_read;
-                close(pipe_write)?;
+        Ok(Some((fds, targets)))
+    }
+
+    /// Starts a compound command such as `{ ...; }` with its redirections,
+    /// which apply to all of its commands.
+    fn start_compound_command(
+        &mut self,
+        cmd: CompoundCommand,
+        redirections: &[Redirection],
+        stage: Stage,
+    ) -> Result<Started> {
+        let Stage {
+            stdin,
+            stdout,
+            stderr,
+            pipe_read,
+            in_shell,
+            wait,
+            pgroup,
+            ..
+        } = stage;
+
+        let Some((fds, _)) = self.redirect(redirections, stdin, stdout, stderr)? else {
+            return Ok(Started::Aborted);
+        };
+
+        let context = ExecutionContext {
+            stdin,
+            stdout,
+            stderr,
+            fds,
+            assignments: Default::default(),
+            close: pipe_read.into_iter().collect(),
+            pgroup,
+        };
+
+        // a subshell runs in a child even on its own, so that it cannot
+        // change the state of the shell
+        if in_shell && !matches!(cmd, CompoundCommand::Subshell(_)) {
+            let status = self.execute_redirected(context, |engine| engine.execute_compound(cmd))?;
+            return Ok(Started::Finished(status));
+        }
+
+        let child =
+            Child::Subshell(self.spawn_redirected(context, |engine| engine.execute_compound(cmd))?);
+        Ok(match wait {
+            true => Started::Finished(self.wait_for(child)?),
+            false => Started::Running(child),
+        })
+    }
+
+    /// Executes the commands of `cmd`, returning the status of the last one.
+    fn execute_compound(&mut self, cmd: CompoundCommand) -> Result<ExitStatus> {
+        match cmd {
+            CompoundCommand::Brace(group) => self.execute_compound_list(group.body),
+            CompoundCommand::Subshell(subshell) => self.execute_compound_list(subshell.body),
+            CompoundCommand::For(clause) => self.execute_for_clause(clause),
+            CompoundCommand::Case(clause) => self.execute_case_clause(clause),
+            CompoundCommand::If(clause) => self.execute_if_clause(clause),
+            CompoundCommand::While(clause) => {
+                self.execute_loop(clause.predicate, clause.body.body, false)
+            }
+            CompoundCommand::Until(clause) => {
+                self.execute_loop(clause.predicate, clause.body.body, true)
+            }
+        }
+    }
+
+    /// Executes the commands of `list`, returning the status of the last one,
+    /// or 0 if none ran.
+    fn execute_compound_list(&mut self, list: CompoundList) -> Result<ExitStatus> {
+        let codes = self.execute_lists(list.list_with_separator())?;
+        Ok(codes.last().copied().unwrap_or(ExitStatus::from_code(0)))
+    }
+
+    /// Executes the condition of an `if` or a loop, whose failures do not
+    /// trigger the ERR trap, returning whether it succeeded.
+    fn execute_condition(&mut self, list: CompoundList) -> Result<bool> {
+        let unchecked = self.unchecked;
+        self.unchecked = true;
+        let status = self.execute_compound_list(list);
+        self.unchecked = unchecked;
+        Ok(status?.is_ok())
+    }
+
+    /// Executes the body after the first condition of `clause` that succeeds,
+    /// or its `else` part, with a status of 0 if neither runs.
+    fn execute_if_clause(&mut self, clause: IfClause) -> Result<ExitStatus> {
+        if self.execute_condition(clause.predicate)? {
+            return self.execute_compound_list(clause.body);
+        }
+
+        let Some(else_part) = clause.else_part else {
+            return Ok(ExitStatus::from_code(0));
+        };
+        for elif in else_part.elseifs {
+            if self.execute_condition(elif.predicate)? {
+                return self.execute_compound_list(elif.body);
             }
         }
+        match else_part.else_part {
+            Some((_, body)) => self.execute_compound_list(body),
+            None => Ok(ExitStatus::from_code(0)),
+        }
+    }
+
+    /// Executes `body` for as long as `predicate` succeeds, or fails if
+    /// `until` is set, returning the status of the last iteration, or 0 if
+    /// there was none. A ^C stops the loop between iterations, even if its
+    /// commands only run in the shell, such as `while :; do x=1; done`.
+    fn execute_loop(
+        &mut self,
+        predicate: CompoundList,
+        body: CompoundList,
+        until: bool,
+    ) -> Result<ExitStatus> {
+        self.in_loop(|engine| {
+            let mut status = ExitStatus::from_code(0);
+            loop {
+                if engine.cancel.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                let done = engine.execute_condition(predicate.clone())? == until;
+                if engine.loop_flow() || done {
+                    return Ok(status);
+                }
+                status = engine.execute_compound_list(body.clone())?;
+                if engine.loop_flow() {
+                    return Ok(status);
+                }
+            }
+        })
+    }
+
+    /// Executes the body of `clause` with its variable set to each of its
+    /// words in turn, or to each positional parameter if it has none. As in
+    /// POSIX, the variable is set in the shell itself, and keeps the last
+    /// value after the loop.
+    fn execute_for_clause(&mut self, clause: ForClause) -> Result<ExitStatus> {
+        let Some(name) = clause.name else {
+            return Ok(ExitStatus::from_code(0));
+        };
+
+        self.expansion_error = None;
+        let values = match clause.wordlist {
+            Some(wordlist) => {
+                let mut values = Vec::new();
+                for word in wordlist.words {
+                    values.append(&mut word.expand(self));
+                }
+                values
+            }
+            None => self.parameters.clone(),
+        };
+        if let Some(e) = self.expansion_error.take() {
+            return Err(e);
+        }
+
+        self.in_loop(|engine| {
+            let mut status = ExitStatus::from_code(0);
+            for value in values {
+                if engine.cancel.is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+                engine.variables.set(&name.name, value)?;
+                status = engine.execute_compound_list(clause.body.body.clone())?;
+                if engine.loop_flow() {
+                    break;
+                }
+            }
+            Ok(status)
+        })
+    }
+
+    /// Executes the body of the first item of `clause` with a pattern that
+    /// matches its word, with a status of 0 if none does.
+    fn execute_case_clause(&mut self, clause: CaseClause) -> Result<ExitStatus> {
+        let Some(word) = clause.word else {
+            return Ok(ExitStatus::from_code(0));
+        };
+
+        self.expansion_error = None;
+        let word = expand::expand_assignment(word, self);
+        for item in clause.items {
+            let pattern = item.pattern;
+            let patterns = iter::once(pattern.head).chain(pattern.tail.into_iter().map(|(_, w)| w));
+            for pattern in patterns {
+                let pattern = expand::expand_pattern(pattern, self);
+                if let Some(e) = self.expansion_error.take() {
+                    return Err(e);
+                }
+                if glob::matches_str(&pattern, &word) {
+                    return match item.body {
+                        Some(body) => self.execute_compound_list(body),
+                        None => Ok(ExitStatus::from_code(0)),
+                    };
+                }
+            }
+        }
+        Ok(ExitStatus::from_code(0))
+    }
+
+    /// Runs `f` as a loop, which `break` and `continue` apply to.
+    fn in_loop(&mut self, f: impl FnOnce(&mut Self) -> Result<ExitStatus>) -> Result<ExitStatus> {
+        self.loops += 1;
+        let result = f(self);
+        self.loops -= 1;
+        result
+    }
 
-        self.last_status = vec![last_status];
+    /// Handles a `break` or `continue` of the innermost loop after its
+    /// commands ran, returning whether the loop stops.
+    fn loop_flow(&mut self) -> bool {
+        match self.flow {
+            Some(Flow::Break(1)) => {
+                self.flow = None;
+                true
+            }
+            Some(Flow::Continue(1)) => {
+                self.flow = None;
+                false
+            }
+            Some(Flow::Break(n)) => {
+                self.flow = Some(Flow::Break(n - 1));
+                true
+            }
+            Some(Flow::Continue(n)) => {
+                self.flow = Some(Flow::Continue(n - 1));
+                true
+            }
+            Some(Flow::Return(_)) => true,
+            None => false,
+        }
+    }
 
-        Ok(if has_bang { !last_status } else { last_status })
+    /// The input that a here-document gives: its body, with the leading
+    /// tabs of every line removed for `<<-`, and expanded unless any part
+    /// of its delimiter is quoted.
+    fn here_document(&mut self, ty: &HereDocType, end: &Word, content: &Word) -> String {
+        let body = match ty {
+            HereDocType::Normal => content.name.clone(),
+            HereDocType::StripTabs => content
+                .name
+                .split_inclusive('\n')
+                .map(|line| line.trim_start_matches('\t'))
+                .collect(),
+        };
+
+        match end.name.contains(['\'', '"', '\\']) {
+            true => body,
+            false => expand::here_document(&body, self),
+        }
     }
 
     pub fn execute_and_or_list(
@@ -325,46 +1919,209 @@ impl Engine {
         and_or_list: AndOrList,
         background: bool,
     ) -> Result<Vec<ExitStatus>> {
-        let mut prev_status = self.execute_pipeline(and_or_list.head, background)?;
+        // the pipelines after the first depend on the ones before, so the
+        // whole list runs in a subshell of its own
+        if background && !and_or_list.tail.is_empty() {
+            self.spawn_and_or_list(and_or_list)?;
+            return Ok(vec![ExitStatus::from_code(0)]);
+        }
+
+        // only the last pipeline of the list can trigger the ERR trap, and
+        // a lone group only through its own commands
+        let mut checked = !and_or_list.head.has_bang() && and_or_list.tail.is_empty();
+        let mut group = is_group(&and_or_list.head);
+        let mut prev_status =
+            self.execute_checked_pipeline(and_or_list.head, background, checked)?;
         let mut codes = vec![prev_status];
 
-        for (op, _, expr) in and_or_list.tail {
+        let len = and_or_list.tail.len();
+        for (i, (op, _, expr)) in and_or_list.tail.into_iter().enumerate() {
+            if self.flow.is_some() {
+                break;
+            }
             match (op, prev_status.is_ok()) {
                 (LogicalOp::And(_), true) | (LogicalOp::Or(_), false) => {
-                    prev_status = self.execute_pipeline(expr, background)?;
+                    checked = !expr.has_bang() && i + 1 == len;
+                    group = is_group(&expr);
+                    prev_status = self.execute_checked_pipeline(expr, background, checked)?;
                     codes.push(prev_status);
                 }
-                _ => {}
+                _ => checked = false,
             }
         }
 
+        if checked && !group && !background && !prev_status.is_ok() && self.flow.is_none() {
+            self.failed(prev_status);
+        }
+
         Ok(codes)
     }
 
+    /// Executes `pipeline`, where `checked` tells whether its failure
+    /// triggers the ERR trap, and so whether the failures of the commands
+    /// in a group of it can.
+    fn execute_checked_pipeline(
+        &mut self,
+        pipeline: Pipeline,
+        background: bool,
+        checked: bool,
+    ) -> Result<ExitStatus> {
+        let unchecked = self.unchecked;
+        self.unchecked = unchecked || !checked;
+        let status = self.execute_pipeline(pipeline, background);
+        self.unchecked = unchecked;
+        status
+    }
+
+    /// Runs `and_or_list` in a subshell in the background, in a process
+    /// group of its own, as a single job.
+    fn spawn_and_or_list(&mut self, and_or_list: AndOrList) -> Result<()> {
+        let command = and_or_list.to_string().trim().to_string();
+        let job_control = self.job_control;
+        let capture = match self.options.capture_bg {
+            true => Some(pipe2(OFlag::O_CLOEXEC)?),
+            false => None,
+        };
+
+        let child = util::spawn_subshell(|| {
+            self.enter_subshell();
+
+            let run = || -> Result<i32> {
+                setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+                util::restore_signals()?;
+                if !job_control {
+                    let stdin = background_stdin()?;
+                    dup2(stdin, 0)?;
+                    close(stdin)?;
+                }
+                if let Some((capture_read, capture_write)) = capture {
+                    close(capture_read)?;
+                    dup2(capture_write, 1)?;
+                    dup2(capture_write, 2)?;
+                }
+
+                let status = self
+                    .execute_and_or_list(and_or_list, false)?
+                    .last()
+                    .copied();
+                io::stdout().flush()?;
+                Ok(status.map_or(0, |status| status.raw_code()))
+            };
+
+            // the subshell must never return into the shell it was forked from
+            let code = run().unwrap_or_else(|e| {
+                self.report(e);
+                1
+            });
+            std::process::exit(code);
+        })?;
+        join_pgroup(child, Some(child));
+
+        let output = match capture {
+            Some((capture_read, capture_write)) => {
+                close(capture_write)?;
+                Some(Output::capture(capture_read))
+            }
+            None => None,
+        };
+        self.add_background_job(command, vec![child], Some(child), output);
+        Ok(())
+    }
+
+    /// Adds the processes `pids`, started in the background, as a job, and
+    /// tells its number and the pid of its last process in an interactive
+    /// shell, as in `[1] 12345`.
+    fn add_background_job(
+        &mut self,
+        command: String,
+        pids: Vec<Pid>,
+        pgid: Option<Pid>,
+        output: Option<Output>,
+    ) {
+        let last = pids[pids.len() - 1];
+        let id = self
+            .jobs
+            .add(command, pids, pgid, output, self.options.pipefail);
+
+        self.last_background = Some(last);
+        if self.interactive {
+            eprintln!("[{id}] {last}");
+        }
+    }
+
     pub fn execute(&mut self, cmd: CompleteCommand) -> Result<Vec<ExitStatus>> {
-        let lists_with_separator = cmd.list_with_separator();
+        self.execute_lists(cmd.list_with_separator())
+    }
 
+    /// Executes the and-or lists of a command one after another, those
+    /// followed by `&` in the background.
+    fn execute_lists(
+        &mut self,
+        lists_with_separator: Vec<(AndOrList, SeparatorOp)>,
+    ) -> Result<Vec<ExitStatus>> {
         let mut codes = Vec::new();
 
         for (and_or_list, separator) in lists_with_separator {
+            if self.flow.is_some() {
+                break;
+            }
             let res = self.execute_and_or_list(and_or_list, separator.is_async());
 
             if let Err(e @ Error::UnknownCommand(_)) = res {
-                codes.push(ExitStatus::from_code(127));
-                eprintln!("psh: {e}");
+                let status = ExitStatus::from_code(127);
+                codes.push(status);
+                self.report(e);
+                self.failed(status);
             } else {
                 codes.append(&mut res?);
             }
+
+            self.signal_traps();
         }
 
         Ok(codes)
     }
 
-    fn walk_ast(&mut self, ast: SyntaxTree) -> Result<Vec<ExitStatus>> {
+    /// Executes the commands of `ast`, which is the content of the file
+    /// being executed if `in_file`, in which case the line of each command
+    /// is kept in its frame.
+    fn walk_ast(&mut self, ast: SyntaxTree, in_file: bool) -> Result<Vec<ExitStatus>> {
+        let newlines = |s: String| s.matches('\n').count();
+
         let mut results = Vec::new();
-        if let Some((cmds, _)) = ast.commands {
-            for cmd in cmds.full() {
-                results.append(&mut self.execute(cmd)?);
+        let mut line = 1 + newlines(ast.leading.to_string());
+
+        if let Some((cmds, linebreak)) = ast.commands {
+            let tail = cmds.tail.into_iter().map(|(nl, cmd)| (Some(nl), cmd));
+            let mut cmds = iter::once((None, cmds.head)).chain(tail).peekable();
+            while let Some((newline_list, cmd)) = cmds.next() {
+                if self.flow.is_some() {
+                    break;
+                }
+                line += newline_list.map_or(0, |nl| newlines(nl.to_string()));
+                if let Some(frame) = self.frames.last_mut().filter(|_| in_file) {
+                    frame.line = line;
+                }
+
+                // aliases are substituted as each command is about to run, so
+                // that the ones defined by the commands before apply, and the
+                // command is parsed again along with its here-documents, which
+                // follow it
+                let text = cmd.to_string();
+                let following = match cmds.peek() {
+                    Some((Some(newline_list), _)) => newline_list.to_string(),
+                    _ => linebreak.to_string(),
+                };
+                match alias::substitute(&format!("{text}{following}"), &self.aliases) {
+                    Some(substituted) => {
+                        let cmds = parse(substituted, false)?.commands;
+                        for cmd in cmds.into_iter().flat_map(|(cmds, _)| cmds.full()) {
+                            results.append(&mut self.execute(cmd)?);
+                        }
+                    }
+                    None => results.append(&mut self.execute(cmd)?),
+                }
+                line += newlines(text);
             }
         }
         Ok(results)
@@ -377,7 +2134,29 @@ impl Default for Engine {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Where a runnable command name was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandSource {
+    Alias,
+    Abbreviation,
+    Function,
+    Builtin,
+    Path(PathBuf),
+}
+
+impl fmt::Display for CommandSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Alias => write!(f, "alias"),
+            Self::Abbreviation => write!(f, "abbreviation"),
+            Self::Function => write!(f, "function"),
+            Self::Builtin => write!(f, "builtin"),
+            Self::Path(dir) => write!(f, "{}", path::compress_tilde(dir.display().to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExitStatus {
     Code(i32),
     Signal(i32),
@@ -0,0 +1,205 @@
+/// Shell options that can be toggled with `shopt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Run the last command of a pipeline in the current shell rather than
+    /// in a subshell, so that e.g. `echo foo | read x` sets `x`. Like in
+    /// bash, this only applies when job control is off.
+    pub lastpipe: bool,
+
+    /// Start external commands with `posix_spawn` instead of `fork` and
+    /// `exec` when possible, which is considerably faster.
+    pub posix_spawn: bool,
+
+    /// Collect the output of pipelines started in the background, so that
+    /// it can be shown with `jobs -o` instead of being written over the
+    /// input line.
+    pub capture_bg: bool,
+
+    /// Sort completion candidates by how often and recently they have been
+    /// used, rather than alphabetically.
+    pub frecency: bool,
+
+    /// Keep the ERR trap in subshells, such as builtins run in a pipeline.
+    /// Otherwise it only applies to the shell itself.
+    pub errtrace: bool,
+
+    /// Write a report that can be attached to an issue when psh crashes,
+    /// with secrets in the offending line masked.
+    pub crash_report: bool,
+
+    /// Show the status and duration of every command on the line it was
+    /// entered on, or below its output.
+    pub cmd_status: bool,
+
+    /// Expand references to the history in entered lines, such as `!!` for
+    /// the previous command and `!$` for its last word, as csh and bash do.
+    pub histexpand: bool,
+
+    /// Show the line changed by expanding an abbreviation or the history
+    /// when it is entered, rather than executing it right away, so that it
+    /// can be checked or edited first. It is executed once entered again.
+    pub histverify: bool,
+
+    /// Read the key bindings and editing mode of readline from `$INPUTRC`
+    /// or `~/.inputrc` once the init files have been run.
+    pub inputrc: bool,
+
+    /// Leave words like `*.rs` as they are, rather than expanding them to
+    /// the pathnames they match.
+    pub noglob: bool,
+
+    /// Redraw the prompt of every command that has been entered as
+    /// `$PSH_TRANSIENT_PROMPT`, which is shorter, to keep the scrollback
+    /// compact.
+    pub transient_prompt: bool,
+
+    /// Expand `a{b,c}` to `ab ac` and `{1..3}` to `1 2 3`, as bash does.
+    /// Set with `set -B`.
+    pub braceexpand: bool,
+
+    /// Exit as soon as a pipeline fails, unless it is negated with `!` or
+    /// followed by `&&` or `||`. Set with `set -e`.
+    pub errexit: bool,
+
+    /// Fail instead of expanding a variable that is not set to nothing.
+    /// Set with `set -u`.
+    pub nounset: bool,
+
+    /// Give pipelines the status of their last command that failed, if any,
+    /// rather than that of their last command. Set with `set -o pipefail`.
+    pub pipefail: bool,
+
+    /// Print every command to stderr before executing it, once it has been
+    /// expanded. Set with `set -x`.
+    pub xtrace: bool,
+
+    /// Edit the input line with Emacs-like keys. Set with `set -o emacs`,
+    /// which unsets `vi`.
+    pub emacs: bool,
+
+    /// Edit the input line with Vi-like keys, starting each line in insert
+    /// mode. Set with `set -o vi`, which unsets `emacs`.
+    pub vi: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            capture_bg: false,
+            crash_report: false,
+            cmd_status: false,
+            lastpipe: false,
+            posix_spawn: true,
+            frecency: true,
+            errtrace: false,
+            histexpand: true,
+            histverify: false,
+            inputrc: false,
+            noglob: false,
+            transient_prompt: false,
+            braceexpand: false,
+            errexit: false,
+            nounset: false,
+            pipefail: false,
+            xtrace: false,
+            emacs: true,
+            vi: false,
+        }
+    }
+}
+
+impl Options {
+    pub const NAMES: &'static [&'static str] = &[
+        "capture_bg",
+        "cmd_status",
+        "crash_report",
+        "errtrace",
+        "frecency",
+        "histexpand",
+        "histverify",
+        "inputrc",
+        "lastpipe",
+        "noglob",
+        "posix_spawn",
+        "transient_prompt",
+    ];
+
+    /// The options set with the `set` builtin rather than `shopt`, as POSIX
+    /// specifies, along with their short flags.
+    pub const SET_NAMES: &'static [(&'static str, Option<char>)] = &[
+        ("braceexpand", Some('B')),
+        ("emacs", None),
+        ("errexit", Some('e')),
+        ("nounset", Some('u')),
+        ("pipefail", None),
+        ("vi", None),
+        ("xtrace", Some('x')),
+    ];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "capture_bg" => Some(self.capture_bg),
+            "cmd_status" => Some(self.cmd_status),
+            "crash_report" => Some(self.crash_report),
+            "errtrace" => Some(self.errtrace),
+            "frecency" => Some(self.frecency),
+            "histexpand" => Some(self.histexpand),
+            "histverify" => Some(self.histverify),
+            "inputrc" => Some(self.inputrc),
+            "lastpipe" => Some(self.lastpipe),
+            "noglob" => Some(self.noglob),
+            "posix_spawn" => Some(self.posix_spawn),
+            "transient_prompt" => Some(self.transient_prompt),
+            "braceexpand" => Some(self.braceexpand),
+            "errexit" => Some(self.errexit),
+            "nounset" => Some(self.nounset),
+            "pipefail" => Some(self.pipefail),
+            "xtrace" => Some(self.xtrace),
+            "emacs" => Some(self.emacs),
+            "vi" => Some(self.vi),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "capture_bg" => Some(&mut self.capture_bg),
+            "cmd_status" => Some(&mut self.cmd_status),
+            "crash_report" => Some(&mut self.crash_report),
+            "errtrace" => Some(&mut self.errtrace),
+            "frecency" => Some(&mut self.frecency),
+            "histexpand" => Some(&mut self.histexpand),
+            "histverify" => Some(&mut self.histverify),
+            "inputrc" => Some(&mut self.inputrc),
+            "lastpipe" => Some(&mut self.lastpipe),
+            "noglob" => Some(&mut self.noglob),
+            "posix_spawn" => Some(&mut self.posix_spawn),
+            "transient_prompt" => Some(&mut self.transient_prompt),
+            "braceexpand" => Some(&mut self.braceexpand),
+            "errexit" => Some(&mut self.errexit),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            "emacs" => Some(&mut self.emacs),
+            "vi" => Some(&mut self.vi),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_name_is_an_option() {
+        let mut options = Options::default();
+        let set_names = Options::SET_NAMES.iter().map(|(name, _)| name);
+        for name in Options::NAMES.iter().chain(set_names) {
+            let value = options.get(name).unwrap();
+            *options.get_mut(name).unwrap() = !value;
+            assert_eq!(Some(!value), options.get(name));
+        }
+        assert_eq!(None, options.get("nonexistent"));
+    }
+}
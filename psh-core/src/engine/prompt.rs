@@ -0,0 +1,229 @@
+//! The backslash escapes of prompts, as in bash: `\u` is the user, `\w` the
+//! working directory, and so on.
+
+use std::env;
+use std::mem::MaybeUninit;
+use std::path::Path;
+
+use nix::libc;
+use nix::unistd::{self, Uid, User};
+
+use crate::engine::quote;
+use crate::{path, Engine};
+
+/// Marks the start of text that takes up no room on the terminal, such as
+/// the escape sequence of a color, written `\[` in a prompt. The line
+/// editor leaves it out, along with `END_INVISIBLE`, as readline does.
+pub const START_INVISIBLE: char = '\x01';
+
+/// Marks the end of the text started by `START_INVISIBLE`, written `\]`.
+pub const END_INVISIBLE: char = '\x02';
+
+/// Replaces the escapes in `prompt`. Those that are not known are left as
+/// they are.
+pub fn expand_escapes(prompt: &str, engine: &Engine) -> String {
+    let mut time = None;
+    replace_escapes(prompt, |c| {
+        let value = match c {
+            'u' => user(),
+            'h' => hostname().split('.').next().unwrap_or_default().to_string(),
+            'H' => hostname(),
+            'w' => quote::escape_controls(&path::compress_tilde(cwd(engine))).into_owned(),
+            'W' => {
+                let cwd = path::compress_tilde(cwd(engine));
+                let name = match cwd.as_str() {
+                    "/" | "~" => &cwd,
+                    _ => cwd.rsplit('/').next().unwrap_or_default(),
+                };
+                quote::escape_controls(name).into_owned()
+            }
+            's' => {
+                let name = Path::new(&engine.shell_name).file_name();
+                name.map_or(engine.shell_name.clone(), |name| {
+                    name.to_string_lossy().to_string()
+                })
+            }
+            'j' => engine.jobs.iter().count().to_string(),
+            '$' if Uid::effective().is_root() => "#".to_string(),
+            '$' => "$".to_string(),
+            't' | 'T' | '@' | 'A' | 'd' => time.get_or_insert_with(LocalTime::now).format(c),
+            _ => return escape(c).map(String::from),
+        };
+        Some(value)
+    })
+}
+
+/// What the escapes that do not depend on the shell stand for.
+fn escape(c: char) -> Option<&'static str> {
+    Some(match c {
+        'n' => "\n",
+        'a' => "\x07",
+        'e' => "\x1b",
+        '[' => "\x01",
+        ']' => "\x02",
+        '\\' => "\\",
+        _ => return None,
+    })
+}
+
+/// Replaces every backslash in `prompt` and the character after it with what
+/// `value` gives for the character, leaving them as they are if it gives
+/// nothing.
+fn replace_escapes(prompt: &str, mut value: impl FnMut(char) -> Option<String>) -> String {
+    let mut replaced = String::with_capacity(prompt.len());
+    let mut chars = prompt.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            replaced.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(c) => match value(c) {
+                Some(value) => replaced.push_str(&value),
+                None => {
+                    replaced.push('\\');
+                    replaced.push(c);
+                }
+            },
+            None => replaced.push('\\'),
+        }
+    }
+
+    replaced
+}
+
+fn user() -> String {
+    match User::from_uid(Uid::effective()) {
+        Ok(Some(user)) => user.name,
+        _ => env::var("USER").unwrap_or_default(),
+    }
+}
+
+fn hostname() -> String {
+    unistd::gethostname()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn cwd(engine: &Engine) -> String {
+    match engine.get_value_of("PWD") {
+        Some(pwd) => pwd,
+        None => env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// The parts of the local time that prompts show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LocalTime {
+    hour: u32,
+    minute: u32,
+    second: u32,
+
+    /// From 0 for Sunday.
+    weekday: usize,
+
+    /// From 0 for January.
+    month: usize,
+    day: u32,
+}
+
+impl LocalTime {
+    fn now() -> Self {
+        let mut tm = MaybeUninit::<libc::tm>::zeroed();
+        // SAFETY: `time` accepts a null pointer, and `localtime_r` only
+        //         writes to the `tm` it is given
+        let tm = unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&now, tm.as_mut_ptr());
+            tm.assume_init()
+        };
+
+        Self {
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+            weekday: tm.tm_wday as usize % 7,
+            month: tm.tm_mon as usize % 12,
+            day: tm.tm_mday as u32,
+        }
+    }
+
+    /// The time as the escape `c` shows it.
+    fn format(&self, c: char) -> String {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+
+        let Self {
+            hour,
+            minute,
+            second,
+            ..
+        } = *self;
+        let hour12 = match hour % 12 {
+            0 => 12,
+            hour => hour,
+        };
+
+        match c {
+            't' => format!("{hour:02}:{minute:02}:{second:02}"),
+            'T' => format!("{hour12:02}:{minute:02}:{second:02}"),
+            '@' => {
+                let suffix = if hour < 12 { "AM" } else { "PM" };
+                format!("{hour12:02}:{minute:02} {suffix}")
+            }
+            'A' => format!("{hour:02}:{minute:02}"),
+            'd' => format!(
+                "{} {} {:02}",
+                WEEKDAYS[self.weekday], MONTHS[self.month], self.day
+            ),
+            _ => unreachable!("not an escape of the time"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let expand = |prompt| {
+            replace_escapes(prompt, |c| match c {
+                'u' => Some("me".to_string()),
+                c => escape(c).map(String::from),
+            })
+        };
+
+        assert_eq!("me $ ", expand("\\u $ "));
+        assert_eq!("a\nb", expand("a\\nb"));
+        assert_eq!("\x01\x1b[1m\x02me", expand("\\[\\e[1m\\]\\u"));
+        assert_eq!("\\u \\q", expand("\\\\u \\q"));
+        assert_eq!("end\\", expand("end\\"));
+    }
+
+    #[test]
+    fn times() {
+        let time = LocalTime {
+            hour: 0,
+            minute: 5,
+            second: 9,
+            weekday: 2,
+            month: 4,
+            day: 7,
+        };
+        assert_eq!("00:05:09", time.format('t'));
+        assert_eq!("12:05:09", time.format('T'));
+        assert_eq!("12:05 AM", time.format('@'));
+        assert_eq!("00:05", time.format('A'));
+        assert_eq!("Tue May 07", time.format('d'));
+
+        let afternoon = LocalTime { hour: 15, ..time };
+        assert_eq!("03:05 PM", afternoon.format('@'));
+        assert_eq!("15:05", afternoon.format('A'));
+    }
+}
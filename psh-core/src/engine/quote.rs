@@ -0,0 +1,283 @@
+//! Quoting of words: which characters are quoted, and what a word looks like
+//! once its quotes have been removed.
+//!
+//! The later expansion steps need to know which parts of a word were quoted,
+//! since neither field splitting nor pathname expansion applies to them.
+//!
+//! Text that is not read back but shown on the terminal, such as the name of
+//! a directory, is escaped instead, so that its control characters are seen
+//! rather than acted on.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuoteState {
+    Single,
+    Double,
+    None,
+}
+
+/// What quoting means for a single character of a word.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Quoting {
+    Unquoted,
+
+    /// The character is quoted, or escaped by a backslash.
+    Quoted,
+
+    /// The character is a quote or a backslash that is removed by quote
+    /// removal.
+    Syntax,
+}
+
+/// A word with its quotes removed.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Unquoted {
+    pub text: String,
+
+    /// The byte ranges of `text` that were quoted.
+    pub quoted: Vec<Range<usize>>,
+}
+
+/// Classifies every character of `s`, together with its byte index.
+pub fn scan(s: &str) -> Vec<(usize, char, Quoting)> {
+    let mut scanned = Vec::with_capacity(s.len());
+    let mut state = QuoteState::None;
+    let mut is_escaped = false;
+
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let quoting = match (c, state, is_escaped) {
+            ('\'', QuoteState::Single, _) => {
+                state = QuoteState::None;
+                is_escaped = false;
+                Quoting::Syntax
+            }
+
+            ('\'', QuoteState::None, false) => {
+                state = QuoteState::Single;
+                is_escaped = false;
+                Quoting::Syntax
+            }
+
+            ('"', QuoteState::Double, false) => {
+                state = QuoteState::None;
+                is_escaped = false;
+                Quoting::Syntax
+            }
+
+            ('"', QuoteState::None, false) => {
+                state = QuoteState::Double;
+                is_escaped = false;
+                Quoting::Syntax
+            }
+
+            // a line continuation disappears entirely
+            ('\\', QuoteState::None | QuoteState::Double, false)
+                if matches!(chars.peek(), Some((_, '\n'))) =>
+            {
+                scanned.push((i, c, Quoting::Syntax));
+                let (i, c) = chars.next().unwrap();
+                is_escaped = false;
+                scanned.push((i, c, Quoting::Syntax));
+                continue;
+            }
+
+            ('\\', QuoteState::None, false) => {
+                is_escaped = true;
+                Quoting::Syntax
+            }
+
+            // inside double quotes, a backslash only escapes the characters
+            // that are special there
+            ('\\', QuoteState::Double, false)
+                if matches!(chars.peek(), Some((_, '"' | '$' | '`' | '\\' | '\n'))) =>
+            {
+                is_escaped = true;
+                Quoting::Syntax
+            }
+
+            (_, QuoteState::None, false) => Quoting::Unquoted,
+
+            _ => {
+                is_escaped = false;
+                Quoting::Quoted
+            }
+        };
+        scanned.push((i, c, quoting));
+    }
+
+    scanned
+}
+
+/// The quote that is left open at the end of `s`, if any.
+pub fn open_quote(s: &str) -> QuoteState {
+    let mut state = QuoteState::None;
+    for (_, c, quoting) in scan(s) {
+        state = match (c, quoting, state) {
+            ('\'', Quoting::Syntax, QuoteState::None) => QuoteState::Single,
+            ('"', Quoting::Syntax, QuoteState::None) => QuoteState::Double,
+            ('\'' | '"', Quoting::Syntax, _) => QuoteState::None,
+            _ => state,
+        };
+    }
+    state
+}
+
+/// The byte ranges of `s` that are quoted, including the quotes and
+/// backslashes themselves.
+pub fn quoted_spans(s: &str) -> Vec<Range<usize>> {
+    spans(
+        scan(s)
+            .into_iter()
+            .filter(|(_, _, quoting)| *quoting != Quoting::Unquoted)
+            .map(|(i, c, _)| i..i + c.len_utf8()),
+    )
+}
+
+/// Removes the quotes from `s`, keeping track of which parts of the result
+/// were quoted.
+pub fn unquote(s: &str) -> Unquoted {
+    let mut text = String::with_capacity(s.len());
+    let mut quoted = Vec::new();
+
+    for (_, c, quoting) in scan(s) {
+        match quoting {
+            Quoting::Syntax => {}
+            Quoting::Unquoted => text.push(c),
+            Quoting::Quoted => {
+                quoted.push(text.len()..text.len() + c.len_utf8());
+                text.push(c);
+            }
+        }
+    }
+
+    Unquoted {
+        text,
+        quoted: spans(quoted.into_iter()),
+    }
+}
+
+/// Removes the quotes from `s`. If nothing is left, `None` is returned if
+/// `remove_empty` is set.
+pub fn remove_quotes(s: &str, remove_empty: bool) -> Option<String> {
+    let name = unquote(s).text;
+
+    if name.is_empty() && remove_empty {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Quotes `s` with single quotes so that it is read back as is, unless it
+/// is not empty and only contains characters that are never special.
+pub fn quote(s: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(format!("'{}'", s.replace('\'', "'\\''")))
+}
+
+/// How the control character `c` is shown, if it is one: in caret notation
+/// if it is in ASCII, as in `^I` for a tab, and otherwise by its code, as in
+/// `\x9b`.
+pub fn escape_control(c: char) -> Option<String> {
+    match c {
+        c if c.is_ascii_control() => Some(format!("^{}", (c as u8 ^ 0x40) as char)),
+        c if c.is_control() => Some(format!("\\x{:02x}", c as u32)),
+        _ => None,
+    }
+}
+
+/// Escapes the control characters of `s`, including newlines, as
+/// `escape_control` does.
+pub fn escape_controls(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(char::is_control) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(
+        s.chars()
+            .map(|c| escape_control(c).unwrap_or_else(|| c.to_string()))
+            .collect(),
+    )
+}
+
+/// Merges adjacent ranges.
+fn spans(ranges: impl Iterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match spans.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => spans.push(range),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backslash_removal() {
+        let input = "hello\\ there";
+        let output = remove_quotes(input, false);
+        assert_eq!(Some("hello there".to_string()), output);
+
+        let input = "'hello\\ there'";
+        let output = remove_quotes(input, false);
+        assert_eq!(Some("hello\\ there".to_string()), output);
+
+        let input = "\"hello\\ there\"";
+        let output = remove_quotes(input, false);
+        assert_eq!(Some("hello\\ there".to_string()), output);
+
+        let input = r#""'foo' \"bar\"""#;
+        let output = remove_quotes(input, false);
+        assert_eq!(Some(r#"'foo' "bar""#.to_string()), output);
+
+        assert_eq!(None, remove_quotes("\\\n", true));
+        assert_eq!(Some(String::new()), remove_quotes("''", false));
+    }
+
+    #[test]
+    fn spans_of_quoted_text() {
+        assert_eq!(vec![2..7, 8..10], quoted_spans("a 'b c'*\\*"));
+        assert!(quoted_spans("a*b").is_empty());
+
+        let unquoted = unquote("a 'b c'*\\*\"d\"");
+        assert_eq!("a b c**d", unquoted.text);
+        assert_eq!(vec![2..5, 6..8], unquoted.quoted);
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!("ls", quote("ls"));
+        assert_eq!("--color=auto", quote("--color=auto"));
+        assert_eq!("''", quote(""));
+        assert_eq!("'a b'", quote("a b"));
+        assert_eq!("'*.rs'", quote("*.rs"));
+        assert_eq!("'it'\\''s'", quote("it's"));
+    }
+
+    #[test]
+    fn open_quotes() {
+        assert_eq!(QuoteState::None, open_quote("a\\ b"));
+        assert_eq!(QuoteState::None, open_quote("'a b'\"c\""));
+        assert_eq!(QuoteState::Single, open_quote("a'b\"c"));
+        assert_eq!(QuoteState::Double, open_quote("\"a\\\"'b"));
+        assert_eq!(QuoteState::None, open_quote("\\'a"));
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!("~/a b", escape_controls("~/a b"));
+        assert_eq!(
+            "/tmp/new^Jline^[[31m\\x9b",
+            escape_controls("/tmp/new\nline\x1b[31m\u{9b}")
+        );
+    }
+}
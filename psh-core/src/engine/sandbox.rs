@@ -0,0 +1,266 @@
+//! A sandbox without network access and with a read-only filesystem, for
+//! running scripts that are not trusted, such as install scripts.
+//!
+//! On Linux the shell moves itself into new user, mount and network
+//! namespaces before it runs anything, so that builtins, the files opened
+//! for redirections and the commands it starts are all confined. Where that
+//! is not possible, entering the sandbox fails rather than running the
+//! script as usual.
+//!
+//! Since a read-only filesystem does not keep a process from connecting to a
+//! unix socket, such as that of docker or an ssh agent, the directories that
+//! usually hold them are replaced by empty ones of its own, unless they are
+//! kept writable. Sockets elsewhere can still be connected to.
+
+use std::path::PathBuf;
+
+use crate::Result;
+
+/// The directories hidden behind an empty one in the sandbox, since they
+/// hold the sockets of services such as docker and ssh agents.
+#[cfg(target_os = "linux")]
+const HIDDEN: &[&str] = &["/run", "/var/run", "/tmp"];
+
+/// What a sandboxed command is allowed to access.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Paths that stay writable, in addition to `/dev`. They stay visible
+    /// even if they are in a directory that is hidden in the sandbox.
+    pub writable: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    /// Confines the calling process, and any process it starts, to the
+    /// sandbox. Fails if the process has more than one thread, which the
+    /// kernel does not allow to enter a user namespace.
+    #[cfg(target_os = "linux")]
+    pub fn enter(&self) -> Result<()> {
+        use std::fs;
+        use std::path::Path;
+
+        use nix::fcntl::{open, OFlag};
+        use nix::mount::{mount, MsFlags};
+        use nix::sched::{unshare, CloneFlags};
+        use nix::sys::stat::Mode;
+        use nix::unistd::{close, getgid, getuid};
+
+        let (uid, gid) = (getuid(), getgid());
+        unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWNET)?;
+
+        // keep the same ids inside the namespace, so that files are still
+        // owned by the user running the command
+        fs::write("/proc/self/setgroups", "deny")?;
+        fs::write("/proc/self/uid_map", format!("{uid} {uid} 1"))?;
+        fs::write("/proc/self/gid_map", format!("{gid} {gid} 1"))?;
+
+        // make sure that nothing done below affects the rest of the system
+        mount(
+            None::<&str>,
+            "/",
+            None::<&str>,
+            MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+            None::<&str>,
+        )?;
+
+        // the writable paths are opened before they may be hidden, so that
+        // they can be mounted back from where they really are
+        let dev = PathBuf::from("/dev");
+        let mut writable = Vec::new();
+        for path in [&dev].into_iter().chain(&self.writable) {
+            let fd = open(path, OFlag::O_PATH | OFlag::O_CLOEXEC, Mode::empty())?;
+            writable.push((path, fd));
+        }
+
+        set_read_only(Path::new("/"), true)?;
+
+        for dir in HIDDEN.iter().map(Path::new) {
+            // a symbolic link such as `/var/run` leads to a directory that is
+            // hidden itself, and one in a writable path is not hidden at all
+            let is_dir = dir.symlink_metadata().is_ok_and(|m| m.is_dir());
+            if !is_dir || self.writable.iter().any(|path| dir.starts_with(path)) {
+                continue;
+            }
+            mount(
+                Some("tmpfs"),
+                dir,
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                Some("mode=1777"),
+            )?;
+        }
+
+        for (path, fd) in writable {
+            if path.symlink_metadata().is_err() {
+                let is_dir = fs::metadata(format!("/proc/self/fd/{fd}"))?.is_dir();
+                match is_dir {
+                    true => fs::create_dir_all(path)?,
+                    false => {
+                        if let Some(parent) = path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(path, "")?;
+                    }
+                }
+            }
+
+            mount(
+                Some(format!("/proc/self/fd/{fd}").as_str()),
+                path,
+                None::<&str>,
+                MsFlags::MS_BIND | MsFlags::MS_REC,
+                None::<&str>,
+            )?;
+            close(fd)?;
+            set_read_only(path, false)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn enter(&self) -> Result<()> {
+        Err(crate::Error::Unimplemented(
+            "sandboxing is only supported on Linux".to_string(),
+        ))
+    }
+}
+
+/// Sets or clears the read-only flag of the mount at `path` and every mount
+/// below it.
+#[cfg(target_os = "linux")]
+fn set_read_only(path: &std::path::Path, read_only: bool) -> Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    use nix::errno::Errno;
+    use nix::libc;
+
+    // `struct mount_attr` from linux/mount.h
+    #[repr(C)]
+    struct MountAttr {
+        attr_set: u64,
+        attr_clr: u64,
+        propagation: u64,
+        userns_fd: u64,
+    }
+
+    const MOUNT_ATTR_RDONLY: u64 = 0x1;
+
+    let (attr_set, attr_clr) = match read_only {
+        true => (MOUNT_ATTR_RDONLY, 0),
+        false => (0, MOUNT_ATTR_RDONLY),
+    };
+    let attr = MountAttr {
+        attr_set,
+        attr_clr,
+        propagation: 0,
+        userns_fd: 0,
+    };
+
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+
+    // SAFETY: the path is nul-terminated, and the size passed along is that
+    //         of the struct pointed to
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::AT_RECURSIVE as libc::c_uint,
+            &attr as *const MountAttr,
+            std::mem::size_of::<MountAttr>(),
+        )
+    };
+    Errno::result(rc)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::wait::{waitpid, WaitStatus};
+
+    use super::*;
+    use crate::engine::history::DummyHistory;
+    use crate::engine::util;
+    use crate::test_util::TempDir;
+    use crate::Engine;
+
+    /// Runs `line` in a shell confined to `sandbox`, which is forked off
+    /// since the tests run in threads, or `None` if the sandbox could not be
+    /// entered.
+    fn run(sandbox: &Sandbox, line: &str) -> Option<i32> {
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        let child = util::spawn_subshell(|| {
+            if sandbox.enter().is_err() {
+                std::process::exit(127);
+            }
+            let code = match engine.execute_line(line) {
+                Ok(statuses) => statuses.last().map_or(0, |status| status.raw_code()),
+                Err(_) => 1,
+            };
+            std::process::exit(code);
+        })
+        .unwrap();
+
+        match waitpid(child, None).unwrap() {
+            WaitStatus::Exited(_, 127) => None,
+            WaitStatus::Exited(_, code) => Some(code),
+            status => panic!("unexpected status {status:?}"),
+        }
+    }
+
+    #[test]
+    fn filesystem_is_read_only() {
+        let dir = TempDir::new("sandbox");
+        let file = dir.join("file");
+
+        // the file is opened by the shell itself for the builtin, and by
+        // the command started from it
+        let redirection = format!("echo pwned 2>/dev/null >{}", file.display());
+        let command = format!("sh -c 'echo pwned 2>/dev/null >{}'", file.display());
+
+        let Some(code) = run(&Sandbox::default(), &redirection) else {
+            eprintln!("sandbox not available, skipping");
+            return;
+        };
+        assert_ne!(0, code);
+        assert!(!file.exists());
+        assert_ne!(Some(0), run(&Sandbox::default(), &command));
+        assert!(!file.exists());
+
+        let sandbox = Sandbox {
+            writable: vec![dir.to_path_buf()],
+        };
+        assert_eq!(Some(0), run(&sandbox, &redirection));
+        assert!(file.exists());
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(Some(0), run(&sandbox, &command));
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn socket_directories_are_hidden() {
+        let dir = TempDir::new("sandbox-hidden");
+        let file = dir.join("file");
+        std::fs::write(&file, "").unwrap();
+        let exists = format!("test -e {}", file.display());
+
+        let Some(code) = run(&Sandbox::default(), "test -e /tmp") else {
+            eprintln!("sandbox not available, skipping");
+            return;
+        };
+        assert_eq!(0, code);
+        if file.starts_with("/tmp") {
+            assert_eq!(Some(1), run(&Sandbox::default(), &exists));
+        }
+        // e.g. `/run/docker.sock`
+        let empty = "test -z \"$(ls -A /run)\"";
+        assert_eq!(Some(0), run(&Sandbox::default(), empty));
+
+        let sandbox = Sandbox {
+            writable: vec![dir.to_path_buf()],
+        };
+        assert_eq!(Some(0), run(&sandbox, &exists));
+    }
+}
@@ -0,0 +1,250 @@
+//! The session of a shell: an id that no other shell running at the same
+//! time has, and a directory of its own for temporary files such as fifos,
+//! which is removed when the shell exits. They are exported as
+//! `$PSH_SESSION_ID` and `$PSH_SESSION_DIR`, so that scripts run from the
+//! shell do not collide with those of other shells either. Like the other
+//! variables of the shell, they start with `PSH_` rather than `POSH_`.
+//!
+//! A shell that is killed, or whose terminal is closed, does not get to
+//! remove its directory. Those are removed by the next shell that starts
+//! instead, once the shells they belong to are no longer running. Every
+//! shell holds a lock on a file in its directory for as long as it runs,
+//! which unlike its pid can be checked from other pid namespaces, such as
+//! those of containers sharing the temporary directory.
+
+use std::env;
+use std::fs::{self, DirBuilder, File};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use nix::sys::signal::kill;
+use nix::unistd::{getpid, getuid, Pid};
+
+use crate::engine::variables::Variables;
+use crate::Result;
+
+/// The file in a session directory that is locked while its shell runs.
+const LOCK: &str = "lock";
+
+#[derive(Debug)]
+pub struct Session {
+    pub id: String,
+
+    /// `None` if it could not be created.
+    pub dir: Option<PathBuf>,
+
+    /// The shell the session belongs to, rather than to a subshell forked
+    /// from it, which shares it.
+    owner: Pid,
+
+    /// The locked file in the directory, which is unlocked once it is closed
+    /// by the shell and the subshells forked from it.
+    lock: Option<File>,
+}
+
+impl Session {
+    /// Starts a session, with its directory in the temporary directory of
+    /// the system, where only the user can access it.
+    pub fn start() -> Self {
+        let owner = getpid();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        let id = format!("{owner}-{nanos:x}");
+
+        let temp = env::temp_dir();
+        sweep(&temp);
+        let dir = temp.join(format!("psh-{id}"));
+        let (dir, lock) = match create(&dir) {
+            Ok(lock) => (Some(dir), Some(lock)),
+            Err(_) => (None, None),
+        };
+
+        Self {
+            id,
+            dir,
+            owner,
+            lock,
+        }
+    }
+
+    /// Sets and exports the variables of the session, replacing those of
+    /// the shell this one was started from, if any.
+    pub fn export(&self, variables: &mut Variables) {
+        let _ = variables.set("PSH_SESSION_ID", &self.id);
+        variables.export("PSH_SESSION_ID");
+
+        match &self.dir {
+            Some(dir) => {
+                let _ = variables.set("PSH_SESSION_DIR", dir.display());
+                variables.export("PSH_SESSION_DIR");
+            }
+            None => {
+                variables.unset("PSH_SESSION_DIR");
+            }
+        }
+    }
+
+    /// Removes the directory, along with what is in it. Does nothing in a
+    /// subshell, since the shell it was forked from may still use it.
+    pub fn end(&mut self) {
+        if getpid() != self.owner {
+            return;
+        }
+        if let Some(dir) = self.dir.take() {
+            let _ = fs::remove_dir_all(dir);
+        }
+        self.lock = None;
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+/// Creates the session directory `dir` with its lock file, returning the
+/// locked file. The directory is made under another name and renamed once
+/// it is locked, so that it is never found unlocked by `sweep`.
+fn create(dir: &Path) -> Result<File> {
+    let mut new = dir.as_os_str().to_owned();
+    new.push(".new");
+    DirBuilder::new().mode(0o700).create(&new)?;
+
+    let new = PathBuf::from(new);
+    let locked = File::create(new.join(LOCK)).and_then(|lock| {
+        flock(lock.as_raw_fd(), FlockArg::LockExclusiveNonblock)?;
+        fs::rename(&new, dir)?;
+        Ok(lock)
+    });
+    if locked.is_err() {
+        let _ = fs::remove_dir_all(&new);
+    }
+    Ok(locked?)
+}
+
+/// Removes the directories in `temp` of the sessions of the user whose
+/// shells are no longer running.
+fn sweep(temp: &Path) {
+    let Ok(entries) = fs::read_dir(temp) else {
+        return;
+    };
+    let uid = getuid().as_raw();
+
+    for entry in entries.flatten() {
+        let Some(owner) = entry.file_name().to_str().and_then(owner_of) else {
+            continue;
+        };
+        let ours = entry
+            .metadata()
+            .is_ok_and(|metadata| metadata.is_dir() && metadata.uid() == uid);
+        if !ours {
+            continue;
+        }
+
+        let dir = entry.path();
+        match File::open(dir.join(LOCK)) {
+            // the lock is held until the directory is gone, so that a shell
+            // sweeping at the same time leaves it alone
+            Ok(lock) => {
+                if flock(lock.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_ok() {
+                    let _ = fs::remove_dir_all(&dir);
+                }
+            }
+            // a directory of an older version of psh, without a lock, for
+            // which sending no signal checks whether the process exists
+            Err(_) if kill(owner, None) == Err(Errno::ESRCH) => {
+                let _ = fs::remove_dir_all(&dir);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+/// The shell that the session directory called `name` belongs to, if it is
+/// one.
+fn owner_of(name: &str) -> Option<Pid> {
+    let (pid, nanos) = name.strip_prefix("psh-")?.split_once('-')?;
+    u64::from_str_radix(nanos, 16).ok()?;
+    pid.parse().ok().map(Pid::from_raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn directory_lives_as_long_as_the_session() {
+        let mut session = Session::start();
+        let other = Session::start();
+        assert_ne!(session.id, other.id);
+
+        let dir = session.dir.clone().unwrap();
+        let mode = fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(0o700, mode & 0o777);
+
+        fs::write(dir.join("file"), "").unwrap();
+        session.end();
+        assert!(!dir.exists());
+
+        let dir = other.dir.clone().unwrap();
+        drop(other);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn directories_of_dead_shells_are_removed() {
+        let temp = TempDir::new("sweep");
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+
+        let left = temp.join(format!("psh-{dead}-1f"));
+        // as seen from another pid namespace, in which the shell is unknown
+        let running = temp.join(format!("psh-{dead}-2f"));
+        let old = temp.join(format!("psh-{dead}-3f"));
+        let old_running = temp.join(format!("psh-{}-1f", std::process::id()));
+        let other = temp.join(format!("psh-{dead}-file"));
+        for dir in [&left, &old, &old_running, &other] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(left.join("fifo"), "").unwrap();
+        fs::write(left.join(LOCK), "").unwrap();
+        let _lock = create(&running).unwrap();
+
+        sweep(&temp);
+        assert!(!left.exists());
+        assert!(running.exists());
+        assert!(!old.exists());
+        assert!(old_running.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn variables() {
+        let session = Session::start();
+        let mut variables = Variables::default();
+        variables.set("PSH_SESSION_DIR", "/inherited").unwrap();
+        session.export(&mut variables);
+
+        assert_eq!(
+            Some(session.id.as_str()),
+            variables.value_of("PSH_SESSION_ID")
+        );
+        assert_eq!(
+            session.dir.as_deref().and_then(|dir| dir.to_str()),
+            variables.value_of("PSH_SESSION_DIR")
+        );
+        assert!(variables.get("PSH_SESSION_ID").unwrap().exported);
+    }
+}
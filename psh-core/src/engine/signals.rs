@@ -0,0 +1,164 @@
+//! The traps set on signals with `trap`. A caught signal only marks itself
+//! as pending, and its trap is executed by the engine between commands,
+//! since almost nothing is safe to do inside a signal handler.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nix::libc;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+use crate::Result;
+
+/// The signals caught since they were last taken, one bit per signal
+/// number.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+extern "C" fn mark_pending(signal: libc::c_int) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+/// What the shell does when it receives a signal with a trap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignalAction {
+    /// Execute the commands, between the commands of the shell.
+    Execute(String),
+
+    /// Ignore the signal, as do the commands that the shell starts.
+    Ignore,
+}
+
+#[derive(Debug, Clone)]
+struct Trap {
+    action: SignalAction,
+
+    /// How the signal was handled before the trap was set, restored when
+    /// it is reset.
+    previous: SigAction,
+}
+
+/// The traps set on signals, keeping what the signals did before so that
+/// `trap - SIG` can restore it.
+#[derive(Debug, Clone, Default)]
+pub struct SignalTraps {
+    traps: HashMap<Signal, Trap>,
+}
+
+impl SignalTraps {
+    /// Parses a signal given by its name, with or without the `SIG` prefix,
+    /// or by its number.
+    pub fn parse(s: &str) -> Option<Signal> {
+        if let Ok(number) = s.parse::<i32>() {
+            return Signal::try_from(number).ok();
+        }
+        match s.starts_with("SIG") {
+            true => s.parse().ok(),
+            false => format!("SIG{s}").parse().ok(),
+        }
+    }
+
+    pub fn get(&self, signal: Signal) -> Option<&SignalAction> {
+        self.traps.get(&signal).map(|trap| &trap.action)
+    }
+
+    /// The traps that are set, ordered by signal number.
+    pub fn iter(&self) -> impl Iterator<Item = (Signal, &SignalAction)> {
+        let mut traps = self
+            .traps
+            .iter()
+            .map(|(signal, trap)| (*signal, &trap.action))
+            .collect::<Vec<_>>();
+        traps.sort_by_key(|(signal, _)| *signal as i32);
+        traps.into_iter()
+    }
+
+    /// Sets the trap of `signal` to `action`, or resets it to what the
+    /// signal did before if `action` is `None`. Fails for signals that
+    /// cannot be caught, such as `SIGKILL`.
+    pub fn set(&mut self, signal: Signal, action: Option<SignalAction>) -> Result<()> {
+        let Some(action) = action else {
+            if let Some(trap) = self.traps.remove(&signal) {
+                // SAFETY: the previous action was installed before the trap
+                unsafe { signal::sigaction(signal, &trap.previous)? };
+            }
+            return Ok(());
+        };
+
+        let handler = match action {
+            SignalAction::Execute(_) => SigHandler::Handler(mark_pending),
+            SignalAction::Ignore => SigHandler::SigIgn,
+        };
+        let sigaction = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+        // SAFETY: the handler only sets an atomic
+        let previous = unsafe { signal::sigaction(signal, &sigaction)? };
+
+        let previous = match self.traps.remove(&signal) {
+            Some(trap) => trap.previous,
+            None => previous,
+        };
+        self.traps.insert(signal, Trap { action, previous });
+        Ok(())
+    }
+
+    /// Handles `signal` as if the shell had caught it, as when a job in the
+    /// foreground was killed by a ^C meant for the shell as well. Returns
+    /// whether it has a trap, which is executed between commands like that
+    /// of a signal that was caught.
+    pub fn deliver(&self, signal: Signal) -> bool {
+        match self.get(signal) {
+            Some(SignalAction::Execute(_)) => {
+                mark_pending(signal as libc::c_int);
+                true
+            }
+            Some(SignalAction::Ignore) => true,
+            None => false,
+        }
+    }
+
+    /// Takes the signals caught since the last call, returning the actions
+    /// of their traps in order of signal number.
+    pub fn take_pending(&self) -> Vec<(Signal, String)> {
+        let pending = PENDING.swap(0, Ordering::SeqCst);
+        self.iter()
+            .filter(|(signal, _)| pending & (1 << *signal as i32) != 0)
+            .filter_map(|(signal, action)| match action {
+                SignalAction::Execute(action) => Some((signal, action.clone())),
+                SignalAction::Ignore => None,
+            })
+            .collect()
+    }
+
+    /// Resets the signals that are caught to their default action, in a
+    /// subshell that was just forked. Ignored signals stay ignored.
+    pub fn reset_caught(&mut self) -> Result<()> {
+        PENDING.store(0, Ordering::SeqCst);
+        let caught = self
+            .traps
+            .iter()
+            .filter(|(_, trap)| matches!(trap.action, SignalAction::Execute(_)))
+            .map(|(signal, _)| *signal)
+            .collect::<Vec<_>>();
+
+        for signal in caught {
+            self.traps.remove(&signal);
+            // SAFETY: the default action installs no handler
+            unsafe { signal::signal(signal, SigHandler::SigDfl)? };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Some(Signal::SIGINT), SignalTraps::parse("INT"));
+        assert_eq!(Some(Signal::SIGTERM), SignalTraps::parse("SIGTERM"));
+        assert_eq!(Some(Signal::SIGHUP), SignalTraps::parse("1"));
+        assert_eq!(None, SignalTraps::parse("FOO"));
+        assert_eq!(None, SignalTraps::parse("int"));
+        assert_eq!(None, SignalTraps::parse("100"));
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::abbreviation::Abbreviation;
+use crate::{Engine, Result};
+
+/// Exported variables that describe the current machine or session rather
+/// than the setup of the shell, and would be wrong to carry over to another.
+const SESSION_VARIABLES: &[&str] = &[
+    "_",
+    "DISPLAY",
+    "HOME",
+    "HOSTNAME",
+    "LOGNAME",
+    "MAIL",
+    "OLDPWD",
+    "PWD",
+    "SHELL",
+    "SHLVL",
+    "USER",
+    "XAUTHORITY",
+];
+
+const SESSION_PREFIXES: &[&str] = &["SSH_", "XDG_RUNTIME_", "XDG_SESSION_"];
+
+/// The parts of the shell setup that can be carried over to another shell,
+/// e.g. on a remote machine, with `--dump-env` and `--load-env`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvSnapshot {
+    /// Exported variables, except for the ones describing the session.
+    pub variables: BTreeMap<String, String>,
+
+    /// The bodies of the functions, as they were written. Missing from the
+    /// snapshots of older versions.
+    #[serde(default)]
+    pub functions: BTreeMap<String, String>,
+
+    pub aliases: BTreeMap<String, String>,
+    pub abbreviations: BTreeMap<String, Abbreviation>,
+}
+
+impl EnvSnapshot {
+    pub fn of(engine: &Engine) -> Self {
+        let is_session_variable = |name: &str| {
+            SESSION_VARIABLES.contains(&name)
+                || SESSION_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        };
+
+        let variables = engine
+            .variables
+            .iter()
+            .filter(|(name, var)| var.exported && !is_session_variable(name))
+            .map(|(name, var)| (name.clone(), var.value.clone()))
+            .collect();
+
+        let functions = engine
+            .functions
+            .iter()
+            .map(|(name, body)| (name.clone(), body.to_string()))
+            .collect();
+
+        Self {
+            variables,
+            functions,
+            aliases: engine.aliases.clone().into_iter().collect(),
+            abbreviations: engine.abbreviations.clone().into_iter().collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Sets up `engine` like the shell the snapshot was taken of, replacing
+    /// any variables, functions, aliases and abbreviations with the same
+    /// names.
+    pub fn restore(self, engine: &mut Engine) {
+        for (name, value) in self.variables {
+            if let Err(e) = engine.variables.set(&name, value) {
+                eprintln!("psh: {e}");
+                continue;
+            }
+            engine.variables.export(name);
+        }
+        // the functions are defined before the aliases, which could
+        // otherwise be substituted into their definitions
+        for (name, body) in self.functions {
+            if let Err(e) = engine.execute_line(format!("{name}(){body}")) {
+                eprintln!("psh: {name}: {e}");
+            }
+        }
+        engine.aliases.extend(self.aliases);
+        engine.abbreviations.extend(self.abbreviations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::history::DummyHistory;
+
+    #[test]
+    fn round_trip() {
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine
+            .variables
+            .set("PSH_TEST_SNAPSHOT", "it's \"quoted\"")
+            .unwrap();
+        engine.variables.export("PSH_TEST_SNAPSHOT");
+        engine.variables.set("PSH_TEST_UNEXPORTED", "no").unwrap();
+        engine.aliases.insert("ll".to_string(), "ls -l".to_string());
+        engine
+            .abbreviations
+            .insert("g".to_string(), Abbreviation::new("git"));
+        engine
+            .execute_line("greet() { echo \"hi $1\" >&2; }")
+            .unwrap();
+
+        let snapshot = EnvSnapshot::of(&engine);
+        assert!(snapshot.variables.contains_key("PSH_TEST_SNAPSHOT"));
+        assert!(!snapshot.variables.contains_key("PSH_TEST_UNEXPORTED"));
+        assert!(!snapshot.variables.contains_key("PWD"));
+
+        let json = snapshot.to_json().unwrap();
+        assert_eq!(snapshot, EnvSnapshot::from_json(&json).unwrap());
+
+        engine.variables.unset("PSH_TEST_SNAPSHOT");
+        engine.functions.clear();
+        engine.aliases.clear();
+        engine.abbreviations.clear();
+        EnvSnapshot::from_json(&json).unwrap().restore(&mut engine);
+
+        let var = engine.variables.get("PSH_TEST_SNAPSHOT").unwrap();
+        assert_eq!("it's \"quoted\"", var.value);
+        assert!(var.exported);
+        assert_eq!(
+            Some(" { echo \"hi $1\" >&2; }"),
+            engine
+                .functions
+                .get("greet")
+                .map(|body| body.to_string())
+                .as_deref()
+        );
+        assert_eq!(Some("ls -l"), engine.aliases.get("ll").map(String::as_str));
+        assert_eq!(
+            Some("git"),
+            engine
+                .abbreviations
+                .get("g")
+                .map(|abbreviation| abbreviation.expansion.as_str())
+        );
+
+        engine.variables.unset("PSH_TEST_SNAPSHOT");
+    }
+}
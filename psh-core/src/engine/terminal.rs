@@ -0,0 +1,279 @@
+//! Putting the terminal back the way commands expect it, after one left it
+//! in a weird state, e.g. by crashing before it could restore it: with what
+//! is typed not shown, the cursor hidden, or only part of the screen
+//! scrolling.
+
+use std::cell::Cell;
+use std::io::{stdout, Write};
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::sys::termios::{self, InputFlags, LocalFlags, OutputFlags, SetArg, Termios};
+use nix::unistd::{self, isatty};
+
+use crate::engine::util;
+use crate::Result;
+
+/// Undoes what full-screen programs change, without clearing the screen.
+const RESET: &str = concat!(
+    // leave the alternate screen
+    "\x1b[?1049l",
+    // show the cursor
+    "\x1b[?25h",
+    // reset colors and other attributes
+    "\x1b[0m",
+    // reset the scroll region, which moves the cursor to the top, so it is
+    // saved and restored around it
+    "\x1b7\x1b[r\x1b8",
+    // wrap lines
+    "\x1b[?7h",
+    // normal cursor and keypad keys
+    "\x1b[?1l\x1b>",
+    // the ASCII character set
+    "\x1b(B",
+    // stop reporting the mouse and pastes
+    "\x1b[?1000l\x1b[?1002l\x1b[?1003l\x1b[?1006l\x1b[?2004l",
+);
+
+/// Written before every prompt, since a program that hides the cursor,
+/// changes the colors or stops lines from wrapping leaves nothing that shows
+/// it.
+const REPAIR: &str = "\x1b[?25h\x1b[0m\x1b[?7h";
+
+/// Asks whether the alternate screen is in use, and then for the attributes
+/// of the terminal, which every terminal answers, so that one that does not
+/// know the first question is not waited on.
+const QUERY_ALTERNATE_SCREEN: &str = "\x1b[?1049$p\x1b[c";
+
+/// How long a terminal has to answer a query.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The flags that commands expect to be set, or unset, as `stty sane` does.
+const INPUT_FLAGS: InputFlags = InputFlags::ICRNL.union(InputFlags::IXON);
+const NO_INPUT_FLAGS: InputFlags = InputFlags::INLCR.union(InputFlags::IGNCR);
+const OUTPUT_FLAGS: OutputFlags = OutputFlags::OPOST.union(OutputFlags::ONLCR);
+const LOCAL_FLAGS: LocalFlags = LocalFlags::ECHO
+    .union(LocalFlags::ECHOE)
+    .union(LocalFlags::ECHOK)
+    .union(LocalFlags::ICANON)
+    .union(LocalFlags::ISIG)
+    .union(LocalFlags::IEXTEN);
+
+/// The settings of a terminal, which it is put back to.
+#[derive(Debug, Clone)]
+pub struct Terminal {
+    fd: RawFd,
+    settings: Termios,
+
+    /// Whether the terminal answers queries, which it is not asked again
+    /// once it did not.
+    answers: Cell<bool>,
+}
+
+impl Terminal {
+    /// Saves the current settings of the terminal `fd`, or returns `None` if
+    /// it is not one.
+    pub fn save(fd: RawFd) -> Option<Self> {
+        if !isatty(fd).unwrap_or(false) {
+            return None;
+        }
+        let settings = termios::tcgetattr(fd).ok()?;
+        Some(Self {
+            fd,
+            settings,
+            answers: Cell::new(true),
+        })
+    }
+
+    /// Whether the settings that commands depend on, such as whether what is
+    /// typed is shown, have changed since they were saved.
+    pub fn changed(&self) -> Result<bool> {
+        let current = termios::tcgetattr(self.fd)?;
+        let saved = &self.settings;
+
+        let input = INPUT_FLAGS | NO_INPUT_FLAGS;
+        Ok(current.input_flags & input != saved.input_flags & input
+            || current.output_flags & OUTPUT_FLAGS != saved.output_flags & OUTPUT_FLAGS
+            || current.local_flags & LOCAL_FLAGS != saved.local_flags & LOCAL_FLAGS)
+    }
+
+    /// Puts the settings back.
+    pub fn restore(&self) -> Result<()> {
+        termios::tcsetattr(self.fd, SetArg::TCSADRAIN, &self.settings)?;
+        Ok(())
+    }
+
+    /// Whether the terminal shows the alternate screen, which full-screen
+    /// programs draw on, and leave again when they exit cleanly. `None` if
+    /// that is not known, because the terminal did not say, or because
+    /// there is input waiting, which asking would swallow.
+    pub fn on_alternate_screen(&self) -> Result<Option<bool>> {
+        if !self.answers.get() || util::wait_for_input(self.fd, Some(Instant::now()))? {
+            return Ok(None);
+        }
+
+        // the answer is read as it comes, without being shown
+        let mut settings = termios::tcgetattr(self.fd)?;
+        let saved = settings.clone();
+        settings
+            .local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+        termios::tcsetattr(self.fd, SetArg::TCSANOW, &settings)?;
+        let reply = ask(self.fd, QUERY_ALTERNATE_SCREEN);
+        termios::tcsetattr(self.fd, SetArg::TCSANOW, &saved)?;
+
+        let reply = reply?;
+        if !answered(&reply) {
+            self.answers.set(false);
+        }
+        Ok(alternate_screen(&reply))
+    }
+
+    /// Repairs what a command may have left behind, before a prompt. The
+    /// terminal is reset if its settings changed, which is likely the work
+    /// of a program that crashed, or if it was `handed_off` to a command
+    /// that left it on the alternate screen, where the prompt would vanish
+    /// once something leaves it. Returns whether it was reset.
+    pub fn repair(&self, handed_off: bool) -> Result<bool> {
+        let changed = self.changed()?;
+        if changed {
+            self.restore()?;
+        }
+        let reset = changed || handed_off && self.on_alternate_screen()? == Some(true);
+
+        let mut stdout = stdout();
+        stdout.write_all(if reset { RESET } else { REPAIR }.as_bytes())?;
+        stdout.flush()?;
+        Ok(reset)
+    }
+}
+
+/// Resets the terminal, going back to the `saved` settings, or otherwise to
+/// sane ones if stdin is a terminal.
+pub fn reset(saved: Option<&Terminal>) -> Result<()> {
+    match saved {
+        Some(terminal) => terminal.restore()?,
+        None if isatty(0).unwrap_or(false) => {
+            let mut settings = termios::tcgetattr(0)?;
+            sanitize(&mut settings);
+            termios::tcsetattr(0, SetArg::TCSADRAIN, &settings)?;
+        }
+        None => {}
+    }
+
+    let mut stdout = stdout();
+    stdout.write_all(RESET.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Writes `query` and reads the answer of the terminal `fd`, until it has
+/// answered the attributes query that ends it, or `REPLY_TIMEOUT` passed.
+fn ask(fd: RawFd, query: &str) -> Result<String> {
+    let mut stdout = stdout();
+    stdout.write_all(query.as_bytes())?;
+    stdout.flush()?;
+
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    let mut reply = Vec::new();
+    let mut buf = [0; 64];
+    while !answered(&String::from_utf8_lossy(&reply)) && util::wait_for_input(fd, Some(deadline))? {
+        match unistd::read(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(n) => reply.extend_from_slice(&buf[..n]),
+            Err(Errno::EINTR) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
+/// Whether `reply` has the answer to the attributes query in it, such as
+/// `\x1b[?62;22c`.
+fn answered(reply: &str) -> bool {
+    reply.split("\x1b[?").skip(1).any(|answer| {
+        answer
+            .trim_start_matches(|c: char| c.is_ascii_digit() || c == ';')
+            .starts_with('c')
+    })
+}
+
+/// What `reply` says about the alternate screen, which is `\x1b[?1049;1$y`
+/// if it is in use, and `\x1b[?1049;2$y` if not.
+fn alternate_screen(reply: &str) -> Option<bool> {
+    let (_, answer) = reply.split_once("\x1b[?1049;")?;
+    match answer.get(..3)? {
+        "1$y" | "3$y" => Some(true),
+        "2$y" | "4$y" => Some(false),
+        _ => None,
+    }
+}
+
+/// Sets the flags that commands expect, as `stty sane` does.
+fn sanitize(settings: &mut Termios) {
+    settings.input_flags.insert(INPUT_FLAGS);
+    settings.input_flags.remove(NO_INPUT_FLAGS);
+    settings.output_flags.insert(OUTPUT_FLAGS);
+    settings.local_flags.insert(LOCAL_FLAGS);
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::pty::openpty;
+    use nix::unistd::close;
+
+    use super::*;
+
+    #[test]
+    fn changed_settings_are_put_back() {
+        let pty = openpty(None, None).unwrap();
+        let fd = pty.slave;
+        let terminal = Terminal::save(fd).unwrap();
+        assert!(!terminal.changed().unwrap());
+
+        // as a program that reads a password and then crashes would leave it
+        let mut settings = termios::tcgetattr(fd).unwrap();
+        settings
+            .local_flags
+            .remove(LocalFlags::ECHO | LocalFlags::ICANON);
+        termios::tcsetattr(fd, SetArg::TCSANOW, &settings).unwrap();
+        assert!(terminal.changed().unwrap());
+
+        terminal.restore().unwrap();
+        assert!(!terminal.changed().unwrap());
+
+        sanitize(&mut settings);
+        assert!(settings
+            .local_flags
+            .contains(LocalFlags::ECHO | LocalFlags::ICANON));
+
+        close(pty.master).unwrap();
+        close(pty.slave).unwrap();
+    }
+
+    #[test]
+    fn replies() {
+        let on = "\x1b[?1049;1$y\x1b[?62;22c";
+        assert!(answered(on));
+        assert_eq!(Some(true), alternate_screen(on));
+
+        let off = "\x1b[?1049;2$y\x1b[?62;22c";
+        assert_eq!(Some(false), alternate_screen(off));
+
+        // a terminal that does not know the question only answers the
+        // attributes query
+        let unknown = "\x1b[?6c";
+        assert!(answered(unknown));
+        assert_eq!(None, alternate_screen(unknown));
+
+        assert!(!answered("\x1b[?1049;1$y\x1b[?62;2"));
+        assert!(!answered(""));
+    }
+
+    #[test]
+    fn not_a_terminal() {
+        assert!(Terminal::save(-1).is_none());
+    }
+}
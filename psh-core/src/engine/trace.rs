@@ -0,0 +1,173 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::{FromRawFd, RawFd};
+
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg};
+use serde::Serialize;
+
+use crate::ast::nodes::{FileDescriptor, RedirectionType};
+use crate::Result;
+
+/// A single executed simple command, written as one line of JSON.
+#[derive(Debug, Serialize)]
+pub struct TraceEntry {
+    pub argv: Vec<String>,
+    pub redirections: Vec<TraceRedirection>,
+    pub cwd: String,
+    pub pid: i32,
+    pub kind: CommandKind,
+    pub background: bool,
+
+    /// `None` if the command was not waited for, e.g. if run in the background.
+    pub status: Option<i32>,
+    pub duration_us: u128,
+}
+
+/// What a traced command runs, where a function takes the place of a builtin
+/// of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandKind {
+    External,
+    Builtin,
+    Function,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraceRedirection {
+    pub fd: i32,
+    #[serde(rename = "type")]
+    pub ty: RedirectionType,
+    pub target: String,
+}
+
+impl TraceRedirection {
+    pub fn new(fd: FileDescriptor, ty: RedirectionType, target: String) -> Self {
+        Self {
+            fd: fd.as_raw_fd(),
+            ty,
+            target,
+        }
+    }
+}
+
+/// Writes a `TraceEntry` for every executed simple command to a file or an
+/// already open file descriptor.
+pub struct Tracer {
+    out: File,
+}
+
+impl Tracer {
+    /// Opens the trace target, which is either a path to a file that will be
+    /// appended to, or `fd:N` for the already open file descriptor `N`.
+    pub fn open(target: &str) -> Result<Self> {
+        let out = match target.strip_prefix("fd:") {
+            Some(fd) => {
+                let fd = fd.parse::<RawFd>().map_err(|_| Errno::EBADF)?;
+                // the tracer writes to a duplicate of its own, so that `fd`
+                // stays open for the shell, and is not inherited by commands
+                let fd = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(0))?;
+                // SAFETY: the duplicate was just opened, and nothing else
+                //         owns it
+                unsafe { File::from_raw_fd(fd) }
+            }
+            None => OpenOptions::new().append(true).create(true).open(target)?,
+        };
+
+        Ok(Self { out })
+    }
+
+    pub fn record(&mut self, entry: &TraceEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::engine::history::DummyHistory;
+    use crate::test_util::TempDir;
+    use crate::Engine;
+
+    #[test]
+    fn entry_is_one_json_line() {
+        let dir = TempDir::new("trace");
+        let path = dir.join("trace");
+
+        let entry = TraceEntry {
+            argv: vec!["echo".to_string(), "hi".to_string()],
+            redirections: vec![TraceRedirection::new(
+                FileDescriptor::Stdout,
+                RedirectionType::Output,
+                "/dev/null".to_string(),
+            )],
+            cwd: "/".to_string(),
+            pid: 1,
+            kind: CommandKind::External,
+            background: true,
+            status: None,
+            duration_us: 10,
+        };
+
+        let mut tracer = Tracer::open(path.to_str().unwrap()).unwrap();
+        tracer.record(&entry).unwrap();
+        tracer.record(&entry).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let expected = r#"{"argv":["echo","hi"],"redirections":[{"fd":1,"type":"output","target":"/dev/null"}],"cwd":"/","pid":1,"kind":"external","background":true,"status":null,"duration_us":10}"#;
+        assert_eq!(format!("{expected}\n{expected}\n"), content);
+    }
+
+    #[test]
+    fn fd_target_is_left_open() {
+        let (read, write) = unistd::pipe().unwrap();
+        let tracer = Tracer::open(&format!("fd:{write}")).unwrap();
+        drop(tracer);
+
+        assert_eq!(Ok(1), unistd::write(write, b"x"));
+        unistd::close(write).unwrap();
+        unistd::close(read).unwrap();
+    }
+
+    #[test]
+    fn every_command_of_a_pipeline_has_a_status() {
+        let dir = TempDir::new("trace-pipeline");
+        // a number is the name of a file like any other
+        let path = dir.join("3");
+
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine.tracer = Some(Tracer::open(path.to_str().unwrap()).unwrap());
+        engine
+            .execute_line("f() { :; }; true | sh -c 'exit 3' | f")
+            .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let mut statuses = content
+            .lines()
+            .map(|line| {
+                let entry = serde_json::from_str::<Value>(line).unwrap();
+                let argv0 = entry["argv"][0].clone();
+                (argv0, entry["kind"].clone(), entry["status"].clone())
+            })
+            .collect::<Vec<_>>();
+        statuses.sort_by_key(|(argv0, ..)| argv0.to_string());
+
+        assert_eq!(
+            vec![
+                (Value::from(":"), Value::from("builtin"), Value::from(0)),
+                (Value::from("f"), Value::from("function"), Value::from(0)),
+                (Value::from("sh"), Value::from("external"), Value::from(3)),
+                (Value::from("true"), Value::from("external"), Value::from(0)),
+            ],
+            statuses
+        );
+    }
+}
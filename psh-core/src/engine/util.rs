@@ -1,8 +1,17 @@
-use std::os::unix::prelude::PermissionsExt;
+use std::ffi::{CStr, CString};
+use std::mem::MaybeUninit;
+use std::os::fd::RawFd;
+use std::ptr;
+use std::time::Instant;
 
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::signal::{self, SigHandler, SigSet, Signal};
+use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd::{fork, ForkResult, Pid};
 
-use crate::{Error, Result};
+use crate::{Error, ExitStatus, Result};
 
 pub fn spawn_subshell<F>(child_fn: F) -> Result<Pid>
 where
@@ -18,13 +27,189 @@ where
     }
 }
 
-pub fn is_executable(path: &str) -> bool {
-    match std::fs::metadata(path) {
-        Ok(metadata) => {
-            let mode = metadata.permissions().mode();
-            !metadata.is_dir() && mode & 0o111 != 0
+/// The signals that the shell ignores, but that the commands it starts
+/// should not: SIGPIPE, which Rust ignores, and the job control signals,
+/// which an interactive shell ignores.
+const RESTORED_SIGNALS: [Signal; 4] = [
+    Signal::SIGPIPE,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+];
+
+/// Restores the default disposition of the signals ignored by the shell, in
+/// a child that is about to run a command.
+pub fn restore_signals() -> Result<()> {
+    for signal in RESTORED_SIGNALS {
+        // SAFETY: restoring the default disposition has no handler that
+        //         could be unsafe to run
+        unsafe { signal::signal(signal, SigHandler::SigDfl)? };
+    }
+    Ok(())
+}
+
+/// Spawns `file` with `posix_spawnp`, which does not need to copy the page
+/// tables of the shell like `fork` does, making it a lot faster for large
+/// processes. The child first closes the fds in `close`, then duplicates
+/// every `(src, dst)` pair in `dups`, is put in the process group `pgroup`
+/// if given, and has the signals ignored by the shell restored to their
+/// default disposition.
+pub fn posix_spawn(
+    file: &CStr,
+    args: &[CString],
+    env: &[CString],
+    close: &[RawFd],
+    dups: &[(RawFd, RawFd)],
+    pgroup: Option<Pid>,
+) -> Result<Pid> {
+    fn check(rc: libc::c_int) -> Result<()> {
+        match rc {
+            0 => Ok(()),
+            errno => Err(Error::Nix(Errno::from_i32(errno))),
+        }
+    }
+
+    let argv = args
+        .iter()
+        .map(|s| s.as_ptr() as *mut libc::c_char)
+        .chain([ptr::null_mut()])
+        .collect::<Vec<_>>();
+    let envp = env
+        .iter()
+        .map(|s| s.as_ptr() as *mut libc::c_char)
+        .chain([ptr::null_mut()])
+        .collect::<Vec<_>>();
+
+    let mut sigdefault = SigSet::empty();
+    for signal in RESTORED_SIGNALS {
+        sigdefault.add(signal);
+    }
+    let mut flags = libc::POSIX_SPAWN_SETSIGDEF;
+
+    let mut actions = MaybeUninit::uninit();
+    let mut attr = MaybeUninit::uninit();
+    let mut pid = 0;
+
+    // SAFETY: the file actions and attributes are initialized before use and
+    //         destroyed before returning, and every pointer passed along points
+    //         to a nul-terminated string or array that outlives the call
+    unsafe {
+        check(libc::posix_spawn_file_actions_init(actions.as_mut_ptr()))?;
+        let mut actions = actions.assume_init();
+
+        if let Err(e) = check(libc::posix_spawnattr_init(attr.as_mut_ptr())) {
+            libc::posix_spawn_file_actions_destroy(&mut actions);
+            return Err(e);
+        }
+        let mut attr = attr.assume_init();
+
+        let result = (|| {
+            for &fd in close {
+                check(libc::posix_spawn_file_actions_addclose(&mut actions, fd))?;
+            }
+            for &(src, dst) in dups {
+                check(libc::posix_spawn_file_actions_adddup2(
+                    &mut actions,
+                    src,
+                    dst,
+                ))?;
+            }
+
+            check(libc::posix_spawnattr_setsigdefault(
+                &mut attr,
+                sigdefault.as_ref(),
+            ))?;
+            if let Some(pgroup) = pgroup {
+                check(libc::posix_spawnattr_setpgroup(&mut attr, pgroup.as_raw()))?;
+                flags |= libc::POSIX_SPAWN_SETPGROUP;
+            }
+            check(libc::posix_spawnattr_setflags(
+                &mut attr,
+                flags as libc::c_short,
+            ))?;
+
+            check(libc::posix_spawnp(
+                &mut pid,
+                file.as_ptr(),
+                &actions,
+                &attr,
+                argv.as_ptr(),
+                envp.as_ptr(),
+            ))
+        })();
+
+        libc::posix_spawnattr_destroy(&mut attr);
+        libc::posix_spawn_file_actions_destroy(&mut actions);
+        result?;
+    }
+
+    Ok(Pid::from_raw(pid))
+}
+
+/// What to change the scheduling priority of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    Process(Pid),
+    Group(Pid),
+}
+
+/// Lowers the scheduling priority of `of` to `by` below that of the shell,
+/// as `nice` does, but no lower than the lowest there is.
+pub fn lower_priority(of: Priority, by: i32) -> Result<()> {
+    let (which, who) = match of {
+        Priority::Process(pid) => (libc::PRIO_PROCESS, pid),
+        Priority::Group(pgid) => (libc::PRIO_PGRP, pgid),
+    };
+
+    // SAFETY: neither call touches memory. -1 is a valid priority, so
+    //         errors are told apart by `errno`
+    unsafe {
+        Errno::clear();
+        let current = libc::getpriority(libc::PRIO_PROCESS, 0);
+        if current == -1 && Errno::last() != Errno::UnknownErrno {
+            return Err(Error::Nix(Errno::last()));
         }
 
-        Err(_) => false,
+        let priority = current.saturating_add(by).clamp(-20, 19);
+        if libc::setpriority(which, who.as_raw() as libc::id_t, priority) == -1 {
+            return Err(Error::Nix(Errno::last()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits for `child` to exit. A child killed by a signal exits with 128 plus
+/// the signal number, as is customary.
+pub fn wait_for(child: Pid) -> Result<ExitStatus> {
+    let code = match waitpid(child, None)? {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+        _ => 0,
+    };
+    Ok(ExitStatus::from_code(code))
+}
+
+/// Waits until there is input to read on `fd`, or `deadline` has passed.
+/// Returns whether there is input.
+pub fn wait_for_input(fd: RawFd, deadline: Option<Instant>) -> Result<bool> {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                // round up, so that the deadline has passed once poll returns
+                left.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+
+        match poll(&mut fds, timeout) {
+            Ok(0) => return Ok(false),
+            Ok(_) => return Ok(true),
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
     }
 }
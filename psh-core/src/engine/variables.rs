@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+
+    /// Whether the value can no longer be changed, as set with `readonly`.
+    pub readonly: bool,
+}
+
+/// Whether `s` can be the name of a variable.
+pub fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// How a variable differs from the environment the shell was started with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Unchanged,
+    Modified,
+    Added,
+    Removed,
+}
+
+/// The variable store of the shell. Exported variables are kept in sync with
+/// the process environment, so that child processes inherit them, while other
+/// variables only live inside the shell.
+#[derive(Debug, Default)]
+pub struct Variables {
+    vars: HashMap<String, Variable>,
+
+    /// The environment as it looked when the shell was started.
+    initial: HashMap<String, String>,
+}
+
+impl Variables {
+    /// The variables of the environment, where those that are not valid
+    /// UTF-8 are left out.
+    pub fn from_env() -> Self {
+        let initial = env::vars_os()
+            .filter_map(|(key, val)| Some((key.into_string().ok()?, val.into_string().ok()?)))
+            .collect::<HashMap<_, _>>();
+        let vars = initial
+            .iter()
+            .map(|(key, val)| {
+                let var = Variable {
+                    value: val.clone(),
+                    exported: true,
+                    readonly: false,
+                };
+                (key.clone(), var)
+            })
+            .collect();
+
+        let mut variables = Self { vars, initial };
+        variables.init_pwd();
+        variables
+    }
+
+    /// Sets `$PWD` to the working directory, unless it already refers to it,
+    /// possibly through a symlink, as it does when the shell is started from
+    /// another shell.
+    fn init_pwd(&mut self) {
+        let Ok(cwd) = env::current_dir() else {
+            return;
+        };
+        let current = self.value_of("PWD").is_some_and(|pwd| {
+            Path::new(pwd).is_absolute() && fs::canonicalize(pwd).is_ok_and(|pwd| pwd == cwd)
+        });
+
+        // nothing is readonly yet, so setting it can not fail
+        if !current && self.set("PWD", cwd.display()).is_ok() {
+            self.export("PWD");
+        }
+    }
+
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&Variable> {
+        self.vars.get(name.as_ref())
+    }
+
+    pub fn value_of(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.get(name).map(|var| var.value.as_str())
+    }
+
+    pub fn contains(&self, name: impl AsRef<str>) -> bool {
+        self.vars.contains_key(name.as_ref())
+    }
+
+    /// Sets the value of `name`, keeping its flags if it already exists.
+    /// Fails if it is readonly.
+    pub fn set(&mut self, name: impl ToString, value: impl ToString) -> Result<()> {
+        let (name, value) = (name.to_string(), value.to_string());
+
+        match self.vars.get_mut(&name) {
+            Some(var) if var.readonly => return Err(Error::Readonly(name)),
+            Some(var) => {
+                if var.exported {
+                    env::set_var(&name, &value);
+                }
+                var.value = value;
+            }
+            None => {
+                let var = Variable {
+                    value,
+                    exported: false,
+                    readonly: false,
+                };
+                self.vars.insert(name, var);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks `name` as exported, creating it with an empty value if needed.
+    pub fn export(&mut self, name: impl ToString) {
+        let name = name.to_string();
+        let var = self.entry(&name);
+        var.exported = true;
+        env::set_var(name, &var.value);
+    }
+
+    /// Stops exporting `name`, which keeps its value in the shell.
+    pub fn unexport(&mut self, name: impl AsRef<str>) {
+        let name = name.as_ref();
+        if let Some(var) = self.vars.get_mut(name) {
+            var.exported = false;
+            env::remove_var(name);
+        }
+    }
+
+    /// Marks `name` as readonly, creating it with an empty value if needed.
+    pub fn set_readonly(&mut self, name: impl ToString) {
+        self.entry(&name.to_string()).readonly = true;
+    }
+
+    fn entry(&mut self, name: &str) -> &mut Variable {
+        self.vars.entry(name.to_string()).or_insert(Variable {
+            value: Default::default(),
+            exported: false,
+            readonly: false,
+        })
+    }
+
+    /// Puts back `var` as `name`, as it was before being changed for a
+    /// single command, or removes `name` if it did not exist.
+    pub fn restore(&mut self, name: impl ToString, var: Option<Variable>) {
+        let name = name.to_string();
+        match var {
+            Some(var) => {
+                match var.exported {
+                    true => env::set_var(&name, &var.value),
+                    false => env::remove_var(&name),
+                }
+                self.vars.insert(name, var);
+            }
+            None => {
+                self.unset(name);
+            }
+        }
+    }
+
+    pub fn unset(&mut self, name: impl AsRef<str>) -> Option<Variable> {
+        let name = name.as_ref();
+        let var = self.vars.remove(name)?;
+        if var.exported {
+            env::remove_var(name);
+        }
+        Some(var)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Variable)> {
+        self.vars.iter()
+    }
+
+    pub fn initial_value(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.initial.get(name.as_ref()).map(String::as_str)
+    }
+
+    pub fn change_of(&self, name: impl AsRef<str>) -> Change {
+        let name = name.as_ref();
+        match (self.value_of(name), self.initial_value(name)) {
+            (Some(current), Some(initial)) if current == initial => Change::Unchanged,
+            (Some(_), Some(_)) => Change::Modified,
+            (Some(_), None) => Change::Added,
+            (None, Some(_)) => Change::Removed,
+            (None, None) => Change::Unchanged,
+        }
+    }
+
+    /// Returns every variable that currently exists or existed at shell start,
+    /// sorted by name, together with how it has changed since.
+    pub fn diff(&self) -> Vec<(&str, Change)> {
+        let mut names = self
+            .vars
+            .keys()
+            .chain(self.initial.keys().filter(|k| !self.vars.contains_key(*k)))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+
+        names
+            .into_iter()
+            .map(|name| (name, self.change_of(name)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(initial: &[(&str, &str)]) -> Variables {
+        let initial = initial
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        let vars = initial
+            .iter()
+            .map(|(k, v)| {
+                let var = Variable {
+                    value: v.clone(),
+                    exported: false,
+                    readonly: false,
+                };
+                (k.clone(), var)
+            })
+            .collect();
+        Variables { vars, initial }
+    }
+
+    #[test]
+    fn set_keeps_export_flag() {
+        let mut vars = vars(&[]);
+        vars.set("PSH_TEST_FOO", "bar").unwrap();
+        assert_eq!(Some("bar"), vars.value_of("PSH_TEST_FOO"));
+        assert!(!vars.get("PSH_TEST_FOO").unwrap().exported);
+
+        vars.export("PSH_TEST_FOO");
+        vars.set("PSH_TEST_FOO", "baz").unwrap();
+        assert!(vars.get("PSH_TEST_FOO").unwrap().exported);
+        assert_eq!(Ok("baz".to_string()), env::var("PSH_TEST_FOO"));
+
+        vars.unset("PSH_TEST_FOO");
+        assert!(env::var("PSH_TEST_FOO").is_err());
+    }
+
+    #[test]
+    fn diff_against_initial() {
+        let mut vars = vars(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        vars.set("b", "two").unwrap();
+        vars.unset("c");
+        vars.set("d", "4").unwrap();
+
+        let expected = vec![
+            ("a", Change::Unchanged),
+            ("b", Change::Modified),
+            ("c", Change::Removed),
+            ("d", Change::Added),
+        ];
+        assert_eq!(expected, vars.diff());
+    }
+
+    #[test]
+    fn readonly_and_restore() {
+        let mut vars = vars(&[]);
+        vars.set("PSH_TEST_RO", "1").unwrap();
+        vars.set_readonly("PSH_TEST_RO");
+        assert!(matches!(
+            vars.set("PSH_TEST_RO", "2"),
+            Err(Error::Readonly(name)) if name == "PSH_TEST_RO"
+        ));
+        assert_eq!(Some("1"), vars.value_of("PSH_TEST_RO"));
+
+        let saved = vars.get("PSH_TEST_TMP").cloned();
+        vars.set("PSH_TEST_TMP", "temp").unwrap();
+        vars.export("PSH_TEST_TMP");
+        assert_eq!(Ok("temp".to_string()), env::var("PSH_TEST_TMP"));
+        vars.restore("PSH_TEST_TMP", saved);
+        assert!(!vars.contains("PSH_TEST_TMP"));
+        assert!(env::var("PSH_TEST_TMP").is_err());
+
+        vars.set("PSH_TEST_TMP", "kept").unwrap();
+        vars.export("PSH_TEST_TMP");
+        vars.unexport("PSH_TEST_TMP");
+        assert_eq!(Some("kept"), vars.value_of("PSH_TEST_TMP"));
+        assert!(env::var("PSH_TEST_TMP").is_err());
+    }
+}
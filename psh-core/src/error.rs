@@ -19,13 +19,36 @@ pub enum Error {
     UnknownCommand(String),
     UnknownBuiltin(String),
     Unimplemented(String),
-    SyntaxError(String),
+
+    /// What could not be parsed, and the line of the input it starts on if
+    /// known.
+    SyntaxError(String, Option<usize>),
     ParseError(String),
     CancelledLine,
+    Cancelled,
     Incomplete(String),
+    #[cfg(feature = "engine")]
     Nix(nix::Error),
     Var(env::VarError),
     NonExistentFile(String),
+    Arithmetic(String),
+    UnboundVariable(String),
+
+    /// The name of a parameter and the message of `${name?message}`.
+    ParameterNotSet(String, String),
+
+    /// The name of a readonly variable that was assigned to.
+    Readonly(String),
+
+    /// The name of a function called while too many calls were nested.
+    TooManyCalls(String),
+
+    /// A reference to the history, such as `!foo`, that no entry matches.
+    EventNotFound(String),
+
+    /// An error in a file being executed, with the path of the file and the
+    /// line of the command it happened in.
+    At(String, usize, Box<Error>),
 
     #[cfg(feature = "serde")]
     Json(serde_json::Error),
@@ -45,13 +68,23 @@ impl fmt::Display for Error {
                 Self::UnknownCommand(cmd) => format!("unknown command: '{}'", cmd),
                 Self::UnknownBuiltin(cmd) => format!("unknown builtin: '{}'", cmd),
                 Self::Unimplemented(s) => s.to_string(),
-                Self::SyntaxError(s) => format!("could not parse the following: {s}"),
+                Self::SyntaxError(s, _) => format!("could not parse the following: {s}"),
                 Self::ParseError(e) => e.to_string(),
                 Self::CancelledLine => "line input cancelled".to_string(),
+                Self::Cancelled => "cancelled".to_string(),
                 Self::Incomplete(line) => format!("incomplete line: '{line}'"),
+                #[cfg(feature = "engine")]
                 Self::Nix(e) => format!("errno: {e}"),
                 Self::Var(e) => e.to_string(),
                 Self::NonExistentFile(file) => format!("{file}: no such file"),
+                Self::Arithmetic(e) => format!("arithmetic expansion: {e}"),
+                Self::UnboundVariable(name) => format!("{name}: unbound variable"),
+                Self::ParameterNotSet(name, message) => format!("{name}: {message}"),
+                Self::Readonly(name) => format!("{name}: readonly variable"),
+                Self::TooManyCalls(name) =>
+                    format!("{name}: maximum function nesting level exceeded"),
+                Self::EventNotFound(event) => format!("{event}: event not found"),
+                Self::At(source, line, e) => format!("{source}:{line}: {e}"),
 
                 #[cfg(feature = "serde")]
                 Self::Json(e) => e.to_string(),
@@ -60,6 +93,26 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    /// The error, as happened at `line` of the file `source`, unless it is
+    /// already known where it happened, or it is a cancellation, which is
+    /// not reported.
+    pub fn at(self, source: &str, line: usize) -> Self {
+        match self {
+            Self::At(..) | Self::Cancelled | Self::CancelledLine => self,
+            e => Self::At(source.to_string(), line, Box::new(e)),
+        }
+    }
+
+    /// The error itself, without where it happened.
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::At(_, _, e) => e.inner(),
+            e => e,
+        }
+    }
+}
+
 impl error::Error for Error {}
 
 impl From<io::Error> for Error {
@@ -68,6 +121,7 @@ impl From<io::Error> for Error {
     }
 }
 
+#[cfg(feature = "engine")]
 impl From<nix::Error> for Error {
     fn from(e: nix::Error) -> Self {
         Self::Nix(e)
@@ -1,8 +1,39 @@
+//! The engine behind the `psh` shell.
+//!
+//! Embedders can compile only part of it, with the default features off:
+//!
+//! - `parser`: the lexer and the parser, see [`ast::parse`].
+//! - `expand`: the expansions of words, in an [`engine::expand::Environment`]
+//!   provided by the embedder.
+//! - `engine`: the [`Engine`] that runs commands, on by default.
+
+#[cfg(feature = "engine")]
 pub mod engine;
+#[cfg(feature = "parser")]
 pub mod error;
+#[cfg(feature = "parser")]
 pub mod parser;
+#[cfg(feature = "engine")]
 pub mod path;
+#[cfg(any(test, feature = "test-util"))]
+#[doc(hidden)]
+pub mod test_util;
+
+/// The expansions, without the rest of the engine.
+#[cfg(all(feature = "expand", not(feature = "engine")))]
+pub mod engine {
+    pub mod arith;
+    pub mod brace;
+    pub mod cancel;
+    pub mod expand;
+    pub mod glob;
+    pub mod quote;
+    pub mod variables;
+}
 
+#[cfg(feature = "engine")]
 pub use crate::engine::{Engine, ExitStatus};
+#[cfg(feature = "parser")]
 pub use crate::error::{Error, Result};
+#[cfg(feature = "parser")]
 pub use crate::parser::{ast, consumer, tok};
@@ -0,0 +1,217 @@
+//! Alias substitution, which replaces the first word of a simple command
+//! with the value of the alias of that name before the command is parsed.
+//! As in POSIX, an alias is not substituted again within its own value, and
+//! if the value ends with a blank, the word after it is substituted too.
+
+use std::collections::HashMap;
+
+use crate::ast::here_doc::{self, ends_word, Quotes};
+use crate::tok::{lex, ReservedWord, Token};
+
+/// Where the substitution is, in the input or in a nested command.
+#[derive(Debug, Clone, Copy)]
+struct Nesting {
+    /// Whether the next word is in command position.
+    command: bool,
+
+    /// The parentheses opened since the nested command started, which are
+    /// closed before its own.
+    parens: usize,
+}
+
+struct Substitution<'a> {
+    aliases: &'a HashMap<String, String>,
+
+    /// The aliases whose values are being substituted.
+    active: Vec<String>,
+    tokens: Vec<Token>,
+}
+
+/// Substitutes the aliases of `input`, returning the input that results if
+/// any of them applies.
+pub fn substitute(input: &str, aliases: &HashMap<String, String>) -> Option<String> {
+    if aliases.is_empty() {
+        return None;
+    }
+
+    let (tokens, _) = here_doc::gather(lex(input));
+    let mut substitution = Substitution {
+        aliases,
+        active: Vec::new(),
+        tokens: Vec::with_capacity(tokens.len()),
+    };
+    substitution.substitute(tokens);
+
+    let substituted = substitution
+        .tokens
+        .iter()
+        .map(Token::as_str)
+        .collect::<String>();
+    (substituted != input).then_some(substituted)
+}
+
+impl Substitution<'_> {
+    /// Substitutes the aliases of `tokens`, returning whether the word after
+    /// them is in command position.
+    fn substitute(&mut self, tokens: Vec<Token>) -> bool {
+        let mut tokens = tokens.into_iter().peekable();
+        let mut quotes = Quotes::default();
+        let mut nesting = vec![Nesting {
+            command: true,
+            parens: 0,
+        }];
+
+        // whether the last token is part of a word, and whether the next word
+        // is the target of a redirection rather than a command
+        let (mut in_word, mut redirection) = (false, false);
+
+        while let Some(token) = tokens.next() {
+            if quotes.quoted(&token) {
+                self.tokens.push(token);
+                continue;
+            }
+
+            // a nested command starts a word of the command around it
+            if let Token::CmdSubStart | Token::ArithmeticStart = token {
+                let arithmetic = token == Token::ArithmeticStart;
+                if let Some(current) = nesting.last_mut() {
+                    current.command = false;
+                }
+                nesting.push(Nesting {
+                    command: !arithmetic,
+                    parens: usize::from(arithmetic),
+                });
+                in_word = false;
+                self.tokens.push(token);
+                continue;
+            }
+
+            let nested = nesting.len() > 1;
+            let Some(current) = nesting.last_mut() else {
+                break;
+            };
+
+            match &token {
+                Token::RParen if current.parens == 0 && nested => {
+                    nesting.pop();
+                    in_word = true;
+                }
+                Token::HereDocument { .. } => {}
+                Token::RedirectInput | Token::RedirectOutput => {
+                    redirection = true;
+                    in_word = false;
+                }
+                Token::Whitespace(' ' | '\t') => in_word = false,
+                token if ends_word(token) => {
+                    match token {
+                        Token::LParen => current.parens += 1,
+                        Token::RParen => current.parens = current.parens.saturating_sub(1),
+                        _ => {}
+                    }
+                    current.command = true;
+                    in_word = false;
+                }
+
+                _ if in_word => {}
+                _ if redirection => {
+                    redirection = false;
+                    in_word = true;
+                }
+                _ if !current.command => in_word = true,
+
+                Token::Word(name) if ends_word_or_input(tokens.peek()) => {
+                    if let Some(value) = self.alias(name) {
+                        self.active.push(name.to_string());
+                        let command = self.substitute(lex(&value));
+                        self.active.pop();
+
+                        current.command = command || value.ends_with([' ', '\t']);
+                        continue;
+                    }
+                    current.command = false;
+                    in_word = true;
+                }
+                // the command name comes after the assignments
+                Token::Word(_) if tokens.peek() == Some(&Token::Equals) => in_word = true,
+                Token::Reserved(
+                    ReservedWord::Bang
+                    | ReservedWord::LBrace
+                    | ReservedWord::Do
+                    | ReservedWord::Elif
+                    | ReservedWord::Else
+                    | ReservedWord::If
+                    | ReservedWord::Then
+                    | ReservedWord::Until
+                    | ReservedWord::While,
+                ) => {}
+                _ => {
+                    current.command = false;
+                    in_word = true;
+                }
+            }
+
+            self.tokens.push(token);
+        }
+
+        nesting.first().is_some_and(|nesting| nesting.command)
+    }
+
+    /// The value of the alias `name`, unless it is being substituted.
+    fn alias(&self, name: &str) -> Option<String> {
+        match self.active.iter().any(|active| active == name) {
+            true => None,
+            false => self.aliases.get(name).cloned(),
+        }
+    }
+}
+
+fn ends_word_or_input(token: Option<&Token>) -> bool {
+    token.is_none_or(ends_word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substituting() {
+        let aliases = HashMap::from([
+            ("ll".to_string(), "ls -l".to_string()),
+            ("ls".to_string(), "ls -F".to_string()),
+            ("sudo".to_string(), "sudo ".to_string()),
+            ("lsp".to_string(), "ls |".to_string()),
+            ("loop".to_string(), "loop2 a".to_string()),
+            ("loop2".to_string(), "loop b".to_string()),
+        ]);
+        let substituted = |input| substitute(input, &aliases);
+
+        assert_eq!(Some("ls -F -l".to_string()), substituted("ll"));
+        assert_eq!(
+            Some("ls -F -l x; ls -F".to_string()),
+            substituted("ll x; ls")
+        );
+        assert_eq!(Some("x=1 ls -F -l".to_string()), substituted("x=1 ll"));
+        assert_eq!(Some("sudo  ls -F -l".to_string()), substituted("sudo ll"));
+        assert_eq!(Some("ls -F | ls -F -l".to_string()), substituted("lsp ll"));
+        assert_eq!(Some("> out ls -F -l".to_string()), substituted("> out ll"));
+        assert_eq!(Some("loop b a".to_string()), substituted("loop"));
+        assert_eq!(
+            Some("echo $(ls -F -l) ll".to_string()),
+            substituted("echo $(ll) ll")
+        );
+        assert_eq!(
+            Some("if true; then ls -F -l; fi".to_string()),
+            substituted("if true; then ll; fi")
+        );
+        assert_eq!(
+            Some("cat <<EOF && ls -F\nll\nEOF\n".to_string()),
+            substituted("cat <<EOF && ls\nll\nEOF\n")
+        );
+
+        assert_eq!(None, substituted("echo ll"));
+        assert_eq!(None, substituted("'ll' \"ll\" \\ll ll=1"));
+        assert_eq!(None, substituted("echo $((ll)) > ll"));
+        assert_eq!(None, substituted("llama"));
+        assert_eq!(None, substitute("ll", &HashMap::new()));
+    }
+}
@@ -0,0 +1,787 @@
+//! Conformance tests for the productions of the POSIX shell grammar, as
+//! written in the doc comments of `nodes`. Every production has a test of
+//! its own, with inputs that it must accept along with the shape of the
+//! tree they are parsed into, and inputs that it must reject, so that a
+//! regression points at the production that broke.
+//!
+//! A shape is the tree written as an s-expression, leaving out whitespace,
+//! linebreaks and comments. Lists, and-or lists and pipelines of a single
+//! element are left out as well, so that `echo a` is `(simple echo a)`
+//! rather than that wrapped in four lists.
+
+use super::*;
+use shape::shape;
+
+/// Writes syntax trees as shapes, with a function for every node.
+mod shape {
+    use std::iter;
+
+    use super::super::*;
+
+    pub(super) fn shape(tree: &SyntaxTree) -> String {
+        let Some((commands, _)) = &tree.commands else {
+            return String::from("(program)");
+        };
+        let mut shapes = iter::once(&commands.head)
+            .chain(commands.tail.iter().map(|(_, cmd)| cmd))
+            .filter_map(complete_command);
+
+        match (shapes.next(), shapes.next()) {
+            (None, _) => String::from("(program)"),
+            (Some(first), None) => first,
+            (first, second) => {
+                let shapes = first.into_iter().chain(second).chain(shapes);
+                format!("(program {})", shapes.collect::<Vec<_>>().join(" "))
+            }
+        }
+    }
+
+    fn complete_command(cmd: &CompleteCommand) -> Option<String> {
+        match cmd {
+            CompleteCommand::List {
+                list, separator_op, ..
+            } => Some(list_of(
+                iter::once(and_or(&list.head)).chain(
+                    list.tail
+                        .iter()
+                        .flat_map(|(op, cmd)| [op.to_string().trim().to_string(), and_or(cmd)]),
+                ),
+                separator_op.as_ref(),
+            )),
+            CompleteCommand::Comment { .. } => None,
+        }
+    }
+
+    /// A list of and-or lists with the separators between them, which is left
+    /// out if there is only one that is not run asynchronously.
+    fn list_of(items: impl Iterator<Item = String>, separator: Option<&SeparatorOp>) -> String {
+        let mut items = items.collect::<Vec<_>>();
+        if let Some(separator @ SeparatorOp::Async(_)) = separator {
+            items.push(separator.to_string().trim().to_string());
+        }
+        match items.as_slice() {
+            [item] => item.clone(),
+            items => format!("(list {})", items.join(" ")),
+        }
+    }
+
+    fn and_or(list: &AndOrList) -> String {
+        if list.tail.is_empty() {
+            return pipeline(&list.head);
+        }
+        let tail = list
+            .tail
+            .iter()
+            .map(|(op, _, cmd)| format!("{} {}", op.to_string().trim(), pipeline(cmd)));
+        format!(
+            "(and_or {} {})",
+            pipeline(&list.head),
+            tail.collect::<Vec<_>>().join(" ")
+        )
+    }
+
+    fn pipeline(pipeline: &Pipeline) -> String {
+        let sequence = &pipeline.sequence;
+        let commands = iter::once(command(&sequence.head))
+            .chain(sequence.tail.iter().map(|(_, _, cmd)| command(cmd)))
+            .collect::<Vec<_>>();
+
+        match (&pipeline.bang, commands.as_slice()) {
+            (None, [command]) => command.clone(),
+            (bang, commands) => format!(
+                "(pipeline {}{})",
+                if bang.is_some() { "! " } else { "" },
+                commands.join(" ")
+            ),
+        }
+    }
+
+    fn command(command: &Command) -> String {
+        match command {
+            Command::Simple(simple) => simple_command(simple),
+            Command::Compound(compound, redirections) if redirections.is_empty() => {
+                compound_command(compound)
+            }
+            Command::Compound(compound, redirections) => format!(
+                "(redirected {} {})",
+                compound_command(compound),
+                redirections
+                    .iter()
+                    .map(redirection)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            Command::FunctionDefinition(definition) => match &definition.body {
+                Some(body) if body.redirections.is_empty() => format!(
+                    "(function {} {})",
+                    definition.name.name,
+                    compound_command(&body.command)
+                ),
+                Some(body) => format!(
+                    "(function {} (redirected {} {}))",
+                    definition.name.name,
+                    compound_command(&body.command),
+                    body.redirections
+                        .iter()
+                        .map(redirection)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+                None => format!("(function {})", definition.name.name),
+            },
+        }
+    }
+
+    fn compound_command(command: &CompoundCommand) -> String {
+        match command {
+            CompoundCommand::Brace(group) => format!("(brace {})", compound_list(&group.body)),
+            CompoundCommand::Subshell(subshell) => {
+                format!("(subshell {})", compound_list(&subshell.body))
+            }
+            CompoundCommand::For(clause) => {
+                let name = clause.name.as_ref().map_or("", |name| name.name.as_str());
+                let words = match &clause.wordlist {
+                    Some(wordlist) => format!(
+                        " (in{})",
+                        wordlist
+                            .words
+                            .iter()
+                            .map(|word| format!(" {}", word.name))
+                            .collect::<String>()
+                    ),
+                    None => String::new(),
+                };
+                format!("(for {name}{words} {})", do_group(&clause.body))
+            }
+            CompoundCommand::Case(clause) => {
+                let word = clause.word.as_ref().map_or("", |word| word.name.as_str());
+                let items = clause.items.iter().map(|item| {
+                    let pattern = iter::once(&item.pattern.head)
+                        .chain(item.pattern.tail.iter().map(|(_, word)| word))
+                        .map(|word| word.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    match &item.body {
+                        Some(body) => format!(" (item ({pattern}) {})", compound_list(body)),
+                        None => format!(" (item ({pattern}))"),
+                    }
+                });
+                format!("(case {word}{})", items.collect::<String>())
+            }
+            CompoundCommand::If(clause) => {
+                let mut s = format!(
+                    "(if {} (then {})",
+                    compound_list(&clause.predicate),
+                    compound_list(&clause.body)
+                );
+                if let Some(else_part) = &clause.else_part {
+                    for elif in &else_part.elseifs {
+                        s += &format!(
+                            " (elif {} (then {}))",
+                            compound_list(&elif.predicate),
+                            compound_list(&elif.body)
+                        );
+                    }
+                    if let Some((_, body)) = &else_part.else_part {
+                        s += &format!(" (else {})", compound_list(body));
+                    }
+                }
+                s + ")"
+            }
+            CompoundCommand::While(clause) => format!(
+                "(while {} {})",
+                compound_list(&clause.predicate),
+                do_group(&clause.body)
+            ),
+            CompoundCommand::Until(clause) => format!(
+                "(until {} {})",
+                compound_list(&clause.predicate),
+                do_group(&clause.body)
+            ),
+        }
+    }
+
+    fn do_group(group: &DoGroup) -> String {
+        format!("(do {})", compound_list(&group.body))
+    }
+
+    fn compound_list(list: &CompoundList) -> String {
+        let separator = |separator: &Separator| match separator {
+            Separator::Explicit(op, _) => op.to_string().trim().to_string(),
+            Separator::Implicit(_) => String::from(";"),
+        };
+        let last = match &list.separator {
+            Some(Separator::Explicit(op, _)) => Some(op),
+            _ => None,
+        };
+        list_of(
+            iter::once(and_or(&list.term.head)).chain(
+                list.term
+                    .tail
+                    .iter()
+                    .flat_map(|(sep, cmd)| [separator(sep), and_or(cmd)]),
+            ),
+            last,
+        )
+    }
+
+    fn simple_command(command: &SimpleCommand) -> String {
+        let prefixes = command.prefixes.iter().map(|prefix| match prefix {
+            CmdPrefix::Redirection(r) => redirection(r),
+            CmdPrefix::Assignment(assignment) => assignment.to_string().trim().to_string(),
+        });
+        let suffixes = command.suffixes.iter().map(|suffix| match suffix {
+            CmdSuffix::Redirection(r) => redirection(r),
+            CmdSuffix::Word(word) => word.name.clone(),
+        });
+        let words = prefixes
+            .chain(command.name.iter().map(|name| name.name.clone()))
+            .chain(suffixes)
+            .collect::<Vec<_>>();
+        format!("(simple {})", words.join(" "))
+    }
+
+    fn redirection(redirection: &Redirection) -> String {
+        match redirection {
+            Redirection::File {
+                input_fd,
+                ty,
+                target,
+                ..
+            } => format!(
+                "({}{} {})",
+                input_fd.map(|fd| fd.to_string()).unwrap_or_default(),
+                ty.to_string(),
+                target.name
+            ),
+            Redirection::Here {
+                input_fd,
+                ty,
+                end,
+                content,
+                ..
+            } => format!(
+                "({}{} {} {:?})",
+                input_fd.map(|fd| fd.to_string()).unwrap_or_default(),
+                ty.to_string(),
+                end.name,
+                content.name
+            ),
+        }
+    }
+}
+
+/// Checks that every input is accepted and parsed into its shape.
+fn accepts(cases: &[(&str, &str)]) {
+    for (input, expected) in cases {
+        match parse(input, false) {
+            Ok(tree) => assert_eq!(*expected, shape(&tree), "{input:?}"),
+            Err(e) => panic!("{input:?} was rejected: {e}"),
+        }
+    }
+}
+
+/// Checks that every input is rejected, either as invalid or incomplete.
+fn rejects(inputs: &[&str]) {
+    for input in inputs {
+        if let Ok(tree) = parse(input, false) {
+            panic!("{input:?} was accepted as {}", shape(&tree));
+        }
+    }
+}
+
+#[test]
+fn program() {
+    accepts(&[
+        ("", "(program)"),
+        ("\n\n", "(program)"),
+        ("# only a comment", "(program)"),
+        ("\necho a\n\n", "(simple echo a)"),
+    ]);
+    rejects(&[")", "echo a )"]);
+}
+
+#[test]
+fn complete_commands() {
+    accepts(&[
+        ("a\nb", "(program (simple a) (simple b))"),
+        ("a\n\n\nb\nc", "(program (simple a) (simple b) (simple c))"),
+        ("# comment\na\n", "(simple a)"),
+    ]);
+    rejects(&["a\n)\nb"]);
+}
+
+#[test]
+fn complete_command() {
+    accepts(&[
+        ("a;", "(simple a)"),
+        ("a &", "(list (simple a) &)"),
+        ("a # comment", "(simple a)"),
+        ("a; b & # comment", "(list (simple a) ; (simple b) &)"),
+    ]);
+    rejects(&[";", "&", "a;;"]);
+}
+
+#[test]
+fn list() {
+    accepts(&[
+        ("a; b", "(list (simple a) ; (simple b))"),
+        ("a & b &", "(list (simple a) & (simple b) &)"),
+        (
+            "a && b; c",
+            "(list (and_or (simple a) && (simple b)) ; (simple c))",
+        ),
+    ]);
+    rejects(&["a ; ; b", "; a", "a & & b"]);
+}
+
+#[test]
+fn and_or() {
+    accepts(&[
+        ("a && b", "(and_or (simple a) && (simple b))"),
+        (
+            "a && b || c",
+            "(and_or (simple a) && (simple b) || (simple c))",
+        ),
+        ("a &&\n\nb", "(and_or (simple a) && (simple b))"),
+        (
+            "a | b || c",
+            "(and_or (pipeline (simple a) (simple b)) || (simple c))",
+        ),
+    ]);
+    rejects(&["&& a", "a ||", "a && || b"]);
+}
+
+#[test]
+fn pipeline() {
+    accepts(&[
+        ("! a", "(pipeline ! (simple a))"),
+        ("! a | b", "(pipeline ! (simple a) (simple b))"),
+    ]);
+    rejects(&["! ! a", "!"]);
+}
+
+#[test]
+fn pipe_sequence() {
+    accepts(&[
+        ("a | b", "(pipeline (simple a) (simple b))"),
+        ("a | b | c", "(pipeline (simple a) (simple b) (simple c))"),
+        ("a |\n\nb", "(pipeline (simple a) (simple b))"),
+        (
+            "{ a; } | (b)",
+            "(pipeline (brace (simple a)) (subshell (simple b)))",
+        ),
+    ]);
+    rejects(&["| a", "a | | b", "a |"]);
+}
+
+#[test]
+fn command() {
+    accepts(&[
+        ("a", "(simple a)"),
+        (
+            "{ a; } > f 2>&1",
+            "(redirected (brace (simple a)) (> f) (2>& 1))",
+        ),
+        ("(a) < f", "(redirected (subshell (simple a)) (< f))"),
+        ("f() { a; }", "(function f (brace (simple a)))"),
+    ]);
+    rejects(&["(a) b", "{ a; } b"]);
+}
+
+#[test]
+fn compound_command() {
+    accepts(&[
+        ("{ a; }", "(brace (simple a))"),
+        ("(a)", "(subshell (simple a))"),
+        ("for i do a; done", "(for i (do (simple a)))"),
+        ("case x in esac", "(case x)"),
+        ("if a; then b; fi", "(if (simple a) (then (simple b)))"),
+        ("while a; do b; done", "(while (simple a) (do (simple b)))"),
+        ("until a; do b; done", "(until (simple a) (do (simple b)))"),
+    ]);
+    rejects(&["do a; done", "done", "then", "fi", "esac"]);
+}
+
+#[test]
+fn subshell() {
+    accepts(&[
+        ("(a)", "(subshell (simple a))"),
+        ("( a; b )", "(subshell (list (simple a) ; (simple b)))"),
+        ("(\na\n)", "(subshell (simple a))"),
+        (
+            "((a) | b)",
+            "(subshell (pipeline (subshell (simple a)) (simple b)))",
+        ),
+    ]);
+    rejects(&["( )", "( a", "a )"]);
+}
+
+#[test]
+fn compound_list() {
+    accepts(&[
+        ("{ a; }", "(brace (simple a))"),
+        ("{\n\na\n\n}", "(brace (simple a))"),
+        ("{ a & }", "(brace (list (simple a) &))"),
+    ]);
+    rejects(&["{ }", "{ ; }"]);
+}
+
+#[test]
+fn term() {
+    accepts(&[
+        ("{ a; b; }", "(brace (list (simple a) ; (simple b)))"),
+        ("{ a\nb\n}", "(brace (list (simple a) ; (simple b)))"),
+        ("{ a & b; }", "(brace (list (simple a) & (simple b)))"),
+        ("{ a; b & }", "(brace (list (simple a) ; (simple b) &))"),
+    ]);
+    rejects(&["{ a; ; b; }", "{a; }"]);
+}
+
+#[test]
+fn for_clause() {
+    accepts(&[
+        (
+            "for i in 1 2; do echo $i; done",
+            "(for i (in 1 2) (do (simple echo $i)))",
+        ),
+        ("for i do a; done", "(for i (do (simple a)))"),
+        ("for i; do a; done", "(for i (do (simple a)))"),
+        ("for i\ndo a\ndone", "(for i (do (simple a)))"),
+        ("for i\nin a\ndo b\ndone", "(for i (in a) (do (simple b)))"),
+    ]);
+    rejects(&[
+        "for i in a b do c; done",
+        "for i in a; b; done",
+        "for; do a; done",
+    ]);
+}
+
+#[test]
+fn wordlist() {
+    accepts(&[
+        ("for i in; do a; done", "(for i (in) (do (simple a)))"),
+        (
+            "for i in a \"b c\" $d; do e; done",
+            "(for i (in a \"b c\" $d) (do (simple e)))",
+        ),
+        (
+            "for i in do done; do a; done",
+            "(for i (in do done) (do (simple a)))",
+        ),
+    ]);
+}
+
+#[test]
+fn name() {
+    accepts(&[
+        (
+            "for _a1 in x; do b; done",
+            "(for _a1 (in x) (do (simple b)))",
+        ),
+        ("f_1() { a; }", "(function f_1 (brace (simple a)))"),
+    ]);
+    rejects(&[
+        "for 1 in a; do b; done",
+        "for a-b in c; do d; done",
+        "1() { a; }",
+    ]);
+}
+
+#[test]
+fn case_clause() {
+    accepts(&[
+        (
+            "case $x in a) b;; c|d) e;; esac",
+            "(case $x (item (a) (simple b)) (item (c d) (simple e)))",
+        ),
+        ("case x in esac", "(case x)"),
+        ("case x\nin\nesac", "(case x)"),
+        ("case x in a) b\nesac", "(case x (item (a) (simple b)))"),
+    ]);
+    rejects(&[
+        "case x a) b;; esac",
+        "case in a) b;; esac",
+        "case x in a) b;;",
+    ]);
+}
+
+#[test]
+fn case_item() {
+    accepts(&[
+        ("case x in (a) b;; esac", "(case x (item (a) (simple b)))"),
+        ("case x in a) ;; esac", "(case x (item (a)))"),
+        (
+            "case x in\na)\nb\n;;\nesac",
+            "(case x (item (a) (simple b)))",
+        ),
+        (
+            "case x in a) b; c;; esac",
+            "(case x (item (a) (list (simple b) ; (simple c))))",
+        ),
+    ]);
+    rejects(&["case x in a b;; esac", "case x in a) b;; c d) e;; esac"]);
+}
+
+#[test]
+fn pattern() {
+    accepts(&[
+        (
+            "case x in a|b|c) d;; esac",
+            "(case x (item (a b c) (simple d)))",
+        ),
+        (
+            "case x in *.rs|'q'*) d;; esac",
+            "(case x (item (*.rs 'q'*) (simple d)))",
+        ),
+        (
+            "case x in a|esac|done) d;; esac",
+            "(case x (item (a esac done) (simple d)))",
+        ),
+    ]);
+    rejects(&["case x in a|) d;; esac", "case x in |a) d;; esac"]);
+}
+
+#[test]
+fn if_clause() {
+    accepts(&[
+        ("if a; then b; fi", "(if (simple a) (then (simple b)))"),
+        (
+            "if a; b; then c; fi",
+            "(if (list (simple a) ; (simple b)) (then (simple c)))",
+        ),
+        ("if a\nthen\nb\nfi", "(if (simple a) (then (simple b)))"),
+    ]);
+    rejects(&[
+        "if a; then fi",
+        "if a; b; fi",
+        "if then b; fi",
+        "if a; then b",
+    ]);
+}
+
+#[test]
+fn else_part() {
+    accepts(&[
+        (
+            "if a; then b; else c; fi",
+            "(if (simple a) (then (simple b)) (else (simple c)))",
+        ),
+        (
+            "if a; then b; elif c; then d; else e; fi",
+            "(if (simple a) (then (simple b)) (elif (simple c) (then (simple d))) (else (simple e)))",
+        ),
+        (
+            "if a; then b; elif c; then d; elif e; then f; fi",
+            "(if (simple a) (then (simple b)) (elif (simple c) (then (simple d))) (elif (simple e) (then (simple f))))",
+        ),
+    ]);
+    rejects(&[
+        "if a; then b; else fi",
+        "if a; then b; elif c; fi",
+        "if a; then b; else c; elif d; then e; fi",
+    ]);
+}
+
+#[test]
+fn while_clause() {
+    accepts(&[
+        ("while a; do b; done", "(while (simple a) (do (simple b)))"),
+        (
+            "while a; b; do c; done",
+            "(while (list (simple a) ; (simple b)) (do (simple c)))",
+        ),
+    ]);
+    rejects(&["while a; done", "while do b; done"]);
+}
+
+#[test]
+fn until_clause() {
+    accepts(&[
+        ("until a; do b; done", "(until (simple a) (do (simple b)))"),
+        ("until a\ndo\nb\ndone", "(until (simple a) (do (simple b)))"),
+    ]);
+    rejects(&["until a do b done", "until a; done"]);
+}
+
+#[test]
+fn function_definition() {
+    accepts(&[
+        ("f() { a; }", "(function f (brace (simple a)))"),
+        ("f () ( a )", "(function f (subshell (simple a)))"),
+        ("f( ) { a; }", "(function f (brace (simple a)))"),
+        ("f()\n\n{\na\n}", "(function f (brace (simple a)))"),
+    ]);
+    rejects(&["f() a", "f(a) { b; }", "f() }"]);
+}
+
+#[test]
+fn function_body() {
+    accepts(&[
+        (
+            "f() { a; } > out",
+            "(function f (redirected (brace (simple a)) (> out)))",
+        ),
+        (
+            "f() if a; then b; fi",
+            "(function f (if (simple a) (then (simple b))))",
+        ),
+        (
+            "f() for i in 1; do a; done",
+            "(function f (for i (in 1) (do (simple a))))",
+        ),
+        (
+            "f() while a; do b; done",
+            "(function f (while (simple a) (do (simple b))))",
+        ),
+    ]);
+    rejects(&["f() a b", "f() ! { a; }"]);
+}
+
+#[test]
+fn brace_group() {
+    accepts(&[
+        ("{ a; }", "(brace (simple a))"),
+        ("{ { a; }; }", "(brace (brace (simple a)))"),
+        ("{ a\n}", "(brace (simple a))"),
+    ]);
+    rejects(&["{ }", "{a; }", "{ a }", "{ a;"]);
+}
+
+#[test]
+fn do_group() {
+    accepts(&[
+        (
+            "while a; do b; c; done",
+            "(while (simple a) (do (list (simple b) ; (simple c))))",
+        ),
+        ("while a; do\nb\ndone", "(while (simple a) (do (simple b)))"),
+    ]);
+    rejects(&["while a; do done", "while a; do b done", "while a; do b;"]);
+}
+
+#[test]
+fn simple_command() {
+    accepts(&[
+        ("echo a b c", "(simple echo a b c)"),
+        ("a=1 b=2 c d e", "(simple a=1 b=2 c d e)"),
+        ("a=1", "(simple a=1)"),
+        ("> f", "(simple (> f))"),
+        ("echo if then", "(simple echo if then)"),
+        (
+            "\"quoted word\" 'single'",
+            "(simple \"quoted word\" 'single')",
+        ),
+    ]);
+    rejects(&["a > > f", "a b )"]);
+}
+
+#[test]
+fn cmd_prefix() {
+    accepts(&[
+        ("a=1 > f b", "(simple a=1 (> f) b)"),
+        ("2> err a", "(simple (2> err) a)"),
+        ("< in a=1", "(simple (< in) a=1)"),
+    ]);
+    rejects(&["a=1 >"]);
+}
+
+#[test]
+fn cmd_suffix() {
+    accepts(&[
+        ("a b > f c", "(simple a b (> f) c)"),
+        ("a b=1", "(simple a b=1)"),
+        ("a 2>&1 b", "(simple a (2>& 1) b)"),
+    ]);
+    rejects(&["a b >"]);
+}
+
+#[test]
+fn io_redirect() {
+    accepts(&[
+        ("a 2> f", "(simple a (2> f))"),
+        ("a 10< f", "(simple a (10< f))"),
+        ("a 2<<END\nx\nEND", "(simple a (2<< END \"x\\n\"))"),
+    ]);
+    rejects(&["a 2>", "a >"]);
+}
+
+#[test]
+fn io_file() {
+    accepts(&[
+        ("a < f", "(simple a (< f))"),
+        ("a <& 3", "(simple a (<& 3))"),
+        ("a > f", "(simple a (> f))"),
+        ("a >&-", "(simple a (>& -))"),
+        ("a >> f", "(simple a (>> f))"),
+        ("a <> f", "(simple a (<> f))"),
+        ("a >| f", "(simple a (>| f))"),
+    ]);
+    rejects(&["a <", "a > > f", "a >> | f"]);
+}
+
+#[test]
+fn io_here() {
+    accepts(&[
+        ("cat <<END\nhi\nEND", "(simple cat (<< END \"hi\\n\"))"),
+        (
+            "cat <<-END\n\thi\n\tEND",
+            "(simple cat (<<- END \"\\thi\\n\"))",
+        ),
+        ("cat <<'END'\n$x\nEND", "(simple cat (<< 'END' \"$x\\n\"))"),
+        ("cat <<END\nEND", "(simple cat (<< END \"\"))"),
+        // a shift in an arithmetic expansion is not a here-document
+        ("echo $(( 1 << 2 ))", "(simple echo $(( 1 << 2 )))"),
+        (
+            "x=$((1<<3)) cat <<END\nhi\nEND",
+            "(simple x=$((1<<3)) cat (<< END \"hi\\n\"))",
+        ),
+    ]);
+    rejects(&["cat <<END", "a <<"]);
+}
+
+#[test]
+fn newline_list() {
+    accepts(&[
+        ("a\n\n\nb", "(program (simple a) (simple b))"),
+        ("\n\n\n", "(program)"),
+    ]);
+}
+
+#[test]
+fn linebreak() {
+    accepts(&[
+        ("a &&\n\n\nb", "(and_or (simple a) && (simple b))"),
+        ("a |\nb", "(pipeline (simple a) (simple b))"),
+        ("case x in\n\nesac", "(case x)"),
+    ]);
+}
+
+#[test]
+fn separator_op() {
+    accepts(&[
+        ("a & b", "(list (simple a) & (simple b))"),
+        ("a ; b", "(list (simple a) ; (simple b))"),
+        ("a&b;c", "(list (simple a) & (simple b) ; (simple c))"),
+    ]);
+    rejects(&["a &; b", "a ;& b"]);
+}
+
+#[test]
+fn separator() {
+    accepts(&[
+        ("{ a &\n\nb; }", "(brace (list (simple a) & (simple b)))"),
+        ("{ a\n\nb\n}", "(brace (list (simple a) ; (simple b)))"),
+    ]);
+    rejects(&["{ a ;\n; b; }"]);
+}
+
+#[test]
+fn sequential_sep() {
+    accepts(&[
+        ("for i in a; do b; done", "(for i (in a) (do (simple b)))"),
+        ("for i in a\n\ndo b; done", "(for i (in a) (do (simple b)))"),
+        ("for i in a;\ndo b; done", "(for i (in a) (do (simple b)))"),
+    ]);
+    rejects(&["for i in a & do b; done", "for i in a;; do b; done"]);
+}
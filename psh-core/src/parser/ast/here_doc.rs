@@ -0,0 +1,320 @@
+//! Here-documents, whose bodies are the lines following the line of their
+//! `<<` operator. Before parsing, the lines of every body are gathered into
+//! a single [`Token::HereDocument`] right after the newline ending that
+//! line, where the parser keeps them as part of the newline, and where the
+//! redirection looks them up.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::tok::Token;
+
+/// Tracks the quotes of a line of tokens, to tell operators from quoted
+/// text.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Quotes {
+    single: bool,
+    double: bool,
+    escaped: bool,
+}
+
+impl Quotes {
+    /// Steps over `token`, returning whether it is quoted.
+    pub(super) fn quoted(&mut self, token: &Token) -> bool {
+        let quoted = self.single || self.double || self.escaped;
+        match token {
+            Token::SingleQuote if !self.double && !self.escaped => self.single ^= true,
+            Token::DoubleQuote if !self.single && !self.escaped => self.double ^= true,
+            _ => {}
+        }
+        self.escaped = !self.single && !self.escaped && *token == Token::Backslash;
+        quoted
+    }
+}
+
+/// Tracks the parens of arithmetic expansions, in which `<<` is a shift
+/// rather than a here-document.
+#[derive(Clone, Debug, Default)]
+struct Arithmetic {
+    parens: usize,
+}
+
+impl Arithmetic {
+    /// Steps over the unquoted `token`, returning whether it is part of an
+    /// arithmetic expansion.
+    fn inside(&mut self, token: &Token) -> bool {
+        match token {
+            Token::ArithmeticStart => self.parens += 2,
+            Token::LParen if self.parens > 0 => self.parens += 1,
+            Token::RParen if self.parens > 0 => self.parens -= 1,
+            _ => return self.parens > 0,
+        }
+        true
+    }
+}
+
+/// Whether `token` ends a word when it is not quoted.
+pub(super) fn ends_word(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Whitespace(_)
+            | Token::And
+            | Token::Or
+            | Token::DoubleSemicolon
+            | Token::SyncSeparator
+            | Token::AsyncSeparator
+            | Token::Pipe
+            | Token::RedirectInput
+            | Token::RedirectOutput
+            | Token::LParen
+            | Token::RParen
+    )
+}
+
+/// The delimiter that the word `raw` stands for, which is the word with
+/// its quotes removed.
+fn unquote(raw: &str) -> String {
+    let mut delimiter = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {}
+            '\\' => delimiter.extend(chars.next()),
+            c => delimiter.push(c),
+        }
+    }
+    delimiter
+}
+
+struct Pending {
+    delimiter: String,
+    strip_tabs: bool,
+}
+
+impl Pending {
+    /// Whether `line` ends the here-document.
+    fn is_delimiter(&self, line: &str) -> bool {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        match self.strip_tabs {
+            true => line.trim_start_matches('\t') == self.delimiter,
+            false => line == self.delimiter,
+        }
+    }
+}
+
+/// Moves the lines of every here-document in `tokens` into a
+/// [`Token::HereDocument`] following the newline that ends the line of its
+/// operator. Also returns whether the input ends before the delimiter of a
+/// here-document.
+pub fn gather(tokens: Vec<Token>) -> (Vec<Token>, bool) {
+    let mut tokens = VecDeque::from(tokens);
+    let mut gathered = Vec::with_capacity(tokens.len());
+    let mut pending = Vec::new();
+    let mut quotes = Quotes::default();
+    let mut arithmetic = Arithmetic::default();
+    let mut unterminated = false;
+
+    while let Some(token) = tokens.pop_front() {
+        if quotes.quoted(&token) || arithmetic.inside(&token) {
+            gathered.push(token);
+            continue;
+        }
+
+        match token {
+            // `<<<` is not a here-document
+            Token::RedirectInput
+                if tokens.front() == Some(&Token::RedirectInput)
+                    && tokens.get(1) != Some(&Token::RedirectInput) =>
+            {
+                gathered.push(token);
+                gathered.extend(tokens.pop_front());
+
+                // the lexer joins the `-` of `<<-` with the delimiter
+                let mut strip_tabs = false;
+                if let Some(Token::Word(word)) = tokens.front() {
+                    if let Some(rest) = word.strip_prefix('-') {
+                        let rest = Rc::<str>::from(rest);
+                        tokens.pop_front();
+                        if !rest.is_empty() {
+                            tokens.push_front(Token::Word(rest));
+                        }
+                        gathered.push(Token::Word(Rc::from("-")));
+                        strip_tabs = true;
+                    }
+                }
+
+                while let Some(Token::Whitespace(' ' | '\t')) = tokens.front() {
+                    gathered.extend(tokens.pop_front());
+                }
+
+                let mut raw = String::new();
+                while let Some(token) = tokens.front() {
+                    let mut ahead = quotes.clone();
+                    if !ahead.quoted(token) && ends_word(token) {
+                        break;
+                    }
+                    quotes = ahead;
+                    raw += &token.as_str();
+                    gathered.extend(tokens.pop_front());
+                }
+
+                if !raw.is_empty() {
+                    pending.push(Pending {
+                        delimiter: unquote(&raw),
+                        strip_tabs,
+                    });
+                }
+            }
+
+            Token::Whitespace('\n') if !pending.is_empty() => {
+                gathered.push(token);
+                for here_doc in pending.drain(..) {
+                    let (document, terminated) = read_lines(&mut tokens, &here_doc);
+                    gathered.push(document);
+                    unterminated |= !terminated;
+                }
+            }
+
+            token => gathered.push(token),
+        }
+    }
+
+    // a here-document on the last line has no body yet
+    unterminated |= !pending.is_empty();
+    (gathered, unterminated)
+}
+
+/// Takes the lines of `here_doc` from `tokens`, up to and including the
+/// line with its delimiter, and whether that line was found.
+fn read_lines(tokens: &mut VecDeque<Token>, here_doc: &Pending) -> (Token, bool) {
+    let mut body = String::new();
+    let mut line = String::new();
+
+    let terminated = loop {
+        match tokens.pop_front() {
+            Some(Token::Whitespace('\n')) => {
+                line.push('\n');
+                if here_doc.is_delimiter(&line) {
+                    break true;
+                }
+                body += &std::mem::take(&mut line);
+            }
+            Some(token) => line += &token.as_str(),
+            None if here_doc.is_delimiter(&line) => break true,
+            None => {
+                body += &std::mem::take(&mut line);
+                break false;
+            }
+        }
+    };
+
+    let document = Token::HereDocument {
+        body: Rc::from(body),
+        delimiter: Rc::from(line),
+    };
+    (document, terminated)
+}
+
+/// The body of the here-document whose delimiter was just parsed, given the
+/// tokens that follow it. The bodies of the here-documents of a line follow
+/// it in order, so its body is followed by those of the ones after it.
+pub fn body(tokens: impl Iterator<Item = Token>) -> Option<Rc<str>> {
+    let mut tokens = tokens.peekable();
+    let mut quotes = Quotes::default();
+    let mut arithmetic = Arithmetic::default();
+    let mut later = 0;
+
+    while let Some(token) = tokens.next() {
+        if quotes.quoted(&token) || arithmetic.inside(&token) {
+            continue;
+        }
+        match token {
+            Token::RedirectInput if tokens.peek() == Some(&Token::RedirectInput) => {
+                tokens.next();
+                if tokens.peek() != Some(&Token::RedirectInput) {
+                    later += 1;
+                }
+            }
+            Token::HereDocument { .. } => {
+                let bodies = std::iter::once(token)
+                    .chain(tokens)
+                    .map_while(|token| match token {
+                        Token::HereDocument { body, .. } => Some(body),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                let i = bodies.len().checked_sub(later + 1)?;
+                return bodies.into_iter().nth(i);
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tok::lex;
+
+    fn documents(input: &str) -> (Vec<(String, String)>, bool) {
+        let (tokens, unterminated) = gather(lex(input));
+        let documents = tokens
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::HereDocument { body, delimiter } => {
+                    Some((body.to_string(), delimiter.to_string()))
+                }
+                _ => None,
+            })
+            .collect();
+        (documents, unterminated)
+    }
+
+    fn document(body: &str, delimiter: &str) -> (String, String) {
+        (body.to_string(), delimiter.to_string())
+    }
+
+    #[test]
+    fn gathering() {
+        assert_eq!(
+            (vec![document("a $x\nb\n", "EOF\n")], false),
+            documents("cat <<EOF\na $x\nb\nEOF\necho done"),
+        );
+        assert_eq!(
+            (vec![document("", "EOF")], false),
+            documents("cat <<'EOF'\nEOF"),
+        );
+        assert_eq!(
+            (vec![document("\ta\n", "\tEND\n")], false),
+            documents("cat <<-END\n\ta\n\tEND\n"),
+        );
+        assert_eq!(
+            (vec![document("1\n", "A\n"), document("2\n", "B\n")], false),
+            documents("cat <<A; cat <<B\n1\nA\n2\nB\n"),
+        );
+        assert_eq!((vec![], false), documents("echo '<<EOF'\nEOF"));
+        assert_eq!(
+            (vec![], false),
+            documents("echo $(( (1) << 2 ))\nx=$((1<<3))")
+        );
+        assert_eq!(
+            (vec![document("1\n", "A\n")], false),
+            documents("echo $((1 << 2)) <<A\n1\nA\n"),
+        );
+        assert_eq!((vec![], true), documents("cat <<EOF"));
+        assert_eq!(
+            (vec![document("a\n", "")], true),
+            documents("cat <<EOF\na\n")
+        );
+
+        let input = "cat <<A | grep x\nx\nA\n";
+        let (tokens, _) = gather(lex(input));
+        let text = tokens
+            .iter()
+            .map(|token| token.as_str())
+            .collect::<String>();
+        assert_eq!(input, text);
+    }
+}
@@ -1,9 +1,13 @@
+pub mod alias;
+mod here_doc;
 pub mod nodes;
 pub mod reconstruct;
 
 #[cfg(feature = "serde")]
 mod serialize;
 
+#[cfg(test)]
+mod conformance;
 #[cfg(test)]
 mod tests;
 
@@ -12,7 +16,7 @@ use std::iter::Peekable;
 use crate::ast::nodes::*;
 use crate::consumer::Consumer;
 use crate::error::{ParseError, ParseResult};
-use crate::tok::{ReservedWord, Token, Tokenizer};
+use crate::tok::{lex, ReservedWord, Token, Tokenizer};
 use crate::{Error, Result};
 
 pub fn parse(input: impl AsRef<str>, allow_errors: bool) -> Result<SyntaxTree> {
@@ -22,34 +26,51 @@ pub fn parse(input: impl AsRef<str>, allow_errors: bool) -> Result<SyntaxTree> {
         return Ok(Default::default());
     }
 
-    match input
-        .chars()
-        .peekable()
-        .tokenize()
-        .into_iter()
-        .peekable()
-        .parse(true)
-    {
-        Ok(ast) => Ok(ast),
+    // the parser clones its input whenever it needs to backtrack, which is
+    // cheap for an iterator over a slice
+    let tokens = input.chars().peekable().tokenize();
+    let (tokens, unterminated) = here_doc::gather(tokens);
+
+    if unterminated && !allow_errors {
+        return Err(Error::Incomplete(input.to_string()));
+    }
+
+    match tokens.iter().cloned().peekable().parse(true) {
+        Ok(ast) if allow_errors || ast.is_ok() => Ok(ast),
 
         Err(Ok(ast)) if allow_errors => Ok(ast),
 
         Err(Ok(ast)) if ast.is_ok() => Err(Error::Incomplete(ast.to_string())),
 
-        Err(Ok(ast)) => Err(Error::SyntaxError(format!(
-            "`{}'",
-            ast.unparsed.trim_start()
-        ))),
+        Ok(ast) | Err(Ok(ast)) => {
+            let unparsed = ast.unparsed.trim_start();
+            let line = input
+                .strip_suffix(unparsed)
+                .map(|parsed| 1 + parsed.matches('\n').count());
+            Err(Error::SyntaxError(format!("`{unparsed}'"), line))
+        }
 
-        Err(Err(e @ ParseError::InvalidSyntaxInCmdSub)) => Err(Error::SyntaxError(format!(
-            "command substitution: `{}'",
-            e,
-        ))),
+        Err(Err(e @ ParseError::InvalidSyntaxInCmdSub)) => Err(Error::SyntaxError(
+            format!("command substitution: `{}'", e),
+            None,
+        )),
 
         Err(Err(e)) => Err(Error::ParseError(e.to_string())),
     }
 }
 
+/// Parses `input` like `parse` with errors allowed, and returns the syntax
+/// tree as JSON, or `{"error": message}` if it could not be parsed at all.
+/// Meant for embedders that cannot handle Rust types, such as a playground
+/// running the parser compiled to WASM.
+#[cfg(feature = "serde")]
+pub fn parse_to_json(input: impl AsRef<str>) -> String {
+    match parse(input, true).and_then(|ast| ast.as_json()) {
+        Ok(json) => json,
+        Err(e) => serde_json::json!({ "error": e.to_string() }).to_string(),
+    }
+}
+
 type StdResult<T, E> = std::result::Result<T, E>;
 
 pub trait Parser: Iterator<Item = Token> + Clone {
@@ -125,9 +146,6 @@ pub trait Parser: Iterator<Item = Token> + Clone {
     fn parse_term(&mut self) -> ParseResult<Term>;
     fn parse_for_clause(&mut self) -> ParseResult<ForClause>;
     fn parse_case_clause(&mut self) -> ParseResult<CaseClause>;
-    fn parse_case_list_ns(&mut self) -> ParseResult<CaseListNs>;
-    fn parse_case_list(&mut self) -> ParseResult<CaseList>;
-    fn parse_case_item_ns(&mut self) -> ParseResult<CaseItemNs>;
     fn parse_case_item(&mut self) -> ParseResult<CaseItem>;
     fn parse_pattern(&mut self) -> ParseResult<Pattern>;
     fn parse_if_clause(&mut self) -> ParseResult<IfClause>;
@@ -162,7 +180,26 @@ pub trait Parser: Iterator<Item = Token> + Clone {
     fn parse_bang(&mut self) -> ParseResult<Bang>;
     fn parse_logical_op(&mut self) -> ParseResult<LogicalOp>;
 
+    fn parse_reserved_word(&mut self, word: ReservedWord) -> Option<LeadingWhitespace>;
+    fn parse_keyword(
+        &mut self,
+        word: ReservedWord,
+        ws: &mut Option<LeadingWhitespace>,
+    ) -> ParseResult<()>;
+    fn parse_clause_list(&mut self, list: &mut CompoundList) -> ParseResult<()>;
+    fn finish_clause<T: std::fmt::Debug>(
+        &mut self,
+        initial: Self,
+        result: ParseResult<()>,
+        clause: T,
+    ) -> ParseResult<T>;
+
+    fn scan_braces(&mut self) -> (String, String, bool);
+
     fn swallow_whitespace(&mut self) -> LeadingWhitespace;
+
+    /// Whether only whitespace is left of the input.
+    fn at_end(&self) -> bool;
 }
 
 impl<I> Parser for Peekable<I>
@@ -392,16 +429,41 @@ where
     }
 
     fn parse_compound_command(&mut self) -> ParseResult<CompoundCommand> {
-        // TODO
-        Err(ParseError::None)
-        // self.parse_brace_group()
-        //     .map(CompoundCommand::Brace)
-        //     .or_else(|_| self.parse_subshell().map(CompoundCommand::Subshell))
-        //     .or_else(|_| self.parse_for_clause().map(CompoundCommand::For))
-        //     .or_else(|_| self.parse_case_clause().map(CompoundCommand::Case))
-        //     .or_else(|_| self.parse_if_clause().map(CompoundCommand::If))
-        //     .or_else(|_| self.parse_while_clause().map(CompoundCommand::While))
-        //     .or_else(|_| self.parse_until_clause().map(CompoundCommand::Until))
+        let mut tokens = self
+            .clone()
+            .skip_while(|token| matches!(token, Token::Whitespace(' ' | '\t')));
+
+        match tokens.next() {
+            Some(Token::Reserved(ReservedWord::LBrace)) => self
+                .parse_brace_group()
+                .map(CompoundCommand::Brace)
+                .map_err(|e| e.cast_with(CompoundCommand::Brace)),
+            Some(Token::LParen) => self
+                .parse_subshell()
+                .map(CompoundCommand::Subshell)
+                .map_err(|e| e.cast_with(CompoundCommand::Subshell)),
+            Some(Token::Reserved(ReservedWord::For)) => self
+                .parse_for_clause()
+                .map(CompoundCommand::For)
+                .map_err(|e| e.cast_with(CompoundCommand::For)),
+            Some(Token::Reserved(ReservedWord::Case)) => self
+                .parse_case_clause()
+                .map(CompoundCommand::Case)
+                .map_err(|e| e.cast_with(CompoundCommand::Case)),
+            Some(Token::Reserved(ReservedWord::If)) => self
+                .parse_if_clause()
+                .map(CompoundCommand::If)
+                .map_err(|e| e.cast_with(CompoundCommand::If)),
+            Some(Token::Reserved(ReservedWord::While)) => self
+                .parse_while_clause()
+                .map(CompoundCommand::While)
+                .map_err(|e| e.cast_with(CompoundCommand::While)),
+            Some(Token::Reserved(ReservedWord::Until)) => self
+                .parse_until_clause()
+                .map(CompoundCommand::Until)
+                .map_err(|e| e.cast_with(CompoundCommand::Until)),
+            _ => Err(ParseError::None),
+        }
     }
 
     fn parse_subshell(&mut self) -> ParseResult<Subshell> {
@@ -413,31 +475,48 @@ where
             return Err(ParseError::None);
         };
 
-        let Ok(body) = self.parse_compound_list() else {
-            *self = initial;
-            return Err(ParseError::None);
+        let mut subshell = Subshell {
+            lparen_ws,
+            body: CompoundList::noop(),
+            rparen_ws: None,
         };
 
-        let rparen_ws = self.swallow_whitespace();
-        if self.consume_single(Token::RParen).is_none() {
-            *self = initial;
-            return Err(ParseError::None);
-        };
+        let result = (|| {
+            self.parse_clause_list(&mut subshell.body)?;
 
-        Ok(Subshell {
-            lparen_ws,
-            body,
-            rparen_ws,
-        })
+            let before = self.clone();
+            let rparen_ws = self.swallow_whitespace();
+            if self.consume_single(Token::RParen).is_some() {
+                subshell.rparen_ws = Some(rparen_ws);
+                return Ok(());
+            }
+            *self = before;
+            match self.at_end() {
+                true => Err(ParseError::Unfinished(None, ())),
+                false => Err(ParseError::None),
+            }
+        })();
+
+        self.finish_clause(initial, result, subshell)
     }
 
     fn parse_compound_list(&mut self) -> ParseResult<CompoundList> {
         let initial = self.clone();
         let linebreak = self.parse_linebreak();
 
-        let Ok(term) = self.parse_term() else {
-            *self = initial;
-            return Err(ParseError::None);
+        let term = match self.parse_term() {
+            Ok(term) => term,
+            Err(e @ ParseError::Unfinished(_, _)) => {
+                return Err(e.cast_with(|term| CompoundList {
+                    linebreak,
+                    term,
+                    separator: None,
+                }))
+            }
+            Err(_) => {
+                *self = initial;
+                return Err(ParseError::None);
+            }
         };
 
         let separator = self.parse_separator().ok();
@@ -462,12 +541,18 @@ where
 
         let mut prev = self.clone();
         let mut tail = Vec::new();
-        while let Ok((sep, and_or)) = self.parse_separator().and_then(|sep| {
-            self.parse_and_or_list()
-                .map(|a| (sep, a))
-                .map_err(|e| e.force_cast())
-        }) {
-            tail.push((sep, and_or));
+        while let Ok(sep) = self.parse_separator() {
+            match self.parse_and_or_list() {
+                Ok(and_or) => tail.push((sep, and_or)),
+                Err(e @ ParseError::Unfinished(_, _)) => {
+                    return Err(e.cast_with(|and_or| {
+                        tail.push((sep, and_or));
+                        Term { head, tail }
+                    }))
+                }
+                // the separator ends the list that the term is part of
+                Err(_) => break,
+            }
             prev = self.clone();
         }
 
@@ -477,47 +562,368 @@ where
     }
 
     fn parse_for_clause(&mut self) -> ParseResult<ForClause> {
-        Err(ParseError::Unimplemented("for clause".to_string()))
+        let initial = self.clone();
+        let Some(for_ws) = self.parse_reserved_word(ReservedWord::For) else {
+            return Err(ParseError::None);
+        };
+
+        let mut clause = ForClause {
+            for_ws,
+            name: None,
+            wordlist: None,
+            separator: None,
+            body: DoGroup::noop(),
+        };
+
+        let result = (|| {
+            match self.parse_name() {
+                Ok(name) => clause.name = Some(name),
+                Err(_) if self.at_end() => return Err(ParseError::Unfinished(None, ())),
+                Err(_) => return Err(ParseError::None),
+            }
+
+            let before = self.clone();
+            let linebreak = self.parse_linebreak();
+            match self.parse_reserved_word(ReservedWord::In) {
+                Some(in_ws) => {
+                    let wordlist = clause.wordlist.insert(Wordlist {
+                        linebreak,
+                        in_ws,
+                        words: Vec::new(),
+                    });
+                    loop {
+                        match self.parse_word(true) {
+                            Ok(word) => wordlist.words.push(word),
+                            Err(ParseError::None) => break,
+                            Err(e) => {
+                                let mut word = Word::new("", "");
+                                let result = store(Err(e), &mut word);
+                                wordlist.words.push(word);
+                                return result;
+                            }
+                        }
+                    }
+                }
+                None => *self = before,
+            }
+
+            clause.separator = self.parse_sequential_separator().ok();
+            if clause.wordlist.is_some() && clause.separator.is_none() && !self.at_end() {
+                return Err(ParseError::None);
+            }
+            store(self.parse_do_group(), &mut clause.body)
+        })();
+
+        self.finish_clause(initial, result, clause)
     }
 
     fn parse_case_clause(&mut self) -> ParseResult<CaseClause> {
-        Err(ParseError::Unimplemented("case clause".to_string()))
-    }
+        let initial = self.clone();
+        let Some(case_ws) = self.parse_reserved_word(ReservedWord::Case) else {
+            return Err(ParseError::None);
+        };
 
-    fn parse_case_list_ns(&mut self) -> ParseResult<CaseListNs> {
-        Err(ParseError::Unimplemented("case list NS".to_string()))
-    }
+        let mut clause = CaseClause {
+            case_ws,
+            word: None,
+            in_ws: None,
+            items: Vec::new(),
+            esac_ws: None,
+        };
 
-    fn parse_case_list(&mut self) -> ParseResult<CaseList> {
-        Err(ParseError::Unimplemented("case list".to_string()))
-    }
+        let result = (|| {
+            match self.parse_word(true) {
+                Err(ParseError::None) if self.at_end() => {
+                    return Err(ParseError::Unfinished(None, ()))
+                }
+                result => store(
+                    result.map(Some).map_err(|e| e.cast_with(Some)),
+                    &mut clause.word,
+                )?,
+            }
+
+            let linebreak = self.parse_linebreak();
+            match self.parse_reserved_word(ReservedWord::In) {
+                Some(in_ws) => clause.in_ws = Some((linebreak, in_ws)),
+                None if self.at_end() => return Err(ParseError::Unfinished(None, ())),
+                None => return Err(ParseError::None),
+            }
+
+            // every item but the last ends with a `;;`
+            while clause
+                .items
+                .last()
+                .is_none_or(|item| item.dsemi_ws.is_some())
+            {
+                let before = self.clone();
+                let linebreak = self.parse_linebreak();
+                if let Some(esac_ws) = self.parse_reserved_word(ReservedWord::Esac) {
+                    clause.esac_ws = Some((linebreak, esac_ws));
+                    return Ok(());
+                }
+                *self = before;
+
+                match self.parse_case_item() {
+                    Ok(item) => clause.items.push(item),
+                    Err(ParseError::None) => break,
+                    Err(ParseError::Unfinished(ws, item)) => {
+                        clause.items.push(item);
+                        return Err(ParseError::Unfinished(ws, ()));
+                    }
+                    Err(e) => return Err(e.force_cast()),
+                }
+            }
+
+            let before = self.clone();
+            let linebreak = self.parse_linebreak();
+            match self.parse_reserved_word(ReservedWord::Esac) {
+                Some(esac_ws) => {
+                    clause.esac_ws = Some((linebreak, esac_ws));
+                    Ok(())
+                }
+                None if self.at_end() => {
+                    *self = before;
+                    Err(ParseError::Unfinished(None, ()))
+                }
+                None => Err(ParseError::None),
+            }
+        })();
 
-    fn parse_case_item_ns(&mut self) -> ParseResult<CaseItemNs> {
-        Err(ParseError::Unimplemented("case item NS".to_string()))
+        self.finish_clause(initial, result, clause)
     }
 
     fn parse_case_item(&mut self) -> ParseResult<CaseItem> {
-        Err(ParseError::Unimplemented("case item".to_string()))
+        let initial = self.clone();
+        let linebreak = self.parse_linebreak();
+
+        let before = self.clone();
+        let lparen_ws = self.swallow_whitespace();
+        let lparen_ws = match self.consume_single(Token::LParen) {
+            Some(_) => Some(lparen_ws),
+            None => {
+                *self = before;
+                None
+            }
+        };
+
+        // an item starts with a pattern, unless `(` makes it unambiguous
+        let pattern = match self.parse_pattern() {
+            Ok(pattern) => pattern,
+            Err(ParseError::None) if lparen_ws.is_none() => {
+                *self = initial;
+                return Err(ParseError::None);
+            }
+            Err(ParseError::None) => Pattern {
+                head: Word::new("", ""),
+                tail: Vec::new(),
+            },
+            Err(e @ ParseError::Unfinished(_, _)) => {
+                return Err(e.cast_with(|pattern| CaseItem {
+                    linebreak,
+                    lparen_ws,
+                    pattern,
+                    rparen_ws: None,
+                    body: None,
+                    dsemi_ws: None,
+                }))
+            }
+            Err(e) => return Err(e.force_cast()),
+        };
+
+        let mut item = CaseItem {
+            linebreak,
+            lparen_ws,
+            pattern,
+            rparen_ws: None,
+            body: None,
+            dsemi_ws: None,
+        };
+
+        let result = (|| {
+            if item.pattern.head.name.is_empty() {
+                return match self.at_end() {
+                    true => Err(ParseError::Unfinished(None, ())),
+                    false => Err(ParseError::None),
+                };
+            }
+
+            let rparen_ws = self.swallow_whitespace();
+            if self.consume_single(Token::RParen).is_none() {
+                return match self.at_end() {
+                    true => Err(ParseError::Unfinished(None, ())),
+                    false => Err(ParseError::None),
+                };
+            }
+            item.rparen_ws = Some(rparen_ws);
+
+            match self.parse_compound_list() {
+                Err(ParseError::None) => {}
+                result => store(
+                    result.map(Some).map_err(|e| e.cast_with(Some)),
+                    &mut item.body,
+                )?,
+            }
+
+            let before = self.clone();
+            let linebreak = self.parse_linebreak();
+            let dsemi_ws = self.swallow_whitespace();
+            match self.consume_single(Token::DoubleSemicolon) {
+                Some(_) => item.dsemi_ws = Some((linebreak, dsemi_ws)),
+                None => *self = before,
+            }
+            Ok(())
+        })();
+
+        self.finish_clause(initial, result, item)
     }
 
     fn parse_pattern(&mut self) -> ParseResult<Pattern> {
-        Err(ParseError::Unimplemented("pattern".to_string()))
+        let head = match self.parse_word(true) {
+            Ok(word) => word,
+            Err(e) => {
+                return Err(e.cast_with(|head| Pattern {
+                    head,
+                    tail: Vec::new(),
+                }))
+            }
+        };
+
+        let mut tail = Vec::new();
+        loop {
+            let before = self.clone();
+            let ws = self.swallow_whitespace();
+            if self.consume_single(Token::Pipe).is_none() {
+                *self = before;
+                break;
+            }
+            match self.parse_word(true) {
+                Ok(word) => tail.push((ws, word)),
+                Err(ParseError::None) => {
+                    *self = before;
+                    break;
+                }
+                Err(e) => {
+                    return Err(e.cast_with(|word| {
+                        tail.push((ws, word));
+                        Pattern { head, tail }
+                    }))
+                }
+            }
+        }
+
+        Ok(Pattern { head, tail })
     }
 
     fn parse_if_clause(&mut self) -> ParseResult<IfClause> {
-        Err(ParseError::Unimplemented("if clause".to_string()))
+        let initial = self.clone();
+        let Some(if_ws) = self.parse_reserved_word(ReservedWord::If) else {
+            return Err(ParseError::None);
+        };
+
+        let mut clause = IfClause {
+            if_ws,
+            predicate: CompoundList::noop(),
+            then_ws: None,
+            body: CompoundList::noop(),
+            else_part: None,
+            fi_ws: None,
+        };
+
+        let result = (|| {
+            self.parse_clause_list(&mut clause.predicate)?;
+            self.parse_keyword(ReservedWord::Then, &mut clause.then_ws)?;
+            self.parse_clause_list(&mut clause.body)?;
+            match self.parse_else_part() {
+                Err(ParseError::None) => {}
+                result => store(
+                    result.map(Some).map_err(|e| e.cast_with(Some)),
+                    &mut clause.else_part,
+                )?,
+            }
+            self.parse_keyword(ReservedWord::Fi, &mut clause.fi_ws)
+        })();
+
+        self.finish_clause(initial, result, clause)
     }
 
     fn parse_else_part(&mut self) -> ParseResult<ElsePart> {
-        Err(ParseError::Unimplemented("else part".to_string()))
+        let initial = self.clone();
+        let mut else_part = ElsePart {
+            elseifs: Vec::new(),
+            else_part: None,
+        };
+
+        let result = (|| {
+            while let Some(elif_ws) = self.parse_reserved_word(ReservedWord::Elif) {
+                let mut elif = ElseIf {
+                    elif_ws,
+                    predicate: CompoundList::noop(),
+                    then_ws: None,
+                    body: CompoundList::noop(),
+                };
+                let result = (|| {
+                    self.parse_clause_list(&mut elif.predicate)?;
+                    self.parse_keyword(ReservedWord::Then, &mut elif.then_ws)?;
+                    self.parse_clause_list(&mut elif.body)
+                })();
+                else_part.elseifs.push(elif);
+                result?;
+            }
+
+            if let Some(else_ws) = self.parse_reserved_word(ReservedWord::Else) {
+                let mut body = CompoundList::noop();
+                let result = self.parse_clause_list(&mut body);
+                else_part.else_part = Some((else_ws, body));
+                result?;
+            }
+
+            match else_part.elseifs.is_empty() && else_part.else_part.is_none() {
+                true => Err(ParseError::None),
+                false => Ok(()),
+            }
+        })();
+
+        self.finish_clause(initial, result, else_part)
     }
 
     fn parse_while_clause(&mut self) -> ParseResult<WhileClause> {
-        Err(ParseError::Unimplemented("while clause".to_string()))
+        let initial = self.clone();
+        let Some(while_ws) = self.parse_reserved_word(ReservedWord::While) else {
+            return Err(ParseError::None);
+        };
+
+        let mut clause = WhileClause {
+            while_ws,
+            predicate: CompoundList::noop(),
+            body: DoGroup::noop(),
+        };
+
+        let result = (|| {
+            self.parse_clause_list(&mut clause.predicate)?;
+            store(self.parse_do_group(), &mut clause.body)
+        })();
+
+        self.finish_clause(initial, result, clause)
     }
 
     fn parse_until_clause(&mut self) -> ParseResult<UntilClause> {
-        Err(ParseError::Unimplemented("until clause".to_string()))
+        let initial = self.clone();
+        let Some(until_ws) = self.parse_reserved_word(ReservedWord::Until) else {
+            return Err(ParseError::None);
+        };
+
+        let mut clause = UntilClause {
+            until_ws,
+            predicate: CompoundList::noop(),
+            body: DoGroup::noop(),
+        };
+
+        let result = (|| {
+            self.parse_clause_list(&mut clause.predicate)?;
+            store(self.parse_do_group(), &mut clause.body)
+        })();
+
+        self.finish_clause(initial, result, clause)
     }
 
     fn parse_function_definition(&mut self) -> ParseResult<FunctionDefinition> {
@@ -531,7 +937,7 @@ where
                     name,
                     parens: Default::default(),
                     linebreak: Default::default(),
-                    body: FunctionBody::noop(),
+                    body: None,
                 }));
             }
         };
@@ -548,19 +954,29 @@ where
             };
         }
 
-        let linebreak = self.parse_linebreak();
-
-        let Ok(body) = self.parse_function_body() else {
-            *self = initial;
-            return Err(ParseError::Unimplemented("function definition".to_string()));
-        };
-
-        Ok(FunctionDefinition {
+        let mut definition = FunctionDefinition {
             name,
             parens,
-            linebreak,
-            body,
-        })
+            linebreak: self.parse_linebreak(),
+            body: None,
+        };
+
+        // the body can be on a line of its own, after the name
+        match self.parse_function_body() {
+            Ok(body) => {
+                definition.body = Some(body);
+                Ok(definition)
+            }
+            Err(ParseError::None) if self.at_end() => Err(ParseError::Unfinished(None, definition)),
+            Err(ParseError::None) => {
+                *self = initial;
+                Err(ParseError::None)
+            }
+            Err(e) => Err(e.cast_with(|body| {
+                definition.body = Some(body);
+                definition
+            })),
+        }
     }
 
     fn parse_function_body(&mut self) -> ParseResult<FunctionBody> {
@@ -594,9 +1010,31 @@ where
             return Err(ParseError::None);
         };
 
-        let Ok(body) = self.parse_compound_list() else {
-            *self = initial;
-            return Err(ParseError::None);
+        // the group is unfinished rather than invalid if the input ends
+        // before its `}`, so that more of it can be read
+        let body = match self.parse_compound_list() {
+            Ok(body) => body,
+            Err(e @ ParseError::Unfinished(_, _)) => {
+                return Err(e.cast_with(|body| BraceGroup {
+                    lbrace_ws,
+                    body,
+                    rbrace_ws: None,
+                }))
+            }
+            Err(_) if self.at_end() => {
+                return Err(ParseError::Unfinished(
+                    None,
+                    BraceGroup {
+                        lbrace_ws,
+                        body: CompoundList::noop(),
+                        rbrace_ws: None,
+                    },
+                ))
+            }
+            Err(_) => {
+                *self = initial;
+                return Err(ParseError::None);
+            }
         };
 
         let rbrace_ws = self.swallow_whitespace();
@@ -604,6 +1042,16 @@ where
             .consume_single(Token::Reserved(ReservedWord::RBrace))
             .is_none()
         {
+            if self.at_end() {
+                return Err(ParseError::Unfinished(
+                    Some(rbrace_ws),
+                    BraceGroup {
+                        lbrace_ws,
+                        body,
+                        rbrace_ws: None,
+                    },
+                ));
+            }
             *self = initial;
             return Err(ParseError::None);
         };
@@ -611,27 +1059,21 @@ where
         Ok(BraceGroup {
             lbrace_ws,
             body,
-            rbrace_ws,
+            rbrace_ws: Some(rbrace_ws),
         })
     }
 
     fn parse_do_group(&mut self) -> ParseResult<DoGroup> {
         let initial = self.clone();
+        let mut group = DoGroup::noop();
 
-        // FIXME: whitespace
-        self.consume_single(Token::Reserved(ReservedWord::Do))
-            .ok_or_else(|| ParseError::Unimplemented("do group (do)".to_string()))
-            .and_then(|_| self.parse_compound_list())
-            .and_then(|list| {
-                self.consume_single(Token::Reserved(ReservedWord::Done))
-                    .map(|_| list)
-                    .ok_or_else(|| ParseError::Unimplemented("do group (done)".to_string()))
-            })
-            .map(|body| DoGroup { body })
-            .map_err(|_| {
-                *self = initial;
-                ParseError::None
-            })
+        let result = (|| {
+            self.parse_keyword(ReservedWord::Do, &mut group.do_ws)?;
+            self.parse_clause_list(&mut group.body)?;
+            self.parse_keyword(ReservedWord::Done, &mut group.done_ws)
+        })();
+
+        self.finish_clause(initial, result, group)
     }
 
     fn parse_simple_command(&mut self) -> ParseResult<SimpleCommand> {
@@ -741,6 +1183,10 @@ where
             if let Some(Token::Whitespace(c @ '\n')) = self.next() {
                 whitespace.push_str(ws.as_ref());
                 whitespace.push(c);
+                // the lines of the here-documents of the line that ended
+                while let Some(Token::HereDocument { .. }) = self.peek() {
+                    whitespace += &self.next().unwrap().as_str();
+                }
                 prev = self.clone();
             } else {
                 *self = prev;
@@ -792,9 +1238,10 @@ where
     fn parse_sequential_separator(&mut self) -> ParseResult<SequentialSeparator> {
         let initial = self.clone();
 
+        let ws = self.swallow_whitespace();
         if self.consume_single(Token::SyncSeparator).is_some() {
             let linebreak = self.parse_linebreak();
-            Ok(SequentialSeparator::Semi(linebreak))
+            Ok(SequentialSeparator::Semi(ws, linebreak))
         } else {
             *self = initial;
             self.parse_newline_list()
@@ -808,7 +1255,10 @@ where
         let whitespace = self.swallow_whitespace();
 
         match self.next() {
-            Some(Token::Word(name)) if is_name(&name) => Ok(Name { whitespace, name }),
+            Some(Token::Word(name)) if is_name(&name) => Ok(Name {
+                whitespace,
+                name: name.to_string(),
+            }),
 
             _ => {
                 *self = initial;
@@ -909,8 +1359,10 @@ where
             }
         };
 
-        // FIXME: actually parse content
-        let content = Word::new("", "");
+        let content = match here_doc::body(self.clone()) {
+            Some(body) => Word::new(&body, ""),
+            None => Word::new("", ""),
+        };
 
         Ok(Redirection::Here {
             whitespace,
@@ -992,6 +1444,12 @@ where
             return Err(ParseError::None);
         }
 
+        // whitespace ends the assignment, so that in `FOO= cmd` the value is
+        // empty, and `cmd` is the name of the command
+        if let Some(Token::Whitespace(_)) | None = self.peek() {
+            return Ok(VariableAssignment::new(lhs, None, whitespace));
+        }
+
         let rhs = match self.parse_word(true) {
             Ok(word) => Some(word),
             Err(ParseError::None) => None,
@@ -1112,9 +1570,139 @@ where
                     is_escaped = false;
                 }
 
+                Some(Token::Backtick) if !in_single_quote && !is_escaped => {
+                    let mut part = String::from(self.next().unwrap().as_str());
+                    let mut commands = String::new();
+                    let mut finished = false;
+                    let mut escaped = false;
+
+                    // a backslash only quotes `$`, `` ` ``, `\`, and `"` inside
+                    // double quotes, and is removed before the commands are
+                    // parsed
+                    for token in self.by_ref() {
+                        part += &token.as_str();
+                        match token {
+                            Token::Backtick if !escaped => {
+                                finished = true;
+                                break;
+                            }
+                            Token::Backslash if !escaped => {
+                                escaped = true;
+                                continue;
+                            }
+                            Token::Dollar
+                            | Token::CmdSubStart
+                            | Token::ArithmeticStart
+                            | Token::Backtick
+                            | Token::Backslash => {}
+                            Token::DoubleQuote if in_double_quote => {}
+                            _ if escaped => commands.push('\\'),
+                            _ => {}
+                        }
+                        commands += &token.as_str();
+                        escaped = false;
+                    }
+
+                    let tree = match finished {
+                        true => match parse(&commands, false) {
+                            Ok(tree) => tree,
+                            Err(_) => return Err(ParseError::InvalidSyntaxInCmdSub),
+                        },
+                        false => Default::default(),
+                    };
+
+                    let len = part.len();
+                    full += &part;
+                    expansions.push(Expansion::Command {
+                        range: index..=index + len - 1,
+                        part,
+                        tree,
+                        finished,
+                        quoted: in_double_quote,
+                    });
+
+                    index += len;
+                    is_escaped = false;
+                }
+
+                Some(Token::ArithmeticStart) if !in_single_quote && !is_escaped => {
+                    let mut part = String::from(self.next().unwrap().as_str());
+                    let mut expression = String::new();
+                    let mut finished = false;
+                    let mut depth = 0;
+
+                    while let Some(token) = self.next() {
+                        part += &token.as_str();
+                        match token {
+                            Token::LParen | Token::CmdSubStart => depth += 1,
+                            Token::ArithmeticStart => depth += 2,
+                            Token::RParen if depth > 0 => depth -= 1,
+                            Token::RParen if self.peek() == Some(&Token::RParen) => {
+                                part += &self.next().unwrap().as_str();
+                                finished = true;
+                                break;
+                            }
+                            _ => {}
+                        }
+                        expression += &token.as_str();
+                    }
+
+                    // the expression is expanded like a word in double quotes
+                    let quoted = format!("\"{expression}\"");
+                    let expression = lex(&quoted)
+                        .into_iter()
+                        .peekable()
+                        .parse_word(false)
+                        .unwrap_or_else(|_| Word::new(&expression, ""));
+
+                    let len = part.len();
+                    full += &part;
+                    expansions.push(Expansion::Arithmetic {
+                        range: index..=index + len - 1,
+                        expression,
+                        finished,
+                        quoted: in_double_quote,
+                    });
+
+                    index += len;
+                    is_escaped = false;
+                }
+
                 Some(Token::Dollar) if !in_single_quote && !is_escaped => {
-                    // TODO: support ${}
                     self.next();
+                    is_escaped = false;
+
+                    let braced = match self.peek() {
+                        Some(Token::Reserved(ReservedWord::LBrace)) => true,
+                        Some(Token::Word(word)) => word.starts_with('{'),
+                        _ => false,
+                    };
+                    if braced {
+                        let (part, rest, finished) = self.scan_braces();
+                        let len = 1 + part.len();
+                        let parsed = match finished {
+                            true => parse_braced(&part[1..part.len() - 1], in_double_quote),
+                            false => Some((String::new(), None)),
+                        };
+
+                        // an invalid expansion is left as it is
+                        if let Some((name, modifier)) = parsed {
+                            expansions.push(Expansion::Parameter {
+                                range: index..=index + len - 1,
+                                name,
+                                modifier,
+                                finished,
+                                quoted: in_double_quote,
+                            });
+                        }
+
+                        full.push('$');
+                        full += &part;
+                        full += &rest;
+                        index += len + rest.len();
+                        continue;
+                    }
+
                     let mut parameter = String::new();
                     let mut rest = String::new();
 
@@ -1124,6 +1712,33 @@ where
                             self.next();
                         }
 
+                        Some(Token::Dollar) => {
+                            parameter.push('$');
+                            self.next();
+                        }
+
+                        Some(Token::Pound) => {
+                            parameter.push('#');
+                            self.next();
+                        }
+
+                        Some(Token::Reserved(ReservedWord::Bang)) => {
+                            parameter.push('!');
+                            self.next();
+                        }
+
+                        // the other special parameters, and the positional
+                        // ones, of which only `$0` to `$9` need no braces
+                        Some(Token::Word(word))
+                            if word
+                                .starts_with(|c: char| c.is_ascii_digit() || "!@*".contains(c)) =>
+                        {
+                            let (name, tail) = word.split_at(1);
+                            parameter.push_str(name);
+                            rest = tail.to_string();
+                            self.next();
+                        }
+
                         Some(Token::Word(word)) => {
                             let mut chars = word.chars().peekable();
                             while let Some(c) = chars.peek() {
@@ -1141,7 +1756,6 @@ where
                     }
 
                     full.push('$');
-                    is_escaped = false;
 
                     if !parameter.is_empty() {
                         let len = parameter.len();
@@ -1149,6 +1763,7 @@ where
                         let expansion = Expansion::Parameter {
                             range: index..=index + len,
                             name: parameter,
+                            modifier: None,
                             finished: true,
                             quoted: in_double_quote,
                         };
@@ -1175,7 +1790,7 @@ where
                             Some(Token::Word(word)) => {
                                 let slash_index = word.find('/').unwrap_or(word.len());
                                 let name = &word[..slash_index];
-                                if name.is_empty() || is_name(name) {
+                                if is_tilde_prefix(name) {
                                     expansions.push(Expansion::Tilde {
                                         range: index..=index + name.len(),
                                         name: name.to_string(),
@@ -1257,7 +1872,7 @@ where
         let initial = self.clone();
 
         if let Some(Token::Word(word)) = self.next() {
-            match word.as_str() {
+            match &*word {
                 "0" => return Ok(FileDescriptor::Stdin),
                 "1" => return Ok(FileDescriptor::Stdout),
                 "2" => return Ok(FileDescriptor::Stderr),
@@ -1333,6 +1948,51 @@ where
         }
     }
 
+    /// Takes the text of a parameter expansion in braces following a `$`,
+    /// up to and including the matching `}`, skipping the braces that are
+    /// quoted or part of a command substitution. Also returns the text
+    /// after the `}` in the same token, and whether the `}` was found.
+    fn scan_braces(&mut self) -> (String, String, bool) {
+        let mut part = String::new();
+        let mut depth = 0;
+        let mut parens = 0;
+        let (mut single, mut double, mut escaped) = (false, false, false);
+
+        for token in self.by_ref() {
+            let text = token.as_str();
+            match token {
+                Token::SingleQuote if !double && !escaped => single ^= true,
+                Token::DoubleQuote if !single && !escaped => double ^= true,
+                Token::CmdSubStart | Token::LParen if !single => parens += 1,
+                Token::ArithmeticStart if !single => parens += 2,
+                Token::RParen if !single && parens > 0 => parens -= 1,
+                _ => {}
+            }
+
+            if !single && !double && parens == 0 {
+                for (i, c) in text.char_indices() {
+                    if escaped && i == 0 {
+                        continue;
+                    }
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 1 => {
+                            part += &text[..=i];
+                            return (part, text[i + 1..].to_string(), true);
+                        }
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+
+            escaped = !single && !escaped && token == Token::Backslash;
+            part += &text;
+        }
+
+        (part, String::new(), false)
+    }
+
     fn swallow_whitespace(&mut self) -> LeadingWhitespace {
         let mut s = LeadingWhitespace::default();
         while let Some(Token::Whitespace(c @ (' ' | '\t'))) = self.peek() {
@@ -1341,12 +2001,204 @@ where
         }
         s
     }
+
+    fn at_end(&self) -> bool {
+        self.clone()
+            .all(|token| matches!(token, Token::Whitespace(_)))
+    }
+
+    /// Parses `word` and the whitespace before it, or nothing if it is not
+    /// next.
+    fn parse_reserved_word(&mut self, word: ReservedWord) -> Option<LeadingWhitespace> {
+        let initial = self.clone();
+        let ws = self.swallow_whitespace();
+        if self.consume_single(Token::Reserved(word)).is_none() {
+            *self = initial;
+            return None;
+        }
+        Some(ws)
+    }
+
+    /// Parses a reserved word that a clause needs next into `ws`, which is
+    /// unfinished if the input ends before it.
+    fn parse_keyword(
+        &mut self,
+        word: ReservedWord,
+        ws: &mut Option<LeadingWhitespace>,
+    ) -> ParseResult<()> {
+        match self.parse_reserved_word(word) {
+            Some(parsed) => {
+                *ws = Some(parsed);
+                Ok(())
+            }
+            None if self.at_end() => Err(ParseError::Unfinished(None, ())),
+            None => Err(ParseError::None),
+        }
+    }
+
+    /// Parses a list of the commands of a clause into `list`, which is
+    /// unfinished if the input ends before it does.
+    fn parse_clause_list(&mut self, list: &mut CompoundList) -> ParseResult<()> {
+        match self.parse_compound_list() {
+            Err(e @ ParseError::Unfinished(_, _)) => store(Err(e), list),
+            Err(_) if self.at_end() => Err(ParseError::Unfinished(None, ())),
+            result => store(result, list),
+        }
+    }
+
+    /// Turns the result of parsing the parts of `clause` into the result of
+    /// parsing it, going back to `initial` if it is invalid.
+    fn finish_clause<T: std::fmt::Debug>(
+        &mut self,
+        initial: Self,
+        result: ParseResult<()>,
+        clause: T,
+    ) -> ParseResult<T> {
+        match result {
+            Ok(()) => Ok(clause),
+            Err(e @ ParseError::Unfinished(_, _)) => Err(e.cast_with(|_| clause)),
+            Err(_) => {
+                *self = initial;
+                Err(ParseError::None)
+            }
+        }
+    }
+}
+
+/// Stores the node that `result` parsed into `target`, along with the part
+/// of it that is parsed if it is unfinished.
+fn store<T: std::fmt::Debug>(result: ParseResult<T>, target: &mut T) -> ParseResult<()> {
+    match result {
+        Ok(node) => {
+            *target = node;
+            Ok(())
+        }
+        Err(ParseError::Unfinished(ws, node)) => {
+            *target = node;
+            Err(ParseError::Unfinished(ws, ()))
+        }
+        Err(e) => Err(e.force_cast()),
+    }
+}
+
+/// Parses what is between the braces of a parameter expansion into the
+/// name of the parameter and what to do with its value, or `None` if it is
+/// not a valid expansion.
+fn parse_braced(inner: &str, quoted: bool) -> Option<(String, Option<Modifier>)> {
+    if let Some(name) = inner.strip_prefix('#') {
+        if !name.is_empty() && parameter_name_len(name) == Some(name.len()) {
+            return Some((name.to_string(), Some(Modifier::Length)));
+        }
+    }
+
+    let (name, rest) = inner.split_at(parameter_name_len(inner)?);
+    if rest.is_empty() {
+        return Some((name.to_string(), None));
+    }
+
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let mut chars = rest.chars();
+    let op = chars.next()?;
+    let mut word = chars.as_str();
+    let longest = matches!(op, '#' | '%') && word.starts_with(op);
+    if longest {
+        word = &word[1..];
+    }
+    // patterns are not quoted by the double quotes around the expansion
+    let word = parse_argument(word, quoted && !matches!(op, '#' | '%'));
+
+    let modifier = match (op, colon) {
+        ('-', _) => Modifier::Default { colon, word },
+        ('=', _) => Modifier::Assign { colon, word },
+        ('?', _) => Modifier::Error { colon, word },
+        ('+', _) => Modifier::Alternative { colon, word },
+        ('#', false) => Modifier::RemovePrefix {
+            longest,
+            pattern: word,
+        },
+        ('%', false) => Modifier::RemoveSuffix {
+            longest,
+            pattern: word,
+        },
+        _ => return None,
+    };
+    Some((name.to_string(), Some(modifier)))
+}
+
+/// The length of the name of the parameter at the start of `s`: a name, a
+/// number, or one of the special parameters.
+fn parameter_name_len(s: &str) -> Option<usize> {
+    let first = s.chars().next()?;
+    if first.is_ascii_digit() {
+        Some(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+    } else if first.is_ascii_alphabetic() || first == '_' {
+        Some(s.find(|c| !is_valid_part_of_name(c)).unwrap_or(s.len()))
+    } else if "?$!#@*-".contains(first) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Parses the word of a parameter expansion such as `${name:-word}`, which
+/// may contain whitespace, and is treated as if in double quotes when the
+/// expansion is.
+fn parse_argument(text: &str, quoted: bool) -> Word {
+    if quoted {
+        return lex(format!("\"{text}\""))
+            .into_iter()
+            .peekable()
+            .parse_word(true)
+            .unwrap_or_else(|_| Word::new(text, ""));
+    }
+
+    let mut tokens = lex(text).into_iter().peekable();
+    let mut word = Word::new("", "");
+    loop {
+        let part = match tokens.peek() {
+            None => break,
+            Some(Token::Whitespace(c)) => {
+                word.name.push(*c);
+                tokens.next();
+                continue;
+            }
+            Some(_) => match tokens.parse_word(true) {
+                Ok(part) | Err(ParseError::Unfinished(_, part)) => part,
+                Err(_) => match tokens.next() {
+                    Some(token) => Word::new(&token.as_str(), ""),
+                    None => break,
+                },
+            },
+        };
+
+        let offset = word.name.len();
+        word.name += &part.name;
+        word.expansions
+            .extend(part.expansions.into_iter().map(|mut exp| {
+                *exp.range_mut() = exp.range().start() + offset..=exp.range().end() + offset;
+                exp
+            }));
+    }
+    word
 }
 
 fn is_valid_part_of_name(c: char) -> bool {
     c.is_ascii_alphanumeric() || c == '_'
 }
 
+/// Whether `name` is what can follow a `~` to be expanded: nothing, a login
+/// name, or `+` or `-` for the current or previous working directory.
+fn is_tilde_prefix(name: &str) -> bool {
+    matches!(name, "" | "+" | "-")
+        || !name.starts_with('-')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
 fn is_name(input: impl AsRef<str>) -> bool {
     let mut input = input.as_ref().chars().peekable();
     match input.peek() {
@@ -1,15 +1,18 @@
 use std::ops::RangeInclusive;
+#[cfg(feature = "engine")]
 use std::os::fd::IntoRawFd;
-use std::os::fd::RawFd;
 
 #[cfg(feature = "serde")]
 use serde::Serialize;
 
+#[cfg(feature = "engine")]
 use crate::engine::builtin;
-use crate::engine::expand::remove_quotes;
+#[cfg(feature = "engine")]
 use crate::engine::expand::Expand;
-use crate::Engine;
-use crate::Error;
+#[cfg(feature = "engine")]
+use crate::engine::quote::remove_quotes;
+#[cfg(feature = "engine")]
+use crate::{Engine, Error};
 
 /// ```[no_run]
 /// program : linebreak complete_commands linebreak
@@ -264,7 +267,10 @@ impl CompoundCommand {
 pub struct Subshell {
     pub lparen_ws: LeadingWhitespace,
     pub body: CompoundList,
-    pub rparen_ws: LeadingWhitespace,
+
+    /// The whitespace before the `)`, which is missing while the subshell
+    /// is unfinished.
+    pub rparen_ws: Option<LeadingWhitespace>,
 }
 
 /// ```[no_run]
@@ -281,6 +287,27 @@ pub struct CompoundList {
 }
 
 impl CompoundList {
+    pub fn list_with_separator(self) -> Vec<(AndOrList, SeparatorOp)> {
+        let separator_op = |separator: Separator| match separator {
+            Separator::Explicit(separator_op, _) => separator_op,
+            Separator::Implicit(_) => Default::default(),
+        };
+
+        let mut items = Vec::new();
+        let mut prev_list = self.term.head;
+
+        for (sep, and_or_list) in self.term.tail {
+            items.push((prev_list, separator_op(sep)));
+            prev_list = and_or_list;
+        }
+
+        items.push((
+            prev_list,
+            self.separator.map(separator_op).unwrap_or_default(),
+        ));
+        items
+    }
+
     pub fn noop() -> Self {
         Self {
             linebreak: Default::default(),
@@ -320,10 +347,33 @@ impl Term {
 /// ```
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
-pub enum ForClause {
-    Simple(Name, DoGroup),
-    Padded(Name, SequentialSeparator, DoGroup),
-    Full(Name, Linebreak, Vec<Word>, SequentialSeparator, DoGroup),
+pub struct ForClause {
+    pub for_ws: LeadingWhitespace,
+
+    /// The name of the variable, which is missing while the clause is
+    /// unfinished, as is what comes after it.
+    pub name: Option<Name>,
+
+    /// The words to loop over, or none to loop over the positional
+    /// parameters.
+    pub wordlist: Option<Wordlist>,
+    pub separator: Option<SequentialSeparator>,
+    pub body: DoGroup,
+}
+
+/// The `in` of a `for_clause` and the words after it.
+///
+/// ```[no_run]
+/// wordlist : wordlist WORD
+///          |          WORD
+///          ;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Wordlist {
+    pub linebreak: Linebreak,
+    pub in_ws: LeadingWhitespace,
+    pub words: Vec<Word>,
 }
 
 /// ```[no_run]
@@ -346,48 +396,16 @@ pub struct Name {
 /// ```
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
-pub enum CaseClause {
-    Normal(Word, Linebreak, Linebreak, CaseList),
-    NoSeparator(Word, Linebreak, Linebreak, CaseListNs),
-    Empty(Word, Linebreak, Linebreak),
-}
+pub struct CaseClause {
+    pub case_ws: LeadingWhitespace,
 
-/// ```[no_run]
-/// case_list_ns : case_list case_item_ns
-///              |           case_item_ns
-///              ;
-/// ```
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize))]
-pub struct CaseListNs {
-    pub case_list: Option<CaseList>,
-    pub last: CaseItemNs,
-}
-
-/// ```[no_run]
-/// case_list : case_list case_item
-///           |           case_item
-///           ;
-/// ```
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize))]
-pub struct CaseList {
-    pub head: CaseItem,
-    pub tail: Vec<CaseItem>,
-}
-
-/// ```[no_run]
-/// case_item_ns :     pattern ')' linebreak
-///              |     pattern ')' compound_list
-///              | '(' pattern ')' linebreak
-///              | '(' pattern ')' compound_list
-///              ;
-/// ```
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[cfg_attr(feature = "serde", derive(Serialize))]
-pub enum CaseItemNs {
-    Empty(bool, Pattern, Linebreak),
-    List(bool, Pattern, CompoundList),
+    /// The word to match, and the newlines and whitespace before the `in`
+    /// and the `esac`, each of which is missing while the clause is
+    /// unfinished, as is what comes after it.
+    pub word: Option<Word>,
+    pub in_ws: Option<(Linebreak, LeadingWhitespace)>,
+    pub items: Vec<CaseItem>,
+    pub esac_ws: Option<(Linebreak, LeadingWhitespace)>,
 }
 
 /// ```[no_run]
@@ -397,11 +415,22 @@ pub enum CaseItemNs {
 ///           | '(' pattern ')' compound_list DSEMI linebreak
 ///           ;
 /// ```
+///
+/// The `DSEMI` of the last item, which is a `case_item_ns`, is optional.
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
-pub enum CaseItem {
-    Empty(bool, Pattern, Linebreak, Linebreak),
-    List(bool, Pattern, CompoundList, Linebreak),
+pub struct CaseItem {
+    pub linebreak: Linebreak,
+    pub lparen_ws: Option<LeadingWhitespace>,
+    pub pattern: Pattern,
+
+    /// The whitespace before the `)`, which is missing while the item is
+    /// unfinished, as is what comes after it.
+    pub rparen_ws: Option<LeadingWhitespace>,
+    pub body: Option<CompoundList>,
+
+    /// The newlines and whitespace before the `;;`.
+    pub dsemi_ws: Option<(Linebreak, LeadingWhitespace)>,
 }
 
 /// ```[no_run]
@@ -413,7 +442,9 @@ pub enum CaseItem {
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Pattern {
     pub head: Word,
-    pub tail: Vec<Word>,
+
+    /// The words after the first, with the whitespace before their `|`.
+    pub tail: Vec<(LeadingWhitespace, Word)>,
 }
 
 /// ```[no_run]
@@ -424,9 +455,15 @@ pub struct Pattern {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct IfClause {
+    pub if_ws: LeadingWhitespace,
     pub predicate: CompoundList,
+
+    /// The whitespace before the `then`, or before the `fi`, each of which
+    /// is missing while the clause is unfinished, as is what comes after it.
+    pub then_ws: Option<LeadingWhitespace>,
     pub body: CompoundList,
     pub else_part: Option<ElsePart>,
+    pub fi_ws: Option<LeadingWhitespace>,
 }
 
 /// ```[no_run]
@@ -438,8 +475,21 @@ pub struct IfClause {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct ElsePart {
-    pub elseifs: Vec<(CompoundList, CompoundList)>,
-    pub else_part: Option<CompoundList>,
+    pub elseifs: Vec<ElseIf>,
+
+    /// The whitespace before the `else`, and the commands after it.
+    pub else_part: Option<(LeadingWhitespace, CompoundList)>,
+}
+
+/// An `elif` of an `if_clause`, with the whitespace before its reserved
+/// words like in `IfClause`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ElseIf {
+    pub elif_ws: LeadingWhitespace,
+    pub predicate: CompoundList,
+    pub then_ws: Option<LeadingWhitespace>,
+    pub body: CompoundList,
 }
 
 /// ```[no_run]
@@ -449,6 +499,7 @@ pub struct ElsePart {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct WhileClause {
+    pub while_ws: LeadingWhitespace,
     pub predicate: CompoundList,
     pub body: DoGroup,
 }
@@ -460,6 +511,7 @@ pub struct WhileClause {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct UntilClause {
+    pub until_ws: LeadingWhitespace,
     pub predicate: CompoundList,
     pub body: DoGroup,
 }
@@ -474,7 +526,9 @@ pub struct FunctionDefinition {
     pub name: Name,
     pub parens: String,
     pub linebreak: Linebreak,
-    pub body: FunctionBody,
+
+    /// The body, which is missing while the definition is unfinished.
+    pub body: Option<FunctionBody>,
 }
 
 /// ```[no_run]
@@ -506,7 +560,10 @@ impl FunctionBody {
 pub struct BraceGroup {
     pub lbrace_ws: LeadingWhitespace,
     pub body: CompoundList,
-    pub rbrace_ws: LeadingWhitespace,
+
+    /// The whitespace before the `}`, which is missing while the group is
+    /// unfinished.
+    pub rbrace_ws: Option<LeadingWhitespace>,
 }
 
 impl BraceGroup {
@@ -526,7 +583,21 @@ impl BraceGroup {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct DoGroup {
+    /// The whitespace before the `do`, or before the `done`, each of which
+    /// is missing while the group is unfinished, as is what comes after it.
+    pub do_ws: Option<LeadingWhitespace>,
     pub body: CompoundList,
+    pub done_ws: Option<LeadingWhitespace>,
+}
+
+impl DoGroup {
+    pub fn noop() -> Self {
+        Self {
+            do_ws: None,
+            body: CompoundList::noop(),
+            done_ws: None,
+        }
+    }
 }
 
 /// ```[no_run]
@@ -562,6 +633,7 @@ impl SimpleCommand {
         }
     }
 
+    #[cfg(feature = "engine")]
     pub fn expand_into_args(&self, engine: &mut Engine) -> Vec<String> {
         let mut args = Vec::new();
 
@@ -600,6 +672,7 @@ impl SimpleCommand {
             }))
     }
 
+    #[cfg(feature = "engine")]
     pub fn is_builtin(&self) -> bool {
         matches!(&self.name, Some(Word { name, .. }) if builtin::has(&remove_quotes(name, false).unwrap()))
     }
@@ -648,7 +721,9 @@ impl FileDescriptor {
         }
     }
 
-    pub fn as_raw_fd(&self) -> RawFd {
+    /// The number of the descriptor. A `RawFd` on Unix, but the syntax tree
+    /// is also built for targets without file descriptors, such as WASM.
+    pub fn as_raw_fd(&self) -> i32 {
         match self {
             FileDescriptor::Stdin => 0,
             FileDescriptor::Stdout => 1,
@@ -670,8 +745,8 @@ impl FileDescriptor {
     }
 }
 
-impl From<RawFd> for FileDescriptor {
-    fn from(value: RawFd) -> Self {
+impl From<i32> for FileDescriptor {
+    fn from(value: i32) -> Self {
         match value {
             0 => Self::Stdin,
             1 => Self::Stdout,
@@ -733,6 +808,7 @@ impl RedirectionType {
         }
     }
 
+    #[cfg(feature = "engine")]
     pub fn default_src_fd(&self, path: &str) -> crate::Result<FileDescriptor> {
         let mut options = std::fs::OpenOptions::new();
         match self {
@@ -829,7 +905,9 @@ pub enum Redirection {
         /// The delimiter
         end: Word,
 
-        /// The entire content of the here document
+        /// The body of the here document, without the line with the
+        /// delimiter. Only kept here for executing it, since it is part of
+        /// the newline that follows the redirection in the input
         content: Word,
     },
 }
@@ -983,6 +1061,9 @@ pub enum Expansion {
     Parameter {
         range: RangeInclusive<usize>,
         name: String,
+
+        /// What is done with the value, such as in `${name:-word}`.
+        modifier: Option<Modifier>,
         finished: bool,
         quoted: bool,
     },
@@ -1004,6 +1085,29 @@ pub enum Expansion {
 }
 
 impl Expansion {
+    /// The byte range of the word that the expansion replaces.
+    pub fn range(&self) -> &RangeInclusive<usize> {
+        match self {
+            Self::Tilde { range, .. }
+            | Self::Glob { range, .. }
+            | Self::Brace { range, .. }
+            | Self::Parameter { range, .. }
+            | Self::Command { range, .. }
+            | Self::Arithmetic { range, .. } => range,
+        }
+    }
+
+    pub fn range_mut(&mut self) -> &mut RangeInclusive<usize> {
+        match self {
+            Self::Tilde { range, .. }
+            | Self::Glob { range, .. }
+            | Self::Brace { range, .. }
+            | Self::Parameter { range, .. }
+            | Self::Command { range, .. }
+            | Self::Arithmetic { range, .. } => range,
+        }
+    }
+
     pub fn is_finished(&self) -> bool {
         match self {
             Self::Tilde { .. } => true,
@@ -1016,6 +1120,36 @@ impl Expansion {
     }
 }
 
+/// What a parameter expansion in braces does with the value of the
+/// parameter. Where there is a `colon`, as in `${name:-word}`, a parameter
+/// that is set but empty counts as unset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Modifier {
+    /// `${#name}`, the length of the value in characters.
+    Length,
+
+    /// `${name-word}`, the expanded `word` if the parameter is unset.
+    Default { colon: bool, word: Word },
+
+    /// `${name=word}`, like `Default`, also assigning `word` to it.
+    Assign { colon: bool, word: Word },
+
+    /// `${name?word}`, an error with the message `word` if it is unset.
+    Error { colon: bool, word: Word },
+
+    /// `${name+word}`, the expanded `word` unless the parameter is unset.
+    Alternative { colon: bool, word: Word },
+
+    /// `${name#pattern}`, the value without the shortest prefix matching
+    /// `pattern`, or the longest with `##`.
+    RemovePrefix { longest: bool, pattern: Word },
+
+    /// `${name%pattern}`, the value without the shortest suffix matching
+    /// `pattern`, or the longest with `%%`.
+    RemoveSuffix { longest: bool, pattern: Word },
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum LogicalOp {
     And(LeadingWhitespace),
@@ -1027,7 +1161,8 @@ pub enum LogicalOp {
 ///              ;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct NewlineList {
-    /// This String may contain a mix of ' ', \t, and \n
+    /// This String may contain a mix of ' ', \t, and \n, along with the
+    /// lines of the here-documents of the line before every \n
     pub whitespace: String,
 }
 
@@ -1079,7 +1214,7 @@ pub enum Separator {
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum SequentialSeparator {
-    Semi(Linebreak),
+    Semi(LeadingWhitespace, Linebreak),
     Implicit(NewlineList),
 }
 
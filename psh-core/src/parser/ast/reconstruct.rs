@@ -168,8 +168,10 @@ impl ToString for Subshell {
     fn to_string(&self) -> String {
         let mut s = format!("{}(", &self.lparen_ws);
         s.push_str(&self.body.to_string());
-        s.push_str(self.rparen_ws.as_ref());
-        s.push(')');
+        if let Some(rparen_ws) = &self.rparen_ws {
+            s.push_str(rparen_ws.as_ref());
+            s.push(')');
+        }
         s
     }
 }
@@ -203,147 +205,90 @@ impl ToString for Name {
 
 impl ToString for ForClause {
     fn to_string(&self) -> String {
-        match self {
-            Self::Simple(name, do_group) => {
-                format!("for{}{}", name.to_string(), do_group.to_string())
+        let mut s = reserved_word(Some(&self.for_ws), "for");
+        if let Some(name) = &self.name {
+            s.push_str(&name.to_string());
+        }
+        if let Some(wordlist) = &self.wordlist {
+            s.push_str(&wordlist.linebreak.to_string());
+            s.push_str(&reserved_word(Some(&wordlist.in_ws), "in"));
+            for word in &wordlist.words {
+                s.push_str(&word.to_string());
             }
-            Self::Padded(name, seq_sep, do_group) => format!(
-                "for{}{}{}",
-                name.to_string(),
-                seq_sep.to_string(),
-                do_group.to_string()
-            ),
-            // FIXME
-            Self::Full(name, linebreak, _wordlist, seq_sep, do_group) => format!(
-                "for{}{}in{}{}",
-                name.to_string(),
-                linebreak.to_string(),
-                seq_sep.to_string(),
-                do_group.to_string()
-            ),
         }
+        if let Some(separator) = &self.separator {
+            s.push_str(&separator.to_string());
+        }
+        s.push_str(&self.body.to_string());
+        s
     }
 }
 
 impl ToString for CaseClause {
     fn to_string(&self) -> String {
-        match self {
-            Self::Normal(word, linebreak, linebreak2, case_list) => format!(
-                "case{}{}in{}{}esac",
-                word.to_string(),
-                linebreak.to_string(),
-                linebreak2.to_string(),
-                case_list.to_string()
-            ),
-            Self::NoSeparator(word, linebreak, linebreak2, case_list) => format!(
-                "case{}{}in{}{}esac",
-                word.to_string(),
-                linebreak.to_string(),
-                linebreak2.to_string(),
-                case_list.to_string()
-            ),
-            Self::Empty(word, linebreak, linebreak2) => format!(
-                "case{}{}in{}",
-                word.to_string(),
-                linebreak.to_string(),
-                linebreak2.to_string(),
-            ),
+        let mut s = reserved_word(Some(&self.case_ws), "case");
+        if let Some(word) = &self.word {
+            s.push_str(&word.to_string());
         }
-    }
-}
-
-impl ToString for CaseListNs {
-    fn to_string(&self) -> String {
-        let mut s = String::new();
-        if let Some(list) = &self.case_list {
-            s.push_str(&list.to_string());
+        if let Some((linebreak, in_ws)) = &self.in_ws {
+            s.push_str(&linebreak.to_string());
+            s.push_str(&reserved_word(Some(in_ws), "in"));
         }
-        s.push_str(&self.last.to_string());
-        s
-    }
-}
-
-impl ToString for CaseList {
-    fn to_string(&self) -> String {
-        let mut s = self.head.to_string();
-        for part in &self.tail {
-            s.push_str(&part.to_string());
+        for item in &self.items {
+            s.push_str(&item.to_string());
         }
-        s
-    }
-}
-
-impl ToString for CaseItemNs {
-    fn to_string(&self) -> String {
-        match self {
-            Self::Empty(false, pattern, linebreak) => {
-                format!("{}){}", pattern.to_string(), linebreak.to_string(),)
-            }
-            Self::Empty(true, pattern, linebreak) => {
-                format!("({}){}", pattern.to_string(), linebreak.to_string(),)
-            }
-            Self::List(false, pattern, list) => {
-                format!("{}){}", pattern.to_string(), list.to_string(),)
-            }
-            Self::List(true, pattern, list) => {
-                format!("({}){}", pattern.to_string(), list.to_string(),)
-            }
+        if let Some((linebreak, esac_ws)) = &self.esac_ws {
+            s.push_str(&linebreak.to_string());
+            s.push_str(&reserved_word(Some(esac_ws), "esac"));
         }
+        s
     }
 }
 
 impl ToString for CaseItem {
     fn to_string(&self) -> String {
-        match self {
-            Self::Empty(false, pattern, linebreak, end_linebreak) => format!(
-                "{}){};;{}",
-                pattern.to_string(),
-                linebreak.to_string(),
-                end_linebreak.to_string()
-            ),
-            Self::Empty(true, pattern, linebreak, end_linebreak) => format!(
-                "({}){};;{}",
-                pattern.to_string(),
-                linebreak.to_string(),
-                end_linebreak.to_string()
-            ),
-            Self::List(false, pattern, list, end_linebreak) => format!(
-                "{}){};;{}",
-                pattern.to_string(),
-                list.to_string(),
-                end_linebreak.to_string()
-            ),
-            Self::List(true, pattern, list, end_linebreak) => format!(
-                "({}){};;{}",
-                pattern.to_string(),
-                list.to_string(),
-                end_linebreak.to_string()
-            ),
+        let mut s = self.linebreak.to_string();
+        s.push_str(&reserved_word(self.lparen_ws.as_ref(), "("));
+        s.push_str(&self.pattern.to_string());
+        s.push_str(&reserved_word(self.rparen_ws.as_ref(), ")"));
+        if let Some(body) = &self.body {
+            s.push_str(&body.to_string());
+        }
+        if let Some((linebreak, dsemi_ws)) = &self.dsemi_ws {
+            s.push_str(&linebreak.to_string());
+            s.push_str(&reserved_word(Some(dsemi_ws), ";;"));
         }
+        s
     }
 }
 
 impl ToString for Pattern {
     fn to_string(&self) -> String {
         let mut s = self.head.to_string();
-        for part in &self.tail {
-            s.push('|');
-            s.push_str(&part.to_string());
+        for (ws, word) in &self.tail {
+            s.push_str(&reserved_word(Some(ws), "|"));
+            s.push_str(&word.to_string());
         }
         s
     }
 }
 
+/// A reserved word with the whitespace before it, or nothing if it is
+/// missing.
+fn reserved_word(ws: Option<&LeadingWhitespace>, word: &str) -> String {
+    ws.map_or_else(String::new, |ws| format!("{ws}{word}"))
+}
+
 impl ToString for IfClause {
     fn to_string(&self) -> String {
-        let mut s = "if".to_string();
+        let mut s = reserved_word(Some(&self.if_ws), "if");
         s.push_str(&self.predicate.to_string());
-        s.push_str("then");
+        s.push_str(&reserved_word(self.then_ws.as_ref(), "then"));
         s.push_str(&self.body.to_string());
         if let Some(else_part) = &self.else_part {
             s.push_str(&else_part.to_string());
         }
-        s.push_str("fi");
+        s.push_str(&reserved_word(self.fi_ws.as_ref(), "fi"));
         s
     }
 }
@@ -351,23 +296,23 @@ impl ToString for IfClause {
 impl ToString for ElsePart {
     fn to_string(&self) -> String {
         let mut s = String::new();
-        for (predicate, body) in &self.elseifs {
-            s.push_str("elif");
-            s.push_str(&predicate.to_string());
-            s.push_str("then");
+        for elif in &self.elseifs {
+            s.push_str(&reserved_word(Some(&elif.elif_ws), "elif"));
+            s.push_str(&elif.predicate.to_string());
+            s.push_str(&reserved_word(elif.then_ws.as_ref(), "then"));
+            s.push_str(&elif.body.to_string());
+        }
+        if let Some((else_ws, body)) = &self.else_part {
+            s.push_str(&reserved_word(Some(else_ws), "else"));
             s.push_str(&body.to_string());
         }
-        if let Some(else_part) = &self.else_part {
-            s.push_str("else");
-            s.push_str(&else_part.to_string());
-        }
         s
     }
 }
 
 impl ToString for WhileClause {
     fn to_string(&self) -> String {
-        let mut s = "while".to_string();
+        let mut s = reserved_word(Some(&self.while_ws), "while");
         s.push_str(&self.predicate.to_string());
         s.push_str(&self.body.to_string());
         s
@@ -376,7 +321,7 @@ impl ToString for WhileClause {
 
 impl ToString for UntilClause {
     fn to_string(&self) -> String {
-        let mut s = "until".to_string();
+        let mut s = reserved_word(Some(&self.until_ws), "until");
         s.push_str(&self.predicate.to_string());
         s.push_str(&self.body.to_string());
         s
@@ -388,7 +333,9 @@ impl ToString for FunctionDefinition {
         let mut s = self.name.to_string();
         s.push_str(&self.parens);
         s.push_str(&self.linebreak.to_string());
-        s.push_str(&self.body.to_string());
+        if let Some(body) = &self.body {
+            s.push_str(&body.to_string());
+        }
         s
     }
 }
@@ -408,17 +355,19 @@ impl ToString for BraceGroup {
         let mut s = self.lbrace_ws.clone();
         s.0.push('{');
         s.0.push_str(&self.body.to_string());
-        s.0.push_str(self.rbrace_ws.as_ref());
-        s.0.push('}');
+        if let Some(rbrace_ws) = &self.rbrace_ws {
+            s.0.push_str(rbrace_ws.as_ref());
+            s.0.push('}');
+        }
         s.0
     }
 }
 
 impl ToString for DoGroup {
     fn to_string(&self) -> String {
-        let mut s = "do".to_string();
+        let mut s = reserved_word(self.do_ws.as_ref(), "do");
         s.push_str(&self.body.to_string());
-        s.push_str("done");
+        s.push_str(&reserved_word(self.done_ws.as_ref(), "done"));
         s
     }
 }
@@ -485,10 +434,10 @@ impl ToString for Redirection {
                 whitespace,
                 input_fd,
                 ty,
-                content,
                 end,
+                ..
             } => format!(
-                "{}{}{}{}{}",
+                "{}{}{}{}",
                 whitespace,
                 if let Some(fd) = input_fd {
                     fd.to_string()
@@ -496,7 +445,6 @@ impl ToString for Redirection {
                     String::new()
                 },
                 ty.to_string(),
-                content.to_string(),
                 end.to_string(),
             ),
         }
@@ -606,7 +554,7 @@ impl ToString for Separator {
 impl ToString for SequentialSeparator {
     fn to_string(&self) -> String {
         match self {
-            Self::Semi(linebreak) => format!(";{}", linebreak.to_string()),
+            Self::Semi(ws, linebreak) => format!("{ws};{}", linebreak.to_string()),
             Self::Implicit(newlines) => newlines.to_string(),
         }
     }
@@ -44,6 +44,12 @@ fn parse_variable_assignment() {
     let expected = VariableAssignment::new(name("foo"), None, "  ");
     assert_eq!(Ok(expected), actual);
 
+    let mut tokens = tokenize("foo= bar");
+    let actual = tokens.parse_variable_assignment();
+    let expected = VariableAssignment::new(name("foo"), None, "");
+    assert_eq!(Ok(expected), actual);
+    assert_eq!(Some(Token::Whitespace(' ')), tokens.next());
+
     let mut tokens = tokenize("'foo'=");
     let actual = tokens.parse_variable_assignment();
     assert!(actual.is_err());
@@ -105,6 +111,22 @@ fn parse_simple_command() {
     assert_eq!(Ok(expected), actual);
     assert!(tokens.next().is_none());
 
+    let mut tokens = tokenize("IFS= read -r");
+    let actual = tokens.parse_simple_command();
+
+    let expected = SimpleCommand {
+        name: Some(Word::new("read", " ")),
+        prefixes: vec![CmdPrefix::Assignment(VariableAssignment::new(
+            name("IFS"),
+            None,
+            "",
+        ))],
+        suffixes: vec![CmdSuffix::Word(Word::new("-r", " "))],
+    };
+
+    assert_eq!(Ok(expected), actual);
+    assert!(tokens.next().is_none());
+
     let mut tokens = tokenize("   echo foo bar baz");
     let actual = tokens.parse_simple_command();
 
@@ -621,7 +643,7 @@ fn parse_word() {
     let expected = Word::new("echo", "");
     assert_eq!(Ok(expected), actual);
     assert_eq!(Some(Token::Whitespace(' ')), tokens.next());
-    assert_eq!(Some(Token::Word("foo".to_string())), tokens.next());
+    assert_eq!(Some(Token::Word("foo".into())), tokens.next());
     assert!(tokens.next().is_none());
 
     let mut tokens = tokenize(">foo");
@@ -812,6 +834,7 @@ fn word_with_parameter_expansions() {
         expansions: vec![Expansion::Parameter {
             range: 0..=3,
             name: "foo".to_string(),
+            modifier: None,
             finished: true,
             quoted: false,
         }],
@@ -828,6 +851,7 @@ fn word_with_parameter_expansions() {
         expansions: vec![Expansion::Parameter {
             range: 1..=4,
             name: "foo".to_string(),
+            modifier: None,
             finished: true,
             quoted: true,
         }],
@@ -856,12 +880,14 @@ fn word_with_parameter_expansions() {
             Expansion::Parameter {
                 range: 1..=4,
                 name: "foo".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: true,
             },
             Expansion::Parameter {
                 range: 7..=11,
                 name: "bar_".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: true,
             },
@@ -881,12 +907,14 @@ fn word_with_parameter_expansions() {
             Expansion::Parameter {
                 range: 0..=3,
                 name: "FOO".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: false,
             },
             Expansion::Parameter {
                 range: 6..=7,
                 name: "_".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: false,
             },
@@ -905,24 +933,28 @@ fn word_with_parameter_expansions() {
             Expansion::Parameter {
                 range: 0..=1,
                 name: "a".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: false,
             },
             Expansion::Parameter {
                 range: 3..=6,
                 name: "FOO".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: true,
             },
             Expansion::Parameter {
                 range: 9..=13,
                 name: "_foo".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: true,
             },
             Expansion::Parameter {
                 range: 15..=16,
                 name: "b".to_string(),
+                modifier: None,
                 finished: true,
                 quoted: false,
             },
@@ -931,3 +963,315 @@ fn word_with_parameter_expansions() {
 
     assert_eq!(Ok(expected), actual);
 }
+
+#[test]
+fn parse_brace_group() {
+    let first_command = |ast: SyntaxTree| match ast.commands.map(|(cmds, _)| cmds.head) {
+        Some(CompleteCommand::List { list, .. }) => *list.head.head.sequence.head,
+        cmd => panic!("expected a list, got {cmd:?}"),
+    };
+
+    for input in [
+        "{ echo a; }",
+        "{ x=1; echo a & } > file 2>&1",
+        "{\n  echo a\n  echo b\n} | cat",
+        "{ { echo a; }; echo }; }",
+    ] {
+        let ast = parse(input, false).unwrap();
+        assert_eq!(input, ast.to_string());
+        assert!(matches!(
+            first_command(ast),
+            Command::Compound(CompoundCommand::Brace(_), _)
+        ));
+    }
+
+    let ast = parse("{ x=1; echo a & } > file 2>&1", false).unwrap();
+    let Command::Compound(CompoundCommand::Brace(group), redirections) = first_command(ast) else {
+        unreachable!();
+    };
+    assert_eq!(2, redirections.len());
+    let lists = group.body.list_with_separator();
+    assert_eq!(2, lists.len());
+    assert!(lists[0].1.is_sync());
+    assert!(lists[1].1.is_async());
+
+    for input in [
+        "{ echo a;",
+        "{",
+        "{ echo a\n",
+        "{ { echo a; }",
+        "{ echo a &&",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    assert!(matches!(
+        parse("echo a; } b", false),
+        Err(Error::SyntaxError(_, Some(1)))
+    ));
+    assert!(matches!(
+        parse("echo a\n{ echo b; }\n\necho c; }\n", false),
+        Err(Error::SyntaxError(_, Some(4)))
+    ));
+}
+
+#[test]
+fn parse_if_and_loops() {
+    let first_command = |ast: SyntaxTree| match ast.commands.map(|(cmds, _)| cmds.head) {
+        Some(CompleteCommand::List { list, .. }) => *list.head.head.sequence.head,
+        cmd => panic!("expected a list, got {cmd:?}"),
+    };
+
+    for input in [
+        "if true; then echo a; fi",
+        "if a; then b; elif c; then d; elif e; then f; else g; fi",
+        "if true\nthen\n  echo fi\nfi > file",
+        "while read line; do echo \"$line\"; done < file",
+        "until false\ndo\n  if a; then b; fi\ndone | cat",
+        "while { a; b; }; do :; done",
+    ] {
+        let ast = parse(input, false).unwrap();
+        assert_eq!(input, ast.to_string());
+        assert!(matches!(
+            first_command(ast),
+            Command::Compound(
+                CompoundCommand::If(_) | CompoundCommand::While(_) | CompoundCommand::Until(_),
+                _
+            )
+        ));
+    }
+
+    let ast = parse("if a; then b; elif c; then d; else e; fi 2>&1", false).unwrap();
+    let Command::Compound(CompoundCommand::If(clause), redirections) = first_command(ast) else {
+        unreachable!();
+    };
+    assert_eq!(1, redirections.len());
+    let else_part = clause.else_part.unwrap();
+    assert_eq!(1, else_part.elseifs.len());
+    assert!(else_part.else_part.is_some());
+
+    for input in [
+        "if",
+        "if true",
+        "if true; then",
+        "if true; then echo a;",
+        "if true; then echo a; elif",
+        "if true; then echo a; else\n",
+        "while true; do",
+        "while true\n",
+        "until false; do echo a; done; while",
+        "while true; do if a; then b; fi",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    for input in ["if true; fi", "while true; done", "if; then a; fi", "fi"] {
+        assert!(
+            matches!(parse(input, false), Err(Error::SyntaxError(..))),
+            "{input:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_subshells() {
+    for input in [
+        "(cd /tmp; ls)",
+        "( echo a ) > file",
+        "(\n  echo a\n  (echo b)\n) | cat",
+        "(a) && (b) || { c; }",
+        "echo $( (echo a) )",
+    ] {
+        assert_eq!(input, parse(input, false).unwrap().to_string());
+    }
+
+    let ast = parse("(echo a; echo b) 2>&1", false).unwrap();
+    let Some(CompleteCommand::List { list, .. }) = ast.commands.map(|(cmds, _)| cmds.head) else {
+        unreachable!();
+    };
+    let Command::Compound(CompoundCommand::Subshell(subshell), redirections) =
+        *list.head.head.sequence.head
+    else {
+        panic!("expected a subshell");
+    };
+    assert_eq!(1, redirections.len());
+    assert_eq!(2, subshell.body.list_with_separator().len());
+
+    for input in ["(", "(echo a", "(echo a\n", "(echo a; (echo b)"] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    for input in ["()", "(echo a))", ")"] {
+        assert!(
+            matches!(parse(input, false), Err(Error::SyntaxError(..))),
+            "{input:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_for_clauses() {
+    for input in [
+        "for i in a b c; do echo $i; done",
+        "for i do echo $i; done",
+        "for i; do echo $i; done",
+        "for i\nin a \"b c\"\ndo\n  echo $i\ndone > out",
+        "for i in ; do :; done",
+        "for x in 1 2 ; do for y in a b; do break 2; done; done",
+    ] {
+        assert_eq!(input, parse(input, false).unwrap().to_string());
+    }
+
+    let ast = parse("for i in a $b 'c d'; do :; done", false).unwrap();
+    let Some(CompleteCommand::List { list, .. }) = ast.commands.map(|(cmds, _)| cmds.head) else {
+        unreachable!();
+    };
+    let Command::Compound(CompoundCommand::For(clause), _) = *list.head.head.sequence.head else {
+        panic!("expected a for clause");
+    };
+    assert_eq!("i", clause.name.unwrap().name);
+    assert_eq!(3, clause.wordlist.unwrap().words.len());
+
+    for input in [
+        "for",
+        "for i",
+        "for i in a",
+        "for i in a;",
+        "for i in a; do echo",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    for input in [
+        "for 1 in a; do :; done",
+        "for i in a; done",
+        "for i in a; do done",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::SyntaxError(..))),
+            "{input:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_case_clauses() {
+    for input in [
+        "case $x in a) echo a;; b | c ) echo bc ;; esac",
+        "case $x in (a) echo a; esac",
+        "case x in esac",
+        "case x\nin\n  *.c)\n    echo c\n    ;;\n  *) ;;\nesac",
+        "case x in x)\n;;\nesac 2>&1",
+        "case x in in) echo in;; esac",
+    ] {
+        assert_eq!(input, parse(input, false).unwrap().to_string());
+    }
+
+    let ast = parse("case $x in a|b) ;; (c) echo c; esac", false).unwrap();
+    let Some(CompleteCommand::List { list, .. }) = ast.commands.map(|(cmds, _)| cmds.head) else {
+        unreachable!();
+    };
+    let Command::Compound(CompoundCommand::Case(clause), _) = *list.head.head.sequence.head else {
+        panic!("expected a case clause");
+    };
+    assert_eq!("$x", clause.word.unwrap().name);
+    assert_eq!(2, clause.items.len());
+    assert_eq!(1, clause.items[0].pattern.tail.len());
+    assert!(clause.items[0].body.is_none());
+    assert!(clause.items[1].lparen_ws.is_some());
+    assert!(clause.items[1].dsemi_ws.is_none());
+
+    for input in [
+        "case",
+        "case x",
+        "case x in",
+        "case x in a",
+        "case x in a)",
+        "case x in a) echo;;\n",
+        "case x in a) echo \"b",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    for input in [
+        "case x y",
+        "case x in a) echo a b) echo b;; esac",
+        "case x in a) echo a;;; esac",
+        "case x in ) echo;; esac",
+    ] {
+        assert!(
+            matches!(parse(input, false), Err(Error::SyntaxError(..))),
+            "{input:?}"
+        );
+    }
+}
+
+#[test]
+fn parse_function_definitions() {
+    for input in [
+        "f() { echo $1; }",
+        "f ( ) { echo; }",
+        "f()\n{\n  local x=1\n  return 2\n}",
+        "f() ( cd /; ls ) > out",
+        "f() if true; then echo; fi",
+        "f() { :; }; f a b",
+    ] {
+        assert_eq!(input, parse(input, false).unwrap().to_string());
+    }
+
+    let ast = parse("greet() { echo hi; } 2>&1", false).unwrap();
+    let Some(CompleteCommand::List { list, .. }) = ast.commands.map(|(cmds, _)| cmds.head) else {
+        unreachable!();
+    };
+    let Command::FunctionDefinition(definition) = *list.head.head.sequence.head else {
+        panic!("expected a function definition");
+    };
+    assert_eq!("greet", definition.name.name);
+    let body = definition.body.unwrap();
+    assert!(matches!(body.command, CompoundCommand::Brace(_)));
+    assert_eq!(1, body.redirections.len());
+
+    for input in ["f()", "f()\n", "f() {", "f() { echo"] {
+        assert!(
+            matches!(parse(input, false), Err(Error::Incomplete(_))),
+            "{input:?}"
+        );
+        assert_eq!(input, parse(input, true).unwrap().to_string());
+    }
+    for input in ["f() echo", "f() }", "1() { :; }"] {
+        assert!(
+            matches!(parse(input, false), Err(Error::SyntaxError(..))),
+            "{input:?}"
+        );
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn parse_into_json() {
+    let json = |input| serde_json::from_str::<serde_json::Value>(&parse_to_json(input)).unwrap();
+
+    let tree = json("echo hi");
+    assert!(tree["complete_commands"].is_object());
+    assert!(tree["unparsed"].is_null());
+
+    assert_eq!(")", json("echo hi; )")["unparsed"].as_str().unwrap().trim());
+    let tree = json("echo `;`");
+    assert!(tree["complete_commands"].is_null());
+    assert_eq!("echo `;`", tree["unparsed"]);
+}
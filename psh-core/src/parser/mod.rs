@@ -4,4 +4,6 @@ pub mod tok;
 
 pub use ast::nodes::SyntaxTree;
 pub use ast::parse;
+#[cfg(feature = "serde")]
+pub use ast::parse_to_json;
 pub use tok::lex;
@@ -1,4 +1,4 @@
-use std::{borrow::Cow, iter::Peekable};
+use std::{borrow::Cow, iter::Peekable, rc::Rc};
 
 use crate::parser::consumer::Consumer;
 
@@ -8,11 +8,14 @@ pub fn lex(input: impl AsRef<str>) -> Vec<Token> {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Token {
-    Word(String),
+    /// Reference counted, since tokens are cloned whenever the parser needs
+    /// to backtrack.
+    Word(Rc<str>),
     Reserved(ReservedWord),
     Whitespace(char),
     CmdSubStart,
     ArithmeticStart,
+    Backtick,
     DoubleQuote,
     SingleQuote,
     Equals,
@@ -22,6 +25,7 @@ pub enum Token {
     Backslash,
     And,
     Or,
+    DoubleSemicolon,
     SyncSeparator,
     AsyncSeparator,
     Pipe,
@@ -30,6 +34,14 @@ pub enum Token {
     LParen,
     RParen,
     Pound,
+
+    /// The lines of a here-document, gathered after the lexing by
+    /// [`crate::ast::parse`]: its `body` and the line with its `delimiter`,
+    /// which is empty if the input ends before it.
+    HereDocument {
+        body: Rc<str>,
+        delimiter: Rc<str>,
+    },
 }
 
 impl Token {
@@ -41,6 +53,7 @@ impl Token {
             Self::Whitespace(ws) => Owned(ws.to_string()),
             Self::CmdSubStart => Borrowed("$("),
             Self::ArithmeticStart => Borrowed("$(("),
+            Self::Backtick => Borrowed("`"),
             Self::DoubleQuote => Borrowed("\""),
             Self::SingleQuote => Borrowed("'"),
             Self::Equals => Borrowed("="),
@@ -50,6 +63,7 @@ impl Token {
             Self::Backslash => Borrowed("\\"),
             Self::And => Borrowed("&&"),
             Self::Or => Borrowed("||"),
+            Self::DoubleSemicolon => Borrowed(";;"),
             Self::SyncSeparator => Borrowed(";"),
             Self::AsyncSeparator => Borrowed("&"),
             Self::Pipe => Borrowed("|"),
@@ -58,6 +72,7 @@ impl Token {
             Self::LParen => Borrowed("("),
             Self::RParen => Borrowed(")"),
             Self::Pound => Borrowed("#"),
+            Self::HereDocument { body, delimiter } => Owned(format!("{body}{delimiter}")),
         }
     }
 }
@@ -108,6 +123,7 @@ impl AsRef<str> for ReservedWord {
 pub trait Tokenizer: Iterator<Item = char> {
     fn parse_and(&mut self) -> Option<Token>;
     fn parse_or(&mut self) -> Option<Token>;
+    fn parse_double_semicolon(&mut self) -> Option<Token>;
     fn parse_sync_separator(&mut self) -> Option<Token>;
     fn parse_async_separator(&mut self) -> Option<Token>;
     fn parse_pipe(&mut self) -> Option<Token>;
@@ -119,6 +135,7 @@ pub trait Tokenizer: Iterator<Item = char> {
     fn parse_whitespace(&mut self) -> Option<Token>;
     fn parse_cmd_sub_start(&mut self) -> Option<Token>;
     fn parse_arithmetic_start(&mut self) -> Option<Token>;
+    fn parse_backtick(&mut self) -> Option<Token>;
     fn parse_dollar(&mut self) -> Option<Token>;
     fn parse_question_mark(&mut self) -> Option<Token>;
     fn parse_tilde(&mut self) -> Option<Token>;
@@ -134,6 +151,7 @@ pub trait Tokenizer: Iterator<Item = char> {
             .or_else(|| self.parse_and())
             .or_else(|| self.parse_or())
             .or_else(|| self.parse_async_separator())
+            .or_else(|| self.parse_double_semicolon())
             .or_else(|| self.parse_sync_separator())
             .or_else(|| self.parse_pipe())
             .or_else(|| self.parse_redirect_input())
@@ -144,6 +162,7 @@ pub trait Tokenizer: Iterator<Item = char> {
             .or_else(|| self.parse_reserved_word())
             .or_else(|| self.parse_arithmetic_start())
             .or_else(|| self.parse_cmd_sub_start())
+            .or_else(|| self.parse_backtick())
             .or_else(|| self.parse_dollar())
             .or_else(|| self.parse_question_mark())
             .or_else(|| self.parse_tilde())
@@ -216,6 +235,10 @@ where
             .map(|_| Token::CmdSubStart)
     }
 
+    fn parse_backtick(&mut self) -> Option<Token> {
+        self.consume_single('`').map(|_| Token::Backtick)
+    }
+
     fn parse_backslash(&mut self) -> Option<Token> {
         self.consume_single('\\').map(|_| Token::Backslash)
     }
@@ -257,7 +280,7 @@ where
         if word.is_empty() {
             None
         } else {
-            Some(Token::Word(word))
+            Some(Token::Word(word.into()))
         }
     }
 
@@ -269,6 +292,11 @@ where
         self.consume_multiple(['|', '|']).map(|_| Token::Or)
     }
 
+    fn parse_double_semicolon(&mut self) -> Option<Token> {
+        self.consume_multiple([';', ';'])
+            .map(|_| Token::DoubleSemicolon)
+    }
+
     fn parse_reserved_word(&mut self) -> Option<Token> {
         let mut consume_reserved_word = |s: &str, reserved_word| {
             let initial = self.clone();
@@ -388,40 +416,46 @@ mod tests {
     fn parse_word() {
         let mut input = "foo".chars().peekable();
 
-        assert_eq!(Some(Word("foo".to_string())), input.parse());
+        assert_eq!(Some(Word("foo".into())), input.parse());
         assert!(input.next().is_none());
 
         let mut input = "'foo'".chars().peekable();
 
         assert_eq!(Some(SingleQuote), input.parse());
-        assert_eq!(Some(Word("foo".to_string())), input.parse());
+        assert_eq!(Some(Word("foo".into())), input.parse());
         assert_eq!(Some(SingleQuote), input.parse());
         assert!(input.next().is_none());
 
         let mut input = "foo'bar baz'quux\\ yo".chars().peekable();
 
-        assert_eq!(Some(Word("foo".to_string())), input.parse());
+        assert_eq!(Some(Word("foo".into())), input.parse());
         assert_eq!(Some(SingleQuote), input.parse());
-        assert_eq!(Some(Word("bar".to_string())), input.parse());
+        assert_eq!(Some(Word("bar".into())), input.parse());
         assert_eq!(Some(Whitespace(' ')), input.parse());
-        assert_eq!(Some(Word("baz".to_string())), input.parse());
+        assert_eq!(Some(Word("baz".into())), input.parse());
         assert_eq!(Some(SingleQuote), input.parse());
-        assert_eq!(Some(Word("quux".to_string())), input.parse());
+        assert_eq!(Some(Word("quux".into())), input.parse());
         assert_eq!(Some(Backslash), input.parse());
         assert_eq!(Some(Whitespace(' ')), input.parse());
-        assert_eq!(Some(Word("yo".to_string())), input.parse());
+        assert_eq!(Some(Word("yo".into())), input.parse());
         assert!(input.next().is_none());
 
         let mut input = "$(echo foo)".chars().peekable();
         assert_eq!(Some(CmdSubStart), input.parse());
-        assert_eq!(Some(Word("echo".to_string())), input.parse());
+        assert_eq!(Some(Word("echo".into())), input.parse());
         assert_eq!(Some(Whitespace(' ')), input.parse());
-        assert_eq!(Some(Word("foo".to_string())), input.parse());
+        assert_eq!(Some(Word("foo".into())), input.parse());
         assert_eq!(Some(RParen), input.parse());
 
         let mut input = "$(".chars().peekable();
         let expected = CmdSubStart;
         assert_eq!(Some(expected), input.parse());
+
+        let mut input = "`pwd`".chars().peekable();
+        assert_eq!(Some(Backtick), input.parse());
+        assert_eq!(Some(Word("pwd".into())), input.parse());
+        assert_eq!(Some(Backtick), input.parse());
+        assert!(input.next().is_none());
     }
 
     #[test]
@@ -429,25 +463,25 @@ mod tests {
         test_tokenize(
             r#"foo="bar" <file echo $bar 2>> /dev/null"#,
             vec![
-                Word("foo".to_string()),
+                Word("foo".into()),
                 Equals,
                 DoubleQuote,
-                Word("bar".to_string()),
+                Word("bar".into()),
                 DoubleQuote,
                 Whitespace(' '),
                 RedirectInput,
-                Word("file".to_string()),
+                Word("file".into()),
                 Whitespace(' '),
-                Word("echo".to_string()),
+                Word("echo".into()),
                 Whitespace(' '),
                 Dollar,
-                Word("bar".to_string()),
+                Word("bar".into()),
                 Whitespace(' '),
-                Word("2".to_string()),
+                Word("2".into()),
                 RedirectOutput,
                 RedirectOutput,
                 Whitespace(' '),
-                Word("/dev/null".to_string()),
+                Word("/dev/null".into()),
             ],
         );
     }
@@ -457,17 +491,17 @@ mod tests {
         test_tokenize(
             "echo $(b $(c $(( d ))) ) separate",
             vec![
-                Word("echo".to_string()),
+                Word("echo".into()),
                 Whitespace(' '),
                 CmdSubStart,
-                Word("b".to_string()),
+                Word("b".into()),
                 Whitespace(' '),
                 CmdSubStart,
-                Word("c".to_string()),
+                Word("c".into()),
                 Whitespace(' '),
                 ArithmeticStart,
                 Whitespace(' '),
-                Word("d".to_string()),
+                Word("d".into()),
                 Whitespace(' '),
                 RParen,
                 RParen,
@@ -475,7 +509,7 @@ mod tests {
                 Whitespace(' '),
                 RParen,
                 Whitespace(' '),
-                Word("separate".to_string()),
+                Word("separate".into()),
             ],
         );
     }
@@ -485,7 +519,7 @@ mod tests {
         test_tokenize(
             "echo {",
             vec![
-                Word("echo".to_string()),
+                Word("echo".into()),
                 Whitespace(' '),
                 Reserved(ReservedWord::LBrace),
             ],
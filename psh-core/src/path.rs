@@ -1,13 +1,61 @@
+use std::collections::HashSet;
 use std::env;
+use std::ffi::{CStr, CString};
 use std::os::unix::prelude::PermissionsExt;
 use std::path::PathBuf;
 
+use nix::libc::{self, getpwnam};
+
 use crate::Error;
 
 pub fn home_dir() -> String {
     env::var("HOME").map_err(|_| Error::NoHome).unwrap()
 }
 
+/// The home directory of `user` in the user database, as in `~user`.
+pub fn user_home_dir(user: &str) -> Option<String> {
+    if !is_portable_filename(user) {
+        return None;
+    }
+
+    let c_str = CString::new(user).unwrap();
+    let pointer = c_str.as_ptr();
+    // SAFETY: we own the pointer which was created via CString::new
+    //         from a known Rust string
+    let passwd = unsafe { getpwnam(pointer) };
+
+    if passwd.is_null() {
+        return None;
+    }
+
+    // SAFETY: the input is the return value of the `getpwnam`
+    //         library function, and we know it is not null
+    let dir = unsafe { CStr::from_ptr((*passwd).pw_dir) };
+    Some(dir.to_string_lossy().to_string())
+}
+
+/// The names of the users in the user database, as completed after `~`.
+pub fn user_names() -> Vec<String> {
+    let mut names = Vec::new();
+
+    // SAFETY: every entry is copied before the next call to `getpwent`,
+    //         which may overwrite it
+    unsafe {
+        libc::setpwent();
+        loop {
+            let passwd = libc::getpwent();
+            if passwd.is_null() {
+                break;
+            }
+            let name = CStr::from_ptr((*passwd).pw_name);
+            names.push(name.to_string_lossy().to_string());
+        }
+        libc::endpwent();
+    }
+
+    names
+}
+
 fn cfg_file(file_name: &str, var: &str) -> PathBuf {
     if let Ok(path) = env::var(var) {
         return PathBuf::from(path);
@@ -22,14 +70,27 @@ fn cfg_file(file_name: &str, var: &str) -> PathBuf {
     }
 }
 
+/// The init file shared by every user, which is read before their own.
+pub const SYSTEM_INIT_FILE: &str = "/etc/psh/init.psh";
+
 pub fn init_file() -> PathBuf {
     cfg_file("init.psh", "PSH_INIT")
 }
 
+/// The init files read by an interactive shell, in order: the system-wide
+/// one, and then the one of the user.
+pub fn init_files() -> Vec<PathBuf> {
+    vec![PathBuf::from(SYSTEM_INIT_FILE), init_file()]
+}
+
 pub fn history_file() -> PathBuf {
     cfg_file("history", "PSH_HISTORY")
 }
 
+pub fn jump_file() -> PathBuf {
+    cfg_file("jump", "PSH_JUMP_DB")
+}
+
 pub fn has_relative_command(cmd: impl AsRef<str>) -> bool {
     let cmd = cmd.as_ref();
 
@@ -47,9 +108,86 @@ pub fn has_relative_command(cmd: impl AsRef<str>) -> bool {
     }
 }
 
+/// Expands a leading `~` or `~user` of a directory, as found in for example
+/// `$PATH`.
+pub fn expand_tilde_in_dir(dir: &str) -> PathBuf {
+    let Some(rest) = dir.strip_prefix('~') else {
+        return PathBuf::from(dir);
+    };
+
+    let (user, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = match user {
+        "" => Some(home_dir()),
+        user => user_home_dir(user),
+    };
+    match home {
+        Some(home) => PathBuf::from(format!("{home}{rest}")),
+        None => PathBuf::from(dir),
+    }
+}
+
+/// Returns every executable found in the colon separated list of directories
+/// `path`, together with the directory it was found in. If the same command
+/// exists in multiple directories, only the first one is returned.
+pub fn get_cmds_from_path(path: &str) -> Vec<(String, PathBuf)> {
+    let mut cmds: Vec<(String, PathBuf)> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for dir in path.split(':').filter(|d| !d.is_empty()) {
+        let dir = expand_tilde_in_dir(dir);
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if seen.contains(&name) {
+                continue;
+            }
+
+            if let Ok(metadata) = entry.metadata() {
+                let mode = metadata.permissions().mode();
+                if !metadata.is_dir() && mode & 0o111 != 0 {
+                    seen.insert(name.clone());
+                    cmds.push((name, dir.clone()));
+                }
+            }
+        }
+    }
+
+    cmds
+}
+
+/// Returns the path of `file` in the first directory of the colon separated
+/// list `path` that has it. If `executable`, files that cannot be executed
+/// are passed over, as they are by `get_cmds_from_path`.
+pub fn find_in_path(path: &str, file: &str, executable: bool) -> Option<PathBuf> {
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| expand_tilde_in_dir(dir).join(file))
+        .find(|candidate| match std::fs::metadata(candidate) {
+            Ok(metadata) => {
+                !metadata.is_dir() && (!executable || metadata.permissions().mode() & 0o111 != 0)
+            }
+            Err(_) => false,
+        })
+}
+
+/// Replaces the home directory at the start of the path `s` with `~`, unless
+/// it is only the start of the name of another directory.
 pub fn compress_tilde(s: String) -> String {
-    let home = home_dir();
-    s.replacen(&home, "~", 1)
+    let Ok(home) = env::var("HOME") else {
+        return s;
+    };
+    let home = home.trim_end_matches('/');
+    if home.is_empty() {
+        return s;
+    }
+
+    match s.strip_prefix(home) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{rest}"),
+        _ => s,
+    }
 }
 
 pub fn is_portable_filename(input: impl AsRef<str>) -> bool {
@@ -61,7 +199,10 @@ pub fn is_portable_filename(input: impl AsRef<str>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
     use super::*;
+    use crate::test_util::TempDir;
 
     #[test]
     fn expand_works() {
@@ -78,5 +219,53 @@ mod tests {
         let input = format!("{home}//");
         let expanded = compress_tilde(input);
         assert_eq!("~//", expanded);
+
+        let input = format!("{home}2/foo");
+        assert_eq!(input, compress_tilde(input.clone()));
+
+        let input = format!("/tmp{home}");
+        assert_eq!(input, compress_tilde(input.clone()));
+    }
+
+    #[test]
+    fn user_home_dirs() {
+        assert!(user_home_dir("root").is_some());
+        assert_eq!(None, user_home_dir("no-such-user"));
+        assert_eq!(None, user_home_dir("../root"));
+        assert!(user_names().iter().any(|name| name == "root"));
+    }
+
+    #[test]
+    fn executables_are_found_in_path() {
+        let dir = TempDir::new("find-in-path");
+        for (file, mode) in [("a/tool", 0o644), ("b/tool", 0o755), ("b/sub/x", 0o755)] {
+            let file = dir.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(&file, "").unwrap();
+            fs::set_permissions(&file, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let path = format!("{0}/a::{0}/b", dir.display());
+
+        assert_eq!(Some(dir.join("b/tool")), find_in_path(&path, "tool", true));
+        assert_eq!(Some(dir.join("a/tool")), find_in_path(&path, "tool", false));
+        assert_eq!(None, find_in_path(&path, "sub", false));
+        assert_eq!(None, find_in_path(&path, "none", true));
+    }
+
+    #[test]
+    fn expand_tilde_in_dir_works() {
+        let home = home_dir();
+
+        assert_eq!(PathBuf::from(&home), expand_tilde_in_dir("~"));
+        assert_eq!(
+            PathBuf::from(format!("{home}/bin")),
+            expand_tilde_in_dir("~/bin")
+        );
+        assert_eq!(
+            PathBuf::from(format!("{}/bin", user_home_dir("root").unwrap())),
+            expand_tilde_in_dir("~root/bin")
+        );
+        assert_eq!(PathBuf::from("~foo/bin"), expand_tilde_in_dir("~foo/bin"));
+        assert_eq!(PathBuf::from("/usr/bin"), expand_tilde_in_dir("/usr/bin"));
     }
 }
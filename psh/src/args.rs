@@ -1,19 +1,102 @@
+use std::path::PathBuf;
+
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
     #[arg(short, long, help("Run the specified command and exit"))]
     pub command: bool,
 
-    #[arg(long, help("Only lex the input"))]
+    #[arg(
+        long,
+        visible_alias("tokenize"),
+        conflicts_with("ast"),
+        help("Print the tokens of the input and exit")
+    )]
     pub lex: bool,
 
-    #[arg(long, help("Only produce the AST of the input"))]
+    #[arg(long, help("Print the AST of the input and exit"))]
     pub ast: bool,
 
     #[cfg(feature = "serde")]
     #[arg(long, requires("ast"), help("Prints the AST in JSON format"))]
     pub json: bool,
 
-    #[arg(help("The file or command (if `-c`) to run"), value_name("target"))]
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        value_name("file"),
+        help(
+            "Write a line of JSON for every executed command to the given file, or to the file descriptor N if `fd:N`"
+        )
+    )]
+    pub trace_json: Option<String>,
+
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        help(
+            "Print the exported variables, functions, aliases and abbreviations as JSON and exit"
+        )
+    )]
+    pub dump_env: bool,
+
+    #[cfg(feature = "serde")]
+    #[arg(
+        long,
+        value_name("file"),
+        help("Restore a setup printed by `--dump-env` from the given file, or stdin if `-`")
+    )]
+    pub load_env: Option<String>,
+
+    #[arg(
+        long,
+        help("Run without network access, with a read-only filesystem and empty /run and /tmp, or not at all if that is not supported")
+    )]
+    pub sandbox: bool,
+
+    #[arg(
+        long,
+        value_name("path"),
+        requires("sandbox"),
+        help("Keep the given path writable in the sandbox, can be given multiple times")
+    )]
+    pub sandbox_allow: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        value_name("file"),
+        help("Read the given init file instead of the default ones, can be given multiple times")
+    )]
+    pub rcfile: Vec<PathBuf>,
+
+    #[arg(
+        help("The file or command (if `-c`) to run. With `--lex` or `--ast`, stdin is read if omitted or `-`"),
+        value_name("target")
+    )]
     pub target: Option<String>,
+
+    #[arg(
+        help("The arguments of the file, as `$1` and on. With `-c`, the first one is `$0`"),
+        value_name("arguments"),
+        trailing_var_arg(true),
+        allow_hyphen_values(true)
+    )]
+    pub arguments: Vec<String>,
+
+    #[cfg(feature = "self-update")]
+    #[command(subcommand)]
+    pub subcommand: Option<Subcommand>,
+}
+
+#[cfg(feature = "self-update")]
+#[derive(clap::Subcommand, Debug)]
+pub enum Subcommand {
+    /// Replace this binary by the one of the latest release
+    SelfUpdate {
+        #[arg(
+            long,
+            help("Only print the latest version if it is newer, and exit with 0 if so and 1 otherwise")
+        )]
+        check: bool,
+    },
 }
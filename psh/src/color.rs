@@ -0,0 +1,241 @@
+//! What colors the terminal supports, and parsing colors given as names,
+//! palette indices or hex RGB, converted down to what the terminal can show
+//! so that themes look alike everywhere.
+
+use crossterm::style::Color;
+
+/// The terminals known not to support colors, besides the monochrome
+/// variants of others, such as `xterm-mono`.
+const MONOCHROME_TERMS: &[&str] = &["dumb", "vt52", "vt100", "vt102", "vt220"];
+
+/// The first 16 colors of the palette as xterm shows them by default, which
+/// the other colors are matched against on terminals with only these.
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each component in the 6x6x6 color cube of the 256 color
+/// palette, which starts at index 16.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Whether colors should be used, given the values of `PSH_COLOR`,
+/// `NO_COLOR` and `TERM`. `PSH_COLOR` forces them on with `always` and off
+/// with `never`. Otherwise they are used unless `NO_COLOR` is set to
+/// anything, see <https://no-color.org>, or the terminal does not support
+/// them.
+pub fn enabled(force: Option<&str>, no_color: Option<&str>, term: Option<&str>) -> bool {
+    match force {
+        Some("always") => return true,
+        Some("never") => return false,
+        _ => {}
+    }
+
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+
+    match term {
+        None | Some("") => false,
+        Some(term) => {
+            !MONOCHROME_TERMS.contains(&term) && !term.ends_with("-mono") && !term.ends_with("-m")
+        }
+    }
+}
+
+/// How many colors a terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl Depth {
+    /// The depth told by `COLORTERM` and `TERM`: 24 bit colors if
+    /// `COLORTERM` is `truecolor` or `24bit`, 256 colors for terminals such
+    /// as `xterm-256color`, and otherwise 16.
+    pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+        match term {
+            Some(term) if term.ends_with("-direct") => Self::TrueColor,
+            Some(term) if term.contains("256color") => Self::Ansi256,
+            _ => Self::Ansi16,
+        }
+    }
+}
+
+/// Parses a color given by its name such as `red` or `dark_blue`, by its
+/// index in the 256 color palette, or as hex RGB such as `#ff8700` or
+/// `#f80`.
+pub fn parse(s: &str) -> Option<Color> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let component = |i: usize, len: usize| {
+            let value = u8::from_str_radix(hex.get(i * len..(i + 1) * len)?, 16).ok()?;
+            // `#f80` is short for `#ff8800`
+            Some(if len == 1 { value * 17 } else { value })
+        };
+        let len = match hex.len() {
+            3 => 1,
+            6 => 2,
+            _ => return None,
+        };
+        return Some(Color::Rgb {
+            r: component(0, len)?,
+            g: component(1, len)?,
+            b: component(2, len)?,
+        });
+    }
+
+    match s.parse::<u8>() {
+        Ok(index) => Some(Color::AnsiValue(index)),
+        Err(_) => Color::try_from(s).ok(),
+    }
+}
+
+/// Converts `color` to the closest one that a terminal of `depth` can show.
+pub fn downconvert(color: Color, depth: Depth) -> Color {
+    match (color, depth) {
+        (Color::Rgb { r, g, b }, Depth::Ansi256) => Color::AnsiValue(nearest_256((r, g, b))),
+        (Color::Rgb { r, g, b }, Depth::Ansi16) => Color::AnsiValue(nearest_16((r, g, b))),
+        (Color::AnsiValue(index), Depth::Ansi16) if index >= 16 => {
+            Color::AnsiValue(nearest_16(rgb_of(index)))
+        }
+        (color, _) => color,
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// The RGB of the color at `index` in the 256 color palette.
+fn rgb_of(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |n: u8| CUBE_LEVELS[n as usize];
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance(rgb, rgb_of(i)))
+        .unwrap_or(15)
+}
+
+/// The closest color of the color cube or the greys of the 256 color
+/// palette, leaving out the first 16 colors since terminals differ in how
+/// they show them.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    (16..=255)
+        .min_by_key(|&i| distance(rgb, rgb_of(i)))
+        .unwrap_or(15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabling() {
+        let golden = [
+            (None, None, Some("xterm-256color"), true),
+            (None, None, Some("linux"), true),
+            (None, None, Some("dumb"), false),
+            (None, None, Some("xterm-mono"), false),
+            (None, None, None, false),
+            (None, Some("1"), Some("xterm-256color"), false),
+            (None, Some(""), Some("xterm-256color"), true),
+            (Some("never"), None, Some("xterm-256color"), false),
+            (Some("always"), Some("1"), Some("dumb"), true),
+            (Some("auto"), Some("1"), Some("xterm"), false),
+        ];
+        for (force, no_color, term, expected) in golden {
+            assert_eq!(
+                expected,
+                enabled(force, no_color, term),
+                "PSH_COLOR={force:?} NO_COLOR={no_color:?} TERM={term:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn detecting_depth() {
+        assert_eq!(Depth::TrueColor, Depth::detect(Some("truecolor"), None));
+        assert_eq!(Depth::TrueColor, Depth::detect(None, Some("xterm-direct")));
+        assert_eq!(Depth::Ansi256, Depth::detect(None, Some("xterm-256color")));
+        assert_eq!(Depth::Ansi16, Depth::detect(None, Some("linux")));
+    }
+
+    #[test]
+    fn parsing() {
+        let rgb = |r, g, b| Some(Color::Rgb { r, g, b });
+        assert_eq!(rgb(255, 135, 0), parse("#ff8700"));
+        assert_eq!(rgb(255, 136, 0), parse("#f80"));
+        assert_eq!(Some(Color::AnsiValue(208)), parse("208"));
+        assert_eq!(Some(Color::DarkBlue), parse("dark_blue"));
+        assert_eq!(None, parse("#ff87"));
+        assert_eq!(None, parse("#gg0000"));
+        assert_eq!(None, parse("256"));
+        assert_eq!(None, parse("orange"));
+    }
+
+    #[test]
+    fn downconverting() {
+        let orange = Color::Rgb {
+            r: 255,
+            g: 135,
+            b: 0,
+        };
+        assert_eq!(orange, downconvert(orange, Depth::TrueColor));
+        assert_eq!(Color::AnsiValue(208), downconvert(orange, Depth::Ansi256));
+        assert_eq!(Color::AnsiValue(3), downconvert(orange, Depth::Ansi16));
+
+        let grey = Color::Rgb {
+            r: 100,
+            g: 100,
+            b: 100,
+        };
+        assert_eq!(Color::AnsiValue(241), downconvert(grey, Depth::Ansi256));
+        assert_eq!(Color::AnsiValue(8), downconvert(grey, Depth::Ansi16));
+
+        assert_eq!(
+            Color::AnsiValue(208),
+            downconvert(Color::AnsiValue(208), Depth::Ansi256)
+        );
+        assert_eq!(
+            Color::AnsiValue(9),
+            downconvert(Color::AnsiValue(196), Depth::Ansi16)
+        );
+        assert_eq!(
+            Color::AnsiValue(3),
+            downconvert(Color::AnsiValue(3), Depth::Ansi16)
+        );
+        assert_eq!(Color::Red, downconvert(Color::Red, Depth::Ansi16));
+    }
+}
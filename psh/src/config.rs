@@ -1,19 +1,39 @@
 use crossterm::style::Color;
 use psh_core::Engine;
 
+use crate::color::{self, Depth};
+
 pub struct Colors;
 
+/// The color set in the variable `var`, shown as well as the terminal
+/// allows, or white if it is not set or invalid.
 fn from_var(var: &str, engine: &Engine) -> Color {
-    match engine.get_value_of(var) {
-        Some(color) => match color.parse::<u8>() {
-            Ok(val) => Color::AnsiValue(val),
-            Err(_) => Color::AnsiValue(15),
-        },
-        None => Color::AnsiValue(15),
+    if !Colors::enabled(engine) {
+        return Color::Reset;
     }
+
+    let color = engine
+        .get_value_of(var)
+        .and_then(|color| color::parse(&color))
+        .unwrap_or(Color::AnsiValue(15));
+    let depth = Depth::detect(
+        engine.get_value_of("COLORTERM").as_deref(),
+        engine.get_value_of("TERM").as_deref(),
+    );
+    color::downconvert(color, depth)
 }
 
 impl Colors {
+    /// Whether the input line, the prompt and the completion menu are
+    /// colored, rather than being plain text.
+    pub fn enabled(engine: &Engine) -> bool {
+        color::enabled(
+            engine.get_value_of("PSH_COLOR").as_deref(),
+            engine.get_value_of("NO_COLOR").as_deref(),
+            engine.get_value_of("TERM").as_deref(),
+        )
+    }
+
     pub fn unparsed(engine: &Engine) -> Color {
         from_var("PSH_UNPARSED_COL", engine)
     }
@@ -57,8 +77,19 @@ impl Colors {
     pub fn prompt(engine: &Engine) -> Color {
         from_var("PSH_PROMPT_COL", engine)
     }
+
+    /// The color of quoted text if it is set, which is otherwise colored
+    /// like the rest of its word.
+    pub fn string(engine: &Engine) -> Option<Color> {
+        engine.get_value_of("PSH_STRING_COL")?;
+        Some(from_var("PSH_STRING_COL", engine))
+    }
 }
 
 pub const PS1_USER_PROMPT: &str = "$ ";
 pub const PS1_ROOT_PROMPT: &str = "# ";
 pub const PS2_PROMPT: &str = "> ";
+
+/// What the prompts of entered commands are redrawn as with the
+/// `transient_prompt` option, unless `PSH_TRANSIENT_PROMPT` is set.
+pub const TRANSIENT_PROMPT: &str = "❯ ";
@@ -0,0 +1,290 @@
+//! Crash reports: when psh panics, a report with what is needed to find the
+//! bug is written to a file that can be attached to an issue. This is only
+//! done if the `crash_report` option is set, since the report contains the
+//! line that was being run, and the panic message that may quote it, albeit
+//! with anything looking like a secret masked.
+
+use std::backtrace::Backtrace;
+use std::env::{self, consts};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossterm::terminal;
+
+use psh_core::ast::parse;
+use psh_core::parser::tok::{self, Token};
+use psh_core::{path, Engine};
+
+/// What is replaced for the secrets in the reported line.
+const MASK: &str = "***";
+
+/// The parts of names and flags that make their values secrets.
+const SECRETS: &[&str] = &[
+    "auth",
+    "cookie",
+    "credential",
+    "key",
+    "pass",
+    "secret",
+    "session",
+    "token",
+];
+
+/// The last panic, recorded by the hook for the report.
+static PANIC: Mutex<Option<Panic>> = Mutex::new(None);
+
+struct Panic {
+    message: String,
+    backtrace: Backtrace,
+}
+
+/// Records every panic for a later report, while still printing it as
+/// usual.
+pub fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+        let message = match info.location() {
+            Some(location) => format!("{} at {location}", payload(info)),
+            None => payload(info).to_string(),
+        };
+        let backtrace = Backtrace::force_capture();
+        if let Ok(mut panic) = PANIC.lock() {
+            *panic = Some(Panic { message, backtrace });
+        }
+        default(info);
+    }));
+}
+
+fn payload<'a>(info: &'a PanicHookInfo) -> &'a str {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Reports the last panic, caught while running `line` if given. Writes the
+/// report if the `crash_report` option is set, and otherwise tells how to
+/// get one.
+pub fn report(engine: &Engine, line: Option<&str>) {
+    let Some(panic) = PANIC.lock().ok().and_then(|mut panic| panic.take()) else {
+        return;
+    };
+
+    eprintln!("psh: This is a bug in psh, please report it");
+    if !engine.options.crash_report {
+        eprintln!("psh: Run `shopt -s crash_report` to write a report that can be attached to it");
+        return;
+    }
+
+    let contents = contents(engine, line, &panic);
+    let dir = report_dir();
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let file = dir.join(format!("crash-{secs}.txt"));
+
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&file, contents)) {
+        Ok(()) => eprintln!("psh: Wrote a crash report to {}", file.display()),
+        Err(e) => eprintln!("psh: Could not write a crash report: {e}"),
+    }
+}
+
+/// `$XDG_STATE_HOME/psh`, or `~/.local/state/psh`.
+fn report_dir() -> PathBuf {
+    match env::var("XDG_STATE_HOME") {
+        Ok(dir) => PathBuf::from(dir).join("psh"),
+        Err(_) => PathBuf::from(path::home_dir()).join(".local/state/psh"),
+    }
+}
+
+fn contents(engine: &Engine, line: Option<&str>, panic: &Panic) -> String {
+    let mut s = String::new();
+    let var = |name| env::var(name).unwrap_or_else(|_| "unset".to_string());
+
+    // writing to a string does not fail
+    let _ = writeln!(s, "psh {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(s, "platform: {} {}", consts::OS, consts::ARCH);
+    let _ = writeln!(s, "TERM: {}", var("TERM"));
+    let _ = writeln!(s, "COLORTERM: {}", var("COLORTERM"));
+    let _ = writeln!(s, "TERM_PROGRAM: {}", var("TERM_PROGRAM"));
+    if let Ok((columns, rows)) = terminal::size() {
+        let _ = writeln!(s, "terminal size: {columns}x{rows}");
+    }
+    let _ = writeln!(s, "stdin is a terminal: {}", io::stdin().is_terminal());
+    let _ = writeln!(s, "interactive: {}", engine.interactive);
+    let _ = writeln!(s, "options: {:?}", engine.options);
+
+    let message = redact(&panic.message);
+    let _ = writeln!(s, "\n## Panic\n\n{message}\n\n{}", panic.backtrace);
+
+    if let Some(line) = line {
+        let line = redact(line);
+        let _ = writeln!(s, "## Line (secrets masked)\n\n{line}\n");
+        let _ = writeln!(s, "## Tokens\n\n{:?}\n", tok::lex(&line));
+        let _ = match parse(&line, true) {
+            Ok(ast) => writeln!(s, "## AST\n\n{ast:#?}"),
+            Err(e) => writeln!(s, "## AST\n\n{e}"),
+        };
+    }
+
+    s
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.trim_start_matches('-').to_lowercase();
+    SECRETS.iter().any(|secret| name.contains(secret))
+}
+
+/// Masks the secrets in `line`: values assigned to variables or given to
+/// flags whose names look like they hold secrets, like `TOKEN=...` and
+/// `--password ...`, the values of such headers, like `'Authorization: ...'`,
+/// credentials given with `-u` or `--user`, and credentials in URLs, like
+/// `https://user:pw@host`.
+pub fn redact(line: &str) -> String {
+    // the words of the line, and what separates them
+    let mut parts = Vec::<(String, bool)>::new();
+    let mut quote = None;
+    let mut escaped = false;
+
+    for token in tok::lex(line) {
+        let text = token.as_str();
+        let separator = quote.is_none()
+            && !escaped
+            && matches!(
+                token,
+                Token::Whitespace(_)
+                    | Token::And
+                    | Token::Or
+                    | Token::DoubleSemicolon
+                    | Token::SyncSeparator
+                    | Token::AsyncSeparator
+                    | Token::Pipe
+            );
+
+        match (&token, &quote) {
+            (Token::SingleQuote | Token::DoubleQuote, None) if !escaped => {
+                quote = Some(token.clone())
+            }
+            (_, Some(q)) if *q == token && !escaped => quote = None,
+            _ => {}
+        }
+        escaped = token == Token::Backslash && !escaped;
+
+        match parts.last_mut() {
+            Some((last, false)) if !separator => last.push_str(&text),
+            _ => parts.push((text.into_owned(), separator)),
+        }
+    }
+
+    let mut redacted = String::new();
+    let mut mask_next = false;
+    for (part, separator) in parts {
+        if separator {
+            // a flag at the end of a command has no value to mask
+            mask_next &= part.trim().is_empty();
+            redacted.push_str(&part);
+            continue;
+        }
+
+        if std::mem::take(&mut mask_next) {
+            redacted.push_str(MASK);
+            continue;
+        }
+
+        match part.split_once('=') {
+            Some((name, _)) if is_secret(name) || name == "--user" => {
+                redacted.push_str(name);
+                redacted.push('=');
+                redacted.push_str(MASK);
+            }
+            _ => {
+                mask_next = part.starts_with('-')
+                    && (is_secret(&part) || ["-p", "-u", "--user"].contains(&part.as_str()));
+                match redact_header(&part) {
+                    Some(header) => redacted.push_str(&header),
+                    None => redacted.push_str(&redact_url(&part)),
+                }
+            }
+        }
+    }
+
+    redacted
+}
+
+/// Masks the value of a header such as `'Authorization: Bearer abc'`, as
+/// given to `curl -H`, if its name looks like it holds a secret. The quotes
+/// around it are kept.
+fn redact_header(word: &str) -> Option<String> {
+    let colon = word.find(':')?;
+    let name = word[..colon].trim_start_matches(['\'', '"']);
+    if name.is_empty() || name.contains(char::is_whitespace) || !is_secret(name) {
+        return None;
+    }
+
+    let value = &word[colon + 1..];
+    let space = &value[..value.len() - value.trim_start().len()];
+    let quote = match word.chars().next() {
+        Some(quote @ ('\'' | '"')) if word.len() > 1 && word.ends_with(quote) => quote.to_string(),
+        _ => String::new(),
+    };
+    Some(format!("{}:{space}{MASK}{quote}", &word[..colon]))
+}
+
+/// Masks the user and password in a URL such as `https://user:pw@host`.
+fn redact_url(word: &str) -> String {
+    let Some((scheme, rest)) = word.split_once("://") else {
+        return word.to_string();
+    };
+    let host = rest.find('/').map_or(rest, |end| &rest[..end]);
+    match host.rfind('@') {
+        Some(at) => format!("{scheme}://{MASK}{}", &rest[at..]),
+        None => word.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacting() {
+        let golden = [
+            ("echo foo bar", "echo foo bar"),
+            ("GITHUB_TOKEN=abc gh pr list", "GITHUB_TOKEN=*** gh pr list"),
+            ("API_KEY='a b c' cmd", "API_KEY=*** cmd"),
+            ("export Password=x; ls", "export Password=***; ls"),
+            ("mysql -u root -p hunter2", "mysql -u *** -p ***"),
+            ("curl -u me:pw https://host", "curl -u *** https://host"),
+            ("curl --user=me:pw host", "curl --user=*** host"),
+            (
+                "curl -H 'Authorization: Bearer abc' host",
+                "curl -H 'Authorization: ***' host",
+            ),
+            (
+                "curl -H \"X-Api-Key:abc\" -H 'Accept: text/html'",
+                "curl -H \"X-Api-Key:***\" -H 'Accept: text/html'",
+            ),
+            ("login --password 'a b' && ls", "login --password *** && ls"),
+            ("login --token=abc", "login --token=***"),
+            ("login --password; ls", "login --password; ls"),
+            (
+                "git clone https://me:pw@host/repo",
+                "git clone https://***@host/repo",
+            ),
+            ("curl https://host/a@b", "curl https://host/a@b"),
+            ("echo a=b", "echo a=b"),
+        ];
+        for (line, expected) in golden {
+            assert_eq!(expected, redact(line), "redacting {line}");
+        }
+    }
+}
@@ -1,18 +1,35 @@
 mod args;
+mod color;
 mod config;
+mod crash;
 mod repl;
+#[cfg(feature = "self-update")]
+mod self_update;
 
+use std::io::{IsTerminal, Read};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 
 use clap::Parser;
+use nix::unistd;
 
 use psh_core::ast::parse;
+use psh_core::engine::log;
+use psh_core::engine::sandbox::Sandbox;
+#[cfg(feature = "serde")]
+use psh_core::engine::snapshot::EnvSnapshot;
 use psh_core::parser::tok;
 use psh_core::Engine;
-use psh_core::ExitStatus;
+use psh_core::{Error, ExitStatus};
 
 fn main() {
     let args = args::Args::parse();
+    crash::install_hook();
+
+    #[cfg(feature = "self-update")]
+    if let Some(args::Subcommand::SelfUpdate { check }) = args.subcommand {
+        std::process::exit(self_update::run(check));
+    }
 
     #[cfg(feature = "serde")]
     let json = args.json;
@@ -20,85 +37,277 @@ fn main() {
     #[cfg(not(feature = "serde"))]
     let json = false;
 
+    if args.lex || args.ast {
+        let input = match read_input(args.target.as_deref(), args.command) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("psh: Could not read input: {e}");
+                std::process::exit(1);
+            }
+        };
+
+        let code = if args.lex {
+            print_tokens(&input)
+        } else {
+            print_ast(&input, json)
+        };
+        std::process::exit(code);
+    }
+
+    let mut engine = Engine::default();
+
+    #[cfg(feature = "serde")]
+    if let Some(target) = &args.trace_json {
+        match psh_core::engine::trace::Tracer::open(target) {
+            Ok(tracer) => engine.tracer = Some(tracer),
+            Err(e) => {
+                eprintln!("psh: Could not open trace target `{target}`: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    if args.dump_env {
+        repl::InitFiles::new(args.rcfile.clone()).read(&mut engine);
+        match EnvSnapshot::of(&engine).to_json() {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("psh: Could not dump environment: {e}");
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    }
+
+    #[cfg(feature = "serde")]
+    let mut snapshot = args.load_env.as_ref().map(|file| {
+        let snapshot = read_input(Some(file), false)
+            .map_err(psh_core::Error::from)
+            .and_then(|json| EnvSnapshot::from_json(&json));
+        snapshot.unwrap_or_else(|e| {
+            eprintln!("psh: Could not load environment from `{file}`: {e}");
+            std::process::exit(1);
+        })
+    });
+
+    // the shell enters the sandbox itself, before anything is run, so that
+    // builtins and redirections are confined as well as the commands
+    if args.sandbox {
+        let mut writable = args.sandbox_allow;
+        writable.extend(engine.session.dir.clone());
+        if let Err(e) = (Sandbox { writable }).enter() {
+            eprintln!("psh: Could not enter sandbox: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    // an interactive shell restores the snapshot only once it has read its
+    // init files, see `Repl::run`, and the others read none
+    #[cfg(feature = "serde")]
+    if args.target.is_some() || args.command || !std::io::stdin().is_terminal() {
+        if let Some(snapshot) = snapshot.take() {
+            snapshot.restore(&mut engine);
+        }
+    }
+
     if let Some(target) = args.target {
         if args.command {
-            run_command(&target, args.lex, args.ast, json);
+            if let Some((name, parameters)) = args.arguments.split_first() {
+                engine.shell_name = name.to_string();
+                engine.parameters = parameters.to_vec();
+            }
+            run_command(engine, &target);
         } else {
-            run_file(&target, args.lex, args.ast, json);
+            engine.shell_name = target.clone();
+            engine.parameters = args.arguments;
+            run_file(engine, &target);
         }
+    } else if args.command {
+        eprintln!("psh: -c: option requires an argument");
+        std::process::exit(2);
+    } else if !std::io::stdin().is_terminal() {
+        run_stdin(engine);
     } else {
-        let mut repl = repl::Repl::new();
+        let mut repl = repl::Repl::new(engine, repl::InitFiles::new(args.rcfile));
+        #[cfg(feature = "serde")]
+        {
+            repl.env = snapshot;
+        }
 
-        if let Err(e) = repl.run(args.lex, args.ast, json) {
+        if let Err(e) = repl.run() {
             eprintln!("psh: Unrecoverable error occurred: {e}");
             std::process::exit(7);
         }
     }
 }
 
-fn run_command(command: &str, lex: bool, ast: bool, _json: bool) {
-    if lex {
-        for token in tok::lex(command) {
-            println!("{token:?}");
+/// Reads the input of the debug modes, which is either the command itself
+/// (if `-c`), the content of the target file, or stdin if no target is
+/// given or it is `-`.
+fn read_input(target: Option<&str>, command: bool) -> std::io::Result<String> {
+    match target {
+        Some(command_str) if command => Ok(command_str.to_string()),
+        Some(file) if file != "-" => std::fs::read_to_string(file),
+        _ => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            Ok(input)
         }
-    } else if ast {
-        let ast = parse(command, true);
+    }
+}
 
-        #[cfg(feature = "serde")]
-        if _json {
-            println!("{}", ast.unwrap().as_json().unwrap());
-        } else {
-            println!("{:#?}", ast);
+fn print_tokens(input: &str) -> i32 {
+    for token in tok::lex(input) {
+        println!("{token:?}");
+    }
+    0
+}
+
+fn print_ast(input: &str, _json: bool) -> i32 {
+    let ast = match parse(input, false) {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("psh: {e}");
+            return 2;
         }
+    };
 
-        #[cfg(not(feature = "serde"))]
-        println!("{:#?}", ast);
+    #[cfg(feature = "serde")]
+    if _json {
+        match ast.as_json() {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("psh: {e}");
+                return 1;
+            }
+        }
     } else {
-        let code = match Engine::default().execute_line(command) {
-            Ok(codes) if codes.is_empty() => 0,
+        println!("{:#?}", ast);
+    }
 
-            Ok(codes) => codes.last().map(ExitStatus::raw_code).unwrap(),
+    #[cfg(not(feature = "serde"))]
+    println!("{:#?}", ast);
 
-            Err(e) => {
-                eprintln!("psh: Could not execute command: {e}");
-                1
-            }
+    0
+}
+
+fn run_command(mut engine: Engine, command: &str) {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| engine.execute_line(command)));
+    let code = exit_code(
+        &engine,
+        result.unwrap_or_else(|_| crash(&engine, Some(command))),
+    );
+    engine.exit(ExitStatus::from_code(code.unwrap_or(0)));
+}
+
+fn run_file(mut engine: Engine, file: &String) {
+    let path = PathBuf::from(file);
+    if let Err(e) = std::fs::metadata(&path) {
+        let e = match e.kind() {
+            std::io::ErrorKind::NotFound => Error::NonExistentFile(file.to_string()),
+            _ => Error::Io(e),
         };
-        std::process::exit(code);
+        eprintln!("psh: {e}");
+        engine.exit(ExitStatus::from_code(127));
     }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| engine.execute_file(path, false)));
+    let code = exit_code(&engine, result.unwrap_or_else(|_| crash(&engine, None)));
+    engine.exit(ExitStatus::from_code(code.unwrap_or(0)));
 }
 
-fn run_file(file: &String, lex: bool, ast: bool, _json: bool) {
-    let path = PathBuf::from(file);
-    if lex {
-        let content = std::fs::read_to_string(path).unwrap();
-        for token in tok::lex(content) {
-            println!("{token:?}");
+/// Runs the commands piped to the shell, as in `echo 'echo hi' | psh`. They
+/// are read and run one complete command at a time, so that the input after
+/// a command is left for it to read, as with `read`.
+fn run_stdin(mut engine: Engine) {
+    let mut code = 0;
+    let mut command = String::new();
+
+    loop {
+        let line = match read_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("psh: Could not read input: {e}");
+                code = 1;
+                break;
+            }
+        };
+        command.push_str(&line);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| engine.execute_line(&command)));
+        let result = result.unwrap_or_else(|_| crash(&engine, Some(&command)));
+        if let Err(Error::Incomplete(_)) = result {
+            continue;
         }
-    } else if ast {
-        let content = std::fs::read_to_string(path).unwrap();
-        let ast = parse(content, true);
 
-        #[cfg(feature = "serde")]
-        if _json {
-            println!("{}", ast.unwrap().as_json().unwrap());
-        } else {
-            println!("{:#?}", ast);
+        // as in other shells, the rest of the input is not run after a
+        // syntax error, or an assignment to a readonly variable
+        let fatal = matches!(
+            result.as_ref().map_err(Error::inner),
+            Err(Error::SyntaxError(..) | Error::ParseError(_) | Error::Readonly(_))
+        );
+        command.clear();
+        if let Some(status) = exit_code(&engine, result) {
+            code = status;
+        }
+        if fatal {
+            break;
         }
+    }
 
-        #[cfg(not(feature = "serde"))]
-        println!("{:#?}", ast);
-    } else {
-        let code = match Engine::default().execute_file(path) {
-            Ok(codes) if codes.is_empty() => 0,
+    if !command.trim().is_empty() {
+        eprintln!(
+            "psh: Could not execute command: {}",
+            Error::Incomplete(command)
+        );
+        code = 2;
+    }
+    engine.exit(ExitStatus::from_code(code));
+}
 
-            Ok(codes) => codes.last().map(ExitStatus::raw_code).unwrap(),
+/// Reads a line from stdin one byte at a time, so that nothing after it is
+/// consumed, or `None` at the end of the input.
+fn read_line() -> psh_core::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    let mut buf = [0; 1];
+    while unistd::read(0, &mut buf)? == 1 {
+        bytes.push(buf[0]);
+        if buf[0] == b'\n' {
+            break;
+        }
+    }
 
-            Err(e) => {
-                eprintln!("psh: Could not execute command: {e}");
-                1
+    match bytes.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(String::from_utf8_lossy(&bytes).to_string())),
+    }
+}
+
+/// The exit code of the last command run, if any. If the input could not be
+/// run, the error is reported and logged, and the code is 2 if it did not
+/// parse, as in other shells, or 1 otherwise.
+fn exit_code(engine: &Engine, result: psh_core::Result<Vec<ExitStatus>>) -> Option<i32> {
+    match result {
+        Ok(codes) => codes.last().map(ExitStatus::raw_code),
+
+        Err(e) => {
+            match e {
+                Error::At(..) => eprintln!("psh: {e}"),
+                _ => eprintln!("psh: Could not execute command: {e}"),
             }
-        };
-        std::process::exit(code);
+            engine.log(log::Kind::of(&e), &e);
+            match e.inner() {
+                Error::SyntaxError(..) | Error::ParseError(_) | Error::Incomplete(_) => Some(2),
+                _ => Some(1),
+            }
+        }
     }
 }
+
+/// Reports a panic caught while running `line`, and exits.
+fn crash(engine: &Engine, line: Option<&str>) -> ! {
+    crash::report(engine, line);
+    std::process::exit(70);
+}
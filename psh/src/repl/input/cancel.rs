@@ -0,0 +1,55 @@
+use std::os::unix::io::RawFd;
+use std::thread::{self, JoinHandle};
+
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::unistd::{close, pipe, write};
+
+use psh_core::engine::cancel::CancellationToken;
+use psh_core::Result;
+
+/// Cancels a token as soon as there is input to read on stdin, until dropped.
+/// Used while drawing the input line, so that slow work like expansions done
+/// by the highlighter stops when the user presses a key, instead of delaying
+/// it. The input is left for the next read.
+pub struct CancelOnInput {
+    wake: RawFd,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CancelOnInput {
+    pub fn start(token: CancellationToken) -> Result<Self> {
+        let (woken, wake) = pipe()?;
+
+        let thread = thread::spawn(move || {
+            let mut fds = [
+                PollFd::new(0, PollFlags::POLLIN),
+                PollFd::new(woken, PollFlags::POLLIN),
+            ];
+
+            while let Err(Errno::EINTR) = poll(&mut fds, -1) {}
+
+            let ready = |fd: PollFd| fd.revents().is_some_and(|r| !r.is_empty());
+            if ready(fds[0]) && !ready(fds[1]) {
+                token.cancel();
+            }
+
+            let _ = close(woken);
+        });
+
+        Ok(Self {
+            wake,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for CancelOnInput {
+    fn drop(&mut self) {
+        let _ = write(self.wake, &[0]);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = close(self.wake);
+    }
+}
@@ -0,0 +1,650 @@
+use std::path::PathBuf;
+
+use psh_core::engine::completion::{self, CompletionSpec, Files};
+use psh_core::engine::frecency::Frecency;
+use psh_core::engine::jump::{self, JumpDb};
+use psh_core::engine::quote::{self, QuoteState, Quoting};
+use psh_core::{path, Engine};
+
+use crate::repl::input::editing;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The text that replaces the word being completed.
+    pub value: String,
+
+    /// Where the candidate comes from, shown next to it in the menu.
+    pub annotation: String,
+
+    /// Whether a space should be inserted after the candidate if it is the
+    /// only one, which is not wanted for e.g. directories.
+    pub terminated: bool,
+
+    /// The file that the candidate refers to, which is colored by its type
+    /// in the menu.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The byte index in the line where the word being completed starts.
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Completion {
+    /// The longest prefix shared by all candidates.
+    pub fn common_prefix(&self) -> String {
+        let mut candidates = self.candidates.iter();
+        let Some(first) = candidates.next() else {
+            return String::new();
+        };
+
+        let mut prefix = first.value.as_str();
+        for candidate in candidates {
+            let len = prefix
+                .char_indices()
+                .zip(candidate.value.chars())
+                .find(|((_, a), b)| a != b)
+                .map(|((i, _), _)| i)
+                .unwrap_or_else(|| prefix.len().min(candidate.value.len()));
+            prefix = &prefix[..len];
+        }
+
+        prefix.to_string()
+    }
+}
+
+/// How many of the recently visited directories are offered as the argument
+/// of `cd`, at most.
+const RECENT_DIRECTORIES: usize = 10;
+
+/// Completes the word ending at byte index `index` of `line`: variable names
+/// after a `$`, user names after a `~`, command names in command position,
+/// the arguments of commands as set with `complete`, directories as those of
+/// `cd`, and file names everywhere else.
+pub fn complete(engine: &mut Engine, line: &str, index: usize) -> Completion {
+    let start = editing::shell_word_start(line, index);
+    let word = &line[start..index];
+    let command = is_command_position(&line[..start]);
+    let mut words = command_words(&line[..start]);
+    let spec = words
+        .first()
+        .and_then(|name| engine.completions.get(*name))
+        .cloned();
+
+    let is_cd = matches!(words.first(), Some(&"cd" | &"pushd"));
+
+    let mut candidates = match (command, spec) {
+        _ if parameter_start(word).is_some() => complete_parameter(engine, word),
+        _ if is_user_prefix(word) => complete_user(word),
+        (true, _) if !word.contains('/') => complete_command(engine, word),
+        (true, _) => complete_path(word, true),
+        (false, Some(spec)) => {
+            words.push(word);
+            let replies = match (&spec.function, &spec.command) {
+                (Some(function), _) => completion::reply(engine, function, &words, line, index),
+                (None, Some(command)) => completion::output(engine, command, &words, line, index),
+                (None, None) => Vec::new(),
+            };
+            complete_with(&spec, replies, word)
+        }
+        (false, None) if is_cd => complete_directory(engine, word),
+        (false, None) => complete_path(word, false),
+    };
+    rank(engine, &mut candidates, command && !word.contains('/'));
+
+    Completion { start, candidates }
+}
+
+fn is_command_position(before: &str) -> bool {
+    let mut words = before.split_whitespace().collect::<Vec<_>>();
+
+    while let Some(word) = words.last() {
+        match word.split_once('=') {
+            Some((name, _)) if is_name(name) => {
+                words.pop();
+            }
+            _ => break,
+        }
+    }
+
+    match words.last() {
+        None => true,
+        Some(&word) => {
+            word.ends_with([';', '|', '&', '('])
+                || matches!(
+                    word,
+                    "!" | "{" | "do" | "then" | "else" | "elif" | "if" | "while" | "until"
+                )
+        }
+    }
+}
+
+/// The words of the command whose arguments `before` ends in, starting
+/// with its name, which is its first word that is not an assignment.
+fn command_words(before: &str) -> Vec<&str> {
+    let command = before
+        .rsplit([';', '|', '&', '(', ')'])
+        .next()
+        .unwrap_or(before);
+
+    command
+        .split_whitespace()
+        .skip_while(|word| match word.split_once('=') {
+            Some((name, _)) => is_name(name),
+            None => false,
+        })
+        .collect()
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(|c: char| c.is_ascii_digit())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn complete_command(engine: &Engine, word: &str) -> Vec<Candidate> {
+    let mut candidates = engine
+        .command_candidates()
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(word))
+        .map(|(name, source)| Candidate {
+            value: name,
+            annotation: source.to_string(),
+            terminated: true,
+            path: None,
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+/// Completes an argument with the words and files that `spec` offers, and
+/// the `replies` of its function or command, which are offered as they are.
+fn complete_with(spec: &CompletionSpec, replies: Vec<String>, word: &str) -> Vec<Candidate> {
+    let replied = !replies.is_empty();
+    let source = match spec.function {
+        Some(_) => "function",
+        None => "command",
+    };
+    let word_candidate = |value, annotation: &str| Candidate {
+        value,
+        annotation: annotation.to_string(),
+        terminated: true,
+        path: None,
+    };
+    let mut candidates = spec
+        .words(word)
+        .map(|value| word_candidate(value.to_string(), "word"))
+        .chain(
+            replies
+                .into_iter()
+                .map(|value| word_candidate(value, source)),
+        )
+        .collect::<Vec<_>>();
+
+    if spec.files != Files::None && !replied {
+        candidates.extend(complete_path(word, false).into_iter().filter(|candidate| {
+            let Some(path) = &candidate.path else {
+                return false;
+            };
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            spec.offers_file(&name, path.is_dir())
+        }));
+    }
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates.dedup_by(|a, b| a.value == b.value);
+    candidates
+}
+
+/// The byte index of the `$` that starts the parameter expansion `word` ends
+/// in, if it does, as in `$PA` or `"${PA`.
+fn parameter_start(word: &str) -> Option<usize> {
+    let scanned = quote::scan(word);
+    let (pos, &(start, _, quoting)) = scanned
+        .iter()
+        .enumerate()
+        .rfind(|(_, (_, c, _))| *c == '$')?;
+
+    // a `$` can only be quoted by the double quotes it is in, and not by a
+    // backslash, even one inside them
+    let escaped = pos > 0 && scanned[pos - 1].1 == '\\';
+    if quoting == Quoting::Quoted
+        && (escaped || quote::open_quote(&word[..start]) != QuoteState::Double)
+    {
+        return None;
+    }
+
+    let name = &word[start + 1..];
+    let name = name.strip_prefix('{').unwrap_or(name);
+    (name.is_empty() || is_name(name)).then_some(start)
+}
+
+/// Completes the name of the variable after the `$` that `word` ends in.
+fn complete_parameter(engine: &Engine, word: &str) -> Vec<Candidate> {
+    let Some(start) = parameter_start(word) else {
+        return Vec::new();
+    };
+    let braced = word[start + 1..].starts_with('{');
+    let (before, prefix) = word.split_at(start + 1 + usize::from(braced));
+    let quoted = quote::open_quote(word) != QuoteState::None;
+
+    let mut candidates = engine
+        .variables
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| Candidate {
+            value: format!("{before}{name}{}", if braced { "}" } else { "" }),
+            annotation: "variable".to_string(),
+            terminated: !quoted,
+            path: None,
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+/// Whether `word` is a `~` followed by the start of the name of a user.
+fn is_user_prefix(word: &str) -> bool {
+    word.strip_prefix('~').is_some_and(|user| {
+        user.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    })
+}
+
+/// Completes `~user` with the names of the users, as their home directories.
+fn complete_user(word: &str) -> Vec<Candidate> {
+    let mut candidates = path::user_names()
+        .into_iter()
+        .filter(|name| name.starts_with(&word[1..]))
+        .map(|name| Candidate {
+            value: format!("~{name}/"),
+            annotation: "user".to_string(),
+            terminated: false,
+            path: path::user_home_dir(&name).map(PathBuf::from),
+        })
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates.dedup_by(|a, b| a.value == b.value);
+    candidates
+}
+
+/// Completes the argument of `cd` with directories only, followed by the
+/// directories visited most, see `j`, whose names contain it, unless it is
+/// a path of its own.
+fn complete_directory(engine: &Engine, word: &str) -> Vec<Candidate> {
+    let mut candidates = complete_path(word, false)
+        .into_iter()
+        .filter(|candidate| candidate.path.as_ref().is_some_and(|path| path.is_dir()))
+        .collect::<Vec<_>>();
+
+    if word.contains(['/', '\'', '"', '\\']) {
+        return candidates;
+    }
+    let Ok(db) = JumpDb::of(engine) else {
+        return candidates;
+    };
+
+    let patterns = match word {
+        "" => Vec::new(),
+        word => vec![word],
+    };
+    let recent = db
+        .matches(&patterns, jump::now())
+        .into_iter()
+        .filter(|entry| entry.dir.is_dir())
+        .take(RECENT_DIRECTORIES)
+        .map(|entry| {
+            let dir = path::compress_tilde(entry.dir.display().to_string());
+            let value = match dir.strip_prefix('~') {
+                Some(rest) => format!("~{}/", quote_name(rest, QuoteState::None, false)),
+                None => format!("{}/", quote_name(&dir, QuoteState::None, false)),
+            };
+            Candidate {
+                value,
+                annotation: "recent".to_string(),
+                terminated: false,
+                path: Some(entry.dir.clone()),
+            }
+        });
+
+    for candidate in recent {
+        if !candidates.iter().any(|c| c.value == candidate.value) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// Sorts candidates that are sorted alphabetically by frecency instead, if
+/// the `frecency` option is set. Candidates that are commands are ranked by
+/// their use as commands, and others by their use as arguments.
+fn rank(engine: &mut Engine, candidates: &mut [Candidate], commands: bool) {
+    if !engine.options.frecency || candidates.len() < 2 {
+        return;
+    }
+
+    let Ok(frecency) = Frecency::from_history(engine.history.as_mut()) else {
+        return;
+    };
+
+    let score = |candidate: &Candidate| match commands {
+        true => frecency.command(&candidate.value),
+        false => frecency.word(&candidate.value),
+    };
+
+    // the sort is stable, so equally scored candidates stay in alphabetical order
+    candidates.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+/// Completes a word against the files in the directory it refers to, only
+/// executables and directories if it is a command. The directory is kept as
+/// typed, with a leading `~` expanded only when searching, while the names of
+/// the files are quoted to continue it, see `quote_name`.
+fn complete_path(word: &str, executables: bool) -> Vec<Candidate> {
+    // split the word at its last slash, both as typed and with its quotes
+    // removed, which is what the file system is searched with
+    let mut text = String::with_capacity(word.len());
+    let (mut dir_end, mut text_end) = (0, 0);
+    for (i, c, quoting) in quote::scan(word) {
+        if quoting == Quoting::Syntax {
+            continue;
+        }
+        text.push(c);
+        if c == '/' {
+            (dir_end, text_end) = (i + 1, text.len());
+        }
+    }
+    let file_prefix = &text[text_end..];
+
+    // the names continue the quote left open at the end of the word, which
+    // may have been opened or closed after the directory
+    let open = quote::open_quote(word);
+    let mut dir = word[..dir_end].to_string();
+    let dir_open = quote::open_quote(&dir);
+    if dir_open != open {
+        dir += quote_char(dir_open);
+        dir += quote_char(open);
+    }
+
+    let search_dir = match &text[..text_end] {
+        "" => PathBuf::from("."),
+        search_dir if word.starts_with('~') => path::expand_tilde_in_dir(search_dir),
+        search_dir => PathBuf::from(search_dir),
+    };
+
+    let Ok(entries) = std::fs::read_dir(search_dir) else {
+        return Vec::new();
+    };
+
+    let mut candidates = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with(file_prefix)
+            || (name.starts_with('.') && !file_prefix.starts_with('.'))
+        {
+            continue;
+        }
+
+        let quoted = quote_name(&name, open, dir.is_empty());
+        let closed = format!("{dir}{quoted}{}", quote_char(open));
+
+        let path = entry.path();
+        if path.is_dir() {
+            candidates.push(Candidate {
+                value: format!("{dir}{quoted}/"),
+                annotation: "directory".to_string(),
+                terminated: false,
+                path: Some(path),
+            });
+        } else if is_executable(&path) {
+            candidates.push(Candidate {
+                value: closed,
+                annotation: "executable".to_string(),
+                terminated: true,
+                path: Some(path),
+            });
+        } else if !executables {
+            candidates.push(Candidate {
+                value: closed,
+                annotation: "file".to_string(),
+                terminated: true,
+                path: Some(path),
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+/// Quotes the file name `name` so that it is read back as is after `open`,
+/// the quote left open by the word it completes. `start` is whether it starts
+/// that word, where a `~` would be expanded. Each character is quoted on its
+/// own, so that the quoted names still share the prefixes of the names.
+fn quote_name(name: &str, open: QuoteState, start: bool) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "%+,-./:=@_~".contains(c);
+
+    let mut quoted = String::with_capacity(name.len());
+    for (i, c) in name.char_indices() {
+        match open {
+            QuoteState::Single if c == '\'' => quoted.push_str("'\\''"),
+            QuoteState::Double if matches!(c, '"' | '\\' | '$' | '`') => {
+                quoted.push_str(&format!("\"\\{c}\""));
+            }
+            // a backslash before a newline would remove it instead
+            QuoteState::None if c == '\n' => quoted.push_str("'\n'"),
+            QuoteState::None if !plain(c) || (c == '~' && start && i == 0) => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+/// The character that opens or closes `quote`.
+fn quote_char(quote: QuoteState) -> &'static str {
+    match quote {
+        QuoteState::Single => "'",
+        QuoteState::Double => "\"",
+        QuoteState::None => "",
+    }
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::prelude::PermissionsExt;
+
+    match path.metadata() {
+        Ok(metadata) => metadata.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use psh_core::engine::history::DummyHistory;
+    use psh_core::test_util::TempDir;
+
+    use super::*;
+
+    fn candidate(value: &str) -> Candidate {
+        Candidate {
+            value: value.to_string(),
+            annotation: String::new(),
+            terminated: true,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn common_prefix() {
+        let completion = Completion {
+            start: 0,
+            candidates: vec![candidate("foobar"), candidate("foobaz"), candidate("foo")],
+        };
+        assert_eq!("foo", completion.common_prefix());
+
+        let completion = Completion {
+            start: 0,
+            candidates: vec![candidate("åäö"), candidate("åäx")],
+        };
+        assert_eq!("åä", completion.common_prefix());
+    }
+
+    #[test]
+    fn command_position() {
+        assert!(is_command_position(""));
+        assert!(is_command_position("  "));
+        assert!(is_command_position("echo foo | "));
+        assert!(is_command_position("echo foo;"));
+        assert!(is_command_position("foo=bar "));
+        assert!(is_command_position(": && a=1 b=2 "));
+        assert!(!is_command_position("echo "));
+        assert!(!is_command_position("echo foo=bar "));
+    }
+
+    #[test]
+    fn command_names() {
+        assert_eq!(vec!["git"], command_words("git "));
+        assert_eq!(vec!["git", "checkout"], command_words("A=1 git checkout "));
+        assert_eq!(vec!["svc"], command_words("echo a | svc "));
+        assert_eq!(vec!["svc", "start"], command_words("echo a; svc start "));
+        assert!(command_words("").is_empty());
+        assert!(command_words("ls && ").is_empty());
+    }
+
+    #[test]
+    fn specs() {
+        let dir = TempDir::new("spec");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("site.conf"), "").unwrap();
+        std::fs::write(dir.join("site.txt"), "").unwrap();
+
+        let spec = CompletionSpec {
+            words: vec!["start".to_string(), "stop".to_string()],
+            files: Files::Matching("*.conf".to_string()),
+            function: None,
+            command: None,
+        };
+        let prefix = format!("{}/s", dir.display());
+        let values = complete_with(&spec, Vec::new(), &prefix)
+            .into_iter()
+            .map(|c| c.value.strip_prefix(&prefix).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["ite.conf", "ub/"], values);
+
+        let values = complete_with(&spec, Vec::new(), "st")
+            .into_iter()
+            .map(|c| c.value)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["start", "stop"], values);
+
+        // the files are left out once a function has offered something
+        let replies = vec!["sync".to_string()];
+        let values = complete_with(&spec, replies, &prefix)
+            .into_iter()
+            .map(|c| c.value)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["sync"], values);
+    }
+
+    #[test]
+    fn paths() {
+        let dir = TempDir::new("completion");
+        std::fs::create_dir_all(dir.join("subdir")).unwrap();
+        std::fs::write(dir.join("sub.txt"), "").unwrap();
+        std::fs::write(dir.join(".subhidden"), "").unwrap();
+
+        let word = format!("{}/sub", dir.display());
+        let values = |candidates: Vec<Candidate>| {
+            candidates
+                .into_iter()
+                .map(|c| c.value.strip_prefix(&word).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![".txt", "dir/"], values(complete_path(&word, false)));
+        assert_eq!(vec!["dir/"], values(complete_path(&word, true)));
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(Some(0), parameter_start("$PA"));
+        assert_eq!(Some(1), parameter_start("a$"));
+        assert_eq!(Some(1), parameter_start("\"${PA"));
+        assert_eq!(None, parameter_start("'$PA"));
+        assert_eq!(None, parameter_start("\\$PA"));
+        assert_eq!(None, parameter_start("\"\\$PA"));
+        assert_eq!(None, parameter_start("$PATH/"));
+
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine.variables.set("PSH_COMPLETED", "").unwrap();
+        let values = |word| {
+            complete_parameter(&engine, word)
+                .into_iter()
+                .map(|c| (c.value, c.terminated))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![("$PSH_COMPLETED".to_string(), true)],
+            values("$PSH_COMPL")
+        );
+        assert_eq!(
+            vec![("\"${PSH_COMPLETED}".to_string(), false)],
+            values("\"${PSH_COMPL")
+        );
+    }
+
+    #[test]
+    fn users_and_directories() {
+        assert!(is_user_prefix("~"));
+        assert!(is_user_prefix("~ro"));
+        assert!(!is_user_prefix("~root/"));
+        let values = complete_user("~roo").into_iter().map(|c| c.value);
+        assert!(values.collect::<Vec<_>>().contains(&"~root/".to_string()));
+
+        let dir = TempDir::new("cd");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub.txt"), "").unwrap();
+
+        let engine = Engine::with_history(Box::new(DummyHistory));
+        let prefix = format!("{}/s", dir.display());
+        let values = complete_directory(&engine, &prefix)
+            .into_iter()
+            .map(|c| c.value.strip_prefix(&prefix).unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["ub/"], values);
+    }
+
+    #[test]
+    fn names_are_quoted() {
+        let dir = TempDir::new("quoting");
+        for name in ["my dir", "it's", "new\nline"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+        std::fs::write(dir.join("$HOME.txt"), "").unwrap();
+
+        let base = format!("{}/", dir.display());
+        let values = |word: &str| {
+            complete_path(&format!("{base}{word}"), false)
+                .into_iter()
+                .map(|c| c.value.strip_prefix(&base).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["my\\ dir/"], values("m"));
+        assert_eq!(vec!["my\\ dir/"], values("my\\ "));
+        assert_eq!(vec!["\"my dir/"], values("\"my"));
+        assert_eq!(vec!["it\\'s/"], values("it"));
+        assert_eq!(vec!["'it'\\''s/"], values("'it"));
+        assert_eq!(vec!["new'\n'line/"], values("new"));
+        assert_eq!(vec!["\\$HOME.txt"], values("$"));
+        assert_eq!(vec!["\"\"\\$\"HOME.txt\""], values("\"$"));
+    }
+}
@@ -0,0 +1,384 @@
+//! Moving over the input line by characters and words, and the kill ring
+//! that text cut from it is kept in, to be put back with ^Y or `p`. The
+//! positions on the line are byte indices, always at a character boundary.
+//! A character is moved over together with the ones combined with it, such
+//! as accents, since they are shown as one.
+
+use psh_core::engine::quote::{self, Quoting};
+
+use crate::repl::input::keymap::Action;
+use crate::repl::input::render::combines;
+
+/// The amount of killed texts kept.
+const KILL_RING_SIZE: usize = 16;
+
+/// The index of the character before `index`, or 0 at the start.
+pub fn prev_char(line: &str, index: usize) -> usize {
+    line[..index]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !combines(c))
+        .map_or(0, |(i, _)| i)
+}
+
+/// The index of the character after the one at `index`, or the end of the
+/// line.
+pub fn next_char(line: &str, index: usize) -> usize {
+    line[index..]
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| !combines(c))
+        .map_or(line.len(), |(i, _)| index + i)
+}
+
+/// Whether `c` is part of a word, which is made up of letters and digits.
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || combines(c)
+}
+
+/// The start of the word before `index`.
+pub fn backward_word(line: &str, index: usize) -> usize {
+    let mut chars = line[..index].char_indices().rev().peekable();
+    while chars.next_if(|&(_, c)| !is_word(c)).is_some() {}
+    let mut start = chars.peek().map_or(0, |&(i, _)| i);
+    while let Some((i, _)) = chars.next_if(|&(_, c)| is_word(c)) {
+        start = i;
+    }
+    start
+}
+
+/// The end of the word after `index`.
+pub fn forward_word(line: &str, index: usize) -> usize {
+    let mut chars = line[index..].char_indices().peekable();
+    while chars.next_if(|&(_, c)| !is_word(c)).is_some() {}
+    while chars.next_if(|&(_, c)| is_word(c)).is_some() {}
+    chars.peek().map_or(line.len(), |&(i, _)| index + i)
+}
+
+/// The start of the whitespace-separated word before `index`.
+pub fn unix_word_start(line: &str, index: usize) -> usize {
+    let before = line[..index].trim_end();
+    before
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| next_char(before, i))
+}
+
+/// The start of the shell word before `index`, right after the last blank
+/// or operator before it that is not quoted, so that `my\ dir` and `"my dir`
+/// are single words. This is the word that is completed, or expanded as an
+/// abbreviation.
+pub fn shell_word_start(line: &str, index: usize) -> usize {
+    quote::scan(&line[..index])
+        .into_iter()
+        .rev()
+        .find(|&(_, c, quoting)| separates(c, quoting))
+        .map_or(0, |(i, c, _)| i + c.len_utf8())
+}
+
+/// The start of the current or previous shell word before `index`, where
+/// shell words end as in `shell_word_start`.
+pub fn shell_backward_word(line: &str, index: usize) -> usize {
+    let scanned = quote::scan(line);
+    let mut chars = scanned
+        .iter()
+        .rev()
+        .skip_while(|&&(i, ..)| i >= index)
+        .peekable();
+    while chars
+        .next_if(|&&(_, c, quoting)| separates(c, quoting))
+        .is_some()
+    {}
+    let mut start = chars.peek().map_or(0, |&&(i, ..)| i);
+    while let Some(&(i, ..)) = chars.next_if(|&&(_, c, quoting)| !separates(c, quoting)) {
+        start = i;
+    }
+    start
+}
+
+/// The end of the current or next shell word after `index`.
+pub fn shell_forward_word(line: &str, index: usize) -> usize {
+    let scanned = quote::scan(line);
+    let mut chars = scanned.iter().skip_while(|&&(i, ..)| i < index).peekable();
+    while chars
+        .next_if(|&&(_, c, quoting)| separates(c, quoting))
+        .is_some()
+    {}
+    while chars
+        .next_if(|&&(_, c, quoting)| !separates(c, quoting))
+        .is_some()
+    {}
+    chars.peek().map_or(line.len(), |&&(i, ..)| i)
+}
+
+/// Whether `c`, quoted as `quoting`, ends a shell word, as blanks and
+/// operators do unless they are quoted.
+fn separates(c: char, quoting: Quoting) -> bool {
+    quoting == Quoting::Unquoted && (c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | ')'))
+}
+
+/// The index of the first character that is not whitespace, or the end of
+/// the line.
+pub fn first_non_blank(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The kinds of characters that Vi makes up words of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        c if c.is_whitespace() => Class::Blank,
+        c if is_word(c) || c == '_' => Class::Word,
+        _ => Class::Punctuation,
+    }
+}
+
+/// The start of the next word after `index`, as moved to with `w`.
+pub fn vi_forward_word(line: &str, index: usize) -> usize {
+    let mut chars = line[index..].char_indices().peekable();
+    if let Some(&(_, c)) = chars.peek() {
+        let start = class(c);
+        if start != Class::Blank {
+            while chars.next_if(|&(_, c)| class(c) == start).is_some() {}
+        }
+    }
+    while chars.next_if(|&(_, c)| class(c) == Class::Blank).is_some() {}
+    chars.peek().map_or(line.len(), |&(i, _)| index + i)
+}
+
+/// The start of the word before `index`, as moved to with `b`.
+pub fn vi_backward_word(line: &str, index: usize) -> usize {
+    let mut chars = line[..index].char_indices().rev().peekable();
+    while chars.next_if(|&(_, c)| class(c) == Class::Blank).is_some() {}
+    let Some(&(mut start, c)) = chars.peek() else {
+        return 0;
+    };
+    let word = class(c);
+    while let Some((i, _)) = chars.next_if(|&(_, c)| class(c) == word) {
+        start = i;
+    }
+    start
+}
+
+/// The last character of the word that ends after `index`, as moved to
+/// with `e`.
+pub fn vi_end_of_word(line: &str, index: usize) -> usize {
+    let mut chars = line[index..].char_indices().skip(1).peekable();
+    while chars.next_if(|&(_, c)| class(c) == Class::Blank).is_some() {}
+    let Some(&(mut end, c)) = chars.peek() else {
+        return prev_char(line, line.len());
+    };
+    let word = class(c);
+    while let Some((i, _)) = chars.next_if(|&(_, c)| class(c) == word) {
+        end = i;
+    }
+    index + end
+}
+
+/// Where the motion `action` moves the cursor at `index` to, or `None` if
+/// it is not a motion.
+pub fn motion(action: Action, line: &str, index: usize) -> Option<usize> {
+    Some(match action {
+        Action::BackwardChar => prev_char(line, index),
+        Action::ForwardChar => next_char(line, index),
+        Action::BackwardWord => backward_word(line, index),
+        Action::ForwardWord => forward_word(line, index),
+        Action::ShellBackwardWord => shell_backward_word(line, index),
+        Action::ShellForwardWord => shell_forward_word(line, index),
+        Action::ViForwardWord => vi_forward_word(line, index),
+        Action::ViBackwardWord => vi_backward_word(line, index),
+        Action::ViEndOfWord => vi_end_of_word(line, index),
+        Action::ViFirstNonBlank => first_non_blank(line),
+        Action::BeginningOfLine => 0,
+        Action::EndOfLine => line.len(),
+        _ => return None,
+    })
+}
+
+/// Swaps the character before `index` with the one at it, or the two
+/// before it at the end of the line, returning where the cursor goes after
+/// them, or `None` if there are not two characters to swap.
+pub fn transpose_chars(line: &mut String, index: usize) -> Option<usize> {
+    let index = match index == line.len() {
+        true => prev_char(line, index),
+        false => index,
+    };
+    if index == 0 {
+        return None;
+    }
+
+    let start = prev_char(line, index);
+    let end = next_char(line, index);
+    let (first, second) = line[start..end].split_at(index - start);
+    let swapped = format!("{second}{first}");
+    line.replace_range(start..end, &swapped);
+    Some(end)
+}
+
+/// The texts killed from the line, the most recent last.
+#[derive(Debug, Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+
+    /// The text inserted by the last yank, as the range of the line it is
+    /// in and the index of its entry, so that it can be replaced with the
+    /// entry before it.
+    yanked: Option<(usize, usize, usize)>,
+}
+
+impl KillRing {
+    /// Keeps `text`, killed from the line, added to the last text killed if
+    /// `append` is set: after it if it was killed `forward`, and otherwise
+    /// before it.
+    pub fn kill(&mut self, text: &str, append: bool, forward: bool) {
+        if text.is_empty() {
+            return;
+        }
+        match self.entries.last_mut() {
+            Some(last) if append && forward => last.push_str(text),
+            Some(last) if append => last.insert_str(0, text),
+            _ => {
+                if self.entries.len() == KILL_RING_SIZE {
+                    self.entries.remove(0);
+                }
+                self.entries.push(text.to_string());
+            }
+        }
+    }
+
+    /// Inserts the text killed last at `index`, returning where it ends.
+    pub fn yank(&mut self, line: &mut String, index: usize) -> usize {
+        let Some(text) = self.entries.last() else {
+            return index;
+        };
+        line.insert_str(index, text);
+        let end = index + text.len();
+        self.yanked = Some((index, end, self.entries.len() - 1));
+        end
+    }
+
+    /// Replaces the text just yanked with the text killed before it,
+    /// returning where it ends, or `None` if nothing was yanked.
+    pub fn yank_pop(&mut self, line: &mut String) -> Option<usize> {
+        let (start, end, entry) = self.yanked?;
+        let entry = entry.checked_sub(1).unwrap_or(self.entries.len() - 1);
+        let text = &self.entries[entry];
+        line.replace_range(start..end, text);
+        let end = start + text.len();
+        self.yanked = Some((start, end, entry));
+        Some(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_by_words() {
+        let line = "git commit --amend  -m 'fix'";
+        assert_eq!(4, backward_word(line, 10));
+        assert_eq!(4, backward_word(line, 8));
+        assert_eq!(4, backward_word(line, 13));
+        assert_eq!(10, forward_word(line, 4));
+        assert_eq!(18, forward_word(line, 10));
+        assert_eq!(line.len(), forward_word(line, 27));
+        assert_eq!(11, unix_word_start(line, 18));
+        assert_eq!(11, unix_word_start(line, 20));
+        assert_eq!(0, unix_word_start(line, 3));
+
+        assert_eq!(11, vi_forward_word(line, 4));
+        assert_eq!(13, vi_forward_word(line, 11));
+        assert_eq!(20, vi_forward_word(line, 13));
+        assert_eq!(13, vi_backward_word(line, 20));
+        assert_eq!(11, vi_backward_word(line, 13));
+        assert_eq!(9, vi_end_of_word(line, 4));
+        assert_eq!(12, vi_end_of_word(line, 9));
+        assert_eq!(line.len() - 1, vi_end_of_word(line, line.len() - 1));
+        assert_eq!(2, first_non_blank("  ls"));
+    }
+
+    #[test]
+    fn shell_words() {
+        assert_eq!(0, shell_word_start("ech", 3));
+        assert_eq!(5, shell_word_start("echo fo", 7));
+        assert_eq!(9, shell_word_start("echo foo|ca", 11));
+        assert_eq!(3, shell_word_start("cd my\\ di", 9));
+        assert_eq!(3, shell_word_start("cd \"my di", 9));
+        assert_eq!(3, shell_word_start("cd 'a;b", 7));
+        assert_eq!(10, shell_word_start("ñö;ä\u{3000}åx", 13));
+        assert_eq!(5, shell_word_start("echo 'ä b", 10));
+
+        let line = "cd \"my dir\" && ls my\\ dir|wc";
+        assert_eq!(3, shell_backward_word(line, 12));
+        assert_eq!(3, shell_backward_word(line, 8));
+        assert_eq!(0, shell_backward_word(line, 3));
+        assert_eq!(18, shell_backward_word(line, line.len() - 3));
+        assert_eq!(11, shell_forward_word(line, 3));
+        assert_eq!(17, shell_forward_word(line, 12));
+        assert_eq!(25, shell_forward_word(line, 18));
+        assert_eq!(line.len(), shell_forward_word(line, 25));
+    }
+
+    #[test]
+    fn moving_by_words_over_multibyte_characters() {
+        let line = "åäö  ñé-ü";
+        assert_eq!(8, backward_word(line, 12));
+        assert_eq!(0, backward_word(line, 8));
+        assert_eq!(6, forward_word(line, 0));
+        assert_eq!(12, forward_word(line, 6));
+        assert_eq!(8, unix_word_start(line, line.len()));
+        assert_eq!(0, unix_word_start(line, 8));
+        assert_eq!(8, vi_forward_word(line, 0));
+        assert_eq!(12, vi_forward_word(line, 8));
+        assert_eq!(4, vi_end_of_word(line, 0));
+    }
+
+    #[test]
+    fn combining_characters_are_moved_over() {
+        // the `e` of `café` is followed by a combining accent
+        let line = "cafe\u{301} 語";
+        assert_eq!(3, prev_char(line, 6));
+        assert_eq!(6, next_char(line, 3));
+        assert_eq!(7, next_char(line, 6));
+        assert_eq!(line.len(), next_char(line, 7));
+        assert_eq!(0, backward_word(line, 6));
+        assert_eq!(6, forward_word(line, 0));
+    }
+
+    #[test]
+    fn transposing() {
+        let mut line = "sl -a".to_string();
+        assert_eq!(Some(2), transpose_chars(&mut line, 1));
+        assert_eq!("ls -a", line);
+        assert_eq!(Some(5), transpose_chars(&mut line, 5));
+        assert_eq!("ls a-", line);
+        assert_eq!(None, transpose_chars(&mut line, 0));
+
+        let mut line = "ñx".to_string();
+        assert_eq!(Some(3), transpose_chars(&mut line, 2));
+        assert_eq!("xñ", line);
+    }
+
+    #[test]
+    fn killing_and_yanking() {
+        let mut ring = KillRing::default();
+        ring.kill("one", false, true);
+        ring.kill("two", false, true);
+        ring.kill(" three", true, true);
+        ring.kill("zero ", true, false);
+
+        let mut line = "> <".to_string();
+        assert_eq!(16, ring.yank(&mut line, 2));
+        assert_eq!("> zero two three<", line);
+        assert_eq!(Some(5), ring.yank_pop(&mut line));
+        assert_eq!("> one<", line);
+        assert_eq!(Some(16), ring.yank_pop(&mut line));
+        assert_eq!("> zero two three<", line);
+    }
+}
@@ -0,0 +1,177 @@
+//! History expansion, as in csh and bash: `!!` is the previous command,
+//! `!$` its last word, `!42` the 42nd entry of the history, `!-2` the
+//! command before the previous one, and `!git` the most recent command
+//! starting with `git`.
+
+use psh_core::engine::history::History;
+use psh_core::engine::quote::{self, QuoteState, Quoting};
+use psh_core::{Error, Result};
+
+/// An entry of the history that a `!` refers to.
+#[derive(Debug, PartialEq, Eq)]
+enum Event<'a> {
+    /// `!!`, or `!-n` for the `n`th command back.
+    Back(usize),
+
+    /// `!$`, the last word of the previous command.
+    LastWord,
+
+    /// `!n`, counting from 1 for the oldest entry.
+    Number(usize),
+
+    /// `!prefix`.
+    Prefix(&'a str),
+}
+
+/// Expands the references to the history in `line`, returning `None` if it
+/// has none. A `!` is left as it is in single quotes, after a backslash, or
+/// when it is not followed by a reference, as in `! cmd` or `[ a != b ]`.
+/// Fails if no entry matches a reference.
+pub fn expand(line: &str, history: &mut dyn History) -> Result<Option<String>> {
+    let mut expanded = String::with_capacity(line.len());
+    let mut copied = 0;
+
+    for (i, c, quoting) in quote::scan(line) {
+        if c != '!' || i < copied || !expands(line, i, quoting) {
+            continue;
+        }
+        let Some((event, len)) = parse(&line[i + 1..]) else {
+            continue;
+        };
+
+        let reference = &line[i..i + 1 + len];
+        let entry = find(history, &event)?.ok_or_else(|| Error::EventNotFound(reference.into()))?;
+        expanded.push_str(&line[copied..i]);
+        match event {
+            Event::LastWord => expanded.push_str(last_word(&entry)),
+            _ => expanded.push_str(&entry),
+        }
+        copied = i + 1 + len;
+    }
+
+    if copied == 0 {
+        return Ok(None);
+    }
+    expanded.push_str(&line[copied..]);
+    Ok(Some(expanded))
+}
+
+/// Whether the `!` at byte index `i` of `line` can start a reference.
+fn expands(line: &str, i: usize, quoting: Quoting) -> bool {
+    let before = line[..i].chars().next_back();
+    let after = line[i + 1..].chars().next();
+
+    // `$!` is the pid of the last background job, and `[!a]` a pattern
+    if matches!(before, Some('\\' | '$' | '[')) {
+        return false;
+    }
+    if after.is_none_or(|c| c.is_whitespace() || matches!(c, '=' | '(' | '"')) {
+        return false;
+    }
+    quoting == Quoting::Unquoted || quote::open_quote(&line[..i]) == QuoteState::Double
+}
+
+/// Parses the reference after a `!`, returning it and its length.
+fn parse(s: &str) -> Option<(Event<'_>, usize)> {
+    let number = |s: &str| {
+        let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..len].parse::<usize>().ok()?, len))
+    };
+
+    match s.chars().next()? {
+        '!' => Some((Event::Back(1), 1)),
+        '$' => Some((Event::LastWord, 1)),
+        '-' => {
+            let (n, len) = number(&s[1..])?;
+            Some((Event::Back(n), 1 + len))
+        }
+        c if c.is_ascii_digit() => {
+            let (n, len) = number(s)?;
+            Some((Event::Number(n), len))
+        }
+        _ => {
+            let len = s
+                .find(|c: char| c.is_whitespace() || ";&|()<>'\"`$".contains(c))
+                .unwrap_or(s.len());
+            (len > 0).then(|| (Event::Prefix(&s[..len]), len))
+        }
+    }
+}
+
+/// The entry of the history that `event` refers to, if any.
+fn find(history: &mut dyn History, event: &Event) -> Result<Option<String>> {
+    let back = match *event {
+        Event::Back(0) | Event::Number(0) => return Ok(None),
+        Event::Back(n) => n,
+        Event::LastWord => 1,
+        Event::Number(n) => return Ok(history.read_lines()?.into_iter().nth(n - 1)),
+        Event::Prefix(prefix) => {
+            let mut entries = history.search(prefix)?;
+            return entries
+                .find(|entry| {
+                    entry
+                        .as_ref()
+                        .map_or(true, |entry| entry.starts_with(prefix))
+                })
+                .transpose();
+        }
+    };
+    history.search("")?.nth(back - 1).transpose()
+}
+
+/// The last word of `entry`, with its quotes.
+fn last_word(entry: &str) -> &str {
+    let entry = entry.trim_end();
+    let start = quote::scan(entry)
+        .into_iter()
+        .rev()
+        .find(|(_, c, quoting)| c.is_whitespace() && *quoting == Quoting::Unquoted)
+        .map_or(0, |(i, c, _)| i + c.len_utf8());
+    &entry[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use psh_core::engine::history::FileHistory;
+    use psh_core::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn references() {
+        assert_eq!(Some((Event::Back(1), 1)), parse("!"));
+        assert_eq!(Some((Event::Back(2), 2)), parse("-2 foo"));
+        assert_eq!(Some((Event::Number(42), 2)), parse("42;"));
+        assert_eq!(Some((Event::LastWord, 1)), parse("$"));
+        assert_eq!(Some((Event::Prefix("git"), 3)), parse("git|"));
+        assert_eq!(None, parse("-x"));
+        assert_eq!(None, parse(";"));
+
+        assert_eq!("'b c'", last_word("a 'b c' "));
+        assert_eq!("a", last_word("a"));
+    }
+
+    #[test]
+    fn expanding() {
+        let dir = TempDir::new("bang");
+        let path = dir.join("history");
+        fs::write(&path, "git status\nls -l 'a dir'\necho hi\n").unwrap();
+        let mut history = FileHistory::open(&path).unwrap();
+        let mut expand = |line| expand(line, &mut history).map_err(|e| e.to_string());
+
+        assert_eq!(Ok(Some("echo hi | wc".to_string())), expand("!! | wc"));
+        assert_eq!(Ok(Some("ls -l 'a dir'".to_string())), expand("!-2"));
+        assert_eq!(Ok(Some("cat hi".to_string())), expand("cat !$"));
+        assert_eq!(Ok(Some("git status -s".to_string())), expand("!1 -s"));
+        assert_eq!(Ok(Some("ls -l 'a dir'".to_string())), expand("!ls"));
+        assert_eq!(Ok(Some("echo \"hi\"".to_string())), expand("echo \"!$\""));
+
+        assert_eq!(Ok(None), expand("echo '!!' \\!! $! [!a] ! true"));
+        assert_eq!(Ok(None), expand("[ a != b ] && echo \"wow!\""));
+
+        assert_eq!(Err("!nope: event not found".to_string()), expand("!nope"));
+        assert_eq!(Err("!9: event not found".to_string()), expand("!9"));
+    }
+}
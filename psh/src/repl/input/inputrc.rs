@@ -0,0 +1,619 @@
+//! The part of readline's `inputrc` that the line editor understands, so
+//! that keys bound for other shells work the same in psh: `set
+//! editing-mode`, `set keymap`, the bindings of single keys to the functions
+//! that have an `Action`, and the `$if`, `$else`, `$endif` and `$include`
+//! directives. Anything else is skipped with a warning.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use psh_core::{path, Engine};
+
+use super::keymap::{Action, Keymap};
+use super::Editor;
+
+/// How deep `$include` directives can be nested, so that a file including
+/// itself does not loop forever.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// The readline functions that the line editor has, with their actions.
+const FUNCTIONS: &[(&str, Action)] = &[
+    ("abort", Action::Cancel),
+    ("accept-line", Action::Accept),
+    ("backward-char", Action::BackwardChar),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("backward-kill-line", Action::BackwardKillLine),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("backward-word", Action::BackwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("clear-screen", Action::ClearScreen),
+    ("complete", Action::Complete),
+    ("delete-char", Action::DeleteChar),
+    ("end-of-file", Action::EndOfFile),
+    ("end-of-line", Action::EndOfLine),
+    ("forward-char", Action::ForwardChar),
+    ("forward-word", Action::ForwardWord),
+    ("kill-line", Action::KillLine),
+    ("kill-word", Action::KillWord),
+    ("next-history", Action::NextHistory),
+    ("previous-history", Action::PreviousHistory),
+    ("quoted-insert", Action::QuotedInsert),
+    ("reverse-search-history", Action::ReverseSearch),
+    ("self-insert", Action::SelfInsert),
+    ("shell-backward-kill-word", Action::ShellBackwardKillWord),
+    ("shell-backward-word", Action::ShellBackwardWord),
+    ("shell-forward-word", Action::ShellForwardWord),
+    ("shell-kill-word", Action::ShellKillWord),
+    ("transpose-chars", Action::TransposeChars),
+    ("unix-line-discard", Action::BackwardKillLine),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("vi-movement-mode", Action::ViCommandMode),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+];
+
+/// `$INPUTRC`, or `~/.inputrc`.
+pub fn file() -> PathBuf {
+    match env::var("INPUTRC") {
+        Ok(file) => path::expand_tilde_in_dir(&file),
+        Err(_) => PathBuf::from(path::home_dir()).join(".inputrc"),
+    }
+}
+
+/// Reads the inputrc, if there is one, into the keymaps of `editor` and the
+/// editing mode of `engine`, printing a warning for every line that is not
+/// understood.
+pub fn load(engine: &mut Engine, editor: &mut Editor) {
+    let file = file();
+    let contents = match fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => {
+            eprintln!("psh: {}: {e}", file.display());
+            return;
+        }
+    };
+
+    let mut loader = Loader::new(engine.options.vi);
+    loader.read(editor, &file, &contents);
+    for warning in &loader.warnings {
+        eprintln!("psh: {warning}");
+    }
+    engine.options.vi = loader.vi;
+    engine.options.emacs = !loader.vi;
+}
+
+/// Which of the keymaps of the editor keys are bound in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Which {
+    Emacs,
+    ViInsert,
+    ViNormal,
+}
+
+impl Which {
+    fn of(editor: &mut Editor, which: Which) -> &mut Keymap {
+        match which {
+            Which::Emacs => &mut editor.emacs,
+            Which::ViInsert => &mut editor.vi_insert,
+            Which::ViNormal => &mut editor.vi_normal,
+        }
+    }
+}
+
+struct Loader {
+    /// Whether Vi mode is used, as last set with `set editing-mode`.
+    vi: bool,
+    keymap: Which,
+
+    /// For each `$if` the current line is in, whether the lines around it
+    /// are read, and whether its condition holds.
+    conditions: Vec<(bool, bool)>,
+    depth: usize,
+    warnings: Vec<String>,
+}
+
+impl Loader {
+    fn new(vi: bool) -> Self {
+        Self {
+            vi,
+            keymap: match vi {
+                true => Which::ViInsert,
+                false => Which::Emacs,
+            },
+            conditions: Vec::new(),
+            depth: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn read(&mut self, editor: &mut Editor, file: &Path, contents: &str) {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = self.line(editor, line) {
+                self.warnings
+                    .push(format!("{}:{}: {e}", file.display(), i + 1));
+            }
+        }
+    }
+
+    /// Whether the lines are read, rather than skipped by an `$if`.
+    fn active(&self) -> bool {
+        self.conditions
+            .last()
+            .is_none_or(|&(outer, holds)| outer && holds)
+    }
+
+    fn line(&mut self, editor: &mut Editor, line: &str) -> Result<(), String> {
+        if let Some(directive) = line.strip_prefix('$') {
+            return self.directive(editor, directive);
+        }
+        if !self.active() {
+            return Ok(());
+        }
+
+        match line.split_once(char::is_whitespace) {
+            Some(("set", setting)) => self.set(setting.trim()),
+            _ => self.bind(editor, line),
+        }
+    }
+
+    fn directive(&mut self, editor: &mut Editor, directive: &str) -> Result<(), String> {
+        let (name, arg) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        let arg = arg.trim();
+
+        match name {
+            // a condition that is not understood does not hold, so that
+            // its `$else` and `$endif` still match it
+            "if" => {
+                let holds = self.holds(arg);
+                self.conditions
+                    .push((self.active(), *holds.as_ref().unwrap_or(&false)));
+                holds?;
+            }
+
+            "else" => match self.conditions.last_mut() {
+                Some((_, holds)) => *holds = !*holds,
+                None => return Err("`$else` without `$if`.".to_string()),
+            },
+
+            "endif" => {
+                if self.conditions.pop().is_none() {
+                    return Err("`$endif` without `$if`.".to_string());
+                }
+            }
+
+            "include" if self.active() => {
+                if self.depth == MAX_INCLUDE_DEPTH {
+                    return Err(format!("'{arg}': Included too deeply."));
+                }
+                let file = path::expand_tilde_in_dir(arg);
+                let contents = fs::read_to_string(&file).map_err(|e| format!("'{arg}': {e}"))?;
+
+                // the conditions of the included file end with it
+                let conditions = std::mem::take(&mut self.conditions);
+                self.depth += 1;
+                self.read(editor, &file, &contents);
+                self.depth -= 1;
+                self.conditions = conditions;
+            }
+            "include" => {}
+
+            _ => return Err(format!("'${name}': Unknown directive.")),
+        }
+        Ok(())
+    }
+
+    /// Whether the condition of `$if` holds: the editing mode, the terminal
+    /// or the application being psh.
+    fn holds(&self, condition: &str) -> Result<bool, String> {
+        if let Some(mode) = condition.strip_prefix("mode=") {
+            return match mode {
+                "emacs" => Ok(!self.vi),
+                "vi" => Ok(self.vi),
+                _ => Err(format!("'{mode}': Unknown editing mode.")),
+            };
+        }
+        if let Some(term) = condition.strip_prefix("term=") {
+            let current = env::var("TERM").unwrap_or_default();
+            let base = current.split('-').next().unwrap_or_default();
+            return Ok(current == term || base == term);
+        }
+        if condition.contains(char::is_whitespace) || condition.contains('=') {
+            return Err(format!("'{condition}': Unsupported condition."));
+        }
+        Ok(condition.eq_ignore_ascii_case("psh"))
+    }
+
+    fn set(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = setting
+            .split_once(char::is_whitespace)
+            .unwrap_or((setting, ""));
+        let value = value.trim();
+
+        match name.to_ascii_lowercase().as_str() {
+            "editing-mode" => {
+                (self.vi, self.keymap) = match value {
+                    "emacs" => (false, Which::Emacs),
+                    "vi" => (true, Which::ViInsert),
+                    _ => return Err(format!("'{value}': Unknown editing mode.")),
+                };
+            }
+
+            "keymap" => {
+                self.keymap = match value {
+                    "emacs" | "emacs-standard" => Which::Emacs,
+                    "vi" | "vi-command" | "vi-move" => Which::ViNormal,
+                    "vi-insert" => Which::ViInsert,
+                    _ => return Err(format!("'{value}': Unknown or unsupported keymap.")),
+                };
+            }
+
+            _ => return Err(format!("'{name}': Unsupported setting.")),
+        }
+        Ok(())
+    }
+
+    /// Binds a key to a function, as in `"\C-a": beginning-of-line` or
+    /// `Meta-b: backward-word`.
+    fn bind(&mut self, editor: &mut Editor, line: &str) -> Result<(), String> {
+        let (keys, sent, function) = match line.strip_prefix('"') {
+            Some(quoted) => {
+                let end = closing_quote(quoted).ok_or("Missing closing `\"`.")?;
+                let function = quoted[end + 1..]
+                    .trim_start()
+                    .strip_prefix(':')
+                    .ok_or("Missing `:`.")?;
+                let keys = &quoted[..end];
+                (keys, unescape(keys), function)
+            }
+
+            None => {
+                let (keys, function) = line.split_once(':').ok_or("Missing `:`.")?;
+                let keys = keys.trim();
+                (keys, named(keys), function)
+            }
+        };
+
+        let sent = sent.ok_or_else(|| format!("'{keys}': Unknown key."))?;
+        let (code, modifiers) =
+            key(&sent).ok_or_else(|| format!("'{keys}': Only single keys can be bound."))?;
+
+        let function = function.trim();
+        if function.starts_with(['"', '\'']) {
+            return Err("Binding keys to macros is not supported.".to_string());
+        }
+        let function = function.split_whitespace().next().unwrap_or_default();
+        let action = FUNCTIONS
+            .iter()
+            .find(|(name, _)| *name == function)
+            .map(|&(_, action)| action)
+            .ok_or_else(|| format!("'{function}': Unknown or unsupported function."))?;
+
+        Which::of(editor, self.keymap).bind(code, modifiers, action);
+        Ok(())
+    }
+}
+
+/// The index of the `"` that ends the quoted text `s`.
+fn closing_quote(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' if !escaped => return Some(i),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// The characters a terminal sends for a quoted key sequence, such as
+/// `\C-a` or `\e[A`.
+fn unescape(s: &str) -> Option<Vec<char>> {
+    let mut chars = s.chars().peekable();
+    let mut sent = Vec::new();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            sent.push(c);
+            continue;
+        }
+
+        let c = match chars.next()? {
+            'C' if chars.next_if_eq(&'-').is_some() => control(chars.next()?)?,
+            'M' if chars.next_if_eq(&'-').is_some() => '\x1b',
+            'e' => '\x1b',
+            'a' => '\x07',
+            'b' => '\x08',
+            'd' => '\x7f',
+            'f' => '\x0c',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\x0b',
+            digit @ '0'..='7' => {
+                let mut code = digit.to_digit(8)?;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => code = code * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                char::from_u32(code)?
+            }
+            c => c,
+        };
+        sent.push(c);
+    }
+
+    Some(sent)
+}
+
+/// The characters a terminal sends for a key given by name, such as
+/// `Control-a`, `M-DEL` or `TAB`.
+fn named(name: &str) -> Option<Vec<char>> {
+    let mut name = name;
+    let mut ctrl = false;
+    let mut meta = false;
+
+    while let Some((prefix, rest)) = name.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+        match prefix.to_ascii_lowercase().as_str() {
+            "c" | "control" => ctrl = true,
+            "m" | "meta" => meta = true,
+            _ => break,
+        }
+        name = rest;
+    }
+
+    let mut c = match name.to_ascii_lowercase().as_str() {
+        _ if name.chars().count() == 1 => name.chars().next()?,
+        "del" | "rubout" => '\x7f',
+        "esc" | "escape" => '\x1b',
+        "lfd" | "newline" => '\n',
+        "ret" | "return" => '\r',
+        "spc" | "space" => ' ',
+        "tab" => '\t',
+        _ => return None,
+    };
+    if ctrl {
+        c = control(c)?;
+    }
+
+    Some(match meta {
+        true => vec!['\x1b', c],
+        false => vec![c],
+    })
+}
+
+/// The character sent for `c` pressed along with Control.
+fn control(c: char) -> Option<char> {
+    match c {
+        '?' => Some('\x7f'),
+        '@'..='_' | 'a'..='z' => Some((c.to_ascii_uppercase() as u8 ^ 0x40) as char),
+        _ => None,
+    }
+}
+
+/// The key that `sent` is sent for, as the reverse of `keymap::literal`, if
+/// it is a single one.
+fn key(sent: &[char]) -> Option<(KeyCode, KeyModifiers)> {
+    let key = match sent {
+        ['\t'] => (KeyCode::Tab, KeyModifiers::NONE),
+        ['\r' | '\n'] => (KeyCode::Enter, KeyModifiers::NONE),
+        ['\x1b'] => (KeyCode::Esc, KeyModifiers::NONE),
+        ['\x7f'] => (KeyCode::Backspace, KeyModifiers::NONE),
+        ['\0'] => (KeyCode::Char(' '), KeyModifiers::CONTROL),
+        [c] if c.is_ascii_control() => {
+            let c = (*c as u8 ^ 0x40).to_ascii_lowercase() as char;
+            (KeyCode::Char(c), KeyModifiers::CONTROL)
+        }
+        [c] => (KeyCode::Char(*c), KeyModifiers::NONE),
+        ['\x1b', '[' | 'O', rest @ ..] if !rest.is_empty() => escape_sequence(rest)?,
+
+        // Meta sends an escape before the key
+        ['\x1b', rest @ ..] => match key(rest)? {
+            (code, modifiers) if !modifiers.contains(KeyModifiers::ALT) => {
+                (code, modifiers | KeyModifiers::ALT)
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The key of an escape sequence such as `\e[A` or `\e[1;5C`, without the
+/// `\e[` or `\eO` it starts with.
+fn escape_sequence(seq: &[char]) -> Option<(KeyCode, KeyModifiers)> {
+    let (&last, params) = seq.split_last()?;
+    let params: String = params.iter().collect();
+    let mut params = params.split(';');
+    let number = params.next().unwrap_or_default();
+
+    // the modifiers are sent as one more than the sum of 1 for Shift, 2 for
+    // Alt and 4 for Control
+    let modifiers = match params.next() {
+        Some(n) => {
+            let bits = n.parse::<u8>().ok()?.checked_sub(1)?;
+            let mut modifiers = KeyModifiers::NONE;
+            for (bit, modifier) in [
+                (1, KeyModifiers::SHIFT),
+                (2, KeyModifiers::ALT),
+                (4, KeyModifiers::CONTROL),
+            ] {
+                if bits & bit != 0 {
+                    modifiers |= modifier;
+                }
+            }
+            modifiers
+        }
+        None => KeyModifiers::NONE,
+    };
+    if params.next().is_some() {
+        return None;
+    }
+
+    let code = match (last, number) {
+        ('A', "" | "1") => KeyCode::Up,
+        ('B', "" | "1") => KeyCode::Down,
+        ('C', "" | "1") => KeyCode::Right,
+        ('D', "" | "1") => KeyCode::Left,
+        ('H', "" | "1") | ('~', "1" | "7") => KeyCode::Home,
+        ('F', "" | "1") | ('~', "4" | "8") => KeyCode::End,
+        ('~', "2") => KeyCode::Insert,
+        ('~', "3") => KeyCode::Delete,
+        ('~', "5") => KeyCode::PageUp,
+        ('~', "6") => KeyCode::PageDown,
+        _ => return None,
+    };
+    Some((code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quoted(s: &str) -> Option<(KeyCode, KeyModifiers)> {
+        key(&unescape(s)?)
+    }
+
+    fn load(vi: bool, contents: &str) -> (Editor, Loader) {
+        let mut editor = Editor::default();
+        let mut loader = Loader::new(vi);
+        loader.read(&mut editor, Path::new("inputrc"), contents);
+        (editor, loader)
+    }
+
+    #[test]
+    fn keys() {
+        let ctrl = KeyModifiers::CONTROL;
+        let alt = KeyModifiers::ALT;
+        assert_eq!(Some((KeyCode::Char('a'), ctrl)), quoted(r"\C-a"));
+        assert_eq!(Some((KeyCode::Char('b'), alt)), quoted(r"\M-b"));
+        assert_eq!(Some((KeyCode::Char('b'), alt)), quoted(r"\eb"));
+        assert_eq!(Some((KeyCode::Backspace, alt)), quoted(r"\e\C-?"));
+        assert_eq!(Some((KeyCode::Backspace, alt)), quoted(r"\e\177"));
+        assert_eq!(Some((KeyCode::Up, KeyModifiers::NONE)), quoted(r"\e[A"));
+        assert_eq!(Some((KeyCode::Home, KeyModifiers::NONE)), quoted(r"\eOH"));
+        assert_eq!(Some((KeyCode::Right, ctrl)), quoted(r"\e[1;5C"));
+        assert_eq!(
+            Some((KeyCode::Delete, KeyModifiers::NONE)),
+            quoted(r"\e[3~")
+        );
+        assert_eq!(Some((KeyCode::Tab, KeyModifiers::NONE)), quoted(r"\t"));
+        assert_eq!(None, quoted(r"\C-x\C-e"));
+
+        let named = |name| key(&named(name)?);
+        assert_eq!(Some((KeyCode::Char('u'), ctrl)), named("Control-u"));
+        assert_eq!(Some((KeyCode::Char('f'), alt)), named("Meta-f"));
+        assert_eq!(Some((KeyCode::Char('w'), ctrl | alt)), named("M-C-w"));
+        assert_eq!(Some((KeyCode::Backspace, alt)), named("M-Rubout"));
+        assert_eq!(Some((KeyCode::Char('-'), KeyModifiers::NONE)), named("-"));
+        assert_eq!(Some((KeyCode::Tab, KeyModifiers::NONE)), named("TAB"));
+        assert_eq!(None, named("Hyper-x"));
+    }
+
+    #[test]
+    fn bindings() {
+        let (editor, loader) = load(
+            false,
+            r#"
+            # comment
+            "\C-w": backward-kill-word
+            Meta-Rubout: unix-word-rubout
+            "\e[A": previous-history
+            set keymap vi-command
+            "\C-e": end-of-line
+            "#,
+        );
+        assert!(loader.warnings.is_empty(), "{:?}", loader.warnings);
+
+        let emacs = &editor.emacs;
+        assert_eq!(
+            Some(Action::BackwardKillWord),
+            emacs.action(KeyCode::Char('w'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Some(Action::UnixWordRubout),
+            emacs.action(KeyCode::Backspace, KeyModifiers::ALT)
+        );
+        assert_eq!(
+            Some(Action::EndOfLine),
+            editor
+                .vi_normal
+                .action(KeyCode::Char('e'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            None,
+            editor
+                .vi_insert
+                .action(KeyCode::Char('e'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn editing_mode_and_conditions() {
+        let (editor, loader) = load(
+            false,
+            r#"
+            set editing-mode vi
+            $if mode=vi
+            "\C-a": beginning-of-line
+            $else
+            "\C-a": end-of-line
+            $endif
+            $if Bash
+            "\C-b": backward-char
+            $endif
+            "#,
+        );
+        assert!(loader.vi);
+        assert!(loader.warnings.is_empty(), "{:?}", loader.warnings);
+        assert_eq!(
+            Some(Action::BeginningOfLine),
+            editor
+                .vi_insert
+                .action(KeyCode::Char('a'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            None,
+            editor
+                .vi_insert
+                .action(KeyCode::Char('b'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let (_, loader) = load(
+            false,
+            r#"
+            set bell-style none
+            "\C-x\C-e": accept-line
+            "\C-t": "macro"
+            "\C-o": operate-and-get-next
+            $endif
+            "#,
+        );
+        assert_eq!(
+            vec![
+                "inputrc:2: 'bell-style': Unsupported setting.",
+                r"inputrc:3: '\C-x\C-e': Only single keys can be bound.",
+                "inputrc:4: Binding keys to macros is not supported.",
+                "inputrc:5: 'operate-and-get-next': Unknown or unsupported function.",
+                "inputrc:6: `$endif` without `$if`.",
+            ],
+            loader.warnings
+        );
+    }
+}
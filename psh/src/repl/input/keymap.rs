@@ -0,0 +1,402 @@
+//! The keys of the line editor and what they do, in Emacs mode and in the
+//! insert and normal modes of Vi mode, as chosen with `set -o emacs` and
+//! `set -o vi`.
+
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// What a key does to the line being edited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Execute the line, or continue it on the next one if it is
+    /// incomplete.
+    Accept,
+    Cancel,
+
+    /// Exit on an empty line, and otherwise delete the character under the
+    /// cursor.
+    EndOfFile,
+    ClearScreen,
+    Complete,
+    PreviousHistory,
+    NextHistory,
+    ReverseSearch,
+
+    /// Watch the line, or the last command if the line is empty.
+    Watch,
+
+    /// Insert the character typed.
+    SelfInsert,
+
+    /// Insert a space, expanding the abbreviation before it.
+    InsertSpace,
+
+    /// Insert a space, and stop abbreviations from expanding.
+    InsertLiteralSpace,
+
+    /// Insert the next key as the character it stands for, such as a tab or
+    /// an escape character, rather than doing what it is bound to.
+    QuotedInsert,
+
+    BackwardChar,
+    ForwardChar,
+
+    /// Move to the start of the current or previous word, made up of
+    /// letters and digits.
+    BackwardWord,
+
+    /// Move to the end of the current or next word.
+    ForwardWord,
+
+    /// Like `BackwardWord`, for words as the shell splits them, so that
+    /// `"my dir"` is one word and `a&&b` three.
+    ShellBackwardWord,
+    ShellForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+
+    BackwardDeleteChar,
+    DeleteChar,
+
+    /// Kill from the cursor to the end of the line.
+    KillLine,
+
+    /// Kill from the start of the line to the cursor.
+    BackwardKillLine,
+    KillWord,
+    BackwardKillWord,
+    ShellKillWord,
+    ShellBackwardKillWord,
+
+    /// Kill from the cursor back to the previous whitespace.
+    UnixWordRubout,
+
+    /// Insert the most recently killed text.
+    Yank,
+
+    /// Replace the text just yanked with the text killed before it.
+    YankPop,
+
+    /// Swap the character before the cursor with the one under it, or the
+    /// two before it at the end of the line.
+    TransposeChars,
+
+    /// Switch from insert mode to normal mode.
+    ViCommandMode,
+    ViInsert,
+    ViAppend,
+    ViInsertAtStart,
+    ViAppendAtEnd,
+
+    /// Move to the start of the next word, where a word is either a run of
+    /// letters, digits and underscores, or of other characters that are
+    /// not whitespace.
+    ViForwardWord,
+    ViBackwardWord,
+    ViEndOfWord,
+    ViFirstNonBlank,
+
+    /// Delete the text that the next motion moves over, or the whole line
+    /// if the key is pressed twice, as in `dw` and `dd`.
+    ViDelete,
+
+    /// Like `ViDelete`, and then switch to insert mode.
+    ViChange,
+
+    /// Like `ViDelete`, but only copy the text.
+    ViYank,
+    ViDeleteToEnd,
+    ViChangeToEnd,
+    ViChangeLine,
+
+    /// Delete the character under the cursor, keeping it to be put back.
+    ViDeleteChar,
+    ViBackwardDeleteChar,
+
+    /// Put the text last deleted or copied after the cursor.
+    ViPut,
+    ViPutBefore,
+}
+
+impl Action {
+    /// Whether the action kills text, so that the text killed by the next
+    /// one is added to it rather than killed on its own.
+    pub fn kills(self) -> bool {
+        matches!(
+            self,
+            Self::KillLine
+                | Self::BackwardKillLine
+                | Self::KillWord
+                | Self::BackwardKillWord
+                | Self::ShellKillWord
+                | Self::ShellBackwardKillWord
+                | Self::UnixWordRubout
+        )
+    }
+}
+
+/// The actions bound to keys.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: HashMap<(KeyCode, KeyModifiers), Action>,
+
+    /// Whether characters that are not bound insert themselves.
+    self_insert: bool,
+}
+
+impl Keymap {
+    /// The keys of Emacs mode, which are those of Emacs and of readline.
+    pub fn emacs() -> Self {
+        use Action::*;
+
+        let mut keymap = Self::editing();
+        for (c, action) in [
+            ('a', BeginningOfLine),
+            ('b', BackwardChar),
+            ('d', EndOfFile),
+            ('e', EndOfLine),
+            ('f', ForwardChar),
+            ('h', BackwardDeleteChar),
+            ('k', KillLine),
+            ('n', NextHistory),
+            ('p', PreviousHistory),
+            ('r', ReverseSearch),
+            ('t', TransposeChars),
+            ('u', BackwardKillLine),
+            ('v', QuotedInsert),
+            ('w', UnixWordRubout),
+            ('y', Yank),
+            (' ', InsertLiteralSpace),
+        ] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::CONTROL, action);
+        }
+        for (c, action) in [
+            ('b', BackwardWord),
+            ('d', KillWord),
+            ('f', ForwardWord),
+            ('w', Watch),
+            ('y', YankPop),
+        ] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::ALT, action);
+        }
+        keymap.bind(KeyCode::Backspace, KeyModifiers::ALT, BackwardKillWord);
+        keymap
+    }
+
+    /// The keys of the insert mode of Vi mode, where Esc switches to normal
+    /// mode, and a few control keys edit the line like in Emacs mode, with
+    /// what they kill yanked by Ctrl-Y as in readline.
+    pub fn vi_insert() -> Self {
+        use Action::*;
+
+        let mut keymap = Self::editing();
+        for (c, action) in [
+            ('d', EndOfFile),
+            ('h', BackwardDeleteChar),
+            ('n', NextHistory),
+            ('p', PreviousHistory),
+            ('r', ReverseSearch),
+            ('u', BackwardKillLine),
+            ('v', QuotedInsert),
+            ('w', UnixWordRubout),
+            ('y', Yank),
+            (' ', InsertLiteralSpace),
+        ] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::CONTROL, action);
+        }
+        keymap.bind(KeyCode::Char('w'), KeyModifiers::ALT, Watch);
+        keymap.bind(KeyCode::Esc, KeyModifiers::NONE, ViCommandMode);
+        keymap
+    }
+
+    /// The keys of the normal mode of Vi mode, where letters move the
+    /// cursor and edit the line rather than being inserted.
+    pub fn vi_normal() -> Self {
+        use Action::*;
+
+        let mut keymap = Self::default();
+        for (c, action) in [
+            ('h', BackwardChar),
+            ('l', ForwardChar),
+            (' ', ForwardChar),
+            ('w', ViForwardWord),
+            ('b', ViBackwardWord),
+            ('e', ViEndOfWord),
+            ('0', BeginningOfLine),
+            ('^', ViFirstNonBlank),
+            ('$', EndOfLine),
+            ('k', PreviousHistory),
+            ('j', NextHistory),
+            ('i', ViInsert),
+            ('a', ViAppend),
+            ('I', ViInsertAtStart),
+            ('A', ViAppendAtEnd),
+            ('x', ViDeleteChar),
+            ('X', ViBackwardDeleteChar),
+            ('d', ViDelete),
+            ('c', ViChange),
+            ('y', ViYank),
+            ('D', ViDeleteToEnd),
+            ('C', ViChangeToEnd),
+            ('S', ViChangeLine),
+            ('p', ViPut),
+            ('P', ViPutBefore),
+            ('/', ReverseSearch),
+        ] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::NONE, action);
+        }
+        for (c, action) in [('c', Cancel), ('d', EndOfFile), ('l', ClearScreen)] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::CONTROL, action);
+        }
+        keymap.bind(KeyCode::Enter, KeyModifiers::NONE, Accept);
+        keymap.bind(KeyCode::Left, KeyModifiers::NONE, BackwardChar);
+        keymap.bind(KeyCode::Right, KeyModifiers::NONE, ForwardChar);
+        keymap.bind(KeyCode::Up, KeyModifiers::NONE, PreviousHistory);
+        keymap.bind(KeyCode::Down, KeyModifiers::NONE, NextHistory);
+        keymap.bind(KeyCode::Backspace, KeyModifiers::NONE, BackwardChar);
+        keymap
+    }
+
+    /// The keys that both Emacs mode and the insert mode of Vi mode have.
+    fn editing() -> Self {
+        use Action::*;
+
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+            self_insert: true,
+        };
+        for (code, action) in [
+            (KeyCode::Enter, Accept),
+            (KeyCode::Tab, Complete),
+            (KeyCode::Char(' '), InsertSpace),
+            (KeyCode::Backspace, BackwardDeleteChar),
+            (KeyCode::Delete, DeleteChar),
+            (KeyCode::Left, BackwardChar),
+            (KeyCode::Right, ForwardChar),
+            (KeyCode::Home, BeginningOfLine),
+            (KeyCode::End, EndOfLine),
+            (KeyCode::Up, PreviousHistory),
+            (KeyCode::Down, NextHistory),
+        ] {
+            keymap.bind(code, KeyModifiers::NONE, action);
+        }
+        keymap.bind(KeyCode::Left, KeyModifiers::CONTROL, ShellBackwardWord);
+        keymap.bind(KeyCode::Right, KeyModifiers::CONTROL, ShellForwardWord);
+        for (c, action) in [('c', Cancel), ('l', ClearScreen)] {
+            keymap.bind(KeyCode::Char(c), KeyModifiers::CONTROL, action);
+        }
+        keymap
+    }
+
+    /// Binds the key `code` pressed along with `modifiers` to `action`.
+    pub fn bind(&mut self, code: KeyCode, modifiers: KeyModifiers, action: Action) {
+        self.bindings.insert(key(code, modifiers), action);
+    }
+
+    /// The action bound to the key `code` pressed along with `modifiers`.
+    pub fn action(&self, code: KeyCode, modifiers: KeyModifiers) -> Option<Action> {
+        let key = key(code, modifiers);
+        match self.bindings.get(&key) {
+            Some(&action) => Some(action),
+            None if self.self_insert && key.1 == KeyModifiers::NONE => match code {
+                KeyCode::Char(_) => Some(Action::SelfInsert),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+}
+
+/// The character that the key `code` pressed along with `modifiers` sends
+/// to the terminal, as inserted after `QuotedInsert`, if it is a single one.
+pub fn literal(code: KeyCode, modifiers: KeyModifiers) -> Option<char> {
+    match code {
+        KeyCode::Char('?') if modifiers.contains(KeyModifiers::CONTROL) => Some('\x7f'),
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::CONTROL) => match c {
+            ' ' => Some('\0'),
+            '@'..='_' | 'a'..='z' => Some((c.to_ascii_uppercase() as u8 ^ 0x40) as char),
+            _ => None,
+        },
+        KeyCode::Char(c) if !modifiers.contains(KeyModifiers::ALT) => Some(c),
+        KeyCode::Tab => Some('\t'),
+        KeyCode::Enter => Some('\r'),
+        KeyCode::Esc => Some('\x1b'),
+        KeyCode::Backspace => Some('\x7f'),
+        _ => None,
+    }
+}
+
+/// A key as it is bound. Shift only matters to keys other than characters,
+/// which tell whether it was pressed themselves, as in `A`.
+fn key(code: KeyCode, modifiers: KeyModifiers) -> (KeyCode, KeyModifiers) {
+    match code {
+        KeyCode::Char(_) => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looking_up() {
+        let emacs = Keymap::emacs();
+        let action = |code, modifiers| emacs.action(code, modifiers);
+        assert_eq!(
+            Some(Action::Yank),
+            action(KeyCode::Char('y'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Some(Action::ForwardWord),
+            action(KeyCode::Char('f'), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            Some(Action::SelfInsert),
+            action(KeyCode::Char('A'), KeyModifiers::SHIFT)
+        );
+        assert_eq!(None, action(KeyCode::Char('q'), KeyModifiers::ALT));
+        assert_eq!(
+            Some(Action::ShellForwardWord),
+            action(KeyCode::Right, KeyModifiers::CONTROL)
+        );
+
+        let normal = Keymap::vi_normal();
+        assert_eq!(
+            Some(Action::ViAppendAtEnd),
+            normal.action(KeyCode::Char('A'), KeyModifiers::SHIFT)
+        );
+        assert_eq!(None, normal.action(KeyCode::Char('q'), KeyModifiers::NONE));
+        let insert = Keymap::vi_insert();
+        assert_eq!(
+            Some(Action::ViCommandMode),
+            insert.action(KeyCode::Esc, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            Some(Action::Yank),
+            insert.action(KeyCode::Char('y'), KeyModifiers::CONTROL)
+        );
+    }
+
+    #[test]
+    fn literal_keys() {
+        assert_eq!(Some('\t'), literal(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(Some('\x1b'), literal(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(
+            Some('\x01'),
+            literal(KeyCode::Char('a'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Some('\x1b'),
+            literal(KeyCode::Char('['), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Some('\x7f'),
+            literal(KeyCode::Char('?'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(Some('A'), literal(KeyCode::Char('A'), KeyModifiers::SHIFT));
+        assert_eq!(None, literal(KeyCode::Char('b'), KeyModifiers::ALT));
+        assert_eq!(None, literal(KeyCode::Up, KeyModifiers::NONE));
+    }
+}
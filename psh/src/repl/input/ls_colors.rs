@@ -0,0 +1,149 @@
+//! A parser for `$LS_COLORS`, used to color file names in the completion
+//! menu the way `ls` does.
+
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+
+/// The colors used by `ls` when `$LS_COLORS` is not set.
+const DEFAULT: &str = "di=01;34:ln=01;36:so=01;35:pi=40;33:bd=40;33;01:cd=40;33;01:ex=01;32";
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LsColors {
+    /// The SGR parameters of file types, keyed by their two letter names
+    /// such as `di` for directories.
+    types: HashMap<String, String>,
+
+    /// The SGR parameters of file names ending in a suffix, e.g. `*.tar`.
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    /// Parses the value of `$LS_COLORS`, or the defaults of `ls` if `None`.
+    /// Entries that cannot be parsed are ignored.
+    pub fn new(ls_colors: Option<&str>) -> Self {
+        let mut colors = Self::default();
+
+        for entry in ls_colors.unwrap_or(DEFAULT).split(':') {
+            let Some((key, sgr)) = entry.split_once('=') else {
+                continue;
+            };
+
+            match key.strip_prefix('*') {
+                Some(suffix) => colors.suffixes.push((suffix.to_string(), sgr.to_string())),
+                None => {
+                    colors.types.insert(key.to_string(), sgr.to_string());
+                }
+            }
+        }
+
+        colors
+    }
+
+    /// The SGR parameters that the file at `path` is shown with, if any.
+    pub fn style(&self, path: &Path) -> Option<&str> {
+        let metadata = fs::symlink_metadata(path).ok()?;
+
+        if metadata.is_symlink() {
+            return match fs::metadata(path) {
+                Err(_) => self.get("or").or_else(|| self.get("ln")),
+                Ok(target) if self.get("ln") == Some("target") => {
+                    self.style_of(&fs::canonicalize(path).ok()?, &target)
+                }
+                Ok(_) => self.get("ln"),
+            };
+        }
+
+        self.style_of(path, &metadata)
+    }
+
+    fn style_of(&self, path: &Path, metadata: &Metadata) -> Option<&str> {
+        let file_type = metadata.file_type();
+        let mode = metadata.permissions().mode();
+
+        let key = if file_type.is_dir() {
+            match (mode & 0o1000 != 0, mode & 0o002 != 0) {
+                (true, true) => "tw",
+                (false, true) => "ow",
+                (true, false) => "st",
+                (false, false) => "di",
+            }
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else if mode & 0o4000 != 0 {
+            "su"
+        } else if mode & 0o2000 != 0 {
+            "sg"
+        } else if mode & 0o111 != 0 {
+            "ex"
+        } else {
+            let name = path.file_name()?.to_string_lossy();
+            return self
+                .suffixes
+                .iter()
+                .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+                .map(|(_, sgr)| sgr.as_str())
+                .or_else(|| self.get("fi"));
+        };
+
+        // the more specific kinds of directories and executables fall back
+        // to the general ones
+        let fallback = match key {
+            "tw" | "ow" | "st" => "di",
+            "su" | "sg" => "ex",
+            _ => key,
+        };
+        self.get(key).or_else(|| self.get(fallback))
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.types
+            .get(key)
+            .map(String::as_str)
+            .filter(|sgr| !sgr.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use psh_core::test_util::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn styles_by_type_and_suffix() {
+        let dir = TempDir::new("ls-colors");
+
+        let archive = dir.join("a.tar");
+        let script = dir.join("run.tar");
+        fs::write(&archive, "").unwrap();
+        fs::write(&script, "").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = symlink(&archive, dir.join("link"));
+        let _ = symlink(dir.join("missing"), dir.join("orphan"));
+
+        let colors = LsColors::new(Some("di=34:ln=36:ex=32:or=31:*.tar=01;31:invalid"));
+        assert_eq!(Some("34"), colors.style(&dir));
+        assert_eq!(Some("01;31"), colors.style(&archive));
+        assert_eq!(Some("32"), colors.style(&script));
+        assert_eq!(Some("36"), colors.style(&dir.join("link")));
+        assert_eq!(Some("31"), colors.style(&dir.join("orphan")));
+        assert_eq!(None, colors.style(&dir.join("nonexistent")));
+
+        let colors = LsColors::new(Some("ln=target:*.tar=33"));
+        assert_eq!(Some("33"), colors.style(&dir.join("link")));
+
+        let colors = LsColors::new(None);
+        assert_eq!(Some("01;34"), colors.style(&dir));
+        assert_eq!(None, colors.style(&archive));
+    }
+}
@@ -1,7 +1,20 @@
+mod cancel;
+mod completion;
+mod editing;
+mod history;
+pub mod inputrc;
+pub mod keymap;
+mod ls_colors;
+mod render;
+mod segments;
 mod syntax_highlighting;
+mod watch;
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::io::{stderr, stdout};
+use std::mem;
+use std::ops::Range;
 
 use crossterm::cursor;
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
@@ -11,28 +24,77 @@ use crossterm::style;
 use crossterm::terminal;
 
 use psh_core::ast::parse;
+use psh_core::engine::abbreviation::{self, Abbreviation};
 use psh_core::engine::expand::expand_prompt;
+use psh_core::engine::prompt::{END_INVISIBLE, START_INVISIBLE};
+use psh_core::engine::quote;
 use psh_core::{Engine, Error, Result};
 
 use crate::config::{self, Colors};
 use crate::repl::input::syntax_highlighting::Highlighter;
 use crate::repl::RawMode;
 
+use self::cancel::CancelOnInput;
+use self::editing::KillRing;
+use self::keymap::{Action, Keymap};
+use self::ls_colors::LsColors;
+use self::render::{Frame, Layout};
+use self::segments::Segments;
 use self::syntax_highlighting::Context;
 
-pub fn read_full_command(engine: &mut Engine) -> Result<String> {
+/// How long the input can be, in bytes, before it is edited in a simpler
+/// way: it is no longer highlighted, abbreviations are not expanded, and only
+/// the part around the cursor is shown. Reparsing and redrawing all of it on
+/// every key would freeze the editor when e.g. a binary file is pasted.
+const LONG_LINE: usize = 16 * 1024;
+
+/// How many columns are left for the input after the prompt, at least. The
+/// last line of a prompt that is longer is cut short at its start.
+const MIN_INPUT_COLUMNS: usize = 20;
+
+/// A command read from the user.
+pub struct Input {
+    pub line: String,
+
+    /// The column the line started at, right after the prompt.
+    pub column: u16,
+}
+
+/// What the line editor keeps from one line to the next.
+#[derive(Debug)]
+pub struct Editor {
+    pub emacs: Keymap,
+    pub vi_insert: Keymap,
+    pub vi_normal: Keymap,
+    pub segments: Segments,
+    kill_ring: KillRing,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            emacs: Keymap::emacs(),
+            vi_insert: Keymap::vi_insert(),
+            vi_normal: Keymap::vi_normal(),
+            segments: Segments::default(),
+            kill_ring: KillRing::default(),
+        }
+    }
+}
+
+pub fn read_full_command(engine: &mut Engine, editor: &mut Editor) -> Result<Input> {
     let _raw = RawMode::init()?;
 
-    prompt(engine, false)?;
+    let prompt_rows = prompt(engine, &mut editor.segments, false)?;
 
     let start_pos = cursor::position()?;
-    let mut line = read_line(engine, true, start_pos, None)?;
+    let mut line = read_line(engine, editor, true, start_pos, None)?;
 
     'outer: while let Err(Error::Incomplete(_)) = parse(&line, false) {
         line.push('\n');
 
-        prompt(engine, true)?;
-        match read_line(engine, false, start_pos, Some(&line)) {
+        prompt(engine, &mut editor.segments, true)?;
+        match read_line(engine, editor, false, start_pos, Some(&line)) {
             Ok(l) => line += &l,
             Err(Error::CancelledLine) => {
                 line = String::new();
@@ -42,10 +104,55 @@ pub fn read_full_command(engine: &mut Engine) -> Result<String> {
         }
     }
 
-    Ok(line)
+    let column = match engine.options.transient_prompt && !line.is_empty() {
+        true => transient_prompt(engine, &line, prompt_rows)?,
+        false => start_pos.0,
+    };
+
+    Ok(Input { line, column })
 }
 
-fn prompt(engine: &mut Engine, ps2: bool) -> Result<()> {
+/// Redraws the prompt of the entered `line` as `$PSH_TRANSIENT_PROMPT`,
+/// followed by the line, where the prompt took up `rows` rows above the one
+/// the line was entered on. Returns the column the line now starts at.
+fn transient_prompt(engine: &mut Engine, line: &str, rows: u16) -> Result<u16> {
+    // the cursor is on the row after the line, each line of which was
+    // entered on a row of its own
+    let (_, y) = cursor::position()?;
+    let entered = line.matches('\n').count() as u16 + 1;
+    execute!(
+        stdout(),
+        cursor::MoveTo(0, y.saturating_sub(entered + rows)),
+        terminal::Clear(terminal::ClearType::FromCursorDown)
+    )?;
+
+    let prompt = engine
+        .get_value_of("PSH_TRANSIENT_PROMPT")
+        .unwrap_or_else(|| config::TRANSIENT_PROMPT.to_string());
+    print_prompt(engine, &prompt, false)?;
+
+    let mut state = State::new()?;
+    let start_pos = state.start_pos;
+    state.line = line.to_string();
+    state.index = line.len();
+    state.about_to_exit = true;
+    state.expand_abbreviations = false;
+    write_highlighted_ast(engine, &mut state, start_pos, None)?;
+    execute!(stdout(), style::Print("\r\n"))?;
+
+    Ok(start_pos.0)
+}
+
+/// Prints the prompt of the `segments`, or `PS1` if there are none, or `PS2`
+/// if `ps2`, returning how many rows it takes up above the one the input
+/// starts on.
+fn prompt(engine: &mut Engine, segments: &mut Segments, ps2: bool) -> Result<u16> {
+    if !ps2 {
+        if let Some(prompt) = segments.prompt(engine) {
+            return print_expanded_prompt(engine, &prompt, false);
+        }
+    }
+
     let prompt = if ps2 {
         engine
             .get_value_of("PS2")
@@ -55,7 +162,12 @@ fn prompt(engine: &mut Engine, ps2: bool) -> Result<()> {
             .get_value_of("PS1")
             .unwrap_or_else(|| config::PS1_USER_PROMPT.to_string())
     };
+    print_prompt(engine, &prompt, ps2)
+}
 
+/// Expands and prints `prompt`, or only its last line if `last_only`,
+/// returning how many rows the lines before it take up.
+fn print_prompt(engine: &mut Engine, prompt: &str, last_only: bool) -> Result<u16> {
     use psh_core::parser::ast::Parser;
     use psh_core::parser::tok::Tokenizer;
     let prompt = format!("\"{prompt}\"");
@@ -67,19 +179,111 @@ fn prompt(engine: &mut Engine, ps2: bool) -> Result<()> {
         .peekable()
         .parse_word(true)?;
     let word = expand_prompt(word, engine)?;
-    let word = &word[1..word.len() - 1];
+    print_expanded_prompt(engine, &word[1..word.len() - 1], last_only)
+}
 
+/// Prints `prompt`, whose expansions and escapes were replaced, as
+/// `print_prompt` does.
+fn print_expanded_prompt(engine: &Engine, prompt: &str, last_only: bool) -> Result<u16> {
     let color = Colors::prompt(engine);
+    let (columns, _) = terminal::size()?;
+    let mut lines = prompt_lines(prompt, columns as usize);
+
+    // `PS2` is shown on each row that continues the line, so only its last
+    // line is
+    if last_only {
+        lines.drain(..lines.len() - 1);
+    }
 
+    let mut stderr = stderr();
     queue!(
-        stderr(),
+        stderr,
         cursor::MoveToColumn(0),
-        style::SetForegroundColor(color),
-        style::Print(word),
-        style::ResetColor,
+        style::SetForegroundColor(color)
     )?;
+    for (i, line) in lines.iter().enumerate() {
+        // the terminal is in raw mode, where a newline does not return to
+        // the start of the row
+        if i > 0 {
+            queue!(stderr, style::Print("\r\n"))?;
+        }
+        queue!(
+            stderr,
+            style::Print(line.replace([START_INVISIBLE, END_INVISIBLE], ""))
+        )?;
+    }
+    queue!(stderr, style::ResetColor)?;
 
-    Ok(())
+    Ok(rows_above(&lines, columns as usize))
+}
+
+/// How many rows of a terminal with `columns` columns the `lines` of a
+/// prompt take up before the last one, which is never wrapped.
+fn rows_above(lines: &[Cow<'_, str>], columns: usize) -> u16 {
+    let rows: usize = lines[..lines.len() - 1]
+        .iter()
+        .map(|line| {
+            render::width(&visible(line))
+                .div_ceil(columns.max(1))
+                .max(1)
+        })
+        .sum();
+    rows as u16
+}
+
+/// The lines of `prompt`, with the last one, after which the input starts,
+/// cut short so that at least `MIN_INPUT_COLUMNS` of the `columns` of the
+/// terminal are left for the input.
+fn prompt_lines(prompt: &str, columns: usize) -> Vec<Cow<'_, str>> {
+    let mut lines: Vec<_> = prompt.split('\n').map(Cow::Borrowed).collect();
+
+    let max = columns.saturating_sub(MIN_INPUT_COLUMNS).max(1);
+    let last = lines.last_mut().expect("split yields at least one line");
+    let width = render::width(&visible(last));
+    if width > max {
+        // the end is kept, since that is where e.g. `$ ` is, and so is what
+        // is invisible in the rest, since that is where e.g. colors are set
+        let mut excess = width + 1 - max;
+        let mut invisible = false;
+        let mut hidden = String::new();
+        let mut start = last.len();
+        for (i, c) in last.char_indices() {
+            if excess == 0 && !invisible {
+                start = i;
+                break;
+            }
+            invisible |= c == START_INVISIBLE;
+            if invisible || c == END_INVISIBLE {
+                hidden.push(c);
+            } else {
+                let width = render::width(c.encode_utf8(&mut [0; 4]));
+                excess = excess.saturating_sub(width);
+            }
+            invisible &= c != END_INVISIBLE;
+        }
+        *last = Cow::Owned(format!("{hidden}…{}", &last[start..]));
+    }
+
+    lines
+}
+
+/// What of a line of a prompt is shown on the terminal, leaving out what is
+/// between `\[` and `\]`.
+fn visible(line: &str) -> String {
+    let mut invisible = false;
+    line.chars()
+        .filter(|&c| match c {
+            START_INVISIBLE => {
+                invisible = true;
+                false
+            }
+            END_INVISIBLE => {
+                invisible = false;
+                false
+            }
+            _ => !invisible,
+        })
+        .collect()
 }
 
 struct State {
@@ -106,52 +310,170 @@ struct State {
 
     /// Will be `false` if the user inputs '^ ', which will make abbreviations not expand.
     expand_abbreviations: bool,
+
+    /// The amount of rows below the input line occupied by the completion menu.
+    menu_rows: u16,
+
+    /// The frame currently on the terminal, and where it was drawn.
+    rendered: Option<(Layout, Frame)>,
+
+    /// Where drawing the last frame left the cursor.
+    cursor_pos: Option<(u16, u16)>,
+
+    /// The last ambiguous completion, if nothing but Tab has been pressed
+    /// since.
+    cycle: Option<Cycle>,
+
+    /// The search through the history started with ^R, if any.
+    search: Option<Search>,
+
+    /// Whether Vi mode is in normal mode, where keys move the cursor and
+    /// edit the line rather than being inserted.
+    normal: bool,
+
+    /// The Vi operator, such as `d`, waiting for the motion it applies to.
+    operator: Option<Action>,
+
+    /// The action of the last key pressed.
+    last_action: Option<Action>,
+
+    /// Whether the next key is inserted as it is, after ^V.
+    quoted: bool,
+}
+
+/// An ambiguous completion, whose candidates are inserted in turn by
+/// pressing Tab repeatedly.
+struct Cycle {
+    completion: completion::Completion,
+
+    /// The candidate currently inserted, `None` before the first one is.
+    current: Option<usize>,
+
+    /// The byte index in the line where the inserted text ends.
+    end: usize,
+}
+
+/// An incremental search backwards through the history. The line shows the
+/// current match, and the query is shown below it.
+struct Search {
+    query: String,
+
+    /// The entries matching `query` that have not been looked at yet, the
+    /// most recent first.
+    matches: Box<dyn Iterator<Item = Result<String>>>,
+
+    /// The entries shown so far, so that duplicates are skipped.
+    shown: HashSet<String>,
+
+    /// Whether nothing (more) matches `query`.
+    failed: bool,
+
+    /// The line from before the search, restored if it is cancelled.
+    original: String,
+}
+
+impl Search {
+    fn start(engine: &mut Engine, line: &str) -> Result<Self> {
+        Ok(Self {
+            query: String::new(),
+            matches: engine.history.search("")?,
+            shown: HashSet::new(),
+            failed: false,
+            original: line.to_string(),
+        })
+    }
+
+    /// Searches from the most recent entry again, after the query changed.
+    fn restart(&mut self, engine: &mut Engine) -> Result<()> {
+        self.matches = engine.history.search(&self.query)?;
+        self.shown.clear();
+        Ok(())
+    }
+
+    /// The next older entry matching the query.
+    fn next_match(&mut self) -> Result<Option<String>> {
+        if self.query.is_empty() {
+            return Ok(None);
+        }
+        for entry in self.matches.by_ref() {
+            let entry = entry?;
+            if self.shown.insert(entry.clone()) {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl State {
+    /// The state of an empty line, starting where the cursor is.
+    fn new() -> Result<Self> {
+        Ok(Self {
+            line: Default::default(),
+            index: 0,
+            start_pos: cursor::position()?,
+            size: terminal::size()?,
+            about_to_exit: false,
+            cancelled: false,
+            cleared: false,
+            expand_abbreviations: true,
+            menu_rows: 0,
+            rendered: None,
+            cursor_pos: None,
+            cycle: None,
+            search: None,
+            normal: false,
+            operator: None,
+            last_action: None,
+            quoted: false,
+        })
+    }
+
     fn pos(&self) -> Result<(u16, u16)> {
         Ok(cursor::position()?)
     }
 
-    fn next_pos(&self) -> cursor::MoveTo {
-        let (sx, sy) = self.start_pos;
-
-        let (cx, _) = self.pos().unwrap_or((sx, sy));
-        let (width, _) = self.size;
-
-        let mut x = sx + self.index as u16;
-        let mut y = sy;
+    /// Removes `range` from the line into `kill_ring`, as in
+    /// `KillRing::kill`.
+    fn kill(&mut self, kill_ring: &mut KillRing, range: Range<usize>, append: bool, forward: bool) {
+        kill_ring.kill(&self.line[range.clone()], append, forward);
+        self.line.replace_range(range.clone(), "");
+        self.index = range.start;
+    }
 
-        if cx == width {
-            x = sx;
-            y = sy + 1;
+    /// Keeps the cursor on a character in normal mode, where it cannot be
+    /// past the end of the line.
+    fn clamp_index(&mut self) {
+        if self.normal && self.index == self.line.len() {
+            self.index = editing::prev_char(&self.line, self.index);
         }
-
-        cursor::MoveTo(x, y)
     }
 }
 
 fn read_line(
     engine: &mut Engine,
+    editor: &mut Editor,
     ps1: bool,
-    start_pos: (u16, u16),
+    mut start_pos: (u16, u16),
     old_line: Option<&String>,
 ) -> Result<String> {
     let _raw = RawMode::init()?;
 
-    let mut state = State {
-        line: Default::default(),
-        index: 0,
-        start_pos: cursor::position()?,
-        size: terminal::size()?,
-        about_to_exit: false,
-        cancelled: false,
-        cleared: false,
-        expand_abbreviations: true,
-    };
+    let mut state = State::new()?;
+    let mut handoffs = engine.terminal_handoffs;
 
     while !state.about_to_exit {
-        write_highlighted_ast(engine, &state, start_pos, old_line)?;
+        // the positions are stale once a command run from the line, e.g. by
+        // a completion function, had the terminal
+        if engine.terminal_handoffs != handoffs {
+            handoffs = engine.terminal_handoffs;
+            redraw_after_output(engine, editor, &mut state, &mut start_pos)?;
+        }
+
+        write_highlighted_ast(engine, &mut state, start_pos, old_line)?;
+        if state.search.is_some() {
+            start_pos.1 -= show_search(engine, &mut state)?;
+        }
 
         execute!(stdout(), event::EnableBracketedPaste)?;
 
@@ -160,17 +482,15 @@ fn read_line(
         if let Event::Paste(s) = &event {
             state.line.insert_str(state.index, s);
             state.index += s.len();
+        }
 
-            execute!(
-                stdout(),
-                style::Print(&state.line[state.index - 1..]),
-                state.next_pos(),
-            )?;
+        execute!(stdout(), event::DisableBracketedPaste)?;
 
-            write_highlighted_ast(engine, &state, start_pos, old_line)?;
+        if state.menu_rows > 0 {
+            clear_menu(&mut state)?;
         }
 
-        execute!(stdout(), event::DisableBracketedPaste)?;
+        let cycle = state.cycle.take();
 
         let (code, modifiers) = match event {
             Event::Key(KeyEvent {
@@ -179,8 +499,38 @@ fn read_line(
             _ => continue,
         };
 
-        match (code, modifiers) {
-            (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+        if state.quoted {
+            state.quoted = false;
+            if let Some(c) = keymap::literal(code, modifiers) {
+                state.line.insert(state.index, c);
+                state.index += c.len_utf8();
+            }
+            continue;
+        }
+
+        if search_key(engine, &mut state, code, modifiers)? {
+            continue;
+        }
+
+        let keymap = match (engine.options.vi, state.normal) {
+            (false, _) => &editor.emacs,
+            (true, false) => &editor.vi_insert,
+            (true, true) => &editor.vi_normal,
+        };
+        let Some(action) = keymap.action(code, modifiers) else {
+            continue;
+        };
+        let last_action = state.last_action.replace(action);
+        let append = last_action.is_some_and(Action::kills) && action.kills();
+
+        if let Some(operator) = state.operator.take() {
+            vi_operate(&mut editor.kill_ring, &mut state, operator, action);
+            state.clamp_index();
+            continue;
+        }
+
+        match action {
+            Action::Cancel => {
                 if ps1 && state.line.is_empty() {
                     continue;
                 }
@@ -189,72 +539,80 @@ fn read_line(
                 state.cancelled = true;
             }
 
-            (KeyCode::Enter, _) => {
-                if state.expand_abbreviations {
-                    if let Some((expanded_line, diff)) =
-                        expand_abbreviation(&engine.abbreviations, &state.line)
+            Action::Accept => {
+                if engine.options.histexpand && state.line.len() <= LONG_LINE {
+                    match history::expand(&state.line, engine.history.as_mut()) {
+                        Ok(Some(expanded)) => {
+                            state.index = expanded.len();
+                            state.line = expanded;
+
+                            // shown to be entered again, as with abbreviations
+                            if engine.options.histverify {
+                                continue;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            show_error(engine, editor, &mut state, &mut start_pos, old_line, &e)?;
+                            continue;
+                        }
+                    }
+                }
+
+                if state.expand_abbreviations && state.line.len() <= LONG_LINE {
+                    if let Some(expanded) =
+                        expand_abbreviation(&engine.abbreviations, &state.line, state.index)
                     {
-                        state.line = expanded_line;
-                        state.index = state.index.wrapping_add_signed(diff);
+                        state.line = expanded.line;
+                        state.index = expanded.index;
+
+                        // shown to be entered again, without expanding it anew
+                        if engine.options.histverify {
+                            state.expand_abbreviations = false;
+                            continue;
+                        }
                     }
                 }
                 state.about_to_exit = true;
-                write_highlighted_ast(engine, &state, start_pos, old_line)?;
+                write_highlighted_ast(engine, &mut state, start_pos, old_line)?;
             }
 
-            (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+            Action::EndOfFile => {
                 if ps1 && state.line.is_empty() {
                     state.about_to_exit = true;
                     state.line = "exit".to_string();
+                } else if state.index < state.line.len() {
+                    let end = editing::next_char(&state.line, state.index);
+                    state.line.replace_range(state.index..end, "");
                 }
             }
 
-            (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+            Action::PreviousHistory => {
                 state.line = engine.history.prev()?.cloned().unwrap_or_default();
                 state.index = state.line.len();
-
-                execute!(stdout(), state.next_pos())?;
             }
 
-            (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+            Action::NextHistory => {
                 state.line = engine.history.next()?.cloned().unwrap_or_default();
                 state.index = state.line.len();
-
-                execute!(stdout(), state.next_pos())?;
             }
 
-            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
-                state.line.clear();
-                state.index = 0;
-                execute!(stdout(), state.next_pos())?;
+            Action::ReverseSearch => {
+                state.search = Some(Search::start(engine, &state.line)?);
             }
 
-            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
-                if state.index == 0 {
-                    continue;
-                }
-
-                let mut space_index = None;
-                for i in (0..state.index).rev() {
-                    if let Some(' ') = state.line.chars().nth(i) {
-                        space_index = Some(i);
-                        break;
-                    }
-                }
-
-                if let Some(' ') = state.line.chars().nth(state.index - 1) {
-                    // FIXME: this should find the previous space
-                    space_index = Some(0);
+            Action::Watch => {
+                let command = match state.line.trim().is_empty() {
+                    true => engine.history.search("")?.next().transpose()?,
+                    false => Some(state.line.clone()),
+                };
+                if let Some(command) = command {
+                    watch::watch(engine, &command)?;
+                    state.rendered = None;
                 }
-
-                let space_index = space_index.unwrap_or(0);
-                state.line.replace_range(space_index..state.index, "");
-                state.index = space_index;
-
-                execute!(stdout(), state.next_pos())?;
             }
 
-            (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
+            Action::ClearScreen => {
                 let (start_x, _) = state.start_pos;
                 execute!(
                     stdout(),
@@ -265,81 +623,243 @@ fn read_line(
                 break;
             }
 
-            (KeyCode::Left, _) | (KeyCode::Char('b'), KeyModifiers::CONTROL) if state.index > 0 => {
-                state.index -= 1;
+            Action::Complete if cycle.is_some() => {
+                let Some(mut cycle) = cycle else {
+                    continue;
+                };
 
-                execute!(stdout(), state.next_pos())?;
-            }
+                let candidates = &cycle.completion.candidates;
+                let current = cycle.current.map_or(0, |i| (i + 1) % candidates.len());
+                let value = &candidates[current].value;
 
-            (KeyCode::Right, _) | (KeyCode::Char('f'), KeyModifiers::CONTROL)
-                if state.index < state.line.len() =>
-            {
-                state.index += 1;
-                execute!(stdout(), state.next_pos())?;
+                let start = cycle.completion.start;
+                state.line.replace_range(start..cycle.end, value);
+                state.index = start + value.len();
+
+                let scrolled = show_menu(engine, &mut state, candidates, Some(current))?;
+                start_pos.1 -= scrolled;
+
+                cycle.current = Some(current);
+                cycle.end = state.index;
+                state.cycle = Some(cycle);
             }
 
-            (KeyCode::Char(' '), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
-                state.line.insert(state.index, ' ');
-                state.index += 1;
+            Action::Complete => {
+                let completion = completion::complete(engine, &state.line, state.index);
+
+                match completion.candidates.as_slice() {
+                    [] => {}
+
+                    [candidate] => {
+                        let mut value = candidate.value.clone();
+                        if candidate.terminated {
+                            value.push(' ');
+                        }
+                        state
+                            .line
+                            .replace_range(completion.start..state.index, &value);
+                        state.index = completion.start + value.len();
+                    }
 
-                if state.expand_abbreviations {
-                    if let Some((expanded_line, diff)) =
-                        expand_abbreviation(&engine.abbreviations, &state.line)
+                    candidates => {
+                        let prefix = completion.common_prefix();
+                        if prefix.len() > state.index - completion.start {
+                            state
+                                .line
+                                .replace_range(completion.start..state.index, &prefix);
+                            state.index = completion.start + prefix.len();
+                        }
+
+                        let scrolled = show_menu(engine, &mut state, candidates, None)?;
+                        start_pos.1 -= scrolled;
+
+                        state.cycle = Some(Cycle {
+                            end: state.index,
+                            current: None,
+                            completion,
+                        });
+                    }
+                }
+            }
+
+            Action::InsertSpace => {
+                let mut placeholder = false;
+                if state.expand_abbreviations && state.line.len() <= LONG_LINE {
+                    if let Some(expanded) =
+                        expand_abbreviation(&engine.abbreviations, &state.line, state.index)
                     {
-                        state.line = expanded_line;
-                        state.index = state.index.wrapping_add_signed(diff);
+                        state.line = expanded.line;
+                        state.index = expanded.index;
+                        placeholder = expanded.placeholder;
                     }
                 }
 
-                execute!(
-                    stdout(),
-                    terminal::Clear(terminal::ClearType::UntilNewLine),
-                    style::Print(&state.line[state.index - 1..]),
-                    state.next_pos(),
-                )?;
+                // the space is not wanted where the cursor was placed
+                if !placeholder {
+                    state.line.insert(state.index, ' ');
+                    state.index += 1;
+                }
             }
 
-            (KeyCode::Char(' '), KeyModifiers::CONTROL) => {
+            Action::InsertLiteralSpace => {
                 state.line.insert(state.index, ' ');
                 state.index += 1;
                 state.expand_abbreviations = false;
-
-                execute!(
-                    stdout(),
-                    terminal::Clear(terminal::ClearType::UntilNewLine),
-                    style::Print(&state.line[state.index - 1..]),
-                    state.next_pos(),
-                )?;
             }
 
-            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+            Action::QuotedInsert => state.quoted = true,
+
+            Action::SelfInsert => {
+                let KeyCode::Char(c) = code else {
+                    continue;
+                };
                 state.line.insert(state.index, c);
-                state.index += 1;
+                state.index += c.len_utf8();
                 state.expand_abbreviations = c != '|' && c != '&' && c != ';';
+            }
 
-                execute!(
-                    stdout(),
-                    style::Print(&state.line[state.index - 1..]),
-                    state.next_pos(),
-                )?;
+            Action::BackwardDeleteChar => {
+                if state.index > 0 {
+                    let start = editing::prev_char(&state.line, state.index);
+                    state.line.replace_range(start..state.index, "");
+                    state.index = start;
+                    state.expand_abbreviations = true;
+                }
+            }
+
+            Action::DeleteChar => {
+                let end = editing::next_char(&state.line, state.index);
+                state.line.replace_range(state.index..end, "");
             }
 
-            (KeyCode::Backspace, _) if state.index > 0 => {
-                state.index -= 1;
-                state.line.remove(state.index);
-                state.expand_abbreviations = true;
+            Action::KillLine => {
+                let end = state.line.len();
+                state.kill(&mut editor.kill_ring, state.index..end, append, true);
+            }
 
-                execute!(
-                    stdout(),
-                    state.next_pos(),
-                    style::Print(&state.line[state.index..]),
-                    state.next_pos(),
-                )?;
+            Action::BackwardKillLine => {
+                state.kill(&mut editor.kill_ring, 0..state.index, append, false);
+            }
+
+            Action::KillWord => {
+                let end = editing::forward_word(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, state.index..end, append, true);
+            }
+
+            Action::BackwardKillWord => {
+                let start = editing::backward_word(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, start..state.index, append, false);
+            }
+
+            Action::ShellKillWord => {
+                let end = editing::shell_forward_word(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, state.index..end, append, true);
+            }
+
+            Action::ShellBackwardKillWord => {
+                let start = editing::shell_backward_word(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, start..state.index, append, false);
+            }
+
+            Action::UnixWordRubout => {
+                let start = editing::unix_word_start(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, start..state.index, append, false);
+            }
+
+            Action::Yank => {
+                state.index = editor.kill_ring.yank(&mut state.line, state.index);
+            }
+
+            Action::YankPop => {
+                if matches!(last_action, Some(Action::Yank | Action::YankPop)) {
+                    if let Some(end) = editor.kill_ring.yank_pop(&mut state.line) {
+                        state.index = end;
+                    }
+                } else {
+                    state.last_action = last_action;
+                }
+            }
+
+            Action::TransposeChars => {
+                if let Some(index) = editing::transpose_chars(&mut state.line, state.index) {
+                    state.index = index;
+                }
+            }
+
+            Action::ViCommandMode => {
+                state.normal = true;
+                state.index = editing::prev_char(&state.line, state.index);
             }
 
-            _ => {}
+            Action::ViInsert => state.normal = false,
+
+            Action::ViAppend => {
+                state.index = editing::next_char(&state.line, state.index);
+                state.normal = false;
+            }
+
+            Action::ViInsertAtStart => {
+                state.index = editing::first_non_blank(&state.line);
+                state.normal = false;
+            }
+
+            Action::ViAppendAtEnd => {
+                state.index = state.line.len();
+                state.normal = false;
+            }
+
+            Action::ViDelete | Action::ViChange | Action::ViYank => {
+                state.operator = Some(action);
+            }
+
+            Action::ViDeleteToEnd | Action::ViChangeToEnd | Action::ViChangeLine => {
+                let start = match action {
+                    Action::ViChangeLine => 0,
+                    _ => state.index,
+                };
+                let end = state.line.len();
+                state.kill(&mut editor.kill_ring, start..end, false, true);
+                state.normal = action == Action::ViDeleteToEnd;
+            }
+
+            Action::ViDeleteChar => {
+                let end = editing::next_char(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, state.index..end, false, true);
+            }
+
+            Action::ViBackwardDeleteChar => {
+                let start = editing::prev_char(&state.line, state.index);
+                state.kill(&mut editor.kill_ring, start..state.index, false, false);
+            }
+
+            Action::ViPut | Action::ViPutBefore => {
+                if action == Action::ViPut {
+                    state.index = editing::next_char(&state.line, state.index);
+                }
+                let end = editor.kill_ring.yank(&mut state.line, state.index);
+                state.index = editing::prev_char(&state.line, end);
+            }
+
+            Action::BackwardChar
+            | Action::ForwardChar
+            | Action::BackwardWord
+            | Action::ForwardWord
+            | Action::ShellBackwardWord
+            | Action::ShellForwardWord
+            | Action::BeginningOfLine
+            | Action::EndOfLine
+            | Action::ViForwardWord
+            | Action::ViBackwardWord
+            | Action::ViEndOfWord
+            | Action::ViFirstNonBlank => {
+                if let Some(index) = editing::motion(action, &state.line, state.index) {
+                    state.index = index;
+                }
+            }
         }
 
+        state.clamp_index();
+
         if state.about_to_exit {
             break;
         }
@@ -371,22 +891,66 @@ fn read_line(
     }
 }
 
+/// Makes the line be drawn anew, starting at `start_pos`, after something
+/// other than the editor wrote to the terminal, such as a command that had
+/// it and may have drawn anything on it. Full-screen programs put the screen
+/// back as it was when they exit, so the line is drawn where it was, but if
+/// the cursor moved, something was written below the line, and the prompt is
+/// shown again after it.
+fn redraw_after_output(
+    engine: &mut Engine,
+    editor: &mut Editor,
+    state: &mut State,
+    start_pos: &mut (u16, u16),
+) -> Result<()> {
+    state.size = terminal::size()?;
+    state.rendered = None;
+
+    let pos = state.pos()?;
+    if Some(pos) == state.cursor_pos {
+        return Ok(());
+    }
+
+    if pos.0 > 0 {
+        execute!(stdout(), style::Print("\r\n"))?;
+    }
+    // the lines that continue the line are drawn after `PS2` along with it
+    prompt(engine, &mut editor.segments, false)?;
+    let origin = state.pos()?;
+    state.start_pos = match state.start_pos.1.saturating_sub(start_pos.1) {
+        0 => origin,
+        below => (state.start_pos.0, origin.1 + below),
+    };
+    *start_pos = origin;
+
+    Ok(())
+}
+
+/// Shows `error` below the line, which is then drawn again below it, so that
+/// it can be corrected.
+fn show_error(
+    engine: &mut Engine,
+    editor: &mut Editor,
+    state: &mut State,
+    start_pos: &mut (u16, u16),
+    old_line: Option<&String>,
+    error: &Error,
+) -> Result<()> {
+    let index = mem::replace(&mut state.index, state.line.len());
+    write_highlighted_ast(engine, state, *start_pos, old_line)?;
+    state.index = index;
+
+    execute!(stderr(), style::Print(format!("\r\npsh: {error}\r\n")))?;
+    redraw_after_output(engine, editor, state, start_pos)
+}
+
 fn write_highlighted_ast(
     engine: &mut Engine,
-    state: &State,
+    state: &mut State,
     start_pos: (u16, u16),
     old_line: Option<&String>,
 ) -> Result<()> {
-    let (start_x, start_y) = start_pos;
-    let (x, y) = state.pos()?;
-
-    let color = Colors::normal(engine);
-    queue!(
-        stdout(),
-        cursor::MoveTo(start_x, start_y),
-        terminal::Clear(terminal::ClearType::UntilNewLine),
-        style::SetForegroundColor(color),
-    )?;
+    let (start_x, _) = start_pos;
 
     let line = if let Some(l) = old_line {
         format!("{l}{}", state.line)
@@ -394,43 +958,522 @@ fn write_highlighted_ast(
         state.line.clone()
     };
 
-    let starting_point = match engine.get_value_of("PS2") {
-        Some(ps2) => ps2.len() as u16,
+    let indent = match engine.get_value_of("PS2") {
+        Some(ps2) => render::width(&visible(&ps2)) as u16,
         _ => start_x,
     };
 
-    let Ok(ast) = psh_core::ast::parse(line, true) else { return Ok(()); };
-    ast.write_highlighted(
-        engine,
-        Context {
-            start_x: starting_point,
-            abbreviations: state.expand_abbreviations,
-        },
-    )?;
+    let mut frame = Frame::default();
+    frame.set_color(Colors::normal(engine));
+    let cursor = line.len() - state.line.len() + state.index;
+    let long = line.len() > LONG_LINE;
+
+    let (row, col) = if long {
+        (0, print_window(&mut frame, &line, cursor, state.size))
+    } else {
+        match psh_core::ast::parse(&line, true) {
+            Ok(ast) => {
+                let cancel = CancelOnInput::start(engine.cancel.clone())?;
+                let highlighted = ast.write_highlighted(
+                    engine,
+                    &mut frame,
+                    Context {
+                        abbreviations: state.expand_abbreviations,
+                    },
+                );
+                drop(cancel);
+                engine.cancel.reset();
+                highlighted?
+            }
+            Err(_) => frame.print(&line),
+        }
+
+        let before_cursor = &line[..cursor];
+        let row = before_cursor.matches('\n').count();
+        let col = match before_cursor.rfind('\n') {
+            Some(i) => render::width(&before_cursor[i + 1..]),
+            None => render::width(before_cursor),
+        };
+        (row, col)
+    };
 
     if state.cancelled {
-        queue!(stdout(), style::ResetColor, style::Print("^C"))?;
+        frame.reset_color();
+        frame.print("^C");
+    }
+
+    if long && !state.about_to_exit {
+        frame.set_color(Colors::comment(engine));
+        frame.print(format!(
+            "\n{} KiB of input: highlighting and abbreviations are off",
+            line.len() / 1024
+        ));
+    }
+
+    let layout = Layout {
+        origin: start_pos,
+        indent,
+        width: state.size.0,
+    };
+
+    let prev = match &state.rendered {
+        Some((prev_layout, prev)) if *prev_layout == layout => Some(prev),
+        _ => None,
+    };
+    let cursor_pos = render::draw(&mut stdout(), prev, &frame, layout, (row, col))?;
+    state.rendered = Some((layout, frame));
+    state.cursor_pos = Some(cursor_pos);
+
+    Ok(())
+}
+
+/// Prints the part of the long `line` around the byte index `cursor` that
+/// fits in a terminal of the given size, on a single row where newlines are
+/// escaped like other control characters. Returns the column of the cursor.
+fn print_window(frame: &mut Frame, line: &str, cursor: usize, size: (u16, u16)) -> usize {
+    // an escaped character takes up to four cells
+    let cells = size.0 as usize * size.1.saturating_sub(2).max(1) as usize;
+    let half = cells / 8;
+
+    let start = line[..cursor]
+        .char_indices()
+        .rev()
+        .nth(half)
+        .map_or(0, |(i, _)| i);
+    let end = line[cursor..]
+        .char_indices()
+        .nth(half)
+        .map_or(line.len(), |(i, _)| cursor + i);
+
+    let mut col = 0;
+    if start > 0 {
+        frame.print('…');
+        col += 1;
+    }
+    let before_cursor = quote::escape_controls(&line[start..cursor]);
+    col += render::width(&before_cursor);
+    frame.print(before_cursor);
+    frame.print(quote::escape_controls(&line[cursor..end]));
+    if end < line.len() {
+        frame.print('…');
+    }
+    col
+}
+
+/// Prints the completion candidates in columns below the input line, with
+/// the `selected` one highlighted. Returns the amount of rows the terminal
+/// had to be scrolled to make room for them.
+fn show_menu(
+    engine: &Engine,
+    state: &mut State,
+    candidates: &[completion::Candidate],
+    selected: Option<usize>,
+) -> Result<u16> {
+    let (x, mut y) = state.pos()?;
+    let (width, height) = state.size;
+
+    // the names of files can have control characters in them, which are
+    // shown escaped like on the input line
+    let value_width = candidates
+        .iter()
+        .map(|c| render::width(&c.value))
+        .max()
+        .unwrap_or(0);
+    let annotation_width = candidates
+        .iter()
+        .map(|c| render::width(&c.annotation))
+        .max()
+        .unwrap_or(0);
+    let column_width = value_width + annotation_width + 4;
+    let columns = (width as usize / column_width).max(1);
+
+    let max_rows = (height as usize).saturating_sub(2).max(1);
+    let mut rows = candidates.len().div_ceil(columns);
+    let truncated = rows > max_rows;
+    if truncated {
+        rows = max_rows;
+    }
+    let shown = if truncated {
+        (rows - 1) * columns
+    } else {
+        candidates.len()
+    };
+
+    let scrolled = (y as usize + rows + 1).saturating_sub(height as usize) as u16;
+    if scrolled > 0 {
+        queue!(stdout(), terminal::ScrollUp(scrolled))?;
+        y -= scrolled;
+        state.start_pos.1 -= scrolled;
+    }
+
+    let annotation_color = Colors::comment(engine);
+    let ls_colors = match Colors::enabled(engine) {
+        true => LsColors::new(engine.get_value_of("LS_COLORS").as_deref()),
+        false => LsColors::default(),
+    };
+    for (row, chunk) in candidates[..shown].chunks(columns).enumerate() {
+        queue!(
+            stdout(),
+            cursor::MoveTo(0, y + 1 + row as u16),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+        )?;
+        for (i, candidate) in chunk.iter().enumerate() {
+            if selected == Some(row * columns + i) {
+                queue!(stdout(), style::SetAttribute(style::Attribute::Reverse))?;
+            }
+            if let Some(sgr) = candidate.path.as_deref().and_then(|p| ls_colors.style(p)) {
+                queue!(stdout(), style::Print(format!("\x1b[{sgr}m")))?;
+            }
+            queue!(
+                stdout(),
+                style::Print(quote::escape_controls(&candidate.value)),
+                style::SetAttribute(style::Attribute::Reset),
+                style::Print(format!(
+                    "{:<width$}  ",
+                    "",
+                    width = value_width - render::width(&candidate.value)
+                )),
+            )?;
+            queue!(
+                stdout(),
+                style::SetForegroundColor(annotation_color),
+                style::Print(format!(
+                    "{}{:<width$}  ",
+                    quote::escape_controls(&candidate.annotation),
+                    "",
+                    width = annotation_width - render::width(&candidate.annotation)
+                )),
+                style::ResetColor,
+            )?;
+        }
+    }
+
+    if truncated {
+        queue!(
+            stdout(),
+            cursor::MoveTo(0, y + rows as u16),
+            terminal::Clear(terminal::ClearType::UntilNewLine),
+            style::Print(format!("…and {} more", candidates.len() - shown)),
+        )?;
+    }
+
+    execute!(stdout(), cursor::MoveTo(x, y))?;
+    state.menu_rows = rows as u16;
+
+    Ok(scrolled)
+}
+
+/// Handles a key pressed while searching the history: typing narrows the
+/// search, ^R moves to the next older match, Enter keeps the match on the
+/// line, and ^C, ^G or Esc go back to the line from before the search.
+/// Returns `false` if the key was not handled, in which case the search is
+/// ended and the key should be handled as usual.
+fn search_key(
+    engine: &mut Engine,
+    state: &mut State,
+    code: KeyCode,
+    modifiers: KeyModifiers,
+) -> Result<bool> {
+    let Some(search) = &mut state.search else {
+        return Ok(false);
+    };
+
+    let found = match (code, modifiers) {
+        (KeyCode::Char('r'), KeyModifiers::CONTROL) => search.next_match()?,
+
+        (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+            search.query.push(c);
+            search.restart(engine)?;
+            search.next_match()?
+        }
+
+        (KeyCode::Backspace, _) => {
+            search.query.pop();
+            search.restart(engine)?;
+            search.next_match()?
+        }
+
+        (KeyCode::Char('c' | 'g'), KeyModifiers::CONTROL) | (KeyCode::Esc, _) => {
+            state.line = std::mem::take(&mut search.original);
+            state.index = state.line.len();
+            state.search = None;
+            return Ok(true);
+        }
+
+        (KeyCode::Enter, _) => {
+            state.search = None;
+            return Ok(true);
+        }
+
+        _ => {
+            state.search = None;
+            return Ok(false);
+        }
+    };
+
+    match found {
+        Some(entry) => {
+            state.index = entry.find(&search.query).unwrap_or(0);
+            state.line = entry;
+            search.failed = false;
+        }
+        None => search.failed = !search.query.is_empty(),
     }
 
-    execute!(stdout(), style::ResetColor, cursor::MoveTo(x, y))?;
+    Ok(true)
+}
+
+/// Prints the query of the history search below the input line. Returns the
+/// amount of rows the terminal had to be scrolled to make room for it.
+fn show_search(engine: &Engine, state: &mut State) -> Result<u16> {
+    let Some(search) = &state.search else {
+        return Ok(0);
+    };
+    let label = match search.failed {
+        true => "failing reverse-i-search: ",
+        false => "reverse-i-search: ",
+    };
+    let query = search.query.clone();
+
+    let (x, mut y) = state.pos()?;
+    let (_, height) = state.size;
+
+    let scrolled = (y + 2).saturating_sub(height);
+    if scrolled > 0 {
+        queue!(stdout(), terminal::ScrollUp(scrolled))?;
+        y -= scrolled;
+        state.start_pos.1 -= scrolled;
+    }
+
+    queue!(
+        stdout(),
+        cursor::MoveTo(0, y + 1),
+        terminal::Clear(terminal::ClearType::UntilNewLine),
+        style::SetForegroundColor(Colors::comment(engine)),
+        style::Print(label),
+        style::ResetColor,
+        style::Print(query),
+    )?;
+    execute!(stdout(), cursor::MoveTo(x, y))?;
+    state.menu_rows = 1;
+
+    Ok(scrolled)
+}
+
+/// Applies the Vi operator `operator`, such as `d`, to the text that the
+/// motion `action` moves over, or to the whole line if it is the operator
+/// again, as in `dd`.
+fn vi_operate(kill_ring: &mut KillRing, state: &mut State, operator: Action, action: Action) {
+    let (line, index) = (&state.line, state.index);
+    let range = if action == operator {
+        0..line.len()
+    } else {
+        // `cw` changes the rest of the word, like `ce`, rather than up to
+        // the next one
+        let action = match action {
+            Action::ViForwardWord
+                if operator == Action::ViChange
+                    && !line[index..].starts_with(char::is_whitespace) =>
+            {
+                Action::ViEndOfWord
+            }
+            action => action,
+        };
+        let Some(target) = editing::motion(action, line, index) else {
+            return;
+        };
+        match action {
+            Action::ViEndOfWord => index..editing::next_char(line, target),
+            _ => index.min(target)..index.max(target),
+        }
+    };
 
+    match operator {
+        Action::ViYank => {
+            kill_ring.kill(&line[range.clone()], false, true);
+            state.index = range.start;
+        }
+        _ => state.kill(kill_ring, range, false, true),
+    }
+    state.normal = operator != Action::ViChange;
+}
+
+fn clear_menu(state: &mut State) -> Result<()> {
+    let (x, y) = state.pos()?;
+    execute!(
+        stdout(),
+        cursor::MoveTo(0, y + 1),
+        terminal::Clear(terminal::ClearType::FromCursorDown),
+        cursor::MoveTo(x, y),
+    )?;
+    state.menu_rows = 0;
     Ok(())
 }
 
-fn expand_abbreviation<S: AsRef<str>>(
-    abbreviations: &HashMap<String, String>,
-    line: S,
-) -> Option<(String, isize)> {
-    let line = line.as_ref();
-    let mut iter = line.split(' ');
-    match iter.next() {
-        Some(part) => match abbreviations.get(part) {
-            Some(exp) => {
-                let diff = exp.len() as isize - part.len() as isize;
-                Some((line.replacen(part, exp, 1), diff))
-            }
-            None => None,
+/// Marks where the cursor goes when an abbreviation is expanded, as in
+/// `abbr gco='git checkout %|%'`.
+const PLACEHOLDER: &str = "%|%";
+
+/// A line whose word before the cursor was expanded as an abbreviation.
+#[derive(Debug, PartialEq, Eq)]
+struct Expanded {
+    line: String,
+
+    /// Where the cursor goes: the placeholder of the abbreviation if it has
+    /// one, and otherwise right after the expansion.
+    index: usize,
+
+    /// Whether the abbreviation had a placeholder.
+    placeholder: bool,
+}
+
+/// Expands the word of `line` right before `index`, the cursor, if it is an
+/// abbreviation, given whether it is in command position.
+fn expand_abbreviation(
+    abbreviations: &HashMap<String, Abbreviation>,
+    line: &str,
+    index: usize,
+) -> Option<Expanded> {
+    let before = &line[..index];
+    let start = editing::shell_word_start(line, index);
+    let word = &before[start..];
+    if word.is_empty() {
+        return None;
+    }
+
+    let command = before[..start]
+        .trim_end()
+        .chars()
+        .last()
+        .is_none_or(|c| matches!(c, ';' | '|' | '&' | '('));
+    let expansion = &abbreviation::find(abbreviations, word, command)?.expansion;
+    let (head, rest) = (&line[..start], &line[index..]);
+
+    Some(match expansion.split_once(PLACEHOLDER) {
+        Some((before, after)) => Expanded {
+            line: format!("{head}{before}{after}{rest}"),
+            index: start + before.len(),
+            placeholder: true,
+        },
+        None => Expanded {
+            line: format!("{head}{expansion}{rest}"),
+            index: start + expansion.len(),
+            placeholder: false,
         },
-        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use psh_core::engine::abbreviation::Position;
+
+    use super::*;
+
+    #[test]
+    fn long_lines_are_windowed() {
+        let line = format!("{}\n{}", "a".repeat(LONG_LINE), "b".repeat(LONG_LINE));
+
+        // 10 * 4 cells fit five characters on either side of the cursor
+        let mut frame = Frame::default();
+        let col = print_window(&mut frame, &line, LONG_LINE + 1, (10, 6));
+
+        let mut expected = Frame::default();
+        expected.print("…aaaaa^Jbbbbb…");
+        assert_eq!(expected, frame);
+        assert_eq!(8, col);
+
+        let mut frame = Frame::default();
+        assert_eq!(0, print_window(&mut frame, &line, 0, (10, 6)));
+        let mut expected = Frame::default();
+        expected.print("aaaaa…");
+        assert_eq!(expected, frame);
+    }
+
+    #[test]
+    fn prompts() {
+        let lines = |prompt: &str, columns| -> Vec<String> {
+            prompt_lines(prompt, columns)
+                .into_iter()
+                .map(Cow::into_owned)
+                .collect()
+        };
+
+        assert_eq!(vec!["$ "], lines("$ ", 80));
+        assert_eq!(vec!["~/src/psh", "$ "], lines("~/src/psh\n$ ", 80));
+
+        // at least 20 columns are left for the input
+        let long = format!("{}/psh $ ", "x".repeat(40));
+        assert_eq!(vec!["…xxxxxxxxxxxx/psh $ "], lines(&long, 40));
+        assert_eq!(vec![long.clone()], lines(&long, 80));
+        assert_eq!(vec![long.clone(), "…ö $ ".to_string()], {
+            lines(&format!("{long}\nööö $ "), 25)
+        });
+
+        let rows = |prompt, columns| rows_above(&prompt_lines(prompt, columns), columns);
+        assert_eq!(0, rows("$ ", 80));
+        assert_eq!(2, rows("\n~/src/psh\n$ ", 80));
+        assert_eq!(3, rows(&format!("{long}\n\x01\x1b[1m\x02$ "), 20));
+
+        // what is between `\[` and `\]` takes up no columns, and is kept
+        let colored = "\x01\x1b[1m\x02~/src\x01\x1b[0m\x02 $ ";
+        assert_eq!(vec![colored], lines(colored, 28));
+        assert_eq!(
+            vec!["\x01\x1b[1m\x02…src\x01\x1b[0m\x02 $ "],
+            lines(&colored.replace("~/src", "~/xsrc"), 27)
+        );
+    }
+
+    #[test]
+    fn abbreviations() {
+        let mut anywhere = Abbreviation::new("| less");
+        anywhere.position = Position::Anywhere;
+
+        let abbreviations = HashMap::from([
+            ("g".to_string(), Abbreviation::new("git")),
+            (
+                "gco".to_string(),
+                Abbreviation::new("git checkout %|% --quiet"),
+            ),
+            ("L".to_string(), anywhere),
+        ]);
+        let expanded = |line: &str, index| {
+            expand_abbreviation(&abbreviations, line, index)
+                .map(|expanded| (expanded.line, expanded.index, expanded.placeholder))
+        };
+
+        assert_eq!(Some(("git".to_string(), 3, false)), expanded("g", 1));
+        assert_eq!(
+            Some(("git status".to_string(), 3, false)),
+            expanded("g status", 1)
+        );
+        assert_eq!(
+            Some(("git checkout  --quiet".to_string(), 13, true)),
+            expanded("gco", 3)
+        );
+        assert_eq!(
+            Some(("ls; git".to_string(), 7, false)),
+            expanded("ls; g", 5)
+        );
+        assert_eq!(
+            Some(("ls | git".to_string(), 8, false)),
+            expanded("ls | g", 6)
+        );
+        assert_eq!(None, expanded("git", 3));
+        assert_eq!(None, expanded("echo g", 6));
+        assert_eq!(None, expanded("g status", 8));
+
+        assert_eq!(
+            Some(("cat x | less".to_string(), 12, false)),
+            expanded("cat x L", 7)
+        );
+
+        // multibyte characters before the word, and quoted words
+        assert_eq!(Some(("ñö;git".to_string(), 8, false)), expanded("ñö;g", 6));
+        assert_eq!(
+            Some(("echo ä | less".to_string(), 14, false)),
+            expanded("echo ä L", 9)
+        );
+        assert_eq!(None, expanded("echo 'ä L", 10));
+        assert_eq!(None, expanded("echo ä\\ L", 10));
     }
 }
@@ -0,0 +1,446 @@
+use std::fmt;
+use std::io::Write;
+
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
+
+use psh_core::engine::quote::escape_control;
+use psh_core::Result;
+use unicode_width::UnicodeWidthChar;
+
+/// A character of a frame, with the color it is printed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cell {
+    /// The character, followed by those that combine with it, see
+    /// `combines`.
+    text: String,
+
+    /// The amount of columns of the terminal it takes up, which is 2 for
+    /// wide characters such as those of CJK and most emoji, and otherwise 1.
+    width: usize,
+    color: Option<Color>,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Self {
+            text: " ".to_string(),
+            width: 1,
+            color: None,
+        }
+    }
+}
+
+/// The input area rendered into memory, as rows of colored characters.
+/// Comparing a frame with the previously drawn one lets only the cells that
+/// changed be written to the terminal, instead of redrawing the whole line on
+/// every keystroke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    rows: Vec<Vec<Cell>>,
+    color: Option<Color>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self {
+            rows: vec![Vec::new()],
+            color: None,
+        }
+    }
+}
+
+impl Frame {
+    /// Colors what is printed next. `Color::Reset`, which every color is
+    /// when colors are disabled, leaves it uncolored.
+    pub fn set_color(&mut self, color: Color) {
+        self.color = (color != Color::Reset).then_some(color);
+    }
+
+    pub fn reset_color(&mut self) {
+        self.color = None;
+    }
+
+    /// The color of what is printed next, which `restore_color` goes back to
+    /// after printing something in another one.
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    pub fn restore_color(&mut self, color: Option<Color>) {
+        self.color = color;
+    }
+
+    /// Prints `s`, where the control characters other than newlines are
+    /// shown as escapes, see `escape_control`, so that the terminal does not
+    /// act on them. Input that is not valid UTF-8 has already been replaced
+    /// by `�` when it was read.
+    pub fn print(&mut self, s: impl fmt::Display) {
+        for c in s.to_string().chars() {
+            if c == '\n' {
+                self.rows.push(Vec::new());
+                continue;
+            }
+
+            let row = self.rows.last_mut().unwrap();
+            let color = self.color;
+            let cell = |text: String, width| Cell { text, width, color };
+            match escape_control(c) {
+                Some(escaped) => row.extend(escaped.chars().map(|c| cell(c.into(), 1))),
+                None if combines(c) => match row.last_mut() {
+                    Some(last) => last.text.push(c),
+                    // there is nothing to combine with, so it is shown on
+                    // its own, over a space
+                    None => row.push(cell(format!(" {c}"), 1)),
+                },
+                None => row.push(cell(c.into(), char_width(c))),
+            }
+        }
+    }
+}
+
+/// Whether `c` takes up no room of its own, and is shown combined with the
+/// character before it, such as a combining accent, a variation selector or
+/// a zero width joiner.
+pub fn combines(c: char) -> bool {
+    !c.is_control() && c.width() == Some(0)
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(1)
+}
+
+/// The amount of columns that `s` takes up in a row of a frame.
+pub fn width(s: &str) -> usize {
+    s.chars()
+        .enumerate()
+        .map(|(i, c)| match escape_control(c) {
+            Some(escaped) => escaped.len(),
+            None if combines(c) => (i == 0) as usize,
+            None => char_width(c),
+        })
+        .sum()
+}
+
+/// What a column of the terminal shows of a row of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column<'a> {
+    /// The first column of a cell.
+    Start(&'a Cell),
+
+    /// The second column of a wide cell.
+    Covered,
+
+    /// The last column of a row of the terminal, left empty since the wide
+    /// cell after it did not fit in it, and was put on the next row.
+    Gap,
+}
+
+/// Where on the terminal a frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// The position of the first cell of the first row.
+    pub origin: (u16, u16),
+
+    /// The column where the rest of the rows start, i.e. after `PS2`.
+    pub indent: u16,
+
+    /// The width of the terminal, used to account for rows that wrap.
+    pub width: u16,
+}
+
+impl Layout {
+    fn start_x(&self, row: usize) -> usize {
+        match row {
+            0 => self.origin.0 as usize,
+            _ => self.indent as usize,
+        }
+    }
+
+    /// The columns of the terminal that the row `i` of a frame, made up of
+    /// `cells`, takes up, from where it starts. A wide cell that does not
+    /// fit at the end of a row of the terminal is put on the next one, as
+    /// terminals do.
+    fn columns<'a>(&self, i: usize, cells: &'a [Cell]) -> Vec<Column<'a>> {
+        let width = self.width.max(1) as usize;
+        let start_x = self.start_x(i);
+        let mut columns = Vec::with_capacity(cells.len());
+
+        for cell in cells {
+            let x = (start_x + columns.len()) % width;
+            if cell.width > 1 && x + cell.width > width && x > 0 {
+                columns.resize(columns.len() + width - x, Column::Gap);
+            }
+            columns.push(Column::Start(cell));
+            columns.resize(
+                columns.len() + cell.width.saturating_sub(1),
+                Column::Covered,
+            );
+        }
+
+        columns
+    }
+
+    /// The amount of terminal rows each row of `frame` occupies.
+    fn heights(&self, frame: &Frame) -> Vec<usize> {
+        let width = self.width.max(1) as usize;
+        frame
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let columns = self.columns(i, row).len();
+                (self.start_x(i) + columns).saturating_sub(1) / width + 1
+            })
+            .collect()
+    }
+
+    /// The terminal position of column `col` of row `row` of a frame with
+    /// rows of the given heights, where the columns are those of
+    /// `Layout::columns`.
+    fn position(&self, heights: &[usize], row: usize, col: usize) -> (u16, u16) {
+        let width = self.width.max(1) as usize;
+        let y = self.origin.1 as usize + heights[..row].iter().sum::<usize>();
+        let offset = self.start_x(row) + col;
+        ((offset % width) as u16, (y + offset / width) as u16)
+    }
+}
+
+/// Draws `next` on the terminal, which currently shows `prev` if any, and
+/// moves the cursor to column `cursor.1` of row `cursor.0` of the frame,
+/// returning where on the terminal that is. If the rows of the frames wrap
+/// differently, or there is no previous frame, the whole frame is drawn.
+pub fn draw(
+    out: &mut impl Write,
+    prev: Option<&Frame>,
+    next: &Frame,
+    layout: Layout,
+    cursor: (usize, usize),
+) -> Result<(u16, u16)> {
+    let heights = layout.heights(next);
+
+    // cells can only be compared if they are in the same place on screen
+    let comparable = prev.filter(|prev| {
+        let prev_heights = layout.heights(prev);
+        prev_heights.iter().zip(&heights).all(|(a, b)| a == b)
+    });
+
+    let mut pen = Pen::default();
+
+    for (i, row) in next.rows.iter().enumerate() {
+        let columns = layout.columns(i, row);
+        let old = comparable
+            .and_then(|prev| prev.rows.get(i))
+            .map(|old| layout.columns(i, old));
+
+        for (col, column) in columns.iter().enumerate() {
+            if old.as_ref().and_then(|old| old.get(col)) == Some(column) {
+                continue;
+            }
+            let pos = layout.position(&heights, i, col);
+            match column {
+                Column::Start(cell) => pen.print(out, layout, pos, cell)?,
+                Column::Gap => pen.print(out, layout, pos, &Cell::blank())?,
+                // printing the cell it is part of covered it
+                Column::Covered => {}
+            }
+        }
+
+        if old.is_none_or(|old| old.len() > columns.len()) {
+            let pos = layout.position(&heights, i, columns.len());
+            pen.move_to(out, pos)?;
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+        }
+    }
+
+    // rows that are no longer part of the input, e.g. after a history entry
+    // spanning multiple lines has been replaced by a shorter one
+    if let Some(prev) = prev {
+        let prev_end = layout.position(&layout.heights(prev), prev.rows.len(), 0).1;
+        let next_end = layout.position(&heights, next.rows.len(), 0).1;
+        for y in next_end..prev_end {
+            queue!(out, MoveTo(0, y), Clear(ClearType::CurrentLine))?;
+        }
+    }
+
+    let row = cursor.0.min(next.rows.len() - 1);
+    let col = cursor_column(&layout.columns(row, &next.rows[row]), cursor.1);
+    let (x, y) = layout.position(&heights, row, col);
+    queue!(out, ResetColor, MoveTo(x, y))?;
+    out.flush()?;
+
+    Ok((x, y))
+}
+
+/// Which of `columns` the cursor is in when it is after `width` columns of
+/// the text of the row, skipping those left empty before wide cells.
+fn cursor_column(columns: &[Column], width: usize) -> usize {
+    let mut text = 0;
+    for (col, column) in columns.iter().enumerate() {
+        if *column == Column::Gap {
+            continue;
+        }
+        if text == width {
+            return col;
+        }
+        text += 1;
+    }
+    columns.len() + width.saturating_sub(text)
+}
+
+/// Keeps track of the cursor position and color while drawing, to avoid
+/// emitting commands that would not change anything.
+#[derive(Debug, Default)]
+struct Pen {
+    pos: Option<(u16, u16)>,
+    color: Option<Option<Color>>,
+}
+
+impl Pen {
+    fn move_to(&mut self, out: &mut impl Write, pos: (u16, u16)) -> Result<()> {
+        if self.pos != Some(pos) {
+            queue!(out, MoveTo(pos.0, pos.1))?;
+            self.pos = Some(pos);
+        }
+        Ok(())
+    }
+
+    fn print(
+        &mut self,
+        out: &mut impl Write,
+        layout: Layout,
+        pos: (u16, u16),
+        cell: &Cell,
+    ) -> Result<()> {
+        self.move_to(out, pos)?;
+
+        if self.color != Some(cell.color) {
+            match cell.color {
+                Some(color) => queue!(out, SetForegroundColor(color))?,
+                None => queue!(out, ResetColor)?,
+            }
+            self.color = Some(cell.color);
+        }
+
+        queue!(out, Print(&cell.text))?;
+
+        // the cursor stays in the last column until the next character is
+        // printed, so its position is not known until it has been moved
+        self.pos = match pos.0 + cell.width as u16 {
+            x if x >= layout.width => None,
+            x => Some((x, pos.1)),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(text: &str) -> Frame {
+        let mut frame = Frame::default();
+        frame.print(text);
+        frame
+    }
+
+    fn draw_to_string(prev: Option<&Frame>, next: &Frame, layout: Layout) -> String {
+        let mut out = Vec::new();
+        draw(&mut out, prev, next, layout, (0, 0)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const CLEAR_UNTIL_NEWLINE: &str = "\x1b[K";
+
+    const LAYOUT: Layout = Layout {
+        origin: (2, 5),
+        indent: 2,
+        width: 10,
+    };
+
+    #[test]
+    fn positions_account_for_wrapping() {
+        let frame = frame("0123456789abc\nxy");
+        let heights = LAYOUT.heights(&frame);
+        assert_eq!(vec![2, 1], heights);
+        assert_eq!((2, 5), LAYOUT.position(&heights, 0, 0));
+        assert_eq!((0, 6), LAYOUT.position(&heights, 0, 8));
+        assert_eq!((2, 7), LAYOUT.position(&heights, 1, 0));
+
+        let cursor = draw(&mut Vec::new(), None, &frame, LAYOUT, (0, 9)).unwrap();
+        assert_eq!((1, 6), cursor);
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let text = "a\tb\x1b\x7f\u{9b}ä\u{fffd}";
+        let frame = frame(text);
+        let row = frame.rows[0]
+            .iter()
+            .map(|cell| cell.text.as_str())
+            .collect::<String>();
+        assert_eq!("a^Ib^[^?\\x9bä\u{fffd}", row);
+        assert_eq!(row.chars().count(), width(text));
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        // `語` takes up two columns, and the accent is combined with the `e`
+        let text = "a語e\u{301}b";
+        let cells = frame(text).rows[0]
+            .iter()
+            .map(|cell| (cell.text.clone(), cell.width))
+            .collect::<Vec<_>>();
+        let expected = [("a", 1), ("語", 2), ("e\u{301}", 1), ("b", 1)]
+            .map(|(text, width)| (text.to_string(), width));
+        assert_eq!(expected.to_vec(), cells);
+        assert_eq!(5, width(text));
+        assert_eq!(1, width("\u{301}"));
+
+        // a wide character does not fit in the last column, and is put on
+        // the next row
+        let frame = frame("1234567語x");
+        assert_eq!(vec![2], LAYOUT.heights(&frame));
+        let cursor = draw(&mut Vec::new(), None, &frame, LAYOUT, (0, 9)).unwrap();
+        assert_eq!((2, 6), cursor);
+        assert!(draw_to_string(None, &frame, LAYOUT).contains("7 "));
+
+        // the cells after a character that got wider moved
+        let drawn = draw_to_string(Some(&self::frame("ab")), &self::frame("語b"), LAYOUT);
+        assert!(drawn.contains("語b"));
+    }
+
+    #[test]
+    fn only_changes_are_drawn() {
+        let prev = frame("echo foo");
+        let next = frame("echo fob");
+
+        let drawn = draw_to_string(Some(&prev), &next, LAYOUT);
+        assert!(drawn.contains('b'));
+        assert!(!drawn.contains("echo"));
+        assert!(!drawn.contains(CLEAR_UNTIL_NEWLINE));
+    }
+
+    #[test]
+    fn removed_cells_are_cleared() {
+        let prev = frame("echo foo");
+        let next = frame("echo");
+
+        let drawn = draw_to_string(Some(&prev), &next, LAYOUT);
+        assert!(!drawn.contains("echo"));
+        assert!(drawn.contains(CLEAR_UNTIL_NEWLINE));
+    }
+
+    #[test]
+    fn changed_wrapping_redraws_everything() {
+        let prev = frame("echo");
+        let next = frame("echo foo bar");
+
+        let drawn = draw_to_string(Some(&prev), &next, LAYOUT);
+        assert!(drawn.contains("echo foo"));
+        assert!(drawn.contains("bar"));
+    }
+}
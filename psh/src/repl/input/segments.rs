@@ -0,0 +1,312 @@
+//! The prompt built from segments, such as the working directory and the
+//! branch of the git repository it is in. It is shown instead of `PS1` when
+//! `PSH_PROMPT_SEGMENTS` lists them, as in
+//!
+//! ```text
+//! PSH_PROMPT_SEGMENTS='cwd git python node duration status jobs \n \$'
+//! ```
+//!
+//! Every segment is colored like an element of `theme`, and left out when
+//! there is nothing to show, such as the status after a command succeeded.
+//! Words that are not segments are shown as they are, once the escapes of
+//! `PS1` in them are replaced, so `\n` starts a new line.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crossterm::style::{Color, SetForegroundColor};
+use crossterm::Command as _;
+
+use psh_core::engine::prompt::{self, END_INVISIBLE, START_INVISIBLE};
+use psh_core::engine::quote;
+use psh_core::Engine;
+
+use crate::config::Colors;
+use crate::repl::status;
+
+/// How long a command has to take for `duration` to show it, unless
+/// `PSH_PROMPT_MIN_DURATION` is set to another amount of seconds.
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+/// What the segments show that is kept from one prompt to the next.
+#[derive(Debug, Default)]
+pub struct Segments {
+    /// How long the last command took.
+    pub duration: Option<Duration>,
+
+    /// The `$PATH` that node was looked up in, and its version, which is
+    /// only asked for again once `$PATH` changes, since it takes a while.
+    node: Option<(String, Option<String>)>,
+}
+
+impl Segments {
+    /// The prompt of the segments in `PSH_PROMPT_SEGMENTS`, with its escapes
+    /// replaced, or `None` if there are none.
+    pub fn prompt(&mut self, engine: &Engine) -> Option<String> {
+        let names = engine.get_value_of("PSH_PROMPT_SEGMENTS")?;
+        let colors = Colors::enabled(engine);
+
+        // segments are separated by a space, except at the start of a line
+        let mut prompt = String::new();
+        let mut line_start = true;
+        for name in names.split_whitespace() {
+            let Some((text, color)) = self.segment(engine, name) else {
+                continue;
+            };
+            if !line_start && !text.starts_with('\n') {
+                prompt.push(' ');
+            }
+            line_start = text.ends_with('\n');
+            match colors {
+                true => {
+                    prompt.push_str(&invisible(color));
+                    prompt.push_str(&text);
+                    prompt.push_str(&invisible(Colors::prompt(engine)));
+                }
+                false => prompt.push_str(&text),
+            }
+        }
+
+        if prompt.is_empty() {
+            return None;
+        }
+        if !line_start {
+            prompt.push(' ');
+        }
+        Some(prompt)
+    }
+
+    /// What the segment `name` shows, and its color, or `None` if it shows
+    /// nothing.
+    fn segment(&mut self, engine: &Engine, name: &str) -> Option<(String, Color)> {
+        let dir = || match engine.get_value_of("PWD") {
+            Some(pwd) => PathBuf::from(pwd),
+            None => env::current_dir().unwrap_or_default(),
+        };
+
+        match name {
+            "cwd" => Some((
+                prompt::expand_escapes("\\w", engine),
+                Colors::prompt(engine),
+            )),
+
+            "git" => {
+                let branch = git_branch(&dir())?;
+                Some((
+                    quote::escape_controls(&branch).into_owned(),
+                    Colors::cmd_sub(engine),
+                ))
+            }
+
+            "status" if engine.last_status.is_ok() => None,
+            "status" => Some((
+                format!("✗ {}", engine.last_status.to_string()),
+                Colors::invalid_cmd(engine),
+            )),
+
+            "duration" => {
+                let min = engine
+                    .get_value_of("PSH_PROMPT_MIN_DURATION")
+                    .and_then(|secs| secs.parse().ok())
+                    .map_or(MIN_DURATION, Duration::from_secs_f64);
+                let duration = self.duration.filter(|&duration| duration >= min)?;
+                Some((status::format_duration(duration), Colors::comment(engine)))
+            }
+
+            "jobs" => match engine.jobs.iter().count() {
+                0 => None,
+                1 => Some(("1 job".to_string(), Colors::op(engine))),
+                n => Some((format!("{n} jobs"), Colors::op(engine))),
+            },
+
+            "python" => {
+                let env = python_env(
+                    engine.get_value_of("VIRTUAL_ENV").as_deref(),
+                    engine.get_value_of("CONDA_DEFAULT_ENV").as_deref(),
+                )?;
+                Some((
+                    format!("({})", quote::escape_controls(&env)),
+                    Colors::rhs(engine),
+                ))
+            }
+
+            "node" => {
+                find_upwards(&dir(), "package.json")?;
+                let node = match self.node_version(engine) {
+                    Some(version) => format!("node {}", quote::escape_controls(&version)),
+                    None => "node".to_string(),
+                };
+                Some((node, Colors::rhs(engine)))
+            }
+
+            text => Some((prompt::expand_escapes(text, engine), Colors::prompt(engine))),
+        }
+    }
+
+    /// The version of node in `$PATH`, such as `v20.11.0`.
+    fn node_version(&mut self, engine: &Engine) -> Option<String> {
+        let path = engine.get_value_of("PATH").unwrap_or_default();
+        if self
+            .node
+            .as_ref()
+            .is_none_or(|(looked_up, _)| *looked_up != path)
+        {
+            let version = Command::new("node")
+                .arg("--version")
+                .env("PATH", &path)
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+            self.node = Some((path, version));
+        }
+        self.node.as_ref()?.1.clone()
+    }
+}
+
+/// `color` as the escape sequence that sets it, marked as taking up no room.
+fn invisible(color: Color) -> String {
+    let mut escape = String::from(START_INVISIBLE);
+    SetForegroundColor(color)
+        .write_ansi(&mut escape)
+        .expect("writing to a string cannot fail");
+    escape.push(END_INVISIBLE);
+    escape
+}
+
+/// The first directory from `dir` up that has an entry called `name`, and
+/// that entry.
+fn find_upwards(dir: &Path, name: &str) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(name))
+        .find(|path| path.exists())
+}
+
+/// The branch checked out in the git repository that `dir` is in, or the
+/// start of the commit if none is, read from its files rather than by
+/// running git, which would slow down every prompt.
+fn git_branch(dir: &Path) -> Option<String> {
+    let dot_git = find_upwards(dir, ".git")?;
+    // the `.git` of a worktree or a submodule is a file pointing to where
+    // the repository is
+    let git_dir = match fs::read_to_string(&dot_git) {
+        Ok(link) => dot_git.parent()?.join(link.strip_prefix("gitdir:")?.trim()),
+        Err(_) => dot_git,
+    };
+
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        ),
+        None => head.get(..7).map(String::from),
+    }
+}
+
+/// The name of the Python environment that is active, from the variables
+/// that `venv` and conda set.
+fn python_env(virtual_env: Option<&str>, conda_env: Option<&str>) -> Option<String> {
+    let venv = virtual_env
+        .filter(|venv| !venv.is_empty())
+        .and_then(|venv| Path::new(venv).file_name())
+        .map(|name| name.to_string_lossy().into_owned());
+    venv.or_else(|| conda_env.filter(|env| !env.is_empty()).map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use psh_core::engine::history::DummyHistory;
+    use psh_core::test_util::TempDir;
+    use psh_core::ExitStatus;
+
+    use super::*;
+
+    #[test]
+    fn git_branches() {
+        let dir = TempDir::new("segments");
+        let git = dir.join("repo/.git");
+        fs::create_dir_all(git.join("refs")).unwrap();
+        fs::create_dir_all(dir.join("repo/src/deep")).unwrap();
+        fs::create_dir_all(dir.join("worktree")).unwrap();
+
+        fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert_eq!(
+            Some("main".to_string()),
+            git_branch(&dir.join("repo/src/deep"))
+        );
+
+        fs::write(git.join("HEAD"), "4e617e7a1b2c3d4e5f\n").unwrap();
+        assert_eq!(Some("4e617e7".to_string()), git_branch(&dir.join("repo")));
+
+        let linked = dir.join("repo/.git/worktrees/other");
+        fs::create_dir_all(&linked).unwrap();
+        fs::write(linked.join("HEAD"), "ref: refs/heads/feature/x\n").unwrap();
+        fs::write(
+            dir.join("worktree/.git"),
+            format!("gitdir: {}\n", linked.display()),
+        )
+        .unwrap();
+        assert_eq!(
+            Some("feature/x".to_string()),
+            git_branch(&dir.join("worktree"))
+        );
+    }
+
+    #[test]
+    fn joining() {
+        let mut engine = Engine::with_history(Box::new(DummyHistory));
+        engine.variables.set("PSH_COLOR", "never").unwrap();
+        engine
+            .variables
+            .set("PSH_PROMPT_MIN_DURATION", "0.5")
+            .unwrap();
+        let mut segments = Segments::default();
+        assert_eq!(None, segments.prompt(&engine));
+
+        // segments with nothing to show are left out
+        engine
+            .variables
+            .set("PSH_PROMPT_SEGMENTS", "status duration")
+            .unwrap();
+        assert_eq!(None, segments.prompt(&engine));
+
+        engine.last_status = ExitStatus::from_code(2);
+        segments.duration = Some(Duration::from_millis(1340));
+        engine
+            .variables
+            .set("PSH_PROMPT_SEGMENTS", "jobs status duration \\n >")
+            .unwrap();
+        assert_eq!(Some("✗ 2 1.3s\n> ".to_string()), segments.prompt(&engine));
+
+        engine.variables.set("PSH_COLOR", "always").unwrap();
+        engine.variables.set("PSH_PROMPT_COL", "blue").unwrap();
+        engine.variables.set("PSH_INVALID_CMD_COL", "red").unwrap();
+        engine
+            .variables
+            .set("PSH_PROMPT_SEGMENTS", "status \\n")
+            .unwrap();
+        assert_eq!(
+            Some("\x01\x1b[38;5;9m\x02✗ 2\x01\x1b[38;5;12m\x02\x01\x1b[38;5;12m\x02\n\x01\x1b[38;5;12m\x02".to_string()),
+            segments.prompt(&engine)
+        );
+    }
+
+    #[test]
+    fn python_envs() {
+        assert_eq!(
+            Some("venv".to_string()),
+            python_env(Some("/src/app/venv"), Some("base"))
+        );
+        assert_eq!(Some("base".to_string()), python_env(Some(""), Some("base")));
+        assert_eq!(None, python_env(None, None));
+    }
+}
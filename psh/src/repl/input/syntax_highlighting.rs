@@ -1,63 +1,62 @@
 use std::collections::HashMap;
-use std::io::stdout;
-
-use crossterm::cursor::{MoveDown, MoveToColumn};
-use crossterm::style::{Print, ResetColor, SetForegroundColor};
-use crossterm::terminal::{Clear, ClearType};
-use crossterm::{execute, queue};
 
 use psh_core::ast::nodes::*;
 use psh_core::engine::expand::Expand;
+use psh_core::engine::quote;
 use psh_core::{Engine, Result};
 
+use crate::repl::input::render::Frame;
 use crate::repl::Colors;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Context {
-    pub start_x: u16,
     pub abbreviations: bool,
 }
 
 pub trait Highlighter {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()>;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()>;
 }
 
 impl Highlighter for SyntaxTree {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.leading.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.leading.write_highlighted(engine, frame, context)?;
 
         if let Some((cmds, linebreak)) = &self.commands {
-            cmds.write_highlighted(engine, context)?;
-            linebreak.write_highlighted(engine, context)?;
+            cmds.write_highlighted(engine, frame, context)?;
+            linebreak.write_highlighted(engine, frame, context)?;
         }
 
         let unparsed_color = Colors::unparsed(engine);
-        queue!(stdout(), SetForegroundColor(unparsed_color))?;
-        for c in self.unparsed.chars() {
-            if c == '\n' {
-                queue!(
-                    stdout(),
-                    MoveToColumn(context.start_x),
-                    MoveDown(1),
-                    Clear(ClearType::UntilNewLine)
-                )?;
-            } else {
-                queue!(stdout(), Print(c))?;
-            }
-        }
-        execute!(stdout(), ResetColor)?;
+        frame.set_color(unparsed_color);
+        frame.print(&self.unparsed);
+        frame.reset_color();
 
         Ok(())
     }
 }
 
 impl Highlighter for CompleteCommands {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.head.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.head.write_highlighted(engine, frame, context)?;
 
         for (newlines, cmd) in &self.tail {
-            newlines.write_highlighted(engine, context)?;
-            cmd.write_highlighted(engine, context)?;
+            newlines.write_highlighted(engine, frame, context)?;
+            cmd.write_highlighted(engine, frame, context)?;
         }
 
         Ok(())
@@ -65,42 +64,47 @@ impl Highlighter for CompleteCommands {
 }
 
 impl Highlighter for CompleteCommand {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
             Self::List {
                 list,
                 separator_op: None,
                 comment: None,
             } => {
-                list.write_highlighted(engine, context)?;
+                list.write_highlighted(engine, frame, context)?;
             }
             Self::List {
                 list,
                 separator_op: Some(separator_op),
                 comment: None,
             } => {
-                list.write_highlighted(engine, context)?;
-                separator_op.write_highlighted(engine, context)?;
+                list.write_highlighted(engine, frame, context)?;
+                separator_op.write_highlighted(engine, frame, context)?;
             }
             Self::List {
                 list,
                 separator_op: None,
                 comment: Some(comment),
             } => {
-                list.write_highlighted(engine, context)?;
-                comment.write_highlighted(engine, context)?;
+                list.write_highlighted(engine, frame, context)?;
+                comment.write_highlighted(engine, frame, context)?;
             }
             Self::List {
                 list,
                 separator_op: Some(separator_op),
                 comment: Some(comment),
             } => {
-                list.write_highlighted(engine, context)?;
-                separator_op.write_highlighted(engine, context)?;
-                comment.write_highlighted(engine, context)?;
+                list.write_highlighted(engine, frame, context)?;
+                separator_op.write_highlighted(engine, frame, context)?;
+                comment.write_highlighted(engine, frame, context)?;
             }
             Self::Comment { comment } => {
-                comment.write_highlighted(engine, context)?;
+                comment.write_highlighted(engine, frame, context)?;
             }
         }
 
@@ -109,12 +113,17 @@ impl Highlighter for CompleteCommand {
 }
 
 impl Highlighter for List {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.head.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.head.write_highlighted(engine, frame, context)?;
 
         for (sep, and_or_list) in &self.tail {
-            sep.write_highlighted(engine, context)?;
-            and_or_list.write_highlighted(engine, context)?;
+            sep.write_highlighted(engine, frame, context)?;
+            and_or_list.write_highlighted(engine, frame, context)?;
         }
 
         Ok(())
@@ -122,13 +131,18 @@ impl Highlighter for List {
 }
 
 impl Highlighter for AndOrList {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.head.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.head.write_highlighted(engine, frame, context)?;
 
         for (op, linebreak, pipeline) in &self.tail {
-            op.write_highlighted(engine, context)?;
-            linebreak.write_highlighted(engine, context)?;
-            pipeline.write_highlighted(engine, context)?;
+            op.write_highlighted(engine, frame, context)?;
+            linebreak.write_highlighted(engine, frame, context)?;
+            pipeline.write_highlighted(engine, frame, context)?;
         }
 
         Ok(())
@@ -136,138 +150,391 @@ impl Highlighter for AndOrList {
 }
 
 impl Highlighter for Pipeline {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         if let Some(bang) = &self.bang {
-            bang.write_highlighted(engine, context)?;
+            bang.write_highlighted(engine, frame, context)?;
         }
 
-        self.sequence.write_highlighted(engine, context)?;
+        self.sequence.write_highlighted(engine, frame, context)?;
 
         Ok(())
     }
 }
 
 impl Highlighter for PipeSequence {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.head.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.head.write_highlighted(engine, frame, context)?;
         for (pipe, linebreak, cmd) in &self.tail {
-            pipe.write_highlighted(engine, context)?;
-            linebreak.write_highlighted(engine, context)?;
-            cmd.write_highlighted(engine, context)?;
+            pipe.write_highlighted(engine, frame, context)?;
+            linebreak.write_highlighted(engine, frame, context)?;
+            cmd.write_highlighted(engine, frame, context)?;
         }
         Ok(())
     }
 }
 
 impl Highlighter for Command {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
-            Command::Simple(cmd) => cmd.write_highlighted(engine, context),
+            Command::Simple(cmd) => cmd.write_highlighted(engine, frame, context),
             Command::Compound(cmd, redirections) => {
-                cmd.write_highlighted(engine, context)?;
+                cmd.write_highlighted(engine, frame, context)?;
                 for redirection in redirections {
-                    redirection.write_highlighted(engine, context)?;
+                    redirection.write_highlighted(engine, frame, context)?;
                 }
                 Ok(())
             }
-            Command::FunctionDefinition(func_def) => func_def.write_highlighted(engine, context),
+            Command::FunctionDefinition(func_def) => {
+                func_def.write_highlighted(engine, frame, context)
+            }
         }
     }
 }
 
 impl Highlighter for CompoundCommand {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
-            CompoundCommand::Brace(brace_group) => brace_group.write_highlighted(engine, context),
-            CompoundCommand::Subshell(_) => todo!(),
-            CompoundCommand::For(_) => todo!(),
-            CompoundCommand::Case(_) => todo!(),
-            CompoundCommand::If(_) => todo!(),
-            CompoundCommand::While(_) => todo!(),
-            CompoundCommand::Until(_) => todo!(),
+            CompoundCommand::Brace(brace_group) => {
+                brace_group.write_highlighted(engine, frame, context)
+            }
+            CompoundCommand::Subshell(subshell) => {
+                subshell.write_highlighted(engine, frame, context)
+            }
+            CompoundCommand::For(for_clause) => {
+                for_clause.write_highlighted(engine, frame, context)
+            }
+            CompoundCommand::Case(case_clause) => {
+                case_clause.write_highlighted(engine, frame, context)
+            }
+            CompoundCommand::If(if_clause) => if_clause.write_highlighted(engine, frame, context),
+            CompoundCommand::While(while_clause) => {
+                while_clause.write_highlighted(engine, frame, context)
+            }
+            CompoundCommand::Until(until_clause) => {
+                until_clause.write_highlighted(engine, frame, context)
+            }
         }
     }
 }
 
 impl Highlighter for CompoundList {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.linebreak.write_highlighted(engine, context)?;
-        self.term.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.linebreak.write_highlighted(engine, frame, context)?;
+        self.term.write_highlighted(engine, frame, context)?;
         if let Some(separator) = &self.separator {
-            separator.write_highlighted(engine, context)?;
+            separator.write_highlighted(engine, frame, context)?;
         }
         Ok(())
     }
 }
 
 impl Highlighter for Term {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.head.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.head.write_highlighted(engine, frame, context)?;
         for (sep, and_or) in &self.tail {
-            sep.write_highlighted(engine, context)?;
-            and_or.write_highlighted(engine, context)?;
+            sep.write_highlighted(engine, frame, context)?;
+            and_or.write_highlighted(engine, frame, context)?;
         }
         Ok(())
     }
 }
 
 impl Highlighter for FunctionDefinition {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         let color = Colors::normal(engine);
-        self.name.write_highlighted(engine, context)?;
-        queue!(
-            stdout(),
-            SetForegroundColor(color),
-            Print(&self.parens),
-            ResetColor
-        )?;
-        self.linebreak.write_highlighted(engine, context)?;
-        self.body.write_highlighted(engine, context)?;
+        self.name.write_highlighted(engine, frame, context)?;
+        frame.set_color(color);
+        frame.print(&self.parens);
+        frame.reset_color();
+        self.linebreak.write_highlighted(engine, frame, context)?;
+        if let Some(body) = &self.body {
+            body.write_highlighted(engine, frame, context)?;
+        }
 
         Ok(())
     }
 }
 
 impl Highlighter for FunctionBody {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        self.command.write_highlighted(engine, context)?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.command.write_highlighted(engine, frame, context)?;
         for redirection in &self.redirections {
-            redirection.write_highlighted(engine, context)?;
+            redirection.write_highlighted(engine, frame, context)?;
         }
         Ok(())
     }
 }
 
 impl Highlighter for BraceGroup {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        let separator_color = Colors::separator(engine);
-        queue!(
-            stdout(),
-            SetForegroundColor(separator_color),
-            Print(&self.lbrace_ws),
-            Print('{'),
-            ResetColor
-        )?;
-        self.body.write_highlighted(engine, context)?;
-        queue!(
-            stdout(),
-            SetForegroundColor(separator_color),
-            Print(&self.rbrace_ws),
-            Print('}'),
-            ResetColor
-        )?;
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.lbrace_ws), "{");
+        self.body.write_highlighted(engine, frame, context)?;
+        write_reserved_word(engine, frame, self.rbrace_ws.as_ref(), "}");
+        Ok(())
+    }
+}
+
+impl Highlighter for Subshell {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.lparen_ws), "(");
+        self.body.write_highlighted(engine, frame, context)?;
+        write_reserved_word(engine, frame, self.rparen_ws.as_ref(), ")");
+        Ok(())
+    }
+}
+
+impl Highlighter for ForClause {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.for_ws), "for");
+        if let Some(name) = &self.name {
+            name.write_highlighted(engine, frame, context)?;
+        }
+        if let Some(wordlist) = &self.wordlist {
+            wordlist
+                .linebreak
+                .write_highlighted(engine, frame, context)?;
+            write_reserved_word(engine, frame, Some(&wordlist.in_ws), "in");
+            for word in &wordlist.words {
+                word.write_highlighted(engine, frame, context)?;
+            }
+        }
+        if let Some(separator) = &self.separator {
+            separator.write_highlighted(engine, frame, context)?;
+        }
+        self.body.write_highlighted(engine, frame, context)
+    }
+}
+
+impl Highlighter for CaseClause {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.case_ws), "case");
+        if let Some(word) = &self.word {
+            word.write_highlighted(engine, frame, context)?;
+        }
+        if let Some((linebreak, in_ws)) = &self.in_ws {
+            linebreak.write_highlighted(engine, frame, context)?;
+            write_reserved_word(engine, frame, Some(in_ws), "in");
+        }
+        for item in &self.items {
+            item.write_highlighted(engine, frame, context)?;
+        }
+        if let Some((linebreak, esac_ws)) = &self.esac_ws {
+            linebreak.write_highlighted(engine, frame, context)?;
+            write_reserved_word(engine, frame, Some(esac_ws), "esac");
+        }
         Ok(())
     }
 }
 
+impl Highlighter for CaseItem {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        self.linebreak.write_highlighted(engine, frame, context)?;
+        write_reserved_word(engine, frame, self.lparen_ws.as_ref(), "(");
+        self.pattern
+            .head
+            .write_highlighted(engine, frame, context)?;
+        for (ws, word) in &self.pattern.tail {
+            write_reserved_word(engine, frame, Some(ws), "|");
+            word.write_highlighted(engine, frame, context)?;
+        }
+        write_reserved_word(engine, frame, self.rparen_ws.as_ref(), ")");
+        if let Some(body) = &self.body {
+            body.write_highlighted(engine, frame, context)?;
+        }
+        if let Some((linebreak, dsemi_ws)) = &self.dsemi_ws {
+            linebreak.write_highlighted(engine, frame, context)?;
+            write_reserved_word(engine, frame, Some(dsemi_ws), ";;");
+        }
+        Ok(())
+    }
+}
+
+impl Highlighter for IfClause {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.if_ws), "if");
+        self.predicate.write_highlighted(engine, frame, context)?;
+        write_reserved_word(engine, frame, self.then_ws.as_ref(), "then");
+        self.body.write_highlighted(engine, frame, context)?;
+        if let Some(else_part) = &self.else_part {
+            else_part.write_highlighted(engine, frame, context)?;
+        }
+        write_reserved_word(engine, frame, self.fi_ws.as_ref(), "fi");
+        Ok(())
+    }
+}
+
+impl Highlighter for ElsePart {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        for elif in &self.elseifs {
+            write_reserved_word(engine, frame, Some(&elif.elif_ws), "elif");
+            elif.predicate.write_highlighted(engine, frame, context)?;
+            write_reserved_word(engine, frame, elif.then_ws.as_ref(), "then");
+            elif.body.write_highlighted(engine, frame, context)?;
+        }
+        if let Some((else_ws, body)) = &self.else_part {
+            write_reserved_word(engine, frame, Some(else_ws), "else");
+            body.write_highlighted(engine, frame, context)?;
+        }
+        Ok(())
+    }
+}
+
+impl Highlighter for WhileClause {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.while_ws), "while");
+        self.predicate.write_highlighted(engine, frame, context)?;
+        self.body.write_highlighted(engine, frame, context)
+    }
+}
+
+impl Highlighter for UntilClause {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, Some(&self.until_ws), "until");
+        self.predicate.write_highlighted(engine, frame, context)?;
+        self.body.write_highlighted(engine, frame, context)
+    }
+}
+
+impl Highlighter for DoGroup {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        write_reserved_word(engine, frame, self.do_ws.as_ref(), "do");
+        self.body.write_highlighted(engine, frame, context)?;
+        write_reserved_word(engine, frame, self.done_ws.as_ref(), "done");
+        Ok(())
+    }
+}
+
+/// Writes a reserved word of a compound command and the whitespace before
+/// it, if it is there yet.
+fn write_reserved_word(
+    engine: &Engine,
+    frame: &mut Frame,
+    ws: Option<&LeadingWhitespace>,
+    word: &str,
+) {
+    if let Some(ws) = ws {
+        frame.set_color(Colors::separator(engine));
+        frame.print(ws);
+        frame.print(word);
+        frame.reset_color();
+    }
+}
+
 impl Highlighter for SimpleCommand {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         for prefix in &self.prefixes {
-            prefix.write_highlighted(engine, context)?;
+            prefix.write_highlighted(engine, frame, context)?;
         }
 
         if let Some(name) = &self.name {
-            let args = name.clone().expand(engine);
+            // command substitutions and arithmetic expansions can have side
+            // effects, so they are not performed while typing, and a name
+            // containing one is assumed to be valid
+            let has_cmd_sub = name.expansions.iter().any(|exp| {
+                matches!(
+                    exp,
+                    Expansion::Command { .. } | Expansion::Arithmetic { .. }
+                )
+            });
+            let args = match has_cmd_sub {
+                true => Vec::new(),
+                false => name.clone().expand(engine),
+            };
 
             let has_cmd = |cmd| {
                 engine.has_executable(cmd)
@@ -275,19 +542,18 @@ impl Highlighter for SimpleCommand {
             };
 
             let cmd_color = match args.first() {
+                _ if has_cmd_sub => Colors::valid_cmd(engine),
                 Some(name) if has_cmd(name) => Colors::valid_cmd(engine),
                 _ => Colors::invalid_cmd(engine),
             };
 
-            queue!(stdout(), SetForegroundColor(cmd_color))?;
-
-            name.write_highlighted(engine, context)?;
-
-            queue!(stdout(), ResetColor)?;
+            frame.set_color(cmd_color);
+            name.write_highlighted(engine, frame, context)?;
+            frame.reset_color();
         }
 
         for suffix in &self.suffixes {
-            suffix.write_highlighted(engine, context)?;
+            suffix.write_highlighted(engine, frame, context)?;
         }
 
         Ok(())
@@ -295,34 +561,47 @@ impl Highlighter for SimpleCommand {
 }
 
 impl Highlighter for CmdPrefix {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
-            Self::Redirection(r) => r.write_highlighted(engine, context),
-            Self::Assignment(a) => a.write_highlighted(engine, context),
+            Self::Redirection(r) => r.write_highlighted(engine, frame, context),
+            Self::Assignment(a) => a.write_highlighted(engine, frame, context),
         }
     }
 }
 
 impl Highlighter for CmdSuffix {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
             Self::Word(w) => {
                 let color = Colors::normal(engine);
-                queue!(stdout(), SetForegroundColor(color))?;
-
-                w.write_highlighted(engine, context)?;
-
-                queue!(stdout(), ResetColor)?;
+                frame.set_color(color);
+                w.write_highlighted(engine, frame, context)?;
+                frame.reset_color();
 
                 Ok(())
             }
-            Self::Redirection(r) => r.write_highlighted(engine, context),
+            Self::Redirection(r) => r.write_highlighted(engine, frame, context),
         }
     }
 }
 
 impl Highlighter for Redirection {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         let lhs_color = Colors::lhs(engine);
         let op_color = Colors::op(engine);
         let rhs_color = Colors::rhs(engine);
@@ -334,22 +613,17 @@ impl Highlighter for Redirection {
                 ty,
                 target,
             } => {
-                queue!(
-                    stdout(),
-                    Print(whitespace),
-                    SetForegroundColor(lhs_color),
-                    Print(if let Some(fd) = input_fd {
-                        fd.to_string()
-                    } else {
-                        String::new()
-                    }),
-                    SetForegroundColor(op_color),
-                    Print(ty.to_string()),
-                    SetForegroundColor(rhs_color),
-                    ResetColor,
-                )?;
-                target.write_highlighted(engine, context)?;
-                queue!(stdout(), ResetColor)?;
+                frame.print(whitespace);
+                frame.set_color(lhs_color);
+                if let Some(fd) = input_fd {
+                    frame.print(fd.to_string());
+                }
+                frame.set_color(op_color);
+                frame.print(ty.to_string());
+                frame.set_color(rhs_color);
+                frame.reset_color();
+                target.write_highlighted(engine, frame, context)?;
+                frame.reset_color();
                 Ok(())
             }
             Redirection::Here {
@@ -357,26 +631,19 @@ impl Highlighter for Redirection {
                 input_fd,
                 ty,
                 end,
-                content,
+                ..
             } => {
-                queue!(
-                    stdout(),
-                    Print(whitespace),
-                    SetForegroundColor(lhs_color),
-                    Print(if let Some(fd) = input_fd {
-                        fd.to_string()
-                    } else {
-                        String::new()
-                    }),
-                    SetForegroundColor(op_color),
-                    Print(ty.to_string()),
-                    SetForegroundColor(rhs_color),
-                    ResetColor,
-                    Print(end.to_string())
-                )?;
-                end.write_highlighted(engine, context)?;
-                content.write_highlighted(engine, context)?;
-                queue!(stdout(), ResetColor)?;
+                frame.print(whitespace);
+                frame.set_color(lhs_color);
+                if let Some(fd) = input_fd {
+                    frame.print(fd.to_string());
+                }
+                frame.set_color(op_color);
+                frame.print(ty.to_string());
+                frame.set_color(rhs_color);
+                frame.reset_color();
+                end.write_highlighted(engine, frame, context)?;
+                frame.reset_color();
                 Ok(())
             }
         }
@@ -384,196 +651,209 @@ impl Highlighter for Redirection {
 }
 
 impl Highlighter for VariableAssignment {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         let lhs_color = Colors::lhs(engine);
         let op_color = Colors::op(engine);
         let rhs_color = Colors::rhs(engine);
 
-        queue!(
-            stdout(),
-            Print(&self.whitespace),
-            SetForegroundColor(lhs_color),
-            Print(self.lhs.to_string()),
-            SetForegroundColor(op_color),
-            Print('='),
-            SetForegroundColor(rhs_color),
-        )?;
+        frame.print(&self.whitespace);
+        frame.set_color(lhs_color);
+        frame.print(self.lhs.to_string());
+        frame.set_color(op_color);
+        frame.print('=');
+        frame.set_color(rhs_color);
 
         if let Some(rhs) = &self.rhs {
-            rhs.write_highlighted(engine, context)?;
+            rhs.write_highlighted(engine, frame, context)?;
         }
 
-        queue!(stdout(), ResetColor)?;
+        frame.reset_color();
         Ok(())
     }
 }
 
 impl Highlighter for NewlineList {
-    fn write_highlighted(&self, _: &mut Engine, context: Context) -> Result<()> {
-        let mut lines = self.whitespace.split('\n').peekable();
-
-        let first = lines.next().unwrap();
-        queue!(stdout(), Clear(ClearType::UntilNewLine), Print(first))?;
-
-        for line in lines {
-            queue!(
-                stdout(),
-                MoveToColumn(context.start_x),
-                MoveDown(1),
-                Clear(ClearType::UntilNewLine),
-                Print(line)
-            )?;
-        }
-
+    fn write_highlighted(&self, _: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
+        frame.print(&self.whitespace);
         Ok(())
     }
 }
 
 impl Highlighter for Linebreak {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         if let Some(newlines) = &self.newlines {
-            newlines.write_highlighted(engine, context)?;
+            newlines.write_highlighted(engine, frame, context)?;
         }
         Ok(())
     }
 }
 
 impl Highlighter for SeparatorOp {
-    fn write_highlighted(&self, engine: &mut Engine, _: Context) -> Result<()> {
+    fn write_highlighted(&self, engine: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
         let separator_color = Colors::separator(engine);
-        queue!(
-            stdout(),
-            SetForegroundColor(separator_color),
-            Print(self.to_string()),
-            ResetColor
-        )?;
+        frame.set_color(separator_color);
+        frame.print(self.to_string());
+        frame.reset_color();
         Ok(())
     }
 }
 
 impl Highlighter for Separator {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
         match self {
             Separator::Explicit(op, linebreak) => {
-                op.write_highlighted(engine, context)?;
-                linebreak.write_highlighted(engine, context)
+                op.write_highlighted(engine, frame, context)?;
+                linebreak.write_highlighted(engine, frame, context)
+            }
+            Separator::Implicit(newlines) => newlines.write_highlighted(engine, frame, context),
+        }
+    }
+}
+
+impl Highlighter for SequentialSeparator {
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        match self {
+            SequentialSeparator::Semi(ws, linebreak) => {
+                write_reserved_word(engine, frame, Some(ws), ";");
+                linebreak.write_highlighted(engine, frame, context)
+            }
+            SequentialSeparator::Implicit(newlines) => {
+                newlines.write_highlighted(engine, frame, context)
             }
-            Separator::Implicit(newlines) => newlines.write_highlighted(engine, context),
         }
     }
 }
 
 impl Highlighter for LogicalOp {
-    fn write_highlighted(&self, engine: &mut Engine, _: Context) -> Result<()> {
+    fn write_highlighted(&self, engine: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
         let separator_color = Colors::separator(engine);
-        Ok(queue!(
-            stdout(),
-            SetForegroundColor(separator_color),
-            Print(self.to_string()),
-            ResetColor
-        )?)
+        frame.set_color(separator_color);
+        frame.print(self.to_string());
+        frame.reset_color();
+        Ok(())
     }
 }
 
 impl Highlighter for Name {
-    fn write_highlighted(&self, _: &mut Engine, _: Context) -> Result<()> {
-        queue!(stdout(), Print(self.to_string()))?;
+    fn write_highlighted(&self, _: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
+        frame.print(self.to_string());
         Ok(())
     }
 }
 
 impl Highlighter for Bang {
-    fn write_highlighted(&self, engine: &mut Engine, _: Context) -> Result<()> {
+    fn write_highlighted(&self, engine: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
         let separator_color = Colors::separator(engine);
-        queue!(
-            stdout(),
-            SetForegroundColor(separator_color),
-            Print(self.to_string()),
-            ResetColor
-        )?;
+        frame.set_color(separator_color);
+        frame.print(self.to_string());
+        frame.reset_color();
         Ok(())
     }
 }
 
 impl Highlighter for Comment {
-    fn write_highlighted(&self, engine: &mut Engine, _: Context) -> Result<()> {
+    fn write_highlighted(&self, engine: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
         let color = Colors::comment(engine);
-        queue!(
-            stdout(),
-            SetForegroundColor(color),
-            Print(self.to_string()),
-            ResetColor
-        )?;
+        frame.set_color(color);
+        frame.print(self.to_string());
+        frame.reset_color();
         Ok(())
     }
 }
 
 impl Highlighter for Pipe {
-    fn write_highlighted(&self, engine: &mut Engine, _: Context) -> Result<()> {
+    fn write_highlighted(&self, engine: &mut Engine, frame: &mut Frame, _: Context) -> Result<()> {
         let color = Colors::separator(engine);
-        queue!(
-            stdout(),
-            SetForegroundColor(color),
-            Print(self.to_string()),
-            ResetColor,
-        )?;
+        frame.set_color(color);
+        frame.print(self.to_string());
+        frame.reset_color();
         Ok(())
     }
 }
 
 impl Highlighter for Word {
-    fn write_highlighted(&self, engine: &mut Engine, context: Context) -> Result<()> {
-        let mut chars = self.name.chars().peekable().enumerate();
+    fn write_highlighted(
+        &self,
+        engine: &mut Engine,
+        frame: &mut Frame,
+        context: Context,
+    ) -> Result<()> {
+        let mut chars = self.name.char_indices().enumerate();
 
         let mut cmd_sub_starts = HashMap::new();
         for exp in &self.expansions {
             if let Expansion::Command {
                 range,
+                part,
                 tree,
                 finished,
                 ..
             } = exp
             {
-                cmd_sub_starts.insert(*range.start(), (*range.end(), tree, finished));
+                cmd_sub_starts.insert(*range.start(), (*range.end(), part, tree, finished));
             }
         }
 
-        queue!(
-            stdout(),
-            Clear(ClearType::UntilNewLine),
-            Print(&self.whitespace)
-        )?;
+        frame.print(&self.whitespace);
+
+        // quoted text, including the quotes, is colored as a string on top
+        // of the color of the word, if strings have a color of their own
+        let quoted = quote::quoted_spans(&self.name);
+        let string_color = Colors::string(engine);
+        let color = frame.color();
 
         let cmd_sub_color = Colors::cmd_sub(engine);
-        while let Some((i, c)) = chars.next() {
-            if let Some((end, tree, &finished)) = cmd_sub_starts.get(&i) {
-                queue!(
-                    stdout(),
-                    SetForegroundColor(cmd_sub_color),
-                    Print("$("),
-                    ResetColor
-                )?;
-                tree.write_highlighted(engine, context)?;
-                if finished {
-                    queue!(
-                        stdout(),
-                        SetForegroundColor(cmd_sub_color),
-                        Print(')'),
-                        ResetColor
-                    )?;
+        while let Some((i, (at, c))) = chars.next() {
+            if let Some((end, part, tree, &finished)) = cmd_sub_starts.get(&i) {
+                // the commands between backticks are not highlighted, since
+                // their backslashes are removed before they are parsed
+                if part.starts_with('`') {
+                    frame.set_color(cmd_sub_color);
+                    frame.print(part);
+                    frame.reset_color();
+                } else {
+                    frame.set_color(cmd_sub_color);
+                    frame.print("$(");
+                    frame.reset_color();
+                    tree.write_highlighted(engine, frame, context)?;
+                    if finished {
+                        frame.set_color(cmd_sub_color);
+                        frame.print(')');
+                        frame.reset_color();
+                    }
                 }
                 for _ in i..*end {
                     chars.next();
                 }
-            } else if c == '\n' {
-                queue!(
-                    stdout(),
-                    MoveToColumn(context.start_x),
-                    MoveDown(1),
-                    Clear(ClearType::UntilNewLine)
-                )?;
             } else {
-                queue!(stdout(), Print(c))?;
+                match string_color {
+                    Some(string_color) if quoted.iter().any(|span| span.contains(&at)) => {
+                        frame.set_color(string_color);
+                        frame.print(c);
+                        frame.restore_color(color);
+                    }
+                    _ => frame.print(c),
+                }
             }
         }
 
@@ -0,0 +1,69 @@
+//! Running a command over and over on the alternate screen, like `watch`,
+//! which is handy for polling things like `kubectl get pods`.
+
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor;
+use crossterm::event::{self, Event};
+use crossterm::execute;
+use crossterm::style;
+use crossterm::terminal;
+
+use psh_core::{Engine, Error, Result};
+
+use crate::config::Colors;
+
+/// The seconds between runs, unless `PSH_WATCH_INTERVAL` says otherwise.
+const DEFAULT_INTERVAL: f64 = 2.0;
+
+/// Runs `command` every `PSH_WATCH_INTERVAL` seconds until a key is
+/// pressed, showing only the output of the latest run. Expects the
+/// terminal to be in raw mode, and leaves it that way.
+pub fn watch(engine: &mut Engine, command: &str) -> Result<()> {
+    let interval = engine
+        .get_value_of("PSH_WATCH_INTERVAL")
+        .and_then(|interval| interval.parse::<f64>().ok())
+        .filter(|interval| interval.is_finite() && *interval > 0.0)
+        .unwrap_or(DEFAULT_INTERVAL);
+
+    execute!(stdout(), terminal::EnterAlternateScreen)?;
+    let result = run(engine, command, interval);
+    execute!(stdout(), terminal::LeaveAlternateScreen)?;
+    result
+}
+
+fn run(engine: &mut Engine, command: &str, interval: f64) -> Result<()> {
+    loop {
+        execute!(
+            stdout(),
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0),
+            style::SetForegroundColor(Colors::comment(engine)),
+            style::Print(format!("Every {interval}s: {command}")),
+            style::ResetColor,
+            style::Print("\r\n\r\n"),
+        )?;
+
+        terminal::disable_raw_mode()?;
+        match engine.execute_line(command) {
+            Ok(statuses) => {
+                if let Some(&status) = statuses.last() {
+                    engine.last_status = status;
+                }
+            }
+            Err(Error::Cancelled) => {}
+            Err(e) => eprintln!("psh: {e}"),
+        }
+        stdout().flush()?;
+        engine.cancel.reset();
+        terminal::enable_raw_mode()?;
+
+        let next = Instant::now() + Duration::from_secs_f64(interval);
+        while let Some(left) = next.checked_duration_since(Instant::now()) {
+            if event::poll(left)? && matches!(event::read()?, Event::Key(_)) {
+                return Ok(());
+            }
+        }
+    }
+}
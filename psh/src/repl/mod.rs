@@ -1,86 +1,183 @@
 pub mod input;
+mod status;
 
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
 use std::process;
+use std::time::Instant;
 
-use crossterm::terminal;
+use crossterm::{cursor, terminal};
 
-use psh_core::{ast, path, tok, Engine, Error, Result};
+#[cfg(feature = "serde")]
+use psh_core::engine::snapshot::EnvSnapshot;
+use psh_core::engine::terminal::Terminal;
+use psh_core::engine::{jump, log};
+use psh_core::{path, Engine, Error, ExitStatus, Result};
 
 use crate::config::{self, Colors};
+use crate::crash;
 
 pub struct Repl {
     engine: Engine,
+    editor: input::Editor,
+    init_files: InitFiles,
+
+    /// How many times the terminal had been handed to a job when the last
+    /// prompt was shown.
+    handoffs: usize,
+
+    /// The setup loaded with `--load-env`, restored once the init files have
+    /// been read so that they do not override it.
+    #[cfg(feature = "serde")]
+    pub env: Option<EnvSnapshot>,
 }
 
 impl Repl {
-    pub fn new() -> Self {
+    pub fn new(mut engine: Engine, init_files: InitFiles) -> Self {
+        engine.interactive = true;
+        engine.chpwd.push(jump::record);
         Self {
-            engine: Engine::default(),
+            engine,
+            editor: Default::default(),
+            init_files,
+            handoffs: 0,
+            #[cfg(feature = "serde")]
+            env: None,
         }
     }
 
-    fn read_init_file(&mut self) -> Result<()> {
-        match self.engine.execute_file(path::init_file()) {
-            Ok(_) => Ok(()),
-            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
-            Err(e) => Err(e),
+    pub fn run(&mut self) -> Result<()> {
+        if let Err(e) = self.engine.enable_job_control() {
+            eprintln!("psh: Could not enable job control: {e}");
+        }
+        self.engine.terminal = Terminal::save(0);
+        self.init_files.read(&mut self.engine);
+        #[cfg(feature = "serde")]
+        if let Some(snapshot) = self.env.take() {
+            snapshot.restore(&mut self.engine);
+        }
+        if self.engine.options.inputrc {
+            input::inputrc::load(&mut self.engine, &mut self.editor);
         }
-    }
-
-    pub fn run(&mut self, lex: bool, ast: bool, _json: bool) -> Result<()> {
-        self.read_init_file()?;
 
         if self.engine.get_value_of("PS1").is_none() {
-            self.engine.assignments.insert(
-                "PS1".to_string(),
+            self.engine.variables.set(
+                "PS1",
                 match is_root() {
                     true => config::PS1_ROOT_PROMPT,
                     false => config::PS1_USER_PROMPT,
-                }
-                .to_string(),
-            );
+                },
+            )?;
         }
         if self.engine.get_value_of("PS2").is_none() {
-            self.engine
-                .assignments
-                .insert("PS2".to_string(), config::PS2_PROMPT.to_string());
+            self.engine.variables.set("PS2", config::PS2_PROMPT)?;
         }
 
-        ctrlc::set_handler(|| {}).expect("psh: Error setting ^C handler");
+        let cancel = self.engine.cancel.clone();
+        ctrlc::set_handler(move || cancel.cancel()).expect("psh: Error setting ^C handler");
 
         loop {
-            let line = input::read_full_command(&mut self.engine)?;
-
-            if lex && line != "exit" {
-                for token in tok::lex(line) {
-                    println!("{token:?}");
-                }
-            } else if ast && line != "exit" {
-                let ast = ast::parse(line, true)?;
-
-                #[cfg(feature = "serde")]
-                if _json {
-                    println!("{}", ast.as_json()?);
-                } else {
-                    println!("{ast:#?}");
+            // a panic is a bug in psh, which is reported, but should not
+            // take the whole shell down with it
+            let mut line = None;
+            match panic::catch_unwind(AssertUnwindSafe(|| self.read_and_execute(&mut line))) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    self.engine.log(log::Kind::Terminal, &e);
+                    return Err(e);
                 }
+                Err(_) => crash::report(&self.engine, line.as_deref()),
+            }
+        }
+    }
 
-                #[cfg(not(feature = "serde"))]
-                println!("{ast:#?}");
-            } else {
-                self.engine.history.append(&line)?;
-                match self.engine.execute_line(line) {
-                    Ok(statuses) if statuses.is_empty() => {}
+    /// Reads a command and executes it, keeping the line in `line` in case
+    /// executing it panics.
+    fn read_and_execute(&mut self, line: &mut Option<String>) -> Result<()> {
+        for job in self.engine.notify_jobs() {
+            eprintln!("{job}");
+        }
 
-                    Ok(statuses) => {
-                        self.engine.last_status = statuses;
-                    }
+        // a command that e.g. crashed may have left the terminal unusable
+        let handed_off = self.engine.terminal_handoffs != self.handoffs;
+        self.handoffs = self.engine.terminal_handoffs;
+        if let Some(terminal) = &self.engine.terminal {
+            if let Err(e) = terminal.repair(handed_off) {
+                self.engine.log(log::Kind::Terminal, &e);
+            }
+        }
 
-                    Err(e) => {
-                        eprintln!("psh: {e}");
+        let input = input::read_full_command(&mut self.engine, &mut self.editor)?;
+        let line = line.insert(input.line.clone());
+        let after = cursor::position()?;
+
+        self.engine.history.append(line)?;
+        self.engine.cancel.reset();
+        let started = Instant::now();
+        let executed = self.engine.execute_line(line.as_str());
+        self.editor.segments.duration = Some(started.elapsed());
+        match executed {
+            Ok(statuses) => {
+                if let Some(&status) = statuses.last() {
+                    self.engine.last_status = status;
+                    if self.engine.options.cmd_status {
+                        status::show(&self.engine, &input, after, status, started.elapsed())?;
                     }
                 }
             }
+
+            Err(Error::Cancelled) => {
+                self.engine.last_status = ExitStatus::from_code(130);
+            }
+
+            Err(e) => {
+                eprintln!("psh: {e}");
+                self.engine.log(log::Kind::of(&e), &e);
+                self.engine.last_status = ExitStatus::from_code(1);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The files that are run when the shell starts, in order.
+pub struct InitFiles {
+    files: Vec<PathBuf>,
+
+    /// Whether the files were given with `--rcfile`, in which case it is an
+    /// error if they do not exist.
+    explicit: bool,
+}
+
+impl InitFiles {
+    /// The given files, or the default ones if none are given.
+    pub fn new(files: Vec<PathBuf>) -> Self {
+        match files.is_empty() {
+            true => Self {
+                files: path::init_files(),
+                explicit: false,
+            },
+            false => Self {
+                files,
+                explicit: true,
+            },
+        }
+    }
+
+    /// Runs every file. An error in one of them is reported, and does not
+    /// keep the remaining ones from being run.
+    pub fn read(&self, engine: &mut Engine) {
+        for file in &self.files {
+            match engine.execute_file(file.clone(), true) {
+                Ok(_) => {}
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound && !self.explicit => {}
+                Err(e) => {
+                    eprintln!("psh: {}: {e}", file.display());
+                    engine.log(log::Kind::of(&e), format!("{}: {e}", file.display()));
+                }
+            }
         }
     }
 }
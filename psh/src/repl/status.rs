@@ -0,0 +1,102 @@
+//! The status and duration of every command, shown at the right edge of the
+//! line it was entered on when the `cmd_status` option is set, like the
+//! prompts of many zsh themes do, without having to build it from `PS1`.
+
+use std::io::{stdout, Write};
+use std::time::Duration;
+
+use crossterm::cursor;
+use crossterm::queue;
+use crossterm::style;
+use crossterm::terminal;
+
+use psh_core::{Engine, ExitStatus, Result};
+
+use crate::config::Colors;
+use crate::repl::input::Input;
+
+/// Shows `status` and `duration` for the command in `input`, where `after`
+/// is where the cursor was right after it was entered. If the command wrote
+/// nothing, they are put on the line the command was entered on, and
+/// otherwise on a line of their own below its output.
+pub fn show(
+    engine: &Engine,
+    input: &Input,
+    after: (u16, u16),
+    status: ExitStatus,
+    duration: Duration,
+) -> Result<()> {
+    let (mark, color) = match status.is_ok() {
+        true => ("✓".to_string(), Colors::valid_cmd(engine)),
+        false => (
+            format!("✗ {}", status.to_string()),
+            Colors::invalid_cmd(engine),
+        ),
+    };
+    let duration = format_duration(duration);
+    let len = (mark.chars().count() + 1 + duration.len()) as u16;
+
+    let (columns, _) = terminal::size()?;
+    let (x, y) = cursor::position()?;
+    let end = input.column as usize + input.line.chars().count();
+    let fits = !input.line.contains('\n') && end + 1 + (len as usize) < columns as usize;
+    let on_input_line = fits && (x, y) == after && after.1 > 0;
+
+    let mut stdout = stdout();
+    if on_input_line {
+        queue!(stdout, cursor::MoveTo(columns - len, after.1 - 1))?;
+    } else {
+        if x > 0 {
+            queue!(stdout, style::Print("\r\n"))?;
+        }
+        queue!(stdout, cursor::MoveToColumn(columns.saturating_sub(len)))?;
+    }
+
+    queue!(
+        stdout,
+        style::SetForegroundColor(color),
+        style::Print(mark),
+        style::SetForegroundColor(Colors::comment(engine)),
+        style::Print(format!(" {duration}")),
+        style::ResetColor,
+    )?;
+
+    if on_input_line {
+        queue!(stdout, cursor::MoveTo(x, y))?;
+    } else {
+        queue!(stdout, style::Print("\r\n"))?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Formats `duration` compactly, such as `42ms`, `1.3s`, `2m05s` or
+/// `1h02m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting_durations() {
+        let golden = [
+            (Duration::from_millis(42), "42ms"),
+            (Duration::from_millis(1340), "1.3s"),
+            (Duration::from_secs(125), "2m05s"),
+            (Duration::from_secs(3720), "1h02m"),
+        ];
+        for (duration, expected) in golden {
+            assert_eq!(expected, format_duration(duration));
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! `psh self-update`, which replaces the running binary by the one of the
+//! latest release. Meant for the standalone binaries, installs done with
+//! `cargo` should be updated with `cargo install` instead.
+//!
+//! Downloads are done with `curl`, and every binary is checked against the
+//! `SHA256SUMS` file published along with it. This only catches downloads
+//! that were corrupted: the checksums come from the same release as the
+//! binary, so anyone who can replace one can replace the other. No
+//! signature is verified.
+
+use std::env::{self, consts};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/lupont/posh/releases/latest";
+const DOWNLOADS: &str = "https://github.com/lupont/posh/releases/download";
+const CHECKSUMS: &str = "SHA256SUMS";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Download(String, String),
+    InvalidRelease(String),
+    MissingChecksum(String),
+    ChecksumMismatch(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Download(url, e) => write!(f, "could not download {url}: {e}"),
+            Self::InvalidRelease(e) => write!(f, "invalid release information: {e}"),
+            Self::MissingChecksum(asset) => write!(f, "no checksum published for {asset}"),
+            Self::ChecksumMismatch(asset) => {
+                write!(f, "checksum of {asset} does not match, not installing it")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A version such as `0.2.1`, optionally prefixed by `v` as in release tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(u64, u64, u64);
+
+impl Version {
+    pub fn current() -> Self {
+        Self::parse(env!("CARGO_PKG_VERSION")).expect("the package version is valid")
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('v').unwrap_or(s);
+        let mut parts = s.splitn(3, '.').map(|part| part.parse().ok());
+        Some(Self(parts.next()??, parts.next()??, parts.next()??))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The name of the binary published for this platform, e.g.
+/// `psh-x86_64-linux`.
+fn asset() -> String {
+    format!("psh-{}-{}", consts::ARCH, consts::OS)
+}
+
+fn download(url: &str) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", url])
+        .output()
+        .map_err(|e| Error::Download(url.to_string(), format!("curl: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Download(url.to_string(), stderr.trim().to_string()));
+    }
+    Ok(output.stdout)
+}
+
+/// The tag and version of the latest release.
+pub fn latest() -> Result<(String, Version)> {
+    let json = download(LATEST_RELEASE)?;
+    let release = serde_json::from_slice::<serde_json::Value>(&json)
+        .map_err(|e| Error::InvalidRelease(e.to_string()))?;
+
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| Error::InvalidRelease("no tag name".to_string()))?;
+    let version = Version::parse(tag)
+        .ok_or_else(|| Error::InvalidRelease(format!("{tag} is not a version")))?;
+
+    Ok((tag.to_string(), version))
+}
+
+/// The checksum of `asset` in the contents of a `SHA256SUMS` file, whose
+/// lines are a hex encoded checksum followed by a file name.
+fn checksum_of<'a>(sums: &'a str, asset: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let (sum, name) = line.split_once(char::is_whitespace)?;
+        // `sha256sum` marks files read in binary mode with a `*`
+        let name = name.trim_start().trim_start_matches('*');
+        (name == asset).then_some(sum)
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Downloads the binary of the release tagged `tag`, verifies it, and
+/// replaces `target` by it. The new binary is written next to `target` and
+/// then renamed over it, so that `target` is never left half written.
+pub fn install(tag: &str, target: &Path) -> Result<()> {
+    let asset = asset();
+    let sums = download(&format!("{DOWNLOADS}/{tag}/{CHECKSUMS}"))?;
+    let sums = String::from_utf8_lossy(&sums);
+    let expected =
+        checksum_of(&sums, &asset).ok_or_else(|| Error::MissingChecksum(asset.clone()))?;
+
+    let binary = download(&format!("{DOWNLOADS}/{tag}/{asset}"))?;
+    if !hex(&Sha256::digest(&binary)).eq_ignore_ascii_case(expected) {
+        return Err(Error::ChecksumMismatch(asset));
+    }
+
+    let mut staged = target.as_os_str().to_owned();
+    staged.push(".new");
+    let staged = Path::new(&staged);
+
+    fs::write(staged, binary)?;
+    fs::set_permissions(staged, fs::Permissions::from_mode(0o755))?;
+    if let Err(e) = fs::rename(staged, target) {
+        let _ = fs::remove_file(staged);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Runs `psh self-update`, returning its exit code. With `check`, only
+/// prints the latest version if it is newer than this one, and exits with
+/// 0 if so and 1 otherwise. Either way, the GitHub API is asked for the
+/// latest release, which it only answers so many times an hour.
+pub fn run(check: bool) -> i32 {
+    let current = Version::current();
+    let (tag, latest) = match latest() {
+        Ok(latest) => latest,
+        Err(e) => {
+            eprintln!("psh: self-update: {e}");
+            return 2;
+        }
+    };
+
+    if check {
+        if latest > current {
+            println!("{latest}");
+            return 0;
+        }
+        return 1;
+    }
+
+    if latest <= current {
+        println!("psh {current} is the latest version");
+        return 0;
+    }
+
+    let result = env::current_exe()
+        .map_err(Error::from)
+        .and_then(|exe| install(&tag, &exe));
+    match result {
+        Ok(()) => {
+            println!("Updated psh from {current} to {latest}");
+            0
+        }
+        Err(e) => {
+            eprintln!("psh: self-update: {e}");
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(Some(Version(0, 1, 0)), Version::parse("0.1.0"));
+        assert_eq!(Some(Version(1, 12, 3)), Version::parse("v1.12.3"));
+        assert_eq!(None, Version::parse("1.2"));
+        assert_eq!(None, Version::parse("1.2.3-rc1"));
+        assert!(Version(0, 10, 0) > Version(0, 9, 9));
+    }
+
+    #[test]
+    fn checksums() {
+        let sums = "\
+            0123abcd  psh-x86_64-linux\n\
+            4567ef01 *psh-aarch64-macos\n";
+        assert_eq!(Some("0123abcd"), checksum_of(sums, "psh-x86_64-linux"));
+        assert_eq!(Some("4567ef01"), checksum_of(sums, "psh-aarch64-macos"));
+        assert_eq!(None, checksum_of(sums, "psh-x86_64"));
+    }
+}